@@ -0,0 +1,15 @@
+CREATE TABLE newsletter_issues(
+    newsletter_issue_id uuid PRIMARY KEY NOT NULL,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    segment TEXT NULL,
+    status TEXT NOT NULL
+    CHECK (status IN ('draft', 'scheduled', 'sending', 'sent', 'cancelled')),
+    scheduled_at TIMESTAMPTZ NULL,
+    created_at TIMESTAMPTZ NOT NULL,
+    sent_at TIMESTAMPTZ NULL
+);
+
+CREATE INDEX newsletter_issues_due_idx ON newsletter_issues (scheduled_at)
+WHERE status = 'scheduled';
@@ -0,0 +1,12 @@
+CREATE TABLE issue_revisions(
+    newsletter_issue_id uuid NOT NULL
+    REFERENCES newsletter_issues (newsletter_issue_id),
+    revision_number INT NOT NULL,
+    title TEXT NOT NULL,
+    text_content TEXT NOT NULL,
+    html_content TEXT NOT NULL,
+    created_by uuid NOT NULL
+    REFERENCES users (user_id),
+    created_at TIMESTAMPTZ NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, revision_number)
+);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE issue_revisions DROP COLUMN markdown_content;
+ALTER TABLE newsletter_issues DROP COLUMN markdown_content;
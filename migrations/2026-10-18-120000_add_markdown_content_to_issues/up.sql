@@ -0,0 +1,4 @@
+-- The Markdown source, when the issue was authored in Markdown. The HTML and
+-- plain-text bodies are always rendered from it on save.
+ALTER TABLE newsletter_issues ADD COLUMN markdown_content TEXT NULL;
+ALTER TABLE issue_revisions ADD COLUMN markdown_content TEXT NULL;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE newsletter_issues DROP COLUMN layout;
+DROP TABLE transactional_templates;
+DROP TABLE email_layouts;
@@ -0,0 +1,44 @@
+-- Named layouts wrapping every email. The header, footer and unsubscribe block are Markdown
+-- templates; the unsubscribe block is only added to newsletters.
+CREATE TABLE email_layouts(
+    name TEXT NOT NULL,
+    PRIMARY KEY (name),
+    header TEXT NOT NULL,
+    footer TEXT NOT NULL,
+    physical_address TEXT NOT NULL,
+    unsubscribe_block TEXT NOT NULL,
+    updated_at timestamptz NOT NULL
+);
+
+INSERT INTO email_layouts (name, header, footer, physical_address, unsubscribe_block, updated_at)
+VALUES (
+    'default',
+    '',
+    'You are receiving this email because you signed up for our newsletter.',
+    '',
+    'Don''t want these emails anymore? [Unsubscribe]({{ unsubscribe_url }}).',
+    now()
+);
+
+-- The body of each transactional email, as a Markdown template rendered in a layout.
+CREATE TABLE transactional_templates(
+    name TEXT NOT NULL,
+    PRIMARY KEY (name),
+    subject TEXT NOT NULL,
+    layout TEXT NOT NULL REFERENCES email_layouts (name),
+    body TEXT NOT NULL,
+    updated_at timestamptz NOT NULL
+);
+
+INSERT INTO transactional_templates (name, subject, layout, body, updated_at)
+VALUES
+(
+    'confirmation',
+    'Welcome!',
+    'default',
+    E'Welcome to our newsletter, {{ subscriber.name }}!\n\n[Confirm your subscription]({{ confirmation_link }}) to start receiving it.',
+    now()
+);
+
+ALTER TABLE newsletter_issues
+    ADD COLUMN layout TEXT NOT NULL DEFAULT 'default' REFERENCES email_layouts (name);
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+DROP TABLE tracking_events;
+DROP TABLE issue_links;
+ALTER TABLE newsletter_issues DROP COLUMN tracking_enabled;
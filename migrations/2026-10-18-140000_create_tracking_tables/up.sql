@@ -0,0 +1,21 @@
+ALTER TABLE newsletter_issues ADD COLUMN tracking_enabled BOOLEAN NOT NULL DEFAULT FALSE;
+
+-- The links found in an issue. Click tracking only ever redirects to one of these.
+CREATE TABLE issue_links(
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    link_number INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    PRIMARY KEY (newsletter_issue_id, link_number),
+    UNIQUE (newsletter_issue_id, url)
+);
+
+CREATE TABLE tracking_events(
+    tracking_event_id BIGSERIAL PRIMARY KEY,
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('delivered', 'open', 'click', 'unsubscribe')),
+    url TEXT NULL,
+    created_at timestamptz NOT NULL
+);
+
+CREATE INDEX tracking_events_issue_kind_idx ON tracking_events (newsletter_issue_id, kind);
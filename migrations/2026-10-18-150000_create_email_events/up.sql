@@ -0,0 +1,20 @@
+-- Bounces and spam complaints reported by the email delivery provider.
+CREATE TABLE email_events(
+    email_event_id BIGSERIAL PRIMARY KEY,
+    provider TEXT NOT NULL,
+    -- The provider's own id for the event, so that redelivered webhooks are only stored once.
+    provider_event_id TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('bounce', 'spam_complaint')),
+    -- The provider's classification, e.g. `HardBounce` or `SoftBounce`.
+    event_type TEXT NOT NULL,
+    -- Whether the address should not be mailed anymore.
+    permanent BOOLEAN NOT NULL,
+    email TEXT NOT NULL,
+    subscriber_id uuid NULL REFERENCES subscriptions (id) ON DELETE SET NULL,
+    message_id TEXT NULL,
+    description TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    received_at timestamptz NOT NULL,
+    UNIQUE (provider, provider_event_id)
+);
+CREATE INDEX email_events_email ON email_events (email);
@@ -0,0 +1,16 @@
+-- Every attempt to send a newsletter issue to a subscriber, and what the provider answered.
+CREATE TABLE email_deliveries(
+    email_delivery_id BIGSERIAL PRIMARY KEY,
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id) ON DELETE CASCADE,
+    subscriber_id uuid NULL REFERENCES subscriptions (id) ON DELETE SET NULL,
+    email TEXT NOT NULL,
+    status TEXT NOT NULL CHECK (status IN ('sent', 'failed')),
+    provider_message_id TEXT NULL,
+    error_code TEXT NULL,
+    error_message TEXT NULL,
+    attempted_at timestamptz NOT NULL,
+    completed_at timestamptz NOT NULL
+);
+CREATE INDEX email_deliveries_email ON email_deliveries (lower(email));
+CREATE INDEX email_deliveries_newsletter_issue_id ON email_deliveries (newsletter_issue_id);
+CREATE INDEX email_deliveries_provider_message_id ON email_deliveries (provider_message_id);
@@ -0,0 +1,3 @@
+-- This file should undo anything in `up.sql`
+DROP INDEX newsletter_issues_archive_idx;
+ALTER TABLE newsletter_issues DROP COLUMN in_archive;
@@ -0,0 +1,3 @@
+-- Sent issues are published in the public archive and feeds unless they are kept out of it.
+ALTER TABLE newsletter_issues ADD COLUMN in_archive BOOLEAN NOT NULL DEFAULT TRUE;
+CREATE INDEX newsletter_issues_archive_idx ON newsletter_issues (sent_at DESC) WHERE status = 'sent' AND in_archive;
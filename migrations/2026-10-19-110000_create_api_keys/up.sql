@@ -0,0 +1,15 @@
+-- Keys integrations authenticate with on the `/api/v1` endpoints.
+CREATE TABLE api_keys(
+    api_key_id uuid PRIMARY KEY,
+    name TEXT NOT NULL,
+    -- The first characters of the key, so admins can tell keys apart. The key itself is not stored.
+    prefix TEXT NOT NULL,
+    -- Hex-encoded SHA-256 of the key.
+    key_hash TEXT NOT NULL UNIQUE,
+    -- What the key may be used for, e.g. `subscribers:read`.
+    scopes TEXT[] NOT NULL,
+    created_by uuid NOT NULL REFERENCES users (user_id),
+    created_at timestamptz NOT NULL,
+    last_used_at timestamptz NULL,
+    revoked_at timestamptz NULL
+);
@@ -0,0 +1,2 @@
+-- How many subscribers the email provider refused to send a sent issue to.
+ALTER TABLE newsletter_issues ADD COLUMN failed_deliveries INTEGER NOT NULL DEFAULT 0;
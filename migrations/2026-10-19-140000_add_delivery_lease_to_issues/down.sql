@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+ALTER TABLE newsletter_issues DROP COLUMN delivery_failures;
+ALTER TABLE newsletter_issues DROP COLUMN claimed_at;
+UPDATE newsletter_issues SET status = 'cancelled' WHERE status = 'failed';
+ALTER TABLE newsletter_issues DROP CONSTRAINT newsletter_issues_status_check;
+ALTER TABLE newsletter_issues ADD CONSTRAINT newsletter_issues_status_check
+    CHECK (status IN ('draft', 'scheduled', 'sending', 'sent', 'cancelled'));
@@ -0,0 +1,10 @@
+-- Issues that could not be sent after several tries are `failed`, and can be rescheduled.
+ALTER TABLE newsletter_issues DROP CONSTRAINT newsletter_issues_status_check;
+ALTER TABLE newsletter_issues ADD CONSTRAINT newsletter_issues_status_check
+    CHECK (status IN ('draft', 'scheduled', 'sending', 'sent', 'cancelled', 'failed'));
+-- When the issue was claimed for sending, renewed as it makes progress. A `sending` issue whose
+-- claim is old was left behind by a crash.
+ALTER TABLE newsletter_issues ADD COLUMN claimed_at TIMESTAMPTZ NULL;
+UPDATE newsletter_issues SET claimed_at = now() WHERE status = 'sending';
+-- How many times sending the issue failed.
+ALTER TABLE newsletter_issues ADD COLUMN delivery_failures INTEGER NOT NULL DEFAULT 0;
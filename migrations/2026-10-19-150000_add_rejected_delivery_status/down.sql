@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+UPDATE email_deliveries SET status = 'failed' WHERE status = 'rejected';
+ALTER TABLE email_deliveries DROP CONSTRAINT email_deliveries_status_check;
+ALTER TABLE email_deliveries ADD CONSTRAINT email_deliveries_status_check
+    CHECK (status IN ('sent', 'failed'));
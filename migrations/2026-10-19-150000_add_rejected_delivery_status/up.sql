@@ -0,0 +1,4 @@
+-- Recipients the provider refused for good are `rejected`, and are not sent the issue again.
+ALTER TABLE email_deliveries DROP CONSTRAINT email_deliveries_status_check;
+ALTER TABLE email_deliveries ADD CONSTRAINT email_deliveries_status_check
+    CHECK (status IN ('sent', 'failed', 'rejected'));
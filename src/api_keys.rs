@@ -0,0 +1,176 @@
+use crate::db_models::ApiKey;
+use crate::schema::api_keys;
+use chrono::{DateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+// Keys start with it, so they are easy to recognise, e.g. by secret scanners.
+const KEY_PREFIX: &str = "nl_";
+const KEY_LENGTH: usize = 40;
+// How much of a key is kept in the clear to tell keys apart, prefix included.
+const DISPLAYED_LENGTH: usize = 11;
+
+// What an API key may be used for. Every `/api/v1` endpoint requires one of them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiScope {
+    SubscribersRead,
+    SubscribersWrite,
+    IssuesRead,
+    IssuesWrite,
+    StatsRead,
+}
+
+impl ApiScope {
+    pub const ALL: [ApiScope; 5] = [
+        ApiScope::SubscribersRead,
+        ApiScope::SubscribersWrite,
+        ApiScope::IssuesRead,
+        ApiScope::IssuesWrite,
+        ApiScope::StatsRead,
+    ];
+
+    pub fn parse(s: &str) -> Result<ApiScope, String> {
+        Self::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| format!("{} is not a valid API key scope.", s))
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::SubscribersRead => "subscribers:read",
+            ApiScope::SubscribersWrite => "subscribers:write",
+            ApiScope::IssuesRead => "issues:read",
+            ApiScope::IssuesWrite => "issues:write",
+            ApiScope::StatsRead => "stats:read",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            ApiScope::SubscribersRead => "List and look up subscribers",
+            ApiScope::SubscribersWrite => "Add, change and delete subscribers",
+            ApiScope::IssuesRead => "List newsletter issues and check their status",
+            ApiScope::IssuesWrite => "Create, schedule and send newsletter issues",
+            ApiScope::StatsRead => "Read subscriber and issue statistics",
+        }
+    }
+}
+
+impl std::fmt::Display for ApiScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+fn generate_api_key() -> String {
+    let mut rng = thread_rng();
+    let random: String = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(KEY_LENGTH)
+        .collect();
+    format!("{}{}", KEY_PREFIX, random)
+}
+
+// Keys are long and random, so a fast hash is enough: there is nothing to guess them from.
+fn hash_api_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+// Stores a new key and returns it. Only its hash is kept, so this is the one time it can be shown.
+#[tracing::instrument(name = "Create an API key", skip(conn, scopes))]
+pub fn create_api_key(
+    conn: &mut PgConnection,
+    name: &str,
+    scopes: &[ApiScope],
+    created_by: Uuid,
+    now: DateTime<Utc>,
+) -> Result<Secret<String>, diesel::result::Error> {
+    let key = generate_api_key();
+    let scopes: Vec<&str> = scopes.iter().map(ApiScope::as_str).collect();
+    diesel::insert_into(api_keys::table)
+        .values((
+            api_keys::api_key_id.eq(Uuid::new_v4()),
+            api_keys::name.eq(name),
+            api_keys::prefix.eq(&key[..DISPLAYED_LENGTH]),
+            api_keys::key_hash.eq(hash_api_key(&key)),
+            api_keys::scopes.eq(scopes),
+            api_keys::created_by.eq(created_by),
+            api_keys::created_at.eq(now),
+        ))
+        .execute(conn)?;
+    Ok(Secret::new(key))
+}
+
+// Looks up the key that has not been revoked, recording that it was used.
+pub fn find_api_key(
+    conn: &mut PgConnection,
+    key: &Secret<String>,
+    now: DateTime<Utc>,
+) -> Result<Option<ApiKey>, diesel::result::Error> {
+    diesel::update(
+        api_keys::table
+            .filter(api_keys::key_hash.eq(hash_api_key(key.expose_secret())))
+            .filter(api_keys::revoked_at.is_null()),
+    )
+    .set(api_keys::last_used_at.eq(now))
+    .get_result::<ApiKey>(conn)
+    .optional()
+}
+
+pub fn list_api_keys(conn: &mut PgConnection) -> Result<Vec<ApiKey>, diesel::result::Error> {
+    api_keys::table
+        .order(api_keys::created_at.desc())
+        .load::<ApiKey>(conn)
+}
+
+// Returns whether there was a key left to revoke.
+pub fn revoke_api_key(
+    conn: &mut PgConnection,
+    api_key_id: Uuid,
+    now: DateTime<Utc>,
+) -> Result<bool, diesel::result::Error> {
+    let revoked = diesel::update(
+        api_keys::table
+            .find(api_key_id)
+            .filter(api_keys::revoked_at.is_null()),
+    )
+    .set(api_keys::revoked_at.eq(now))
+    .execute(conn)?;
+    Ok(revoked > 0)
+}
+
+impl ApiKey {
+    pub fn has_scope(&self, scope: ApiScope) -> bool {
+        self.scopes.iter().any(|s| s == scope.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claim::assert_err;
+
+    #[test]
+    fn every_scope_round_trips_through_its_string_form() {
+        for scope in ApiScope::ALL {
+            assert_eq!(ApiScope::parse(scope.as_str()), Ok(scope));
+        }
+        assert_err!(ApiScope::parse("admin"));
+    }
+
+    #[test]
+    fn generated_keys_are_unique_and_only_their_hash_depends_on_them() {
+        let key = generate_api_key();
+        assert!(key.starts_with(KEY_PREFIX));
+        assert_eq!(key.len(), KEY_PREFIX.len() + KEY_LENGTH);
+        assert_ne!(key, generate_api_key());
+        assert_eq!(hash_api_key(&key), hash_api_key(&key));
+        assert_ne!(hash_api_key(&key), hash_api_key(&generate_api_key()));
+    }
+}
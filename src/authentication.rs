@@ -55,8 +55,6 @@ fn verify_password_hash(
 ) -> Result<(), AuthError> {
     let expected_password_hash = PasswordHash::new(expected_password_hash.expose_secret())
         .context("Failed to parse hash in PHC string format.")?;
-    // println!("{}", expected_password_hash);
-    // println!("{}", password_candidate.expose_secret());
 
     Argon2::default()
         .verify_password(
@@ -101,7 +99,6 @@ pub async fn validate_credentials(
     //     Err(e) => Err(PublishError::AuthError(e.into())),
     // }
 
-    // println!("{:?}", result.unwrap());
     // Ok(user_id)
     id_user
         .ok_or_else(|| anyhow::anyhow!("Unknown username."))
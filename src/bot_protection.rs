@@ -0,0 +1,213 @@
+use crate::captcha::CaptchaVerifier;
+use crate::signing::SigningKey;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use redis::aio::ConnectionManager;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+use uuid::Uuid;
+
+const FORM: u8 = b'f';
+
+// Forms sent sooner than this after being served were filled in by a script.
+pub const MIN_FILL_TIME: Duration = Duration::from_secs(3);
+// Form tokens older than this are no longer accepted.
+pub const FORM_MAX_AGE: Duration = Duration::from_secs(24 * 3600);
+
+// Issued with the signup form, so a submission shows when the form was served and cannot be
+// reused. Signed with the form token key of `SigningKeys`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FormToken {
+    pub issued_at: DateTime<Utc>,
+    pub nonce: Uuid,
+}
+
+impl FormToken {
+    pub fn new(issued_at: DateTime<Utc>) -> Self {
+        Self {
+            issued_at,
+            nonce: Uuid::new_v4(),
+        }
+    }
+
+    fn payload(&self) -> Vec<u8> {
+        [
+            &[FORM][..],
+            &self.issued_at.timestamp().to_be_bytes(),
+            self.nonce.as_bytes(),
+        ]
+        .concat()
+    }
+
+    pub fn sign(&self, key: &SigningKey) -> String {
+        key.sign(&self.payload())
+    }
+
+    // Returns `None` for anything that was not signed with `key`.
+    pub fn verify(token: &str, key: &SigningKey) -> Option<Self> {
+        let payload = key.verify(token)?;
+        if payload.len() != 25 || payload[0] != FORM {
+            return None;
+        }
+        let timestamp = i64::from_be_bytes(payload[1..9].try_into().ok()?);
+        Some(Self {
+            issued_at: DateTime::from_timestamp(timestamp, 0)?,
+            nonce: Uuid::from_slice(&payload[9..25]).ok()?,
+        })
+    }
+}
+
+// The bot protection fields of a signup form.
+#[derive(Debug, Default)]
+pub struct Submission<'a> {
+    pub form_token: Option<&'a str>,
+    // A field hidden from people, which only bots fill in.
+    pub honeypot: Option<&'a str>,
+    pub captcha_response: Option<&'a str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Verdict {
+    Human,
+    // Why the submission looks automated, for the logs.
+    Suspicious(&'static str),
+}
+
+pub struct BotProtection {
+    key: SigningKey,
+    redis: redis::Client,
+    connection: OnceCell<ConnectionManager>,
+    min_fill_time: Duration,
+    max_age: Duration,
+    captcha: Option<Box<dyn CaptchaVerifier>>,
+}
+
+impl BotProtection {
+    pub fn new(key: SigningKey, redis: redis::Client) -> Self {
+        Self {
+            key,
+            redis,
+            connection: OnceCell::new(),
+            min_fill_time: MIN_FILL_TIME,
+            max_age: FORM_MAX_AGE,
+            captcha: None,
+        }
+    }
+
+    // Forms submitted sooner than `min_fill_time` after being served were filled in by a script;
+    // tokens older than `max_age` are no longer accepted.
+    pub fn with_timing(mut self, min_fill_time: Duration, max_age: Duration) -> Self {
+        self.min_fill_time = min_fill_time;
+        self.max_age = max_age;
+        self
+    }
+
+    pub fn with_captcha(mut self, captcha: Box<dyn CaptchaVerifier>) -> Self {
+        self.captcha = Some(captcha);
+        self
+    }
+
+    pub fn captcha(&self) -> Option<&dyn CaptchaVerifier> {
+        self.captcha.as_deref()
+    }
+
+    pub fn issue_token(&self, now: DateTime<Utc>) -> String {
+        FormToken::new(now).sign(&self.key)
+    }
+
+    async fn connection(&self) -> Result<ConnectionManager, anyhow::Error> {
+        self.connection
+            .get_or_try_init(|| ConnectionManager::new(self.redis.clone()))
+            .await
+            .cloned()
+            .context("Failed to connect to Redis.")
+    }
+
+    // Records the token as used. Returns `false` if it already was.
+    async fn first_use(&self, token: &FormToken) -> Result<bool, anyhow::Error> {
+        let mut conn = self.connection().await?;
+        let stored = redis::cmd("SET")
+            .arg(format!("form_token:{}", token.nonce))
+            .arg(1)
+            .arg("EX")
+            .arg(self.max_age.as_secs().max(1))
+            .arg("NX")
+            .query_async::<Option<String>>(&mut conn)
+            .await
+            .context("Failed to record the use of a form token.")?;
+        Ok(stored.is_some())
+    }
+
+    // Errors only come from the CAPTCHA provider. A failure to reach Redis lets the token through,
+    // like the rate limits do.
+    #[tracing::instrument(name = "Check signup for bots", skip_all)]
+    pub async fn check(
+        &self,
+        submission: &Submission<'_>,
+        now: DateTime<Utc>,
+    ) -> Result<Verdict, anyhow::Error> {
+        if submission.honeypot.is_some_and(|h| !h.is_empty()) {
+            return Ok(Verdict::Suspicious("honeypot filled in"));
+        }
+        let Some(token) = submission.form_token else {
+            return Ok(Verdict::Suspicious("form token missing"));
+        };
+        let Some(token) = FormToken::verify(token, &self.key) else {
+            return Ok(Verdict::Suspicious("form token invalid"));
+        };
+        let elapsed = (now - token.issued_at).to_std().unwrap_or(Duration::ZERO);
+        if elapsed < self.min_fill_time {
+            return Ok(Verdict::Suspicious("form submitted too fast"));
+        }
+        if elapsed > self.max_age {
+            return Ok(Verdict::Suspicious("form token expired"));
+        }
+        match self.first_use(&token).await {
+            Ok(true) => {}
+            Ok(false) => return Ok(Verdict::Suspicious("form token replayed")),
+            Err(e) => tracing::warn!(
+                error.cause_chain = ?e,
+                "Failed to check the form token for replays, letting it through"
+            ),
+        }
+        if let Some(captcha) = &self.captcha {
+            let Some(response) = submission.captcha_response.filter(|r| !r.is_empty()) else {
+                return Ok(Verdict::Suspicious("CAPTCHA missing"));
+            };
+            if !captcha.verify(response).await? {
+                return Ok(Verdict::Suspicious("CAPTCHA failed"));
+            }
+        }
+        Ok(Verdict::Human)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::signing::SigningKeys;
+    use secrecy::Secret;
+
+    fn key(s: &str) -> SigningKey {
+        SigningKeys::derive(&Secret::new(s.to_string())).form_tokens
+    }
+
+    #[test]
+    fn a_signed_token_verifies_to_itself() {
+        let token = FormToken::new(DateTime::from_timestamp(1_700_000_000, 0).unwrap());
+        let signed = token.sign(&key("secret"));
+        assert_eq!(FormToken::verify(&signed, &key("secret")), Some(token));
+    }
+
+    #[test]
+    fn a_token_signed_with_another_key_or_altered_is_rejected() {
+        let signed = FormToken::new(Utc::now()).sign(&key("secret"));
+        assert_eq!(FormToken::verify(&signed, &key("another secret")), None);
+        let mut bytes = base64::decode_config(&signed, base64::URL_SAFE_NO_PAD).unwrap();
+        // Backdates the token by a few seconds.
+        bytes[8] = bytes[8].wrapping_sub(5);
+        let altered = base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+        assert_eq!(FormToken::verify(&altered, &key("secret")), None);
+        assert_eq!(FormToken::verify("", &key("secret")), None);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::templating::escape_html;
+use anyhow::Context;
+use reqwest::Client;
+use secrecy::{ExposeSecret, Secret};
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+
+// Checks the response a CAPTCHA widget added to the signup form.
+pub trait CaptchaVerifier: Send + Sync {
+    // The markup that renders the widget inside the signup form.
+    fn widget_html(&self) -> String;
+
+    // The Content Security Policy of a page showing the widget.
+    fn content_security_policy(&self) -> String;
+
+    // Whether the provider accepts `response`. Errors mean the provider could not be asked.
+    fn verify<'a>(
+        &'a self,
+        response: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, anyhow::Error>> + Send + 'a>>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CaptchaProvider {
+    HCaptcha,
+    Turnstile,
+}
+
+impl CaptchaProvider {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s.trim().to_lowercase().as_str() {
+            "hcaptcha" => Ok(CaptchaProvider::HCaptcha),
+            "turnstile" => Ok(CaptchaProvider::Turnstile),
+            other => Err(format!("`{}` is not a supported CAPTCHA provider", other)),
+        }
+    }
+
+    pub fn verify_url(&self) -> &'static str {
+        match self {
+            CaptchaProvider::HCaptcha => "https://api.hcaptcha.com/siteverify",
+            CaptchaProvider::Turnstile => {
+                "https://challenges.cloudflare.com/turnstile/v0/siteverify"
+            }
+        }
+    }
+
+    // Where the widget loads its scripts, frames and styles from.
+    fn origins(&self) -> &'static str {
+        match self {
+            CaptchaProvider::HCaptcha => "https://hcaptcha.com https://*.hcaptcha.com",
+            CaptchaProvider::Turnstile => "https://challenges.cloudflare.com",
+        }
+    }
+
+    fn script_url(&self) -> &'static str {
+        match self {
+            CaptchaProvider::HCaptcha => "https://js.hcaptcha.com/1/api.js",
+            CaptchaProvider::Turnstile => "https://challenges.cloudflare.com/turnstile/v0/api.js",
+        }
+    }
+
+    fn widget_class(&self) -> &'static str {
+        match self {
+            CaptchaProvider::HCaptcha => "h-captcha",
+            CaptchaProvider::Turnstile => "cf-turnstile",
+        }
+    }
+}
+
+// hCaptcha and Turnstile share the same `siteverify` API: the secret and the widget's response
+// are posted as a form, and `success` says whether the response is genuine.
+#[derive(Clone)]
+pub struct SiteVerifyCaptcha {
+    provider: CaptchaProvider,
+    site_key: String,
+    secret: Secret<String>,
+    verify_url: String,
+    http_client: Client,
+}
+
+#[derive(serde::Deserialize)]
+struct SiteVerifyResponse {
+    success: bool,
+    #[serde(rename = "error-codes", default)]
+    error_codes: Vec<String>,
+}
+
+impl SiteVerifyCaptcha {
+    pub fn new(
+        provider: CaptchaProvider,
+        site_key: String,
+        secret: Secret<String>,
+        verify_url: String,
+    ) -> Self {
+        let http_client = Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .unwrap();
+        Self {
+            provider,
+            site_key,
+            secret,
+            verify_url,
+            http_client,
+        }
+    }
+}
+
+impl CaptchaVerifier for SiteVerifyCaptcha {
+    fn widget_html(&self) -> String {
+        format!(
+            r#"<div class="{}" data-sitekey="{}"></div>
+<script src="{}" async defer></script>"#,
+            self.provider.widget_class(),
+            escape_html(&self.site_key),
+            self.provider.script_url()
+        )
+    }
+
+    fn content_security_policy(&self) -> String {
+        format!(
+            "default-src 'none'; script-src {origins}; frame-src {origins}; \
+            style-src 'self' {origins}; connect-src {origins}; img-src 'self'; \
+            form-action 'self'; frame-ancestors 'none'; base-uri 'none'",
+            origins = self.provider.origins()
+        )
+    }
+
+    fn verify<'a>(
+        &'a self,
+        response: &'a str,
+    ) -> Pin<Box<dyn Future<Output = Result<bool, anyhow::Error>> + Send + 'a>> {
+        Box::pin(async move {
+            let verdict: SiteVerifyResponse = self
+                .http_client
+                .post(&self.verify_url)
+                .form(&[
+                    ("secret", self.secret.expose_secret().as_str()),
+                    ("response", response),
+                ])
+                .send()
+                .await
+                .context("Failed to reach the CAPTCHA provider.")?
+                .error_for_status()
+                .context("The CAPTCHA provider rejected the verification request.")?
+                .json()
+                .await
+                .context("Failed to read the CAPTCHA verification.")?;
+            if !verdict.success {
+                tracing::info!(error_codes = ?verdict.error_codes, "CAPTCHA verification failed");
+            }
+            Ok(verdict.success)
+        })
+    }
+}
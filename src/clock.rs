@@ -0,0 +1,53 @@
+use chrono::{DateTime, Utc};
+use std::sync::Mutex;
+
+// Source of the current time. Everything that compares against "now" (e.g. the
+// newsletter scheduler) goes through a `Clock` so tests can control time.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+// A clock that only moves when told to.
+pub struct MockClock(Mutex<DateTime<Utc>>);
+
+impl MockClock {
+    pub fn new(now: DateTime<Utc>) -> Self {
+        Self(Mutex::new(now))
+    }
+    pub fn set(&self, now: DateTime<Utc>) {
+        *self.0.lock().unwrap() = now;
+    }
+    pub fn advance(&self, by: chrono::Duration) {
+        let mut now = self.0.lock().unwrap();
+        *now += by;
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> DateTime<Utc> {
+        *self.0.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clock::{Clock, MockClock};
+    use chrono::{Duration, Utc};
+
+    #[test]
+    fn a_mock_clock_only_moves_when_advanced() {
+        let start = Utc::now();
+        let clock = MockClock::new(start);
+        assert_eq!(clock.now(), start);
+        clock.advance(Duration::hours(1));
+        assert_eq!(clock.now(), start + Duration::hours(1));
+    }
+}
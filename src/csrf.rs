@@ -0,0 +1,117 @@
+use crate::session_state::TypedSession;
+use crate::utils::{e500, peek_body};
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::Method;
+use actix_web::middleware::Next;
+use actix_web::{FromRequest, HttpMessage, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+
+// The form field the token is submitted in.
+pub const CSRF_FIELD: &str = "csrf_token";
+
+// The anti-forgery token of the current session, made available to handlers by
+// `reject_forged_requests` so they can render it into their forms with `partials/csrf.html`.
+#[derive(Clone, Debug)]
+pub struct CsrfToken(String);
+
+impl CsrfToken {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+fn generate_csrf_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(32)
+        .collect()
+}
+
+// Reads the token field of a form body.
+fn submitted_token(body: &[u8]) -> Option<String> {
+    let fields: Vec<(String, String)> = serde_urlencoded::from_bytes(body).ok()?;
+    fields
+        .into_iter()
+        .find(|(name, _)| name == CSRF_FIELD)
+        .map(|(_, value)| value)
+}
+
+// Compares in constant time, so the token cannot be guessed one character at a time.
+fn tokens_match(submitted: &str, expected: &str) -> bool {
+    submitted.len() == expected.len()
+        && submitted
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |difference, (a, b)| difference | (a ^ b))
+            == 0
+}
+
+fn is_safe(method: &Method) -> bool {
+    matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+// Synchronizer tokens: every session gets a random token, which forms send back in a hidden
+// field. Requests that change something are rejected with 403 Forbidden unless the token they
+// carry is the session's, since another site can make a browser submit a form but cannot read
+// the token.
+pub async fn reject_forged_requests(
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let session = {
+        let (http_request, payload) = req.parts_mut();
+        TypedSession::from_request(http_request, payload).await
+    }?;
+    let token = match session.get_csrf_token().map_err(e500)? {
+        Some(token) => token,
+        None => {
+            let token = generate_csrf_token();
+            session.insert_csrf_token(&token).map_err(e500)?;
+            token
+        }
+    };
+
+    if !is_safe(req.method()) {
+        let body = peek_body(&mut req).await?;
+        let valid =
+            submitted_token(&body).is_some_and(|submitted| tokens_match(&submitted, &token));
+        if !valid {
+            tracing::warn!(
+                path = req.path(),
+                "Rejected a form without a valid CSRF token"
+            );
+            FlashMessage::error("The form has expired, please reload the page and try again.")
+                .send();
+            let response = HttpResponse::Forbidden()
+                .body("The form has expired, please reload the page and try again.");
+            return Ok(req.into_response(response).map_into_right_body());
+        }
+    }
+
+    req.extensions_mut().insert(CsrfToken(token));
+    next.call(req).await.map(|r| r.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_token_is_read_from_the_form() {
+        let body = b"current_password=a&csrf_token=abc123&new_password=b";
+        assert_eq!(submitted_token(body), Some("abc123".into()));
+        assert_eq!(submitted_token(b"current_password=a"), None);
+    }
+
+    #[test]
+    fn only_the_same_token_matches() {
+        assert!(tokens_match("abc123", "abc123"));
+        assert!(!tokens_match("abc124", "abc123"));
+        assert!(!tokens_match("abc12", "abc123"));
+        assert!(!tokens_match("", "abc123"));
+    }
+}
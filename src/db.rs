@@ -31,7 +31,7 @@ pub fn create_database(database_name: &str) {
     sql_query(&create_db_query)
         .execute(&mut connection)
         .expect("Failed to create database");
-    println!("Database '{}' created", database_name);
+    tracing::info!(database_name, "Created the database");
 }
 
 pub fn drop_database(database_name: &str) {
@@ -53,7 +53,7 @@ pub fn drop_database(database_name: &str) {
     "#, database_name);
 
     if let Err(e) = sql_query(&terminate_query).execute(&mut connection) {
-        eprintln!("Failed to terminate connections: {}", e);
+        tracing::error!(error.message = %e, database_name, "Failed to terminate connections");
         return;
     }
 
@@ -61,8 +61,8 @@ pub fn drop_database(database_name: &str) {
     let drop_query = format!(r#"DROP DATABASE IF EXISTS "{}";"#, database_name);
 
     if let Err(e) = sql_query(&drop_query).execute(&mut connection) {
-        eprintln!("Failed to drop database: {}", e);
+        tracing::error!(error.message = %e, database_name, "Failed to drop the database");
     } else {
-        println!("Database '{}' dropped successfully.", database_name);
+        tracing::info!(database_name, "Dropped the database");
     }
 }
\ No newline at end of file
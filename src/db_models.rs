@@ -2,7 +2,10 @@
 
 #![allow(unused)]
 #![allow(clippy::all)]
-use crate::schema::{subscription_tokens, users};
+use crate::schema::{
+    api_keys, email_deliveries, email_layouts, issue_links, issue_revisions, newsletter_issues,
+    subscription_tokens, transactional_templates, users,
+};
 
 use chrono::offset::Utc;
 use chrono::DateTime;
@@ -24,6 +27,7 @@ pub struct Subscription {
     pub name: String,
     pub subscribed_at: DateTime<Utc>,
     pub status: Option<String>,
+    pub tags: Vec<String>,
 }
 
 #[derive(Queryable, Debug, Identifiable)]
@@ -32,4 +36,97 @@ pub struct User {
     pub user_id: Uuid,
     pub username: String,
     pub password_hash: String,
+    pub email: Option<String>,
+}
+
+#[derive(Queryable, Debug, Identifiable)]
+#[diesel(primary_key(newsletter_issue_id))]
+pub struct NewsletterIssue {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub segment: Option<String>,
+    pub status: String,
+    pub scheduled_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+    pub markdown_content: Option<String>,
+    pub layout: String,
+    pub tracking_enabled: bool,
+    pub in_archive: bool,
+    pub failed_deliveries: i32,
+    pub claimed_at: Option<DateTime<Utc>>,
+    pub delivery_failures: i32,
+}
+
+#[derive(Queryable, Debug, Identifiable)]
+#[diesel(primary_key(newsletter_issue_id, revision_number))]
+pub struct IssueRevision {
+    pub newsletter_issue_id: Uuid,
+    pub revision_number: i32,
+    pub title: String,
+    pub text_content: String,
+    pub html_content: String,
+    pub created_by: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub markdown_content: Option<String>,
+}
+
+#[derive(Queryable, Debug, Identifiable)]
+#[diesel(primary_key(name))]
+pub struct EmailLayout {
+    pub name: String,
+    pub header: String,
+    pub footer: String,
+    pub physical_address: String,
+    pub unsubscribe_block: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Debug, Identifiable)]
+#[diesel(primary_key(name))]
+pub struct TransactionalTemplate {
+    pub name: String,
+    pub subject: String,
+    pub layout: String,
+    pub body: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Debug, Identifiable)]
+#[diesel(primary_key(newsletter_issue_id, link_number))]
+pub struct IssueLink {
+    pub newsletter_issue_id: Uuid,
+    pub link_number: i32,
+    pub url: String,
+}
+
+#[derive(Queryable, Debug, Identifiable)]
+#[diesel(table_name = email_deliveries, primary_key(email_delivery_id))]
+pub struct EmailDelivery {
+    pub email_delivery_id: i64,
+    pub newsletter_issue_id: Uuid,
+    pub subscriber_id: Option<Uuid>,
+    pub email: String,
+    pub status: String,
+    pub provider_message_id: Option<String>,
+    pub error_code: Option<String>,
+    pub error_message: Option<String>,
+    pub attempted_at: DateTime<Utc>,
+    pub completed_at: DateTime<Utc>,
+}
+
+#[derive(Queryable, Debug, Identifiable)]
+#[diesel(primary_key(api_key_id))]
+pub struct ApiKey {
+    pub api_key_id: Uuid,
+    pub name: String,
+    pub prefix: String,
+    pub key_hash: String,
+    pub scopes: Vec<String>,
+    pub created_by: Uuid,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub revoked_at: Option<DateTime<Utc>>,
 }
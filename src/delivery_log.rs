@@ -0,0 +1,126 @@
+use crate::db_models::EmailDelivery;
+use crate::email_client::{SendEmailError, SendReceipt};
+use crate::schema::{email_deliveries, newsletter_issues};
+use chrono::{DateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::sql_types::Text;
+use std::collections::HashSet;
+use uuid::Uuid;
+
+define_sql_function!(fn lower(x: Text) -> Text);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeliveryStatus {
+    Sent,
+    // Failed in a way that may not happen again, e.g. the provider could not be reached.
+    Failed,
+    // The provider refused the recipient for good, e.g. because they are inactive.
+    Rejected,
+}
+
+impl DeliveryStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryStatus::Sent => "sent",
+            DeliveryStatus::Failed => "failed",
+            DeliveryStatus::Rejected => "rejected",
+        }
+    }
+}
+
+pub struct DeliveryAttempt<'a> {
+    pub newsletter_issue_id: Uuid,
+    pub subscriber_id: Uuid,
+    pub email: &'a str,
+    pub attempted_at: DateTime<Utc>,
+    pub completed_at: DateTime<Utc>,
+}
+
+// Stores the outcome of sending an issue to one subscriber.
+pub fn record_delivery(
+    conn: &mut PgConnection,
+    attempt: &DeliveryAttempt,
+    outcome: Result<&SendReceipt, &SendEmailError>,
+) -> Result<usize, diesel::result::Error> {
+    let (status, message_id, error_code, error_message) = match outcome {
+        Ok(receipt) => (DeliveryStatus::Sent, receipt.message_id.clone(), None, None),
+        Err(e) => (
+            match e {
+                SendEmailError::MessageRejected { .. } if !e.is_transient() => {
+                    DeliveryStatus::Rejected
+                }
+                _ => DeliveryStatus::Failed,
+            },
+            None,
+            Some(e.error_code()),
+            Some(e.to_string()),
+        ),
+    };
+    diesel::insert_into(email_deliveries::table)
+        .values((
+            email_deliveries::newsletter_issue_id.eq(attempt.newsletter_issue_id),
+            email_deliveries::subscriber_id.eq(attempt.subscriber_id),
+            email_deliveries::email.eq(attempt.email),
+            email_deliveries::status.eq(status.as_str()),
+            email_deliveries::provider_message_id.eq(message_id),
+            email_deliveries::error_code.eq(error_code),
+            email_deliveries::error_message.eq(error_message),
+            email_deliveries::attempted_at.eq(attempt.attempted_at),
+            email_deliveries::completed_at.eq(attempt.completed_at),
+        ))
+        .execute(conn)
+}
+
+// The subscribers an issue was already sent to or rejected for, so that a delivery resumed after
+// a shutdown or a failure skips them.
+pub fn settled_subscribers(
+    conn: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+) -> Result<HashSet<Uuid>, diesel::result::Error> {
+    let subscriber_ids = email_deliveries::table
+        .filter(email_deliveries::newsletter_issue_id.eq(newsletter_issue_id))
+        .filter(email_deliveries::status.eq_any([
+            DeliveryStatus::Sent.as_str(),
+            DeliveryStatus::Rejected.as_str(),
+        ]))
+        .select(email_deliveries::subscriber_id)
+        .load::<Option<Uuid>>(conn)?;
+    Ok(subscriber_ids.into_iter().flatten().collect())
+}
+
+#[derive(Default)]
+pub struct DeliverySearch<'a> {
+    pub email: Option<&'a str>,
+    pub newsletter_issue_id: Option<Uuid>,
+    pub provider_message_id: Option<&'a str>,
+}
+
+// The latest deliveries matching every given filter, with the title of their issue.
+// Emails are matched case-insensitively.
+pub fn search_deliveries(
+    conn: &mut PgConnection,
+    search: &DeliverySearch,
+    limit: i64,
+) -> Result<Vec<(EmailDelivery, String)>, diesel::result::Error> {
+    let mut query = email_deliveries::table
+        .inner_join(newsletter_issues::table)
+        .select((email_deliveries::all_columns, newsletter_issues::title))
+        .into_boxed();
+    if let Some(email) = search.email {
+        query = query.filter(lower(email_deliveries::email).eq(lower(email.to_string())));
+    }
+    if let Some(newsletter_issue_id) = search.newsletter_issue_id {
+        query = query.filter(email_deliveries::newsletter_issue_id.eq(newsletter_issue_id));
+    }
+    if let Some(provider_message_id) = search.provider_message_id {
+        query = query.filter(email_deliveries::provider_message_id.eq(provider_message_id));
+    }
+    query
+        .order((
+            email_deliveries::attempted_at.desc(),
+            email_deliveries::email_delivery_id.desc(),
+        ))
+        .limit(limit)
+        .load::<(EmailDelivery, String)>(conn)
+}
@@ -0,0 +1,83 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IssueStatus {
+    Draft,
+    Scheduled,
+    Sending,
+    Sent,
+    Cancelled,
+    // Sending it failed too many times.
+    Failed,
+}
+
+impl IssueStatus {
+    pub fn parse(s: &str) -> Result<IssueStatus, String> {
+        match s {
+            "draft" => Ok(Self::Draft),
+            "scheduled" => Ok(Self::Scheduled),
+            "sending" => Ok(Self::Sending),
+            "sent" => Ok(Self::Sent),
+            "cancelled" => Ok(Self::Cancelled),
+            "failed" => Ok(Self::Failed),
+            other => Err(format!("{} is not a valid newsletter issue status.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Draft => "draft",
+            Self::Scheduled => "scheduled",
+            Self::Sending => "sending",
+            Self::Sent => "sent",
+            Self::Cancelled => "cancelled",
+            Self::Failed => "failed",
+        }
+    }
+
+    // An issue can only be rescheduled or cancelled until its delivery has started, or once it
+    // has failed.
+    pub fn is_editable(&self) -> bool {
+        matches!(self, Self::Draft | Self::Scheduled | Self::Failed)
+    }
+}
+
+impl std::fmt::Display for IssueStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.as_str().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::IssueStatus;
+    use claim::assert_err;
+
+    #[test]
+    fn every_status_round_trips_through_its_string_form() {
+        for status in [
+            IssueStatus::Draft,
+            IssueStatus::Scheduled,
+            IssueStatus::Sending,
+            IssueStatus::Sent,
+            IssueStatus::Cancelled,
+            IssueStatus::Failed,
+        ] {
+            assert_eq!(IssueStatus::parse(status.as_str()), Ok(status));
+        }
+    }
+
+    #[test]
+    fn unknown_statuses_are_rejected() {
+        assert_err!(IssueStatus::parse("published"));
+    }
+
+    #[test]
+    fn only_issues_that_have_not_started_sending_or_have_failed_are_editable() {
+        assert!(IssueStatus::Draft.is_editable());
+        assert!(IssueStatus::Scheduled.is_editable());
+        assert!(IssueStatus::Failed.is_editable());
+        assert!(!IssueStatus::Sending.is_editable());
+        assert!(!IssueStatus::Sent.is_editable());
+        assert!(!IssueStatus::Cancelled.is_editable());
+    }
+}
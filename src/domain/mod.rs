@@ -1,7 +1,12 @@
 mod subscriber_name;
 mod subscriber_email;
 mod new_subscriber;
+mod subscriber_tag;
+mod segment;
+mod issue_status;
 pub use subscriber_name::SubscriberName;
 pub use new_subscriber::NewSubscriber;
 pub use subscriber_email::SubscriberEmail;
-
+pub use subscriber_tag::SubscriberTag;
+pub use segment::{Segment, SubscriptionFilter};
+pub use issue_status::IssueStatus;
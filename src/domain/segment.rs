@@ -0,0 +1,270 @@
+use crate::domain::SubscriberTag;
+use crate::schema::subscriptions;
+use chrono::NaiveDate;
+use diesel::dsl::not;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::sql_types::Bool;
+
+// A subscriber segment, e.g. `tag:beta AND subscribed_after:2024-01-01 AND NOT tag:churned`.
+//
+// Grammar (keywords are case-insensitive, `AND` binds tighter than `OR`):
+//   expr      := and_expr ("OR" and_expr)*
+//   and_expr  := unary ("AND" unary)*
+//   unary     := "NOT" unary | "(" expr ")" | predicate
+//   predicate := "tag:" TAG | "subscribed_after:" DATE | "subscribed_before:" DATE
+#[derive(Debug, Clone, PartialEq)]
+pub enum Segment {
+    Tag(SubscriberTag),
+    // Subscribed on or after the given day (UTC).
+    SubscribedAfter(NaiveDate),
+    // Subscribed strictly before the given day (UTC).
+    SubscribedBefore(NaiveDate),
+    Not(Box<Segment>),
+    And(Box<Segment>, Box<Segment>),
+    Or(Box<Segment>, Box<Segment>),
+}
+
+// How deeply `NOT` and parentheses can nest, and how many tokens a segment can have. Parsing,
+// compiling and dropping a segment all recurse through it, so these keep the stack bounded.
+const MAX_NESTING: usize = 32;
+const MAX_TOKENS: usize = 512;
+
+pub type SubscriptionFilter = Box<dyn BoxableExpression<subscriptions::table, Pg, SqlType = Bool>>;
+
+impl Segment {
+    pub fn parse(s: &str) -> Result<Segment, String> {
+        let tokens = tokenize(s);
+        if tokens.is_empty() {
+            return Err("The segment is empty.".to_string());
+        }
+        if tokens.len() > MAX_TOKENS {
+            return Err(format!(
+                "The segment is too long, it can have at most {} terms.",
+                MAX_TOKENS
+            ));
+        }
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            nesting: 0,
+        };
+        let segment = parser.expr()?;
+        match parser.peek() {
+            None => Ok(segment),
+            Some(token) => Err(format!("Unexpected `{}` in segment.", token)),
+        }
+    }
+
+    // Compiles the segment to a filter over the `subscriptions` table.
+    pub fn to_filter(&self) -> SubscriptionFilter {
+        match self {
+            Segment::Tag(tag) => {
+                Box::new(subscriptions::tags.contains(vec![tag.as_ref().to_string()]))
+            }
+            Segment::SubscribedAfter(day) => Box::new(
+                subscriptions::subscribed_at.ge(day.and_hms_opt(0, 0, 0).unwrap().and_utc()),
+            ),
+            Segment::SubscribedBefore(day) => Box::new(
+                subscriptions::subscribed_at.lt(day.and_hms_opt(0, 0, 0).unwrap().and_utc()),
+            ),
+            Segment::Not(inner) => Box::new(not(inner.to_filter())),
+            Segment::And(lhs, rhs) => Box::new(lhs.to_filter().and(rhs.to_filter())),
+            Segment::Or(lhs, rhs) => Box::new(lhs.to_filter().or(rhs.to_filter())),
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    for c in s.chars() {
+        if c.is_whitespace() || c == '(' || c == ')' {
+            if !current.is_empty() {
+                tokens.push(std::mem::take(&mut current));
+            }
+            if !c.is_whitespace() {
+                tokens.push(c.to_string());
+            }
+        } else {
+            current.push(c);
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+    tokens
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    position: usize,
+    // How many `NOT`s and `(`s enclose the current position.
+    nesting: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|t| t.as_str())
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn next_is_keyword(&self, keyword: &str) -> bool {
+        self.peek()
+            .map(|t| t.eq_ignore_ascii_case(keyword))
+            .unwrap_or(false)
+    }
+
+    fn expr(&mut self) -> Result<Segment, String> {
+        let mut lhs = self.and_expr()?;
+        while self.next_is_keyword("OR") {
+            self.next();
+            let rhs = self.and_expr()?;
+            lhs = Segment::Or(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn and_expr(&mut self) -> Result<Segment, String> {
+        let mut lhs = self.unary()?;
+        while self.next_is_keyword("AND") {
+            self.next();
+            let rhs = self.unary()?;
+            lhs = Segment::And(Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Segment, String> {
+        if self.next_is_keyword("NOT") {
+            self.next();
+            let inner = self.nested(Self::unary)?;
+            return Ok(Segment::Not(Box::new(inner)));
+        }
+        match self.next() {
+            None => Err("The segment ended unexpectedly.".to_string()),
+            Some(token) if token == "(" => {
+                let inner = self.nested(Self::expr)?;
+                match self.next() {
+                    Some(token) if token == ")" => Ok(inner),
+                    _ => Err("Missing closing `)` in segment.".to_string()),
+                }
+            }
+            Some(token) => predicate(&token),
+        }
+    }
+
+    fn nested(
+        &mut self,
+        parse: fn(&mut Self) -> Result<Segment, String>,
+    ) -> Result<Segment, String> {
+        if self.nesting == MAX_NESTING {
+            return Err(format!(
+                "The segment nests `NOT` and parentheses more than {} deep.",
+                MAX_NESTING
+            ));
+        }
+        self.nesting += 1;
+        let segment = parse(self);
+        self.nesting -= 1;
+        segment
+    }
+}
+
+fn predicate(token: &str) -> Result<Segment, String> {
+    let (field, value) = token
+        .split_once(':')
+        .ok_or_else(|| format!("`{}` is not a valid segment predicate.", token))?;
+    let parse_day = |value: &str| {
+        NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map_err(|_| format!("{} is not a valid date, expected YYYY-MM-DD.", value))
+    };
+    match field.to_lowercase().as_str() {
+        "tag" => Ok(Segment::Tag(SubscriberTag::parse(value.to_string())?)),
+        "subscribed_after" => Ok(Segment::SubscribedAfter(parse_day(value)?)),
+        "subscribed_before" => Ok(Segment::SubscribedBefore(parse_day(value)?)),
+        _ => Err(format!("`{}` is not a known segment field.", field)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::{Segment, SubscriberTag};
+    use chrono::NaiveDate;
+    use claim::assert_err;
+
+    fn tag(s: &str) -> Box<Segment> {
+        Box::new(Segment::Tag(SubscriberTag::parse(s.to_string()).unwrap()))
+    }
+
+    #[test]
+    fn a_single_tag_is_parsed() {
+        assert_eq!(Segment::parse("tag:beta").unwrap(), *tag("beta"));
+    }
+
+    #[test]
+    fn the_documented_example_is_parsed() {
+        let segment =
+            Segment::parse("tag:beta AND subscribed_after:2024-01-01 AND NOT tag:churned").unwrap();
+        let expected = Segment::And(
+            Box::new(Segment::And(
+                tag("beta"),
+                Box::new(Segment::SubscribedAfter(
+                    NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+                )),
+            )),
+            Box::new(Segment::Not(tag("churned"))),
+        );
+        assert_eq!(segment, expected);
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let segment = Segment::parse("tag:a OR tag:b and tag:c").unwrap();
+        let expected = Segment::Or(tag("a"), Box::new(Segment::And(tag("b"), tag("c"))));
+        assert_eq!(segment, expected);
+    }
+
+    #[test]
+    fn parentheses_override_precedence() {
+        let segment = Segment::parse("(tag:a OR tag:b) AND tag:c").unwrap();
+        let expected = Segment::And(Box::new(Segment::Or(tag("a"), tag("b"))), tag("c"));
+        assert_eq!(segment, expected);
+    }
+
+    #[test]
+    fn invalid_segments_are_rejected() {
+        for segment in &[
+            "",
+            "   ",
+            "tag:",
+            "beta",
+            "unknown:beta",
+            "tag:beta AND",
+            "tag:beta OR OR tag:alpha",
+            "(tag:beta",
+            "tag:beta)",
+            "subscribed_after:yesterday",
+            "subscribed_before:2024-13-01",
+            "NOT",
+        ] {
+            assert_err!(Segment::parse(segment), "{} should be rejected", segment);
+        }
+    }
+
+    #[test]
+    fn deeply_nested_segments_are_rejected_instead_of_overflowing_the_stack() {
+        let nested =
+            |depth: usize| format!("{}tag:beta{}", "NOT (".repeat(depth), ")".repeat(depth));
+        assert!(Segment::parse(&nested(16)).is_ok());
+        assert_err!(Segment::parse(&nested(17)));
+        assert_err!(Segment::parse(&"NOT ".repeat(100_000)));
+        assert_err!(Segment::parse(&"(".repeat(100_000)));
+        assert_err!(Segment::parse(&vec!["tag:beta"; 1_000].join(" OR ")));
+    }
+}
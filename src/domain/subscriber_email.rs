@@ -1,5 +1,5 @@
 use validator::validate_email;
-#[derive(Debug, serde::Deserialize)]
+#[derive(Debug, Clone, serde::Deserialize)]
 pub struct SubscriberEmail(String);
 
 impl SubscriberEmail {
@@ -0,0 +1,85 @@
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SubscriberTag(String);
+
+impl SubscriberTag {
+    // Tags are free-form, but they must be usable as a bare word in a segment query
+    // (e.g. `tag:beta`), so we only allow a conservative set of characters.
+    pub fn parse(s: String) -> Result<SubscriberTag, String> {
+        let s = s.trim().to_lowercase();
+        let is_empty = s.is_empty();
+        let is_too_long = s.chars().count() > 64;
+        let contains_forbidden_characters = s
+            .chars()
+            .any(|c| !(c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.'));
+
+        if is_empty || is_too_long || contains_forbidden_characters {
+            Err(format!("{} is not a valid subscriber tag.", s))
+        } else {
+            Ok(Self(s))
+        }
+    }
+
+    // Parses a comma or whitespace separated list of tags, dropping duplicates.
+    pub fn parse_list(s: &str) -> Result<Vec<SubscriberTag>, String> {
+        let mut tags: Vec<SubscriberTag> = Vec::new();
+        for raw in s.split(|c: char| c == ',' || c.is_whitespace()) {
+            if raw.is_empty() {
+                continue;
+            }
+            let tag = SubscriberTag::parse(raw.to_string())?;
+            if !tags.contains(&tag) {
+                tags.push(tag);
+            }
+        }
+        Ok(tags)
+    }
+}
+
+impl AsRef<str> for SubscriberTag {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for SubscriberTag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberTag;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn tags_are_lowercased() {
+        let tag = SubscriberTag::parse("Beta".to_string()).unwrap();
+        assert_eq!(tag.as_ref(), "beta");
+    }
+
+    #[test]
+    fn empty_tag_is_rejected() {
+        assert_err!(SubscriberTag::parse(" ".to_string()));
+    }
+
+    #[test]
+    fn tags_with_forbidden_characters_are_rejected() {
+        for tag in &["a:b", "a(b", "a)b", "a b", "<script>"] {
+            assert_err!(SubscriberTag::parse(tag.to_string()));
+        }
+    }
+
+    #[test]
+    fn a_65_character_long_tag_is_rejected() {
+        assert_ok!(SubscriberTag::parse("a".repeat(64)));
+        assert_err!(SubscriberTag::parse("a".repeat(65)));
+    }
+
+    #[test]
+    fn a_tag_list_is_split_and_deduplicated() {
+        let tags = SubscriberTag::parse_list("beta, early-adopter beta,,").unwrap();
+        let tags: Vec<&str> = tags.iter().map(|t| t.as_ref()).collect();
+        assert_eq!(tags, vec!["beta", "early-adopter"]);
+    }
+}
@@ -1,12 +1,130 @@
 use crate::domain::SubscriberEmail;
-use reqwest::Client;
+use crate::metrics::METRICS;
+use crate::telemetry::trace_context_headers;
+use chrono::{DateTime, Utc};
+use reqwest::{Client, StatusCode};
 use secrecy::{ExposeSecret, Secret};
 use serde;
+use std::time::Duration;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use tokio::time::Instant;
 pub struct EmailClient {
     http_client: Client,
     base_url: String,
     sender: SubscriberEmail,
     authorization_token: Secret<String>,
+    limiter: Limiter,
+}
+
+// How fast emails are handed to the provider. A batch request counts once per message.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SendLimits {
+    pub messages_per_second: f64,
+    pub max_in_flight: usize,
+}
+
+impl Default for SendLimits {
+    fn default() -> Self {
+        Self {
+            messages_per_second: 50.0,
+            max_in_flight: 10,
+        }
+    }
+}
+
+// A token bucket holding up to one second worth of messages, plus a cap on requests in flight.
+// The bucket can go into debt: a batch larger than the bucket waits until the debt is paid off.
+struct Limiter {
+    limits: SendLimits,
+    bucket: std::sync::Mutex<Bucket>,
+    in_flight: Semaphore,
+}
+
+struct Bucket {
+    tokens: f64,
+    refilled_at: Instant,
+    // Set when the provider asked us to back off.
+    paused_until: Instant,
+}
+
+impl Limiter {
+    fn new(limits: SendLimits) -> Self {
+        let now = Instant::now();
+        Self {
+            limits,
+            bucket: std::sync::Mutex::new(Bucket {
+                tokens: limits.messages_per_second.max(1.0),
+                refilled_at: now,
+                paused_until: now,
+            }),
+            in_flight: Semaphore::new(limits.max_in_flight),
+        }
+    }
+
+    // Takes `messages` tokens and returns when they may be sent, with the tokens left over.
+    fn reserve(&self, messages: usize) -> (Instant, f64) {
+        let mut bucket = self.bucket.lock().unwrap();
+        let now = Instant::now();
+        let rate = self.limits.messages_per_second;
+        let refill = now.duration_since(bucket.refilled_at).as_secs_f64() * rate;
+        bucket.tokens = (bucket.tokens + refill).min(rate.max(1.0)) - messages as f64;
+        bucket.refilled_at = now;
+        let ready_at = if bucket.tokens < 0.0 {
+            now + Duration::from_secs_f64(-bucket.tokens / rate)
+        } else {
+            now
+        };
+        (ready_at.max(bucket.paused_until), bucket.tokens)
+    }
+
+    async fn acquire(&self, messages: usize) -> SemaphorePermit<'_> {
+        let started = Instant::now();
+        let (ready_at, tokens) = self.reserve(messages);
+        tokio::time::sleep_until(ready_at).await;
+        let permit = self
+            .in_flight
+            .acquire()
+            .await
+            .expect("The in-flight semaphore is never closed");
+        let span = tracing::Span::current();
+        span.record("rate_limit.tokens", tokens);
+        span.record(
+            "rate_limit.in_flight",
+            self.limits.max_in_flight - self.in_flight.available_permits(),
+        );
+        span.record("rate_limit.waited_ms", started.elapsed().as_millis() as u64);
+        permit
+    }
+
+    // Holds every send back until `until`.
+    fn pause_until(&self, until: Instant) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.paused_until = bucket.paused_until.max(until);
+    }
+}
+
+// How often a request is sent again after the provider answered 429 Too Many Requests.
+const RATE_LIMITED_RETRIES: usize = 3;
+// A longer `Retry-After` is not waited for; the request fails instead.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+// `Retry-After` holds either a number of seconds or an HTTP date.
+fn retry_after(response: &reqwest::Response) -> Option<Duration> {
+    let value = response
+        .headers()
+        .get(reqwest::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
 }
 
 #[derive(serde::Serialize, Debug)]
@@ -17,6 +135,141 @@ struct SendEmailRequest<'a> {
     subject: &'a str,
     html_body: &'a str,
     text_body: &'a str,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    headers: Vec<Header<'a>>,
+}
+
+#[derive(serde::Serialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Header<'a> {
+    name: &'static str,
+    value: std::borrow::Cow<'a, str>,
+}
+
+// Lets mail clients offer their own unsubscribe button, which unsubscribes with a POST to the
+// link (RFC 8058).
+fn unsubscribe_headers(unsubscribe_url: Option<&str>) -> Vec<Header<'_>> {
+    let Some(url) = unsubscribe_url else {
+        return vec![];
+    };
+    vec![
+        Header {
+            name: "List-Unsubscribe",
+            value: format!("<{}>", url).into(),
+        },
+        Header {
+            name: "List-Unsubscribe-Post",
+            value: "List-Unsubscribe=One-Click".into(),
+        },
+    ]
+}
+
+// The provider label of the email metrics.
+const PROVIDER: &str = "postmark";
+
+// Postmark takes at most this many messages per batch request.
+pub const MAX_BATCH_SIZE: usize = 500;
+
+// One message of a batch.
+pub struct Email<'a> {
+    pub recipient: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    // Set for newsletter issues.
+    pub unsubscribe_url: Option<&'a str>,
+}
+
+// What the provider answered for an email it accepted.
+#[derive(serde::Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "PascalCase")]
+pub struct SendReceipt {
+    #[serde(rename = "MessageID")]
+    pub message_id: Option<String>,
+    pub submitted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct ErrorResponse {
+    error_code: i64,
+    message: String,
+}
+
+// The result of one message of a batch, in the same order as the messages.
+#[derive(serde::Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct BatchResult {
+    error_code: i64,
+    #[serde(default)]
+    message: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+    submitted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SendEmailError {
+    #[error("The email provider rejected the email with status {status}: {message}")]
+    Rejected {
+        status: StatusCode,
+        // The provider's own error code, e.g. 406 for an inactive recipient on Postmark.
+        error_code: Option<i64>,
+        message: String,
+    },
+    // A single message of an accepted batch was refused.
+    #[error("The email provider rejected the email: {message}")]
+    MessageRejected { error_code: i64, message: String },
+    #[error("A batch can hold at most {MAX_BATCH_SIZE} emails, got {0}.")]
+    BatchTooLarge(usize),
+    #[error("Failed to send the email request.")]
+    RequestFailed(#[from] reqwest::Error),
+}
+
+impl SendEmailError {
+    // A coarse grouping of failures, for metrics.
+    pub fn error_class(&self) -> &'static str {
+        match self {
+            SendEmailError::Rejected { status, .. } if *status == StatusCode::TOO_MANY_REQUESTS => {
+                "rate_limited"
+            }
+            SendEmailError::Rejected { status, .. } if status.is_server_error() => "provider_error",
+            SendEmailError::Rejected { .. } | SendEmailError::MessageRejected { .. } => "rejected",
+            SendEmailError::BatchTooLarge(_) => "invalid_request",
+            SendEmailError::RequestFailed(e) if e.is_timeout() => "timeout",
+            SendEmailError::RequestFailed(_) => "network",
+        }
+    }
+
+    // Whether sending the email again right away is safe and may work: the provider was overloaded
+    // or could not be connected to. Anything else it rejected, like an inactive recipient, is
+    // rejected every time. A request that failed after it was sent, e.g. by timing out, may have
+    // been accepted, so sending it again could deliver the emails twice.
+    pub fn is_transient(&self) -> bool {
+        match self {
+            SendEmailError::Rejected { status, .. } => {
+                *status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+            }
+            SendEmailError::MessageRejected { error_code, .. } => *error_code == 429,
+            SendEmailError::BatchTooLarge(_) => false,
+            SendEmailError::RequestFailed(e) => e.is_connect(),
+        }
+    }
+
+    // A short code to store with a failed delivery.
+    pub fn error_code(&self) -> String {
+        match self {
+            SendEmailError::Rejected {
+                error_code: Some(code),
+                ..
+            } => code.to_string(),
+            SendEmailError::Rejected { status, .. } => format!("http_{}", status.as_u16()),
+            SendEmailError::MessageRejected { error_code, .. } => error_code.to_string(),
+            SendEmailError::BatchTooLarge(_) => "batch_too_large".to_string(),
+            SendEmailError::RequestFailed(e) if e.is_timeout() => "timeout".to_string(),
+            SendEmailError::RequestFailed(_) => "request_failed".to_string(),
+        }
+    }
 }
 
 impl EmailClient {
@@ -26,7 +279,7 @@ impl EmailClient {
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+    ) -> Result<SendReceipt, SendEmailError> {
         let url = format!("{}/email", self.base_url);
         let request_body = SendEmailRequest {
             from: self.sender.as_ref(),
@@ -34,24 +287,177 @@ impl EmailClient {
             subject: subject,
             html_body: html_content,
             text_body: text_content,
+            headers: vec![],
         };
 
-        println!("Sending request to URL: {}", url);
-        println!("Request body: {:?}", request_body);
-        let builder = self
+        let body = self.post(&url, &request_body, 1).await?;
+        METRICS.record_emails(PROVIDER, Ok(()), 1);
+        // The email has been accepted at this point, so a body we can't make sense of is
+        // no reason to report a failure and send it again.
+        let receipt = serde_json::from_slice(&body).unwrap_or_else(|e| {
+            tracing::warn!(error.message = %e, "The email provider's response could not be parsed");
+            SendReceipt::default()
+        });
+        Ok(receipt)
+    }
+
+    // Sends up to `MAX_BATCH_SIZE` emails in one request. The outer error means that none of
+    // them were sent; otherwise there is one result per email, in the same order.
+    pub async fn send_email_batch(
+        &self,
+        emails: &[Email<'_>],
+    ) -> Result<Vec<Result<SendReceipt, SendEmailError>>, SendEmailError> {
+        if emails.len() > MAX_BATCH_SIZE {
+            let error = SendEmailError::BatchTooLarge(emails.len());
+            METRICS.record_emails(PROVIDER, Err(&error), emails.len() as u64);
+            return Err(error);
+        }
+        let url = format!("{}/email/batch", self.base_url);
+        let request_body: Vec<SendEmailRequest> = emails
+            .iter()
+            .map(|email| SendEmailRequest {
+                from: self.sender.as_ref(),
+                to: email.recipient.as_ref(),
+                subject: email.subject,
+                html_body: email.html_content,
+                text_body: email.text_content,
+                headers: unsubscribe_headers(email.unsubscribe_url),
+            })
+            .collect();
+
+        let body = self.post(&url, &request_body, emails.len()).await?;
+        let results = match serde_json::from_slice::<Vec<BatchResult>>(&body) {
+            Ok(results) if results.len() == emails.len() => results,
+            // As with single emails, the batch has been accepted, so it is not sent again.
+            parsed => {
+                tracing::warn!(
+                    results = ?parsed.map(|r| r.len()).ok(),
+                    emails = emails.len(),
+                    "The email provider's batch response could not be parsed"
+                );
+                METRICS.record_emails(PROVIDER, Ok(()), emails.len() as u64);
+                return Ok(emails.iter().map(|_| Ok(SendReceipt::default())).collect());
+            }
+        };
+        Ok(results
+            .into_iter()
+            .map(|result| {
+                let result = if result.error_code == 0 {
+                    Ok(SendReceipt {
+                        message_id: result.message_id,
+                        submitted_at: result.submitted_at,
+                    })
+                } else {
+                    Err(SendEmailError::MessageRejected {
+                        error_code: result.error_code,
+                        message: result.message,
+                    })
+                };
+                METRICS.record_emails(PROVIDER, result.as_ref().map(|_| ()), 1);
+                result
+            })
+            .collect())
+    }
+
+    // Failed requests are counted here, once per message; sent messages are counted by
+    // the callers, which know what happened to each of them.
+    async fn post(
+        &self,
+        url: &str,
+        request_body: &impl serde::Serialize,
+        messages: usize,
+    ) -> Result<Vec<u8>, SendEmailError> {
+        let outcome = self.send_request(url, request_body, messages).await;
+        if let Err(e) = &outcome {
+            METRICS.record_emails(PROVIDER, Err(e), messages as u64);
+        }
+        outcome
+    }
+
+    #[tracing::instrument(
+        name = "Sending an email request",
+        skip(self, request_body),
+        fields(
+            rate_limit.tokens = tracing::field::Empty,
+            rate_limit.in_flight = tracing::field::Empty,
+            rate_limit.waited_ms = tracing::field::Empty,
+            rate_limit.retry_after_s = tracing::field::Empty,
+            http.status_code = tracing::field::Empty,
+        )
+    )]
+    async fn send_request(
+        &self,
+        url: &str,
+        request_body: &impl serde::Serialize,
+        messages: usize,
+    ) -> Result<Vec<u8>, SendEmailError> {
+        let mut retries = 0;
+        loop {
+            let permit = self.limiter.acquire(messages).await;
+            let response = self
+                .http_client
+                .post(url)
+                .header(
+                    "X-Postmark-Server-Token",
+                    self.authorization_token.expose_secret(),
+                )
+                .headers(trace_context_headers())
+                .json(request_body)
+                .send()
+                .await?;
+            drop(permit);
+            let status = response.status();
+
+            if status == StatusCode::TOO_MANY_REQUESTS && retries < RATE_LIMITED_RETRIES {
+                let delay = retry_after(&response).unwrap_or(Duration::from_secs(1));
+                if delay <= MAX_RETRY_AFTER {
+                    tracing::Span::current().record("rate_limit.retry_after_s", delay.as_secs());
+                    tracing::warn!(
+                        retry_after_s = delay.as_secs_f64(),
+                        "The email provider is rate limiting us, backing off"
+                    );
+                    self.limiter.pause_until(Instant::now() + delay);
+                    retries += 1;
+                    continue;
+                }
+            }
+
+            let body = response.bytes().await?.to_vec();
+            tracing::Span::current().record("http.status_code", status.as_u16());
+
+            if !status.is_success() {
+                let error = serde_json::from_slice::<ErrorResponse>(&body).ok();
+                return Err(SendEmailError::Rejected {
+                    status,
+                    error_code: error.as_ref().map(|e| e.error_code),
+                    message: error
+                        .map(|e| e.message)
+                        .unwrap_or_else(|| String::from_utf8_lossy(&body).into_owned()),
+                });
+            }
+            return Ok(body);
+        }
+    }
+    // Any answer but a server error counts: this tells whether the provider can be reached,
+    // not whether it accepts our token.
+    pub async fn check_reachable(&self) -> Result<(), SendEmailError> {
+        let response = self
             .http_client
-            .post(&url)
+            .get(format!("{}/server", self.base_url))
             .header(
                 "X-Postmark-Server-Token",
                 self.authorization_token.expose_secret(),
             )
-            .json(&request_body)
             .send()
-            .await?
-            .error_for_status()?;
-
-        println!("Received response status: {}", builder.status());
-
+            .await?;
+        let status = response.status();
+        if status.is_server_error() {
+            return Err(SendEmailError::Rejected {
+                status,
+                error_code: None,
+                message: response.text().await.unwrap_or_default(),
+            });
+        }
         Ok(())
     }
     pub fn new(
@@ -66,28 +472,25 @@ impl EmailClient {
             base_url,
             sender,
             authorization_token,
+            limiter: Limiter::new(SendLimits::default()),
         }
     }
-    pub fn timeout() -> std::time::Duration {
-        use dotenv::dotenv;
-        use std::env;
-        dotenv().ok();
-        let timeout_str = env::var("TIMEOUT_MILLISECOND").expect("Can't load timeout value");
-        let timeout_millis: u64 = timeout_str.parse().expect("Can't parse timeout value");
-        std::time::Duration::from_millis(timeout_millis)
+    pub fn with_limits(mut self, limits: SendLimits) -> Self {
+        self.limiter = Limiter::new(limits);
+        self
     }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::domain::SubscriberEmail;
-    use crate::email_client::EmailClient;
+    use crate::email_client::{Email, EmailClient, SendEmailError, SendLimits, MAX_BATCH_SIZE};
     use claim::{assert_err, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
     use fake::{Fake, Faker};
     use secrecy::Secret;
-    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::matchers::{any, body_partial_json, header, header_exists, method, path};
     use wiremock::Request;
     use wiremock::{Mock, MockServer, ResponseTemplate};
     struct SendEmailBodyMatcher;
@@ -172,7 +575,7 @@ mod tests {
             .await;
 
         // Assert
-        assert_ok!(outcome)
+        assert_ok!(outcome);
     }
 
     #[tokio::test]
@@ -194,6 +597,61 @@ mod tests {
         assert_err!(outcome);
     }
 
+    #[tokio::test]
+    async fn send_email_returns_the_message_id_from_the_provider() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "To": "receiver@example.com",
+                "SubmittedAt": "2026-10-18T07:25:01.4178645-05:00",
+                "MessageID": "0a129aee-e1cd-480d-b08d-4f48548ff48d",
+                "ErrorCode": 0,
+                "Message": "OK"
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let receipt = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            receipt.message_id.as_deref(),
+            Some("0a129aee-e1cd-480d-b08d-4f48548ff48d")
+        );
+        assert_eq!(
+            receipt.submitted_at.unwrap().to_rfc3339(),
+            "2026-10-18T12:25:01.417864500+00:00"
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_reports_the_error_code_of_the_provider() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "ErrorCode": 406,
+                "Message": "You tried to send to a recipient that has been marked as inactive."
+            })))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let error = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, SendEmailError::Rejected { .. }));
+        assert_eq!(error.error_code(), "406");
+    }
+
     #[tokio::test]
     async fn send_email_times_out_if_the_server_takes_too_long() {
         let mock_server = MockServer::start().await;
@@ -212,6 +670,270 @@ mod tests {
             .send_email(&email(), &subject(), &content(), &content())
             .await;
 
-        assert_err!(outcome);
+        let error = outcome.unwrap_err();
+        // The provider may have accepted the email before we gave up on it.
+        assert!(!error.is_transient());
+    }
+
+    #[tokio::test]
+    async fn failing_to_connect_is_transient() {
+        // Nothing listens on port 1.
+        let email_client = email_client("http://127.0.0.1:1".into());
+
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert!(outcome.unwrap_err().is_transient());
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_sends_every_email_in_one_request() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let (first, second) = (email(), email());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .and(body_partial_json(serde_json::json!([
+                {
+                    "To": first.as_ref(),
+                    "Headers": [
+                        { "Name": "List-Unsubscribe", "Value": "<https://example.com/unsubscribe>" },
+                        { "Name": "List-Unsubscribe-Post", "Value": "List-Unsubscribe=One-Click" },
+                    ],
+                },
+                { "To": second.as_ref() },
+            ])))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email_batch(&[
+                Email {
+                    recipient: &first,
+                    subject: "Subject",
+                    html_content: "<p>Hi</p>",
+                    text_content: "Hi",
+                    unsubscribe_url: Some("https://example.com/unsubscribe"),
+                },
+                Email {
+                    recipient: &second,
+                    subject: "Subject",
+                    html_content: "<p>Hi</p>",
+                    text_content: "Hi",
+                    unsubscribe_url: None,
+                },
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(outcome.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_reports_the_result_of_each_email() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let (first, second) = (email(), email());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+                {
+                    "To": first.as_ref(),
+                    "SubmittedAt": "2026-10-18T07:25:01.4178645-05:00",
+                    "MessageID": "0a129aee-e1cd-480d-b08d-4f48548ff48d",
+                    "ErrorCode": 0,
+                    "Message": "OK"
+                },
+                {
+                    "ErrorCode": 406,
+                    "Message": "You tried to send to a recipient that has been marked as inactive."
+                }
+            ])))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email_batch(&[
+                Email {
+                    recipient: &first,
+                    subject: "Subject",
+                    html_content: "<p>Hi</p>",
+                    text_content: "Hi",
+                    unsubscribe_url: None,
+                },
+                Email {
+                    recipient: &second,
+                    subject: "Subject",
+                    html_content: "<p>Hi</p>",
+                    text_content: "Hi",
+                    unsubscribe_url: None,
+                },
+            ])
+            .await
+            .unwrap();
+
+        assert_eq!(
+            outcome[0].as_ref().unwrap().message_id.as_deref(),
+            Some("0a129aee-e1cd-480d-b08d-4f48548ff48d")
+        );
+        let error = outcome[1].as_ref().unwrap_err();
+        assert!(matches!(error, SendEmailError::MessageRejected { .. }));
+        assert_eq!(error.error_code(), "406");
+        assert!(!error.is_transient());
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_fails_if_the_server_returns_500() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipient = email();
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let outcome = email_client
+            .send_email_batch(&[Email {
+                recipient: &recipient,
+                subject: "Subject",
+                html_content: "<p>Hi</p>",
+                text_content: "Hi",
+                unsubscribe_url: None,
+            }])
+            .await;
+
+        assert!(outcome.unwrap_err().is_transient());
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_rejects_batches_that_are_too_large() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipient = email();
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        let emails: Vec<Email> = (0..=MAX_BATCH_SIZE)
+            .map(|_| Email {
+                recipient: &recipient,
+                subject: "Subject",
+                html_content: "<p>Hi</p>",
+                text_content: "Hi",
+                unsubscribe_url: None,
+            })
+            .collect();
+        let error = email_client.send_email_batch(&emails).await.unwrap_err();
+
+        assert!(matches!(error, SendEmailError::BatchTooLarge(501)));
+    }
+
+    #[tokio::test]
+    async fn send_email_waits_for_the_rate_limit() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri()).with_limits(SendLimits {
+            messages_per_second: 4.0,
+            max_in_flight: 10,
+        });
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(6)
+            .mount(&mock_server)
+            .await;
+
+        let started = std::time::Instant::now();
+        for _ in 0..6 {
+            assert_ok!(
+                email_client
+                    .send_email(&email(), &subject(), &content(), &content())
+                    .await
+            );
+        }
+
+        // The first four go out at once, the other two wait a quarter of a second each.
+        assert!(started.elapsed() >= std::time::Duration::from_millis(450));
+    }
+
+    #[tokio::test]
+    async fn send_email_caps_the_requests_in_flight() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri()).with_limits(SendLimits {
+            messages_per_second: 100.0,
+            max_in_flight: 1,
+        });
+        Mock::given(any())
+            .respond_with(
+                ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(100)),
+            )
+            .expect(3)
+            .mount(&mock_server)
+            .await;
+
+        let started = std::time::Instant::now();
+        let (a, b, c) = (email(), email(), email());
+        let outcomes = tokio::join!(
+            email_client.send_email(&a, "Subject", "<p>Hi</p>", "Hi"),
+            email_client.send_email(&b, "Subject", "<p>Hi</p>", "Hi"),
+            email_client.send_email(&c, "Subject", "<p>Hi</p>", "Hi"),
+        );
+
+        assert_ok!(outcomes.0);
+        assert_ok!(outcomes.1);
+        assert_ok!(outcomes.2);
+        assert!(started.elapsed() >= std::time::Duration::from_millis(300));
+    }
+
+    #[tokio::test]
+    async fn send_email_honours_retry_after_on_429() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let started = std::time::Instant::now();
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await;
+
+        assert_ok!(outcome);
+        assert!(started.elapsed() >= std::time::Duration::from_secs(1));
+    }
+
+    #[tokio::test]
+    async fn send_email_gives_up_when_rate_limited_for_too_long() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3600"))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        let error = email_client
+            .send_email(&email(), &subject(), &content(), &content())
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.error_code(), "http_429");
     }
 }
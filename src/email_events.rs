@@ -0,0 +1,174 @@
+use crate::schema::{email_events, subscriptions};
+use chrono::{DateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::Deserialize;
+
+// Postmark bounce types after which the address won't accept mail anymore. Others, such as
+// `SoftBounce` or `Transient`, are recorded but the subscriber is kept.
+const PERMANENT_BOUNCE_TYPES: &[&str] = &["HardBounce", "BadEmailAddress", "ManuallyDeactivated"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EmailEventKind {
+    Bounce,
+    SpamComplaint,
+}
+
+impl EmailEventKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmailEventKind::Bounce => "bounce",
+            EmailEventKind::SpamComplaint => "spam_complaint",
+        }
+    }
+
+    // The status given to the subscriber when the event is permanent.
+    pub fn subscriber_status(&self) -> &'static str {
+        match self {
+            EmailEventKind::Bounce => "bounced",
+            EmailEventKind::SpamComplaint => "complained",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct EmailEvent {
+    pub provider_event_id: String,
+    pub kind: EmailEventKind,
+    pub event_type: String,
+    pub permanent: bool,
+    pub email: String,
+    pub message_id: Option<String>,
+    pub description: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkRecord {
+    record_type: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PostmarkPayload {
+    #[serde(rename = "ID")]
+    id: serde_json::Value,
+    #[serde(rename = "Type", default)]
+    event_type: String,
+    email: String,
+    #[serde(rename = "MessageID")]
+    message_id: Option<String>,
+    #[serde(default)]
+    description: String,
+}
+
+// Parses a Postmark webhook payload. Record types other than bounces and spam complaints,
+// e.g. deliveries or opens, are returned as `None`.
+pub fn parse_postmark_event(payload: &[u8]) -> Result<Option<EmailEvent>, serde_json::Error> {
+    let record: PostmarkRecord = serde_json::from_slice(payload)?;
+    let kind = match record.record_type.as_str() {
+        "Bounce" => EmailEventKind::Bounce,
+        "SpamComplaint" => EmailEventKind::SpamComplaint,
+        _ => return Ok(None),
+    };
+    let payload: PostmarkPayload = serde_json::from_slice(payload)?;
+    let permanent = match kind {
+        EmailEventKind::Bounce => PERMANENT_BOUNCE_TYPES.contains(&payload.event_type.as_str()),
+        EmailEventKind::SpamComplaint => true,
+    };
+    // Postmark sends numeric ids, but a string is just as good to tell events apart.
+    let provider_event_id = match payload.id {
+        serde_json::Value::String(id) => id,
+        id => id.to_string(),
+    };
+    Ok(Some(EmailEvent {
+        provider_event_id,
+        kind,
+        event_type: payload.event_type,
+        permanent,
+        email: payload.email,
+        message_id: payload.message_id,
+        description: payload.description,
+    }))
+}
+
+// Stores the event and, if it is permanent, moves the subscriber with that address out of the
+// confirmed ones so no newsletter is sent to it anymore. A complaint is never downgraded to a
+// bounce. Returns `false` if the provider had already reported this event.
+pub fn record_email_event(
+    conn: &mut PgConnection,
+    provider: &str,
+    event: &EmailEvent,
+    payload: &str,
+    now: DateTime<Utc>,
+) -> Result<bool, diesel::result::Error> {
+    conn.transaction(|conn| {
+        let subscriber_id = subscriptions::table
+            .filter(subscriptions::email.eq(&event.email))
+            .select(subscriptions::id)
+            .first::<uuid::Uuid>(conn)
+            .optional()?;
+        let inserted = diesel::insert_into(email_events::table)
+            .values((
+                email_events::provider.eq(provider),
+                email_events::provider_event_id.eq(&event.provider_event_id),
+                email_events::kind.eq(event.kind.as_str()),
+                email_events::event_type.eq(&event.event_type),
+                email_events::permanent.eq(event.permanent),
+                email_events::email.eq(&event.email),
+                email_events::subscriber_id.eq(subscriber_id),
+                email_events::message_id.eq(&event.message_id),
+                email_events::description.eq(&event.description),
+                email_events::payload.eq(payload),
+                email_events::received_at.eq(now),
+            ))
+            .on_conflict_do_nothing()
+            .execute(conn)?;
+        if inserted == 0 {
+            return Ok(false);
+        }
+        if let (true, Some(subscriber_id)) = (event.permanent, subscriber_id) {
+            let status = event.kind.subscriber_status();
+            diesel::update(subscriptions::table.find(subscriber_id))
+                .filter(
+                    subscriptions::status
+                        .ne(EmailEventKind::SpamComplaint.subscriber_status())
+                        .or(subscriptions::status.is_null()),
+                )
+                .set(subscriptions::status.eq(status))
+                .execute(conn)?;
+        }
+        Ok(true)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_postmark_event, EmailEventKind};
+
+    #[test]
+    fn hard_bounces_are_permanent() {
+        let payload = br#"{"RecordType": "Bounce", "ID": 42, "Type": "HardBounce",
+            "Email": "john@example.com", "MessageID": "abc", "Description": "Unknown user"}"#;
+        let event = parse_postmark_event(payload).unwrap().unwrap();
+        assert_eq!(event.kind, EmailEventKind::Bounce);
+        assert_eq!(event.provider_event_id, "42");
+        assert_eq!(event.message_id.as_deref(), Some("abc"));
+        assert!(event.permanent);
+    }
+
+    #[test]
+    fn soft_bounces_are_not_permanent() {
+        let payload = br#"{"RecordType": "Bounce", "ID": 43, "Type": "SoftBounce",
+            "Email": "john@example.com"}"#;
+        let event = parse_postmark_event(payload).unwrap().unwrap();
+        assert!(!event.permanent);
+    }
+
+    #[test]
+    fn other_record_types_are_ignored() {
+        let payload = br#"{"RecordType": "Delivery", "Recipient": "john@example.com"}"#;
+        assert_eq!(parse_postmark_event(payload).unwrap(), None);
+        assert!(parse_postmark_event(b"not json").is_err());
+    }
+}
@@ -0,0 +1,291 @@
+use crate::{
+    db_models::{EmailLayout, TransactionalTemplate},
+    markdown::{render_markdown, RenderedContent},
+    schema::{email_layouts, transactional_templates},
+    templating::Template,
+};
+use anyhow::Context;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use std::fmt::Write;
+
+pub const DEFAULT_LAYOUT: &str = "default";
+
+const LAYOUT_VARIABLES: &[&str] = &["subject"];
+const UNSUBSCRIBE_BLOCK_VARIABLES: &[&str] = &["unsubscribe_url"];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransactionalEmail {
+    Confirmation,
+}
+
+impl TransactionalEmail {
+    pub const ALL: [TransactionalEmail; 1] = [TransactionalEmail::Confirmation];
+
+    pub fn parse(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|email| email.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransactionalEmail::Confirmation => "confirmation",
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            TransactionalEmail::Confirmation => "Subscription confirmation",
+        }
+    }
+
+    pub fn variables(&self) -> &'static [&'static str] {
+        match self {
+            TransactionalEmail::Confirmation => &["subscriber.name", "confirmation_link"],
+        }
+    }
+
+    // The values used when an admin previews the template.
+    pub fn sample_values(&self, application_base_url: &str) -> Vec<(&'static str, String)> {
+        match self {
+            TransactionalEmail::Confirmation => vec![
+                ("subscriber.name", "Ursula Le Guin".to_string()),
+                (
+                    "confirmation_link",
+                    format!(
+                        "{}/subscriptions/confirm?subscription_token=sample",
+                        application_base_url
+                    ),
+                ),
+            ],
+        }
+    }
+}
+
+// A Markdown template, rendered to HTML and plain text up front so that only the merge
+// fields are left to fill in for each email.
+pub struct MarkdownTemplate {
+    html: Template,
+    text: Template,
+}
+
+impl MarkdownTemplate {
+    pub fn parse(markdown: &str, variables: &[&str]) -> Result<Self, String> {
+        let rendered = render_markdown(markdown);
+        Ok(Self {
+            html: Template::parse(&rendered.html, variables)?,
+            text: Template::parse(&rendered.text, variables)?,
+        })
+    }
+
+    pub fn render(&self, values: &[(&str, &str)]) -> RenderedContent {
+        RenderedContent {
+            html: self.html.render_html(values),
+            text: self.text.render_text(values),
+        }
+    }
+}
+
+pub struct Layout {
+    header: Option<MarkdownTemplate>,
+    footer: Option<MarkdownTemplate>,
+    unsubscribe_block: Option<MarkdownTemplate>,
+    physical_address: String,
+}
+
+fn parse_part(
+    markdown: &str,
+    variables: &[&str],
+    part: &str,
+) -> Result<Option<MarkdownTemplate>, String> {
+    if markdown.trim().is_empty() {
+        return Ok(None);
+    }
+    MarkdownTemplate::parse(markdown, variables)
+        .map(Some)
+        .map_err(|e| format!("The {} is not a valid template. {}", part, e))
+}
+
+impl Layout {
+    pub fn parse(layout: &EmailLayout) -> Result<Self, String> {
+        Ok(Self {
+            header: parse_part(&layout.header, LAYOUT_VARIABLES, "header")?,
+            footer: parse_part(&layout.footer, LAYOUT_VARIABLES, "footer")?,
+            unsubscribe_block: parse_part(
+                &layout.unsubscribe_block,
+                UNSUBSCRIBE_BLOCK_VARIABLES,
+                "unsubscribe block",
+            )?,
+            physical_address: layout.physical_address.trim().to_string(),
+        })
+    }
+
+    // Wraps `content` in the layout. The unsubscribe block is only added when there is an
+    // `unsubscribe_url`, i.e. for newsletters.
+    pub fn render(
+        &self,
+        subject: &str,
+        content: &RenderedContent,
+        unsubscribe_url: Option<&str>,
+    ) -> RenderedContent {
+        let values = [("subject", subject)];
+        let header = self.header.as_ref().map(|h| h.render(&values));
+        let footer = self.footer.as_ref().map(|f| f.render(&values));
+        let unsubscribe_block = self
+            .unsubscribe_block
+            .as_ref()
+            .zip(unsubscribe_url)
+            .map(|(block, url)| block.render(&[("unsubscribe_url", url)]));
+
+        let mut html = String::from(r#"<div style="max-width: 600px; margin: 0 auto;">"#);
+        let mut text = String::new();
+        if let Some(header) = &header {
+            html.push_str(&header.html);
+            write!(text, "{}\n\n", header.text).unwrap();
+        }
+        html.push_str(&content.html);
+        text.push_str(content.text.trim_end());
+
+        let footer_parts: Vec<&RenderedContent> =
+            footer.iter().chain(unsubscribe_block.iter()).collect();
+        if !footer_parts.is_empty() || !self.physical_address.is_empty() {
+            html.push_str(
+                r#"<hr style="margin: 24px 0; border: 0; border-top: 1px solid #dddddd;">"#,
+            );
+            text.push_str("\n\n----------");
+        }
+        for part in footer_parts {
+            html.push_str(&part.html);
+            write!(text, "\n\n{}", part.text).unwrap();
+        }
+        if !self.physical_address.is_empty() {
+            let address: Vec<String> = self
+                .physical_address
+                .lines()
+                .map(htmlescape::encode_minimal)
+                .collect();
+            write!(
+                html,
+                r#"<p style="margin: 16px 0 0; font-size: 12px; color: #777777;">{}</p>"#,
+                address.join("<br>")
+            )
+            .unwrap();
+            write!(text, "\n\n{}", self.physical_address).unwrap();
+        }
+        html.push_str("</div>");
+        RenderedContent { html, text }
+    }
+}
+
+pub fn get_layout(
+    conn: &mut PgConnection,
+    name: &str,
+) -> Result<Option<EmailLayout>, diesel::result::Error> {
+    email_layouts::table
+        .find(name)
+        .first::<EmailLayout>(conn)
+        .optional()
+}
+
+pub fn load_layout(conn: &mut PgConnection, name: &str) -> Result<Layout, anyhow::Error> {
+    let layout = get_layout(conn, name)
+        .context("Failed to fetch the email layout.")?
+        .with_context(|| format!("There is no email layout named {}.", name))?;
+    Layout::parse(&layout)
+        .map_err(anyhow::Error::msg)
+        .with_context(|| format!("The email layout {} is invalid.", name))
+}
+
+pub fn get_transactional_template(
+    conn: &mut PgConnection,
+    email: TransactionalEmail,
+) -> Result<TransactionalTemplate, diesel::result::Error> {
+    transactional_templates::table
+        .find(email.name())
+        .first::<TransactionalTemplate>(conn)
+}
+
+// Renders the subject and body of a transactional email in its layout.
+pub fn render_transactional_email(
+    conn: &mut PgConnection,
+    email: TransactionalEmail,
+    values: &[(&str, &str)],
+) -> Result<(String, RenderedContent), anyhow::Error> {
+    let template = get_transactional_template(conn, email)
+        .with_context(|| format!("Failed to fetch the {} email template.", email.name()))?;
+    let subject = Template::parse(&template.subject, email.variables())
+        .map_err(anyhow::Error::msg)
+        .context("The email subject is not a valid template.")?
+        .render_text(values);
+    let body = MarkdownTemplate::parse(&template.body, email.variables())
+        .map_err(anyhow::Error::msg)
+        .context("The email body is not a valid template.")?
+        .render(values);
+    let layout = load_layout(conn, &template.layout)?;
+    let content = layout.render(&subject, &body, None);
+    Ok((subject, content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Layout, TransactionalEmail};
+    use crate::db_models::EmailLayout;
+    use crate::markdown::RenderedContent;
+    use chrono::Utc;
+
+    fn layout() -> EmailLayout {
+        EmailLayout {
+            name: "default".into(),
+            header: "# {{ subject }}".into(),
+            footer: "Thanks for reading.".into(),
+            physical_address: "1 Main Street\nSpringfield".into(),
+            unsubscribe_block: "[Unsubscribe]({{ unsubscribe_url }})".into(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn content() -> RenderedContent {
+        RenderedContent {
+            html: "<p>Body</p>".into(),
+            text: "Body".into(),
+        }
+    }
+
+    #[test]
+    fn newsletters_get_the_whole_layout() {
+        let layout = Layout::parse(&layout()).unwrap();
+        let email = layout.render("Issue <1>", &content(), Some("https://x.com/u?a=1&b=2"));
+        assert!(email.html.contains("Issue &lt;1&gt;</h1>"));
+        assert!(email.html.contains("<p>Body</p>"));
+        assert!(email.html.contains(r#"href="https://x.com/u?a=1&amp;b=2""#));
+        assert!(email.html.contains("1 Main Street<br>Springfield</p>"));
+        assert_eq!(
+            email.text,
+            "Issue <1>\n\nBody\n\n----------\n\nThanks for reading.\n\n\
+            Unsubscribe [1]\n\n[1] https://x.com/u?a=1&b=2\n\n1 Main Street\nSpringfield"
+        );
+    }
+
+    #[test]
+    fn the_unsubscribe_block_is_left_out_without_an_unsubscribe_url() {
+        let layout = Layout::parse(&layout()).unwrap();
+        let email = layout.render("Welcome!", &content(), None);
+        assert!(!email.html.contains("Unsubscribe"));
+        assert!(!email.text.contains("Unsubscribe"));
+    }
+
+    #[test]
+    fn layouts_with_unknown_variables_are_rejected() {
+        let mut invalid = layout();
+        invalid.footer = "Bye {{ subscriber.name }}".into();
+        let error = Layout::parse(&invalid).err().unwrap();
+        assert!(error.starts_with("The footer is not a valid template."));
+    }
+
+    #[test]
+    fn transactional_emails_are_looked_up_by_name() {
+        for email in TransactionalEmail::ALL {
+            assert_eq!(TransactionalEmail::parse(email.name()), Some(email));
+        }
+        assert_eq!(TransactionalEmail::parse("newsletter"), None);
+    }
+}
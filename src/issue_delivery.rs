@@ -0,0 +1,533 @@
+use crate::{
+    clock::Clock,
+    db::PgPool,
+    db_models::NewsletterIssue,
+    delivery_log::{record_delivery, settled_subscribers, DeliveryAttempt},
+    domain::{IssueStatus, Segment, SubscriberEmail},
+    email_client::{Email, EmailClient, MAX_BATCH_SIZE},
+    email_templates::load_layout,
+    markdown::RenderedContent,
+    routes::subscriptions::{generate_subscription_token, store_token},
+    schema::{newsletter_issues, subscription_tokens, subscriptions},
+    shutdown::Shutdown,
+    signing::SigningKey,
+    templating::Template,
+    tracking::{extract_links, record_event, store_issue_links, track_html, TrackingEvent},
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use diesel::Queryable;
+use serde::Deserialize;
+use std::collections::HashMap;
+use uuid::Uuid;
+
+// The merge fields available in the content of a newsletter issue.
+pub const ISSUE_VARIABLES: &[&str] = &[
+    "subscriber.name",
+    "subscriber.email",
+    "unsubscribe_url",
+    "issue.title",
+];
+
+#[derive(Debug, Deserialize, Queryable)]
+pub struct ConfirmedSubscriber {
+    pub subscriber_id: Uuid,
+    pub email: SubscriberEmail,
+    pub name: String,
+    // The token the subscriber confirmed with, which also authorises their unsubscribe link.
+    pub subscription_token: String,
+}
+
+pub struct MergeFields<'a> {
+    pub subscriber_name: &'a str,
+    pub subscriber_email: &'a str,
+    pub unsubscribe_url: &'a str,
+    pub issue_title: &'a str,
+}
+
+pub struct IssueTemplate {
+    html: Template,
+    text: Template,
+}
+
+impl IssueTemplate {
+    pub fn parse(html_content: &str, text_content: &str) -> Result<Self, String> {
+        let html = Template::parse(html_content, ISSUE_VARIABLES)
+            .map_err(|e| format!("The HTML content is not a valid template. {}", e))?;
+        let text = Template::parse(text_content, ISSUE_VARIABLES)
+            .map_err(|e| format!("The plain text content is not a valid template. {}", e))?;
+        Ok(Self { html, text })
+    }
+
+    pub fn render(&self, fields: &MergeFields) -> RenderedContent {
+        let values = [
+            ("subscriber.name", fields.subscriber_name),
+            ("subscriber.email", fields.subscriber_email),
+            ("unsubscribe_url", fields.unsubscribe_url),
+            ("issue.title", fields.issue_title),
+        ];
+        RenderedContent {
+            html: self.html.render_html(&values),
+            text: self.text.render_text(&values),
+        }
+    }
+}
+
+// Links sent in a newsletter issue also name the issue, so unsubscribes show in its stats.
+pub fn unsubscribe_url(
+    application_base_url: &str,
+    subscription_token: &str,
+    newsletter_issue_id: Option<Uuid>,
+) -> String {
+    let mut url = format!(
+        "{}/subscriptions/unsubscribe?subscription_token={}",
+        application_base_url, subscription_token
+    );
+    if let Some(newsletter_issue_id) = newsletter_issue_id {
+        url.push_str(&format!("&newsletter_issue_id={}", newsletter_issue_id));
+    }
+    url
+}
+
+#[derive(Insertable)]
+#[diesel(table_name = newsletter_issues)]
+pub struct NewNewsletterIssue<'a> {
+    pub newsletter_issue_id: Uuid,
+    pub title: &'a str,
+    pub text_content: &'a str,
+    pub html_content: &'a str,
+    pub markdown_content: Option<&'a str>,
+    pub segment: Option<&'a str>,
+    pub layout: &'a str,
+    pub tracking_enabled: bool,
+    pub in_archive: bool,
+    pub status: &'a str,
+    pub scheduled_at: Option<DateTime<Utc>>,
+    pub claimed_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+pub fn confirmed_subscribers_query(
+    segment: Option<&Segment>,
+) -> subscriptions::BoxedQuery<'static, diesel::pg::Pg> {
+    let mut query = subscriptions::table
+        .filter(subscriptions::status.eq("confirmed"))
+        .into_boxed();
+    if let Some(segment) = segment {
+        query = query.filter(segment.to_filter());
+    }
+    query
+}
+
+#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
+pub async fn get_confirmed_subscribers(
+    pool: &PgPool,
+    segment: Option<&Segment>,
+) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
+    let mut conn = pool.get().expect("Failed to get db connection from pool");
+
+    let rows = confirmed_subscribers_query(segment)
+        .select((subscriptions::id, subscriptions::email, subscriptions::name))
+        .load::<(Uuid, String, String)>(&mut conn)?;
+    let subscriber_ids: Vec<Uuid> = rows.iter().map(|(id, _, _)| *id).collect();
+    let mut tokens: HashMap<Uuid, String> = subscription_tokens::table
+        .filter(subscription_tokens::subscriber_id.eq_any(&subscriber_ids))
+        .select((
+            subscription_tokens::subscriber_id,
+            subscription_tokens::subscription_token,
+        ))
+        .load::<(Uuid, String)>(&mut conn)?
+        .into_iter()
+        .collect();
+
+    let mut confirmed_subscribers = Vec::with_capacity(rows.len());
+    for (subscriber_id, email, name) in rows {
+        let email = match SubscriberEmail::parse(email) {
+            Ok(email) => email,
+            Err(error) => {
+                confirmed_subscribers.push(Err(anyhow::anyhow!(error)));
+                continue;
+            }
+        };
+        // Subscribers added before tokens existed get one now, so they can unsubscribe too.
+        let subscription_token = match tokens.remove(&subscriber_id) {
+            Some(token) => token,
+            None => {
+                let token = generate_subscription_token();
+                store_token(&mut conn, &subscriber_id, &token)
+                    .context("Failed to store a subscription token for a subscriber.")?;
+                token
+            }
+        };
+        confirmed_subscribers.push(Ok(ConfirmedSubscriber {
+            subscriber_id,
+            email,
+            name,
+            subscription_token,
+        }));
+    }
+
+    Ok(confirmed_subscribers)
+}
+
+#[tracing::instrument(name = "Save newsletter issue", skip_all, fields(newsletter_issue_id = %new_issue.newsletter_issue_id))]
+pub fn insert_newsletter_issue(
+    pool: &PgPool,
+    new_issue: &NewNewsletterIssue,
+) -> Result<NewsletterIssue, anyhow::Error> {
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    diesel::insert_into(newsletter_issues::table)
+        .values(new_issue)
+        .get_result::<NewsletterIssue>(&mut conn)
+        .context("Failed to store the newsletter issue.")
+}
+
+// How often sending an email to a subscriber is attempted before giving up.
+const DELIVERY_ATTEMPTS: usize = 3;
+
+// An issue rendered for one subscriber.
+struct Message {
+    subscriber: ConfirmedSubscriber,
+    content: RenderedContent,
+    unsubscribe_url: String,
+}
+
+// The emails of a batch that were not sent.
+#[derive(Debug, Default)]
+struct BatchFailures {
+    // Refused by the provider for good, e.g. because the recipient is inactive.
+    rejected: usize,
+    // Still failing with a transient error after `DELIVERY_ATTEMPTS` attempts.
+    undelivered: usize,
+}
+
+// Sends a batch of rendered emails, then sends the ones that failed with a transient error again,
+// up to `DELIVERY_ATTEMPTS` times, or until the shutdown deadline has passed. Every attempt is
+// logged. Fails if the whole request failed in a way that makes sending it again unsafe or
+// pointless, e.g. it timed out after the provider may have accepted it; the issue is then tried
+// again later, without the subscribers it was recorded as sent to.
+async fn send_batch(
+    conn: &mut PgConnection,
+    email_client: &EmailClient,
+    issue: &NewsletterIssue,
+    clock: &dyn Clock,
+    batch: &[Message],
+    shutdown: &Shutdown,
+) -> Result<BatchFailures, anyhow::Error> {
+    let mut failures = BatchFailures::default();
+    let mut pending: Vec<&Message> = batch.iter().collect();
+    for attempt in 1..=DELIVERY_ATTEMPTS {
+        if attempt > 1 && shutdown.deadline_passed() {
+            break;
+        }
+        let emails: Vec<Email> = pending
+            .iter()
+            .map(|message| Email {
+                recipient: &message.subscriber.email,
+                subject: &issue.title,
+                html_content: &message.content.html,
+                text_content: &message.content.text,
+                unsubscribe_url: Some(&message.unsubscribe_url),
+            })
+            .collect();
+        let attempted_at = clock.now();
+        let outcome = email_client.send_email_batch(&emails).await;
+        let completed_at = clock.now();
+
+        let mut failed = vec![];
+        for (index, message) in pending.into_iter().enumerate() {
+            let subscriber = &message.subscriber;
+            // When the whole request failed, every email of the batch failed with it.
+            let result = match &outcome {
+                Ok(results) => results[index].as_ref(),
+                Err(e) => Err(e),
+            };
+            record_delivery(
+                conn,
+                &DeliveryAttempt {
+                    newsletter_issue_id: issue.newsletter_issue_id,
+                    subscriber_id: subscriber.subscriber_id,
+                    email: subscriber.email.as_ref(),
+                    attempted_at,
+                    completed_at,
+                },
+                result,
+            )
+            .context("Failed to record the delivery of the newsletter issue.")?;
+            match result {
+                Ok(_) => {
+                    record_event(
+                        conn,
+                        issue.newsletter_issue_id,
+                        subscriber.subscriber_id,
+                        TrackingEvent::Delivered,
+                        None,
+                        completed_at,
+                    )
+                    .context("Failed to record the delivery of the newsletter issue.")?;
+                }
+                Err(e) if e.is_transient() => {
+                    tracing::warn!(
+                        error.message = %e,
+                        attempt,
+                        subscriber_id = %subscriber.subscriber_id,
+                        "Failed to send the newsletter issue to a subscriber"
+                    );
+                    failed.push(message);
+                }
+                Err(e) => {
+                    tracing::warn!(
+                        error.message = %e,
+                        subscriber_id = %subscriber.subscriber_id,
+                        "The email provider refused to send the newsletter issue to a subscriber"
+                    );
+                    failures.rejected += 1;
+                }
+            }
+        }
+        if let Err(e) = outcome {
+            if !e.is_transient() {
+                return Err(e).context("The email provider refused to send the newsletter issue.");
+            }
+        }
+        if failed.is_empty() {
+            return Ok(failures);
+        }
+        pending = failed;
+    }
+    failures.undelivered = pending.len();
+    Ok(failures)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeliveryOutcome {
+    Sent,
+    // The shutdown deadline passed before every subscriber was sent the issue. It was scheduled
+    // again and the subscribers already sent it are skipped when it is resumed.
+    Interrupted,
+}
+
+// How long an issue stays claimed by whoever is sending it without any batch being sent. Past
+// that, the sender is taken to have crashed and the scheduler claims the issue again.
+pub const DELIVERY_LEASE: chrono::Duration = chrono::Duration::minutes(10);
+
+// How many times sending an issue can fail before it is marked as failed. Until then it is
+// scheduled again, waiting twice as long after each failure.
+const MAX_DELIVERY_FAILURES: i32 = 5;
+const FIRST_RETRY_DELAY: chrono::Duration = chrono::Duration::minutes(1);
+
+// Sends `issue`, with its merge fields filled in and wrapped in its layout, to every confirmed
+// subscriber in its segment that has not been sent it yet and marks it as sent, along with how
+// many subscribers the email provider refused to send it to. The issue is expected to have been moved to `sending` by the caller.
+// If sending fails, the issue is scheduled to be tried again later before the error is returned.
+#[tracing::instrument(
+    name = "Deliver newsletter issue",
+    skip(pool, email_client, issue, clock, application_base_url, tracking_key, shutdown),
+    fields(newsletter_issue_id = %issue.newsletter_issue_id)
+)]
+pub async fn deliver_issue(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    issue: &NewsletterIssue,
+    clock: &dyn Clock,
+    application_base_url: &str,
+    tracking_key: &SigningKey,
+    shutdown: &Shutdown,
+) -> Result<DeliveryOutcome, anyhow::Error> {
+    let outcome = send_issue(
+        pool,
+        email_client,
+        issue,
+        clock,
+        application_base_url,
+        tracking_key,
+        shutdown,
+    )
+    .await;
+    if outcome.is_err() {
+        let mut conn = pool
+            .get()
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        retry_later(&mut conn, issue, clock.now())
+            .context("Failed to schedule the newsletter issue to be sent again.")?;
+    }
+    outcome
+}
+
+async fn send_issue(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    issue: &NewsletterIssue,
+    clock: &dyn Clock,
+    application_base_url: &str,
+    tracking_key: &SigningKey,
+    shutdown: &Shutdown,
+) -> Result<DeliveryOutcome, anyhow::Error> {
+    let template = IssueTemplate::parse(&issue.html_content, &issue.text_content)
+        .map_err(anyhow::Error::msg)
+        .context("The stored content of the newsletter issue is not a valid template.")?;
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let layout = load_layout(&mut conn, &issue.layout)?;
+    let tracked_links = if issue.tracking_enabled {
+        let links = extract_links(&issue.html_content);
+        store_issue_links(&mut conn, issue.newsletter_issue_id, &links)
+            .context("Failed to store the links of the newsletter issue.")?
+    } else {
+        vec![]
+    };
+    let segment = issue
+        .segment
+        .as_deref()
+        .map(Segment::parse)
+        .transpose()
+        .map_err(anyhow::Error::msg)
+        .context("The stored segment of the newsletter issue is invalid.")?;
+    let subscribers = get_confirmed_subscribers(pool, segment.as_ref()).await?;
+    let settled = settled_subscribers(&mut conn, issue.newsletter_issue_id)
+        .context("Failed to load the subscribers the newsletter issue was sent or rejected for.")?;
+
+    let mut messages = Vec::with_capacity(subscribers.len());
+    for subscriber in subscribers {
+        match subscriber {
+            Ok(subscriber) if settled.contains(&subscriber.subscriber_id) => {}
+            Ok(subscriber) => {
+                let unsubscribe_url = unsubscribe_url(
+                    application_base_url,
+                    &subscriber.subscription_token,
+                    Some(issue.newsletter_issue_id),
+                );
+                let content = template.render(&MergeFields {
+                    subscriber_name: &subscriber.name,
+                    subscriber_email: subscriber.email.as_ref(),
+                    unsubscribe_url: &unsubscribe_url,
+                    issue_title: &issue.title,
+                });
+                let mut content = layout.render(&issue.title, &content, Some(&unsubscribe_url));
+                if issue.tracking_enabled {
+                    content.html = track_html(
+                        &content.html,
+                        &tracked_links,
+                        issue.newsletter_issue_id,
+                        subscriber.subscriber_id,
+                        application_base_url,
+                        tracking_key,
+                    );
+                }
+                messages.push(Message {
+                    subscriber,
+                    content,
+                    unsubscribe_url,
+                });
+            }
+            Err(error) => {
+                tracing::warn!(
+                // We record the error chain as a structured fieldon the log record.
+                error.cause_chain = ?error,
+                // Using `\` to split a long string literal overtwo lines, without creating a `\n` character.
+                "Skipping a confirmed subscriber. \
+                Their stored contact details are invalid",
+                );
+            }
+        }
+    }
+
+    let (mut rejected, mut undelivered) = (0, 0);
+    for batch in messages.chunks(MAX_BATCH_SIZE) {
+        if shutdown.deadline_passed() {
+            return interrupt_delivery(&mut conn, issue, clock);
+        }
+        let failures = send_batch(&mut conn, email_client, issue, clock, batch, shutdown).await?;
+        rejected += failures.rejected;
+        undelivered += failures.undelivered;
+        renew_lease(&mut conn, issue, clock.now())
+            .context("Failed to renew the claim on the newsletter issue.")?;
+    }
+    if undelivered > 0 && shutdown.deadline_passed() {
+        return interrupt_delivery(&mut conn, issue, clock);
+    }
+    if undelivered > 0 {
+        anyhow::bail!(
+            "Failed to send the newsletter issue to {} subscribers after {} attempts.",
+            undelivered,
+            DELIVERY_ATTEMPTS
+        );
+    }
+
+    diesel::update(newsletter_issues::table.find(issue.newsletter_issue_id))
+        .set((
+            newsletter_issues::status.eq(IssueStatus::Sent.as_str()),
+            newsletter_issues::sent_at.eq(Some(clock.now())),
+            newsletter_issues::failed_deliveries.eq(rejected as i32),
+            newsletter_issues::claimed_at.eq(None::<DateTime<Utc>>),
+        ))
+        .execute(&mut conn)
+        .context("Failed to mark the newsletter issue as sent.")?;
+    Ok(DeliveryOutcome::Sent)
+}
+
+// Hands the issue back to the scheduler, which resumes it once the application is running again.
+// Issues published without a schedule become due straight away.
+fn interrupt_delivery(
+    conn: &mut PgConnection,
+    issue: &NewsletterIssue,
+    clock: &dyn Clock,
+) -> Result<DeliveryOutcome, anyhow::Error> {
+    diesel::update(newsletter_issues::table.find(issue.newsletter_issue_id))
+        .set((
+            newsletter_issues::status.eq(IssueStatus::Scheduled.as_str()),
+            newsletter_issues::scheduled_at.eq(Some(issue.scheduled_at.unwrap_or(clock.now()))),
+            newsletter_issues::claimed_at.eq(None::<DateTime<Utc>>),
+        ))
+        .execute(conn)
+        .context("Failed to save the progress of the newsletter issue.")?;
+    tracing::warn!("Delivery interrupted by shutdown, the newsletter issue will be resumed");
+    Ok(DeliveryOutcome::Interrupted)
+}
+
+// Keeps the scheduler from claiming the issue while it is still being sent.
+fn renew_lease(
+    conn: &mut PgConnection,
+    issue: &NewsletterIssue,
+    now: DateTime<Utc>,
+) -> Result<usize, diesel::result::Error> {
+    diesel::update(newsletter_issues::table.find(issue.newsletter_issue_id))
+        .set(newsletter_issues::claimed_at.eq(Some(now)))
+        .execute(conn)
+}
+
+// Hands a failed issue back to the scheduler, which tries it again after a delay, or marks it as
+// failed once it failed `MAX_DELIVERY_FAILURES` times. The subscribers already sent it, or
+// rejected by the provider for good, are skipped when it is tried again.
+fn retry_later(
+    conn: &mut PgConnection,
+    issue: &NewsletterIssue,
+    now: DateTime<Utc>,
+) -> Result<usize, diesel::result::Error> {
+    let failures = issue.delivery_failures + 1;
+    let (status, scheduled_at) = if failures >= MAX_DELIVERY_FAILURES {
+        tracing::error!(failures, "Gave up sending the newsletter issue");
+        (IssueStatus::Failed, issue.scheduled_at)
+    } else {
+        let retry_at = now + FIRST_RETRY_DELAY * 2_i32.pow(failures as u32 - 1);
+        tracing::warn!(
+            failures,
+            %retry_at,
+            "Failed to send the newsletter issue, it will be tried again"
+        );
+        (IssueStatus::Scheduled, Some(retry_at))
+    };
+    diesel::update(newsletter_issues::table.find(issue.newsletter_issue_id))
+        .set((
+            newsletter_issues::status.eq(status.as_str()),
+            newsletter_issues::scheduled_at.eq(scheduled_at),
+            newsletter_issues::claimed_at.eq(None::<DateTime<Utc>>),
+            newsletter_issues::delivery_failures.eq(failures),
+        ))
+        .execute(conn)
+}
@@ -1,12 +1,32 @@
+pub mod api_keys;
 pub mod authentication;
+pub mod bot_protection;
+pub mod captcha;
+pub mod clock;
+pub mod csrf;
 pub mod db;
 pub mod db_models;
+pub mod delivery_log;
 pub mod domain;
 pub mod email_client;
+pub mod email_events;
+pub mod email_templates;
+pub mod issue_delivery;
+pub mod log_redaction;
+pub mod markdown;
+pub mod metrics;
 pub mod middleware;
+pub mod rate_limit;
 mod routes;
+pub mod scheduler;
+pub mod security_headers;
+pub mod settings;
 pub mod schema;
 pub mod session_state;
+pub mod shutdown;
+pub mod signing;
 pub mod startup;
 pub mod telemetry;
+pub mod templating;
+pub mod tracking;
 pub mod utils;
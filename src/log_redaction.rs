@@ -0,0 +1,403 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::io::Write;
+use std::sync::Arc;
+use tracing_subscriber::fmt::MakeWriter;
+
+// How the value of a field is masked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Redaction {
+    // Keeps the first character and the domain, e.g. `u***@gmail.com`, so logs stay useful.
+    Email,
+    // Replaces the whole value.
+    Secret,
+}
+
+const REDACTED: &str = "[REDACTED]";
+
+// Which log fields are masked, by field name. A rule for `token` also applies to
+// `request.token`. Strings in every other field are still scanned for email addresses,
+// `Bearer`/`Basic` credentials and `<secret field>=<value>` pairs, as found in URLs.
+#[derive(Debug, Clone)]
+pub struct RedactionRules {
+    fields: HashMap<String, Redaction>,
+}
+
+impl Default for RedactionRules {
+    fn default() -> Self {
+        let mut rules = Self::none();
+        for field in ["email", "subscriber_email", "recipient", "to"] {
+            rules = rules.redact(field, Redaction::Email);
+        }
+        for field in [
+            "authorization",
+            "cookie",
+            "password",
+            "token",
+            "subscription_token",
+            "api_key",
+        ] {
+            rules = rules.redact(field, Redaction::Secret);
+        }
+        rules
+    }
+}
+
+impl RedactionRules {
+    pub fn none() -> Self {
+        Self {
+            fields: HashMap::new(),
+        }
+    }
+
+    pub fn redact(mut self, field: &str, redaction: Redaction) -> Self {
+        self.fields.insert(field.to_lowercase(), redaction);
+        self
+    }
+
+    pub fn keep(mut self, field: &str) -> Self {
+        self.fields.remove(&field.to_lowercase());
+        self
+    }
+
+    // Adjusts the rules by `overrides`, e.g. `user_id:secret,to:none`. Each entry is
+    // `<field>:<email|secret|none>`; `none` turns off a default rule.
+    pub fn parse_overrides(mut self, overrides: &str) -> Result<Self, String> {
+        for entry in overrides
+            .split(',')
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+        {
+            let (field, rule) = entry
+                .split_once(':')
+                .ok_or_else(|| format!("`{}` is not of the form <field>:<rule>", entry))?;
+            self = match rule.trim() {
+                "email" => self.redact(field.trim(), Redaction::Email),
+                "secret" => self.redact(field.trim(), Redaction::Secret),
+                "none" => self.keep(field.trim()),
+                other => return Err(format!("`{}` is not a known redaction rule", other)),
+            };
+        }
+        Ok(self)
+    }
+
+    fn rule_for(&self, field: &str) -> Option<Redaction> {
+        let field = field.to_lowercase();
+        self.fields.get(&field).copied().or_else(|| {
+            let (_, last) = field.rsplit_once('.')?;
+            self.fields.get(last).copied()
+        })
+    }
+
+    // Masks a whole log record, e.g. one line written by the Bunyan formatter.
+    pub fn redact_record(&self, record: &mut Value) {
+        if let Value::Object(fields) = record {
+            for (name, value) in fields.iter_mut() {
+                self.redact_field(name, value);
+            }
+        } else {
+            self.redact_nested(record);
+        }
+    }
+
+    // Masks the value of a single field, e.g. an attribute of an exported span.
+    pub fn redact_field(&self, name: &str, value: &mut Value) {
+        match self.rule_for(name) {
+            Some(redaction) => redact_value(value, redaction),
+            None => self.redact_nested(value),
+        }
+    }
+
+    fn redact_nested(&self, value: &mut Value) {
+        match value {
+            Value::String(text) => *text = self.redact_text(text),
+            Value::Array(values) => values.iter_mut().for_each(|v| self.redact_nested(v)),
+            Value::Object(_) => self.redact_record(value),
+            _ => {}
+        }
+    }
+
+    // Masks what looks like personal data or credentials in free text.
+    pub fn redact_text(&self, text: &str) -> String {
+        let mut text = mask_emails(text);
+        for scheme in ["Bearer ", "Basic "] {
+            text = mask_after(&text, scheme);
+        }
+        let mut secret_fields: Vec<&String> = self
+            .fields
+            .iter()
+            .filter(|(_, redaction)| **redaction == Redaction::Secret)
+            .map(|(field, _)| field)
+            .collect();
+        secret_fields.sort();
+        for field in secret_fields {
+            text = mask_after(&text, &format!("{}=", field));
+        }
+        text
+    }
+}
+
+fn redact_value(value: &mut Value, redaction: Redaction) {
+    match (value, redaction) {
+        (Value::Null, _) => {}
+        (Value::String(text), Redaction::Email) => *text = mask_emails(text),
+        (value, _) => *value = Value::String(REDACTED.into()),
+    }
+}
+
+fn is_local_part(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b"._%+-".contains(&b)
+}
+
+fn is_domain(b: u8) -> bool {
+    b.is_ascii_alphanumeric() || b".-".contains(&b)
+}
+
+// `ursula_le_guin@gmail.com` becomes `u***@gmail.com`.
+fn mask_emails(text: &str) -> String {
+    let bytes = text.as_bytes();
+    let mut masked = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] != b'@' {
+            i += 1;
+            continue;
+        }
+        let mut start = i;
+        while start > copied && is_local_part(bytes[start - 1]) {
+            start -= 1;
+        }
+        let mut end = i + 1;
+        while end < bytes.len() && is_domain(bytes[end]) {
+            end += 1;
+        }
+        while end > i + 1 && bytes[end - 1] == b'.' {
+            end -= 1;
+        }
+        let domain = &text[i + 1..end];
+        if start < i && domain.contains('.') {
+            masked.push_str(&text[copied..start]);
+            masked.push(bytes[start] as char);
+            masked.push_str("***@");
+            masked.push_str(domain);
+            copied = end;
+        }
+        i = end.max(i + 1);
+    }
+    masked.push_str(&text[copied..]);
+    masked
+}
+
+// Replaces the value following each occurrence of `prefix`, up to the next separator.
+fn mask_after(text: &str, prefix: &str) -> String {
+    // ASCII only, so that offsets in `lowercase` are offsets in `text`.
+    let lowercase = text.to_ascii_lowercase();
+    let prefix = prefix.to_ascii_lowercase();
+    let mut masked = String::with_capacity(text.len());
+    let mut copied = 0;
+    let mut search_from = 0;
+    while let Some(offset) = lowercase[search_from..].find(&prefix) {
+        let start = search_from + offset;
+        let value_start = start + prefix.len();
+        search_from = value_start;
+        // Only whole names: `subscription_token=` must not match inside `xsubscription_token=`.
+        let preceded_by_name = text[..start]
+            .bytes()
+            .last()
+            .is_some_and(|b| b.is_ascii_alphanumeric() || b == b'_');
+        if preceded_by_name {
+            continue;
+        }
+        let value_end = text[value_start..]
+            .find(|c: char| c.is_whitespace() || "&\"'#,;".contains(c))
+            .map_or(text.len(), |end| value_start + end);
+        if value_end == value_start {
+            continue;
+        }
+        masked.push_str(&text[copied..value_start]);
+        masked.push_str(REDACTED);
+        copied = value_end;
+        search_from = value_end;
+    }
+    masked.push_str(&text[copied..]);
+    masked
+}
+
+// Wraps the sink of the formatting layer: every line is redacted before it is written.
+pub struct RedactingMakeWriter<M> {
+    inner: M,
+    rules: Arc<RedactionRules>,
+}
+
+impl<M> RedactingMakeWriter<M> {
+    pub fn new(inner: M, rules: RedactionRules) -> Self {
+        Self {
+            inner,
+            rules: Arc::new(rules),
+        }
+    }
+}
+
+impl<'a, M> MakeWriter<'a> for RedactingMakeWriter<M>
+where
+    M: MakeWriter<'a>,
+{
+    type Writer = RedactingWriter<M::Writer>;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        RedactingWriter {
+            inner: self.inner.make_writer(),
+            rules: self.rules.clone(),
+            buffer: Vec::new(),
+        }
+    }
+}
+
+pub struct RedactingWriter<W: Write> {
+    inner: W,
+    rules: Arc<RedactionRules>,
+    buffer: Vec<u8>,
+}
+
+impl<W: Write> RedactingWriter<W> {
+    fn write_line(&mut self, line: &[u8]) -> std::io::Result<()> {
+        let text = String::from_utf8_lossy(line);
+        let redacted = match serde_json::from_str::<Value>(&text) {
+            Ok(mut record) => {
+                self.rules.redact_record(&mut record);
+                record.to_string()
+            }
+            Err(_) => self.rules.redact_text(&text),
+        };
+        self.inner.write_all(redacted.as_bytes())
+    }
+}
+
+impl<W: Write> Write for RedactingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while let Some(newline) = self.buffer.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..=newline).collect();
+            self.write_line(&line[..newline])?;
+            self.inner.write_all(b"\n")?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<W: Write> Drop for RedactingWriter<W> {
+    fn drop(&mut self) {
+        if !self.buffer.is_empty() {
+            let line = std::mem::take(&mut self.buffer);
+            let _ = self.write_line(&line);
+        }
+        let _ = self.inner.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Redaction, RedactionRules};
+    use crate::telemetry::build_subscriber;
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn configured_fields_are_masked() {
+        let rules = RedactionRules::default().redact("user_id", Redaction::Secret);
+        let mut record = serde_json::json!({
+            "msg": "Adding a new subscriber",
+            "subscriber_email": "ursula_le_guin@gmail.com",
+            "http.authorization": "Basic dXNlcjpwYXNz",
+            "user_id": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+            "subscriber_name": "le guin",
+        });
+        rules.redact_record(&mut record);
+
+        assert_eq!(record["subscriber_email"], "u***@gmail.com");
+        assert_eq!(record["http.authorization"], "[REDACTED]");
+        assert_eq!(record["user_id"], "[REDACTED]");
+        assert_eq!(record["subscriber_name"], "le guin");
+    }
+
+    #[test]
+    fn personal_data_in_free_text_is_masked() {
+        let rules = RedactionRules::default();
+        assert_eq!(
+            rules.redact_text("Failed to send newsletter issue to ursula_le_guin@gmail.com."),
+            "Failed to send newsletter issue to u***@gmail.com."
+        );
+        assert_eq!(
+            rules.redact_text("GET /subscriptions/confirm?subscription_token=abc123&x=1"),
+            "GET /subscriptions/confirm?subscription_token=[REDACTED]&x=1"
+        );
+        assert_eq!(
+            rules.redact_text(r#"headers: {"authorization": "Bearer abc.def"}"#),
+            r#"headers: {"authorization": "Bearer [REDACTED]"}"#
+        );
+        assert_eq!(rules.redact_text("user@localhost"), "user@localhost");
+    }
+
+    #[test]
+    fn rules_can_be_overridden_per_field() {
+        let rules = RedactionRules::default()
+            .parse_overrides("to:none, user_id:secret")
+            .unwrap();
+        let mut record = serde_json::json!({ "to": "a@example.com", "user_id": 42 });
+        rules.redact_record(&mut record);
+        // The free text scan still applies to fields without a rule.
+        assert_eq!(record["to"], "a***@example.com");
+        assert_eq!(record["user_id"], "[REDACTED]");
+
+        assert!(RedactionRules::default()
+            .parse_overrides("to:hidden")
+            .is_err());
+    }
+
+    #[derive(Clone, Default)]
+    struct Sink(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Sink {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn emitted_log_records_are_redacted() {
+        let sink = Sink::default();
+        let writer = sink.clone();
+        let subscriber = build_subscriber(
+            "test".into(),
+            "info".into(),
+            move || writer.clone(),
+            RedactionRules::default(),
+            None,
+        );
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!(
+                "Adding a new subscriber",
+                subscriber_email = "ursula_le_guin@gmail.com"
+            );
+            let _guard = span.enter();
+            tracing::info!(
+                token = "secret-token",
+                "Sent a confirmation email to ursula_le_guin@gmail.com"
+            );
+        });
+
+        let logs = String::from_utf8(sink.0.lock().unwrap().clone()).unwrap();
+        assert!(!logs.is_empty());
+        assert!(!logs.contains("ursula_le_guin"));
+        assert!(!logs.contains("secret-token"));
+        assert!(logs.contains("u***@gmail.com"));
+    }
+}
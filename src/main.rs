@@ -1,15 +1,27 @@
+use newsletter::clock::SystemClock;
 use newsletter::db::establish_connection;
+use newsletter::settings::Settings;
 use newsletter::startup::Application;
-use newsletter::telemetry::{get_subscriber, init_subscriber};
+use newsletter::telemetry::{get_subscriber, init_subscriber, shutdown_tracing};
+use std::sync::Arc;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let subscriber = get_subscriber("newsletter_kk".into(), "info".into(), std::io::stdout);
+    let settings = Settings::from_env()?;
+    let subscriber = get_subscriber(
+        "newsletter_kk".into(),
+        "info".into(),
+        std::io::stdout,
+        settings.log_redaction.clone(),
+        settings.otlp_endpoint.as_deref(),
+    )?;
     init_subscriber(subscriber);
 
     let database_name = "newsletter";
     let pool = establish_connection(database_name);
-    let application = Application::build(8080, pool.clone(), None).await?;
+    let application =
+        Application::build(settings, 8080, pool.clone(), None, Arc::new(SystemClock)).await?;
     application.run_until_stopped().await?;
+    shutdown_tracing();
     Ok(())
 }
@@ -0,0 +1,309 @@
+use pulldown_cmark::{html, CowStr, Event, Options, Parser, Tag, TagEnd};
+
+pub struct RenderedContent {
+    pub html: String,
+    pub text: String,
+}
+
+// Email clients drop <style> blocks, so every element we allow carries its own styling.
+const INLINE_STYLES: &[(&str, &str)] = &[
+    ("h1", "margin: 0 0 16px; font-size: 28px; line-height: 1.25;"),
+    ("h2", "margin: 24px 0 12px; font-size: 22px; line-height: 1.25;"),
+    ("h3", "margin: 20px 0 8px; font-size: 18px; line-height: 1.25;"),
+    ("p", "margin: 0 0 16px;"),
+    ("a", "color: #1a73e8; text-decoration: underline;"),
+    ("blockquote", "margin: 0 0 16px; padding: 0 16px; border-left: 4px solid #dddddd; color: #555555;"),
+    ("pre", "margin: 0 0 16px; padding: 12px; background-color: #f6f8fa; overflow: auto;"),
+    ("code", "font-family: Menlo, Consolas, monospace; font-size: 14px;"),
+    ("ul", "margin: 0 0 16px; padding-left: 24px;"),
+    ("ol", "margin: 0 0 16px; padding-left: 24px;"),
+    ("table", "margin: 0 0 16px; border-collapse: collapse;"),
+    ("th", "padding: 6px 12px; border: 1px solid #dddddd; background-color: #f6f8fa; text-align: left;"),
+    ("td", "padding: 6px 12px; border: 1px solid #dddddd;"),
+    ("img", "max-width: 100%; height: auto;"),
+    ("hr", "margin: 24px 0; border: 0; border-top: 1px solid #dddddd;"),
+];
+
+const BODY_STYLE: &str =
+    "font-family: Helvetica, Arial, sans-serif; font-size: 16px; line-height: 1.5; color: #222222;";
+
+fn options() -> Options {
+    Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH
+}
+
+// Renders CommonMark (with tables) into an email-ready HTML body and its plain-text alternative.
+pub fn render_markdown(markdown: &str) -> RenderedContent {
+    // Template tags such as `{{ unsubscribe_url }}` have to come out exactly as written, even
+    // when used as a link destination, so they are swapped for plain placeholders meanwhile.
+    let (markdown, tags) = protect_template_tags(markdown);
+    RenderedContent {
+        html: restore_template_tags(render_html(&markdown), &tags),
+        text: restore_template_tags(render_text(&markdown), &tags),
+    }
+}
+
+fn placeholder(index: usize) -> String {
+    format!("templatetag{}x", index)
+}
+
+fn protect_template_tags(markdown: &str) -> (String, Vec<String>) {
+    let mut protected = String::with_capacity(markdown.len());
+    let mut tags = vec![];
+    let mut rest = markdown;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}").map(|end| start + end + 2) else {
+            break;
+        };
+        protected.push_str(&rest[..start]);
+        protected.push_str(&placeholder(tags.len()));
+        tags.push(rest[start..end].to_string());
+        rest = &rest[end..];
+    }
+    protected.push_str(rest);
+    (protected, tags)
+}
+
+fn restore_template_tags(mut rendered: String, tags: &[String]) -> String {
+    for (index, tag) in tags.iter().enumerate() {
+        rendered = rendered.replace(&placeholder(index), tag);
+    }
+    rendered
+}
+
+fn render_html(markdown: &str) -> String {
+    let mut unsafe_html = String::new();
+    html::push_html(&mut unsafe_html, Parser::new_ext(markdown, options()));
+
+    // Raw HTML in the Markdown source goes through the same sanitizer, which also strips any
+    // `style` attribute written by the author before ours are added.
+    let mut sanitizer = ammonia::Builder::default();
+    for (tag, style) in INLINE_STYLES {
+        sanitizer.set_tag_attribute_value(tag, "style", style);
+    }
+    let safe_html = sanitizer.clean(&unsafe_html).to_string();
+    format!(r#"<div style="{}">{}</div>"#, BODY_STYLE, safe_html)
+}
+
+fn render_text(markdown: &str) -> String {
+    let mut renderer = TextRenderer::default();
+    for event in Parser::new_ext(markdown, options()) {
+        renderer.handle(event);
+    }
+    renderer.finish()
+}
+
+#[derive(Default)]
+struct TextRenderer {
+    // Block quotes and list items are rendered into their own buffer first, so that their
+    // lines can be prefixed once they are complete.
+    buffers: Vec<String>,
+    // The next number of each open list, `None` for bullet lists.
+    lists: Vec<Option<u64>>,
+    // Destination and buffer offset of each open link or image.
+    open_links: Vec<(CowStr<'static>, usize)>,
+    footnotes: Vec<String>,
+    in_code_block: bool,
+    first_cell: bool,
+    // Set between inline `<script>` or `<style>` tags, whose text the sanitizer drops too.
+    in_raw_block: bool,
+}
+
+impl TextRenderer {
+    fn out(&mut self) -> &mut String {
+        if self.buffers.is_empty() {
+            self.buffers.push(String::new());
+        }
+        self.buffers.last_mut().unwrap()
+    }
+
+    fn handle(&mut self, event: Event) {
+        match event {
+            Event::Start(Tag::BlockQuote(_)) | Event::Start(Tag::Item) => {
+                self.out();
+                self.buffers.push(String::new());
+            }
+            Event::End(TagEnd::BlockQuote(_)) => {
+                let quote = self.buffers.pop().unwrap_or_default();
+                let quote = prefix_lines(quote.trim_end(), "> ", "> ");
+                self.out().push_str(&format!("{}\n\n", quote));
+            }
+            Event::Start(Tag::List(start)) => self.lists.push(start),
+            Event::End(TagEnd::List(_)) => {
+                self.lists.pop();
+                if self.lists.is_empty() {
+                    self.out().push('\n');
+                }
+            }
+            Event::End(TagEnd::Item) => {
+                let item = self.buffers.pop().unwrap_or_default();
+                let marker = match self.lists.last_mut() {
+                    Some(Some(number)) => {
+                        *number += 1;
+                        format!("{}. ", *number - 1)
+                    }
+                    _ => "- ".to_string(),
+                };
+                let indent = " ".repeat(marker.len());
+                let item = prefix_lines(item.trim(), &marker, &indent);
+                self.out().push_str(&format!("{}\n", item));
+            }
+            Event::End(TagEnd::Paragraph) | Event::End(TagEnd::Heading(_)) => {
+                self.out().push_str("\n\n")
+            }
+            Event::Start(Tag::CodeBlock(_)) => self.in_code_block = true,
+            Event::End(TagEnd::CodeBlock) => {
+                self.in_code_block = false;
+                self.out().push('\n');
+            }
+            Event::Start(Tag::Link { dest_url, .. })
+            | Event::Start(Tag::Image { dest_url, .. }) => {
+                let offset = self.out().len();
+                self.open_links.push((dest_url.into_static(), offset));
+            }
+            Event::End(TagEnd::Link) | Event::End(TagEnd::Image) => {
+                if let Some((destination, offset)) = self.open_links.pop() {
+                    let label = self.out()[offset..].trim().to_string();
+                    // Autolinks already show their destination.
+                    if label != destination.as_ref() {
+                        let number = self.footnote_number(&destination);
+                        self.out().push_str(&format!(" [{}]", number));
+                    }
+                }
+            }
+            Event::Start(Tag::TableRow) | Event::Start(Tag::TableHead) => self.first_cell = true,
+            Event::End(TagEnd::TableRow) | Event::End(TagEnd::TableHead) => self.out().push('\n'),
+            Event::End(TagEnd::Table) => self.out().push('\n'),
+            Event::Start(Tag::TableCell) => {
+                if !self.first_cell {
+                    self.out().push_str(" | ");
+                }
+                self.first_cell = false;
+            }
+            Event::InlineHtml(html) => {
+                let html = html.to_ascii_lowercase();
+                if html.starts_with("<script") || html.starts_with("<style") {
+                    self.in_raw_block = true;
+                } else if html.starts_with("</script") || html.starts_with("</style") {
+                    self.in_raw_block = false;
+                }
+            }
+            Event::Text(_) if self.in_raw_block => {}
+            Event::Text(text) if self.in_code_block => {
+                let code = prefix_lines(text.trim_end_matches('\n'), "    ", "    ");
+                self.out().push_str(&format!("{}\n", code));
+            }
+            Event::Text(text) | Event::Code(text) => self.out().push_str(&text),
+            Event::SoftBreak | Event::HardBreak => self.out().push('\n'),
+            Event::Rule => self.out().push_str("----------\n\n"),
+            // Raw HTML has no sensible plain-text form.
+            _ => {}
+        }
+    }
+
+    // Links to the same destination share a footnote.
+    fn footnote_number(&mut self, destination: &str) -> usize {
+        match self.footnotes.iter().position(|d| d == destination) {
+            Some(index) => index + 1,
+            None => {
+                self.footnotes.push(destination.to_string());
+                self.footnotes.len()
+            }
+        }
+    }
+
+    fn finish(mut self) -> String {
+        let mut text = self.out().trim_end().to_string();
+        if !self.footnotes.is_empty() {
+            text.push_str("\n\n");
+            for (index, destination) in self.footnotes.iter().enumerate() {
+                text.push_str(&format!("[{}] {}\n", index + 1, destination));
+            }
+            text.truncate(text.trim_end().len());
+        }
+        text
+    }
+}
+
+fn prefix_lines(text: &str, first: &str, rest: &str) -> String {
+    text.lines()
+        .enumerate()
+        .map(|(i, line)| {
+            let prefix = if i == 0 { first } else { rest };
+            format!("{}{}", prefix, line).trim_end().to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_markdown;
+
+    #[test]
+    fn html_body_carries_inline_styles() {
+        let rendered = render_markdown("# Hello\n\nSome *text*.");
+        assert!(rendered.html.starts_with("<div style=\"font-family:"));
+        assert!(rendered.html.contains("<h1 style=\"margin: 0 0 16px;"));
+        assert!(rendered.html.contains("<em>text</em>"));
+    }
+
+    #[test]
+    fn tables_are_rendered() {
+        let rendered = render_markdown("| Plan | Price |\n|------|-------|\n| Pro | 10 |\n");
+        assert!(rendered.html.contains("<table style="));
+        assert!(rendered
+            .html
+            .contains("<td style=\"padding: 6px 12px; border: 1px solid #dddddd;\">Pro</td>"));
+        assert_eq!(rendered.text, "Plan | Price\nPro | 10");
+    }
+
+    #[test]
+    fn dangerous_html_is_removed() {
+        let rendered = render_markdown(
+            "Hi <script>alert(1)</script><a href=\"javascript:alert(1)\" onclick=\"x()\" style=\"color: red\">there</a>",
+        );
+        assert_eq!(rendered.text, "Hi there");
+        assert!(!rendered.html.contains("<script"));
+        assert!(!rendered.html.contains("javascript:"));
+        assert!(!rendered.html.contains("onclick"));
+        assert!(!rendered.html.contains("color: red"));
+    }
+
+    #[test]
+    fn links_become_footnotes_in_plain_text() {
+        let rendered = render_markdown(
+            "Read [the post](https://example.com/post) and [the docs](https://example.com/docs).\n\n\
+            Again: [the post](https://example.com/post), or <https://example.com/raw>.",
+        );
+        assert_eq!(
+            rendered.text,
+            "Read the post [1] and the docs [2].\n\n\
+            Again: the post [1], or https://example.com/raw.\n\n\
+            [1] https://example.com/post\n\
+            [2] https://example.com/docs"
+        );
+    }
+
+    #[test]
+    fn template_tags_are_left_untouched() {
+        let rendered =
+            render_markdown("Hi {{ subscriber.name }}, [unsubscribe]({{ unsubscribe_url }})");
+        assert!(rendered.html.contains("Hi {{ subscriber.name }}, "));
+        assert!(rendered.html.contains(r#"href="{{ unsubscribe_url }}""#));
+        assert_eq!(
+            rendered.text,
+            "Hi {{ subscriber.name }}, unsubscribe [1]\n\n[1] {{ unsubscribe_url }}"
+        );
+    }
+
+    #[test]
+    fn lists_quotes_and_code_are_readable_in_plain_text() {
+        let rendered = render_markdown(
+            "## News\n\n- one\n- two\n\n1. first\n2. second\n\n> quoted\n> text\n\n```\nlet x = 1;\n```\n",
+        );
+        assert_eq!(
+            rendered.text,
+            "News\n\n- one\n- two\n\n1. first\n2. second\n\n> quoted\n> text\n\n    let x = 1;"
+        );
+    }
+}
@@ -0,0 +1,185 @@
+use crate::db::PgPool;
+use crate::domain::IssueStatus;
+use crate::email_client::SendEmailError;
+use crate::schema::{newsletter_issues, subscriptions};
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use anyhow::Context;
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use once_cell::sync::Lazy;
+use prometheus::{
+    Encoder, HistogramOpts, HistogramVec, IntCounter, IntCounterVec, IntGaugeVec, Opts, Registry,
+    TextEncoder,
+};
+use std::time::Instant;
+
+// Every metric of the service, registered once per process.
+pub static METRICS: Lazy<Metrics> = Lazy::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+    http_requests: IntCounterVec,
+    http_request_duration: HistogramVec,
+    db_pool_connections: IntGaugeVec,
+    emails: IntCounterVec,
+    subscribers: IntGaugeVec,
+    queued_issues: IntGaugeVec,
+    login_failures: IntCounter,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new();
+        let http_requests = IntCounterVec::new(
+            Opts::new("http_requests_total", "HTTP requests handled."),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let http_request_duration = HistogramVec::new(
+            HistogramOpts::new(
+                "http_request_duration_seconds",
+                "Time taken to handle HTTP requests.",
+            ),
+            &["method", "route", "status"],
+        )
+        .unwrap();
+        let db_pool_connections = IntGaugeVec::new(
+            Opts::new("db_pool_connections", "Postgres pool connections by state."),
+            &["state"],
+        )
+        .unwrap();
+        let emails = IntCounterVec::new(
+            Opts::new("emails_total", "Emails handed to the email provider."),
+            &["provider", "outcome", "error_class"],
+        )
+        .unwrap();
+        let subscribers = IntGaugeVec::new(
+            Opts::new("subscribers", "Subscribers by status."),
+            &["status"],
+        )
+        .unwrap();
+        let queued_issues = IntGaugeVec::new(
+            Opts::new(
+                "newsletter_issues_queued",
+                "Newsletter issues waiting to be delivered, by status.",
+            ),
+            &["status"],
+        )
+        .unwrap();
+        let login_failures =
+            IntCounter::new("login_failures_total", "Failed login attempts.").unwrap();
+
+        registry.register(Box::new(http_requests.clone())).unwrap();
+        registry
+            .register(Box::new(http_request_duration.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(db_pool_connections.clone()))
+            .unwrap();
+        registry.register(Box::new(emails.clone())).unwrap();
+        registry.register(Box::new(subscribers.clone())).unwrap();
+        registry.register(Box::new(queued_issues.clone())).unwrap();
+        registry.register(Box::new(login_failures.clone())).unwrap();
+        Self {
+            registry,
+            http_requests,
+            http_request_duration,
+            db_pool_connections,
+            emails,
+            subscribers,
+            queued_issues,
+            login_failures,
+        }
+    }
+
+    pub fn record_emails(&self, provider: &str, outcome: Result<(), &SendEmailError>, count: u64) {
+        let (outcome, error_class) = match outcome {
+            Ok(()) => ("sent", ""),
+            Err(e) => ("failed", e.error_class()),
+        };
+        self.emails
+            .with_label_values(&[provider, outcome, error_class])
+            .inc_by(count);
+    }
+
+    pub fn record_login_failure(&self) {
+        self.login_failures.inc();
+    }
+
+    // Refreshes the gauges read from the database and renders every metric in the
+    // Prometheus text format.
+    pub fn render(&self, pool: &PgPool) -> Result<String, anyhow::Error> {
+        let state = pool.state();
+        self.db_pool_connections
+            .with_label_values(&["idle"])
+            .set(state.idle_connections.into());
+        self.db_pool_connections
+            .with_label_values(&["in_use"])
+            .set((state.connections - state.idle_connections).into());
+
+        let mut conn = pool
+            .get()
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        let subscribers = subscriptions::table
+            .group_by(subscriptions::status)
+            .select((subscriptions::status, count_star()))
+            .load::<(Option<String>, i64)>(&mut conn)
+            .context("Failed to count subscribers.")?;
+        self.subscribers.reset();
+        for (status, count) in subscribers {
+            self.subscribers
+                .with_label_values(&[status.as_deref().unwrap_or("unknown")])
+                .set(count);
+        }
+
+        let queued = [IssueStatus::Scheduled, IssueStatus::Sending].map(|s| s.as_str());
+        for status in queued {
+            self.queued_issues.with_label_values(&[status]).set(0);
+        }
+        let issues = newsletter_issues::table
+            .filter(newsletter_issues::status.eq_any(queued))
+            .group_by(newsletter_issues::status)
+            .select((newsletter_issues::status, count_star()))
+            .load::<(String, i64)>(&mut conn)
+            .context("Failed to count queued newsletter issues.")?;
+        for (status, count) in issues {
+            self.queued_issues.with_label_values(&[&status]).set(count);
+        }
+
+        let mut buffer = Vec::new();
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buffer)
+            .context("Failed to encode the metrics.")?;
+        String::from_utf8(buffer).context("The metrics are not valid UTF-8.")
+    }
+}
+
+// Counts and times every request, labelled by its route pattern rather than its path so
+// that ids do not create new series.
+pub async fn record_http_metrics(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let started = Instant::now();
+    let method = req.method().to_string();
+    let response = next.call(req).await;
+    let (route, status) = match &response {
+        Ok(response) => (
+            response
+                .request()
+                .match_pattern()
+                .unwrap_or_else(|| "unmatched".into()),
+            response.status().as_u16().to_string(),
+        ),
+        Err(_) => ("unmatched".into(), "500".into()),
+    };
+    let labels = [method.as_str(), route.as_str(), status.as_str()];
+    METRICS.http_requests.with_label_values(&labels).inc();
+    METRICS
+        .http_request_duration
+        .with_label_values(&labels)
+        .observe(started.elapsed().as_secs_f64());
+    response
+}
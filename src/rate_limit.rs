@@ -0,0 +1,418 @@
+use crate::utils::peek_body;
+use actix_web::body::{EitherBody, MessageBody};
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::http::header::{HeaderValue, RETRY_AFTER};
+use actix_web::middleware::Next;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use redis::aio::ConnectionManager;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::Duration;
+use tokio::sync::OnceCell;
+
+// The public routes that are rate limited. Their names are used in `RATE_LIMITS` and in the
+// Redis keys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LimitedRoute {
+    Subscribe,
+    ConfirmSubscription,
+    Login,
+}
+
+impl LimitedRoute {
+    const ALL: [LimitedRoute; 3] = [
+        LimitedRoute::Subscribe,
+        LimitedRoute::ConfirmSubscription,
+        LimitedRoute::Login,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitedRoute::Subscribe => "subscriptions",
+            LimitedRoute::ConfirmSubscription => "subscriptions_confirm",
+            LimitedRoute::Login => "login",
+        }
+    }
+}
+
+// What requests are counted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LimitKey {
+    Ip,
+    // The `email` field of the submitted form, so one address cannot be mailed from many IPs.
+    Email,
+}
+
+impl LimitKey {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LimitKey::Ip => "ip",
+            LimitKey::Email => "email",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limit {
+    pub requests: u64,
+    pub window: Duration,
+}
+
+impl Limit {
+    pub fn new(requests: u64, window: Duration) -> Self {
+        Self { requests, window }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimits {
+    limits: HashMap<(LimitedRoute, LimitKey), Limit>,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        let hour = Duration::from_secs(3600);
+        Self {
+            limits: HashMap::from([
+                (
+                    (LimitedRoute::Subscribe, LimitKey::Ip),
+                    Limit::new(10, hour),
+                ),
+                (
+                    (LimitedRoute::Subscribe, LimitKey::Email),
+                    Limit::new(3, hour),
+                ),
+                (
+                    (LimitedRoute::ConfirmSubscription, LimitKey::Ip),
+                    Limit::new(30, hour),
+                ),
+                (
+                    (LimitedRoute::Login, LimitKey::Ip),
+                    Limit::new(10, Duration::from_secs(300)),
+                ),
+            ]),
+        }
+    }
+}
+
+impl RateLimits {
+    // Replaces the limits listed in `overrides`, e.g. `subscriptions.ip=20/3600,login.ip=off`.
+    pub fn parse_overrides(mut self, overrides: &str) -> Result<Self, String> {
+        for entry in overrides
+            .split(',')
+            .map(str::trim)
+            .filter(|e| !e.is_empty())
+        {
+            let (name, limit) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("`{}` is not of the form <route>.<key>=<limit>", entry))?;
+            let (route, key) = name
+                .trim()
+                .split_once('.')
+                .ok_or_else(|| format!("`{}` is not of the form <route>.<key>", name))?;
+            let route = LimitedRoute::ALL
+                .into_iter()
+                .find(|r| r.as_str() == route)
+                .ok_or_else(|| format!("`{}` is not a rate limited route", route))?;
+            let key = [LimitKey::Ip, LimitKey::Email]
+                .into_iter()
+                .find(|k| k.as_str() == key)
+                .ok_or_else(|| format!("`{}` is not a rate limit key", key))?;
+            match limit.trim() {
+                "off" => {
+                    self.limits.remove(&(route, key));
+                }
+                limit => {
+                    let (requests, seconds) = limit
+                        .split_once('/')
+                        .and_then(|(r, s)| Some((r.parse().ok()?, s.parse().ok()?)))
+                        .ok_or_else(|| {
+                            format!("`{}` is not of the form <requests>/<seconds>", limit)
+                        })?;
+                    if seconds == 0 {
+                        return Err(format!("The window of `{}` must not be empty", entry));
+                    }
+                    self.limits.insert(
+                        (route, key),
+                        Limit::new(requests, Duration::from_secs(seconds)),
+                    );
+                }
+            }
+        }
+        Ok(self)
+    }
+
+    pub fn get(&self, route: LimitedRoute, key: LimitKey) -> Option<Limit> {
+        self.limits.get(&(route, key)).copied()
+    }
+}
+
+// Proxies whose `X-Forwarded-For` header is believed, e.g. `10.0.0.1,10.0.0.2`.
+pub fn parse_trusted_proxies(proxies: &str) -> Result<Vec<IpAddr>, String> {
+    proxies
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| {
+            p.parse()
+                .map_err(|_| format!("`{}` is not an IP address", p))
+        })
+        .collect()
+}
+
+// The address of the client. `X-Forwarded-For` is only read when the request came from a trusted
+// proxy; the right-most address not belonging to one of them is the client, since anything to
+// its left was sent by the client itself.
+pub fn client_ip(
+    peer: Option<IpAddr>,
+    forwarded_for: Option<&str>,
+    trusted_proxies: &[IpAddr],
+) -> Option<IpAddr> {
+    let peer = peer?;
+    if !trusted_proxies.contains(&peer) {
+        return Some(peer);
+    }
+    let Some(forwarded_for) = forwarded_for else {
+        return Some(peer);
+    };
+    let mut client = peer;
+    for address in forwarded_for.rsplit(',').map(str::trim) {
+        match address.parse::<IpAddr>() {
+            Ok(address) if trusted_proxies.contains(&address) => client = address,
+            Ok(address) => return Some(address),
+            Err(_) => break,
+        }
+    }
+    Some(client)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decision {
+    Allowed,
+    Limited { retry_after: Duration },
+}
+
+// Counts requests in fixed windows stored in Redis, so that every instance shares the counts.
+pub struct RateLimiter {
+    redis: redis::Client,
+    connection: OnceCell<ConnectionManager>,
+    limits: RateLimits,
+    trusted_proxies: Vec<IpAddr>,
+    key_prefix: String,
+}
+
+impl RateLimiter {
+    pub fn new(redis: redis::Client, limits: RateLimits, trusted_proxies: Vec<IpAddr>) -> Self {
+        Self {
+            redis,
+            connection: OnceCell::new(),
+            limits,
+            trusted_proxies,
+            key_prefix: "rate_limit".into(),
+        }
+    }
+
+    // Keeps the counters of separate deployments sharing a Redis apart.
+    pub fn with_key_prefix(mut self, key_prefix: impl ToString) -> Self {
+        self.key_prefix = key_prefix.to_string();
+        self
+    }
+
+    pub fn limits(&self) -> &RateLimits {
+        &self.limits
+    }
+
+    async fn connection(&self) -> Result<ConnectionManager, anyhow::Error> {
+        self.connection
+            .get_or_try_init(|| ConnectionManager::new(self.redis.clone()))
+            .await
+            .cloned()
+            .context("Failed to connect to Redis.")
+    }
+
+    // Counts one request against the limit for `value`.
+    #[tracing::instrument(name = "Check rate limit", skip(self, value), fields(route = route.as_str(), key = key.as_str()))]
+    pub async fn check(
+        &self,
+        route: LimitedRoute,
+        key: LimitKey,
+        value: &str,
+    ) -> Result<Decision, anyhow::Error> {
+        let Some(limit) = self.limits.get(route, key) else {
+            return Ok(Decision::Allowed);
+        };
+        let redis_key = format!(
+            "{}:{}:{}:{}",
+            self.key_prefix,
+            route.as_str(),
+            key.as_str(),
+            value
+        );
+        let mut conn = self.connection().await?;
+        // The first request of a window creates the counter with the window as its expiry.
+        redis::cmd("SET")
+            .arg(&redis_key)
+            .arg(0)
+            .arg("EX")
+            .arg(limit.window.as_secs())
+            .arg("NX")
+            .query_async::<Option<String>>(&mut conn)
+            .await
+            .context("Failed to start a rate limit window.")?;
+        let count = redis::cmd("INCR")
+            .arg(&redis_key)
+            .query_async::<u64>(&mut conn)
+            .await
+            .context("Failed to count a request.")?;
+        if count <= limit.requests {
+            return Ok(Decision::Allowed);
+        }
+        let ttl = redis::cmd("TTL")
+            .arg(&redis_key)
+            .query_async::<i64>(&mut conn)
+            .await
+            .context("Failed to read the end of a rate limit window.")?;
+        let retry_after = if ttl > 0 {
+            Duration::from_secs(ttl as u64)
+        } else {
+            limit.window
+        };
+        Ok(Decision::Limited { retry_after })
+    }
+}
+
+// Reads the `email` field of a form body.
+fn form_email(body: &[u8]) -> Option<String> {
+    #[derive(serde::Deserialize)]
+    struct EmailField {
+        email: String,
+    }
+    let field: EmailField = serde_urlencoded::from_bytes(body).ok()?;
+    Some(field.email.trim().to_lowercase())
+}
+
+fn too_many_requests(retry_after: Duration) -> HttpResponse {
+    // A client retrying within the same second would still be limited.
+    let seconds = retry_after.as_secs().max(1);
+    HttpResponse::TooManyRequests()
+        .insert_header((RETRY_AFTER, HeaderValue::from(seconds)))
+        .body("Too many requests, please try again later.")
+}
+
+// Rejects the request with 429 Too Many Requests once the client's IP, or the email address it
+// submitted, went over the limits of `route`. Requests are let through when Redis cannot be
+// reached, since rejecting every signup would be worse than not limiting them for a while.
+pub async fn enforce_rate_limit(
+    route: LimitedRoute,
+    mut req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<EitherBody<impl MessageBody>>, actix_web::Error> {
+    let Some(limiter) = req.app_data::<web::Data<RateLimiter>>().cloned() else {
+        return next.call(req).await.map(|r| r.map_into_left_body());
+    };
+
+    let mut checks = vec![];
+    let peer = req.peer_addr().map(|address| address.ip());
+    let forwarded_for = req
+        .headers()
+        .get("X-Forwarded-For")
+        .and_then(|v| v.to_str().ok());
+    if let Some(ip) = client_ip(peer, forwarded_for, &limiter.trusted_proxies) {
+        checks.push((LimitKey::Ip, ip.to_string()));
+    }
+    if limiter.limits().get(route, LimitKey::Email).is_some() {
+        let body = peek_body(&mut req).await?;
+        if let Some(email) = form_email(&body) {
+            checks.push((LimitKey::Email, email));
+        }
+    }
+
+    for (key, value) in checks {
+        match limiter.check(route, key, &value).await {
+            Ok(Decision::Allowed) => {}
+            Ok(Decision::Limited { retry_after }) => {
+                tracing::warn!(
+                    route = route.as_str(),
+                    key = key.as_str(),
+                    "Rejected a request over the rate limit"
+                );
+                let response = too_many_requests(retry_after);
+                return Ok(req.into_response(response).map_into_right_body());
+            }
+            Err(e) => {
+                tracing::warn!(
+                    error.cause_chain = ?e,
+                    "Failed to check the rate limit, letting the request through"
+                );
+                break;
+            }
+        }
+    }
+    next.call(req).await.map(|r| r.map_into_left_body())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(address: &str) -> IpAddr {
+        address.parse().unwrap()
+    }
+
+    #[test]
+    fn forwarded_for_is_ignored_unless_the_peer_is_a_trusted_proxy() {
+        let client = client_ip(Some(ip("203.0.113.7")), Some("198.51.100.1"), &[]);
+        assert_eq!(client, Some(ip("203.0.113.7")));
+    }
+
+    #[test]
+    fn the_right_most_untrusted_forwarded_address_is_the_client() {
+        let proxies = [ip("10.0.0.1"), ip("10.0.0.2")];
+        // The left-most entry was made up by the client.
+        let forwarded_for = "1.2.3.4, 198.51.100.1, 10.0.0.2";
+        let client = client_ip(Some(ip("10.0.0.1")), Some(forwarded_for), &proxies);
+        assert_eq!(client, Some(ip("198.51.100.1")));
+    }
+
+    #[test]
+    fn a_request_from_a_trusted_proxy_without_forwarded_for_uses_the_proxy() {
+        let proxies = [ip("10.0.0.1")];
+        let client = client_ip(Some(ip("10.0.0.1")), None, &proxies);
+        assert_eq!(client, Some(ip("10.0.0.1")));
+    }
+
+    #[test]
+    fn limits_can_be_overridden_and_turned_off() {
+        let limits = RateLimits::default()
+            .parse_overrides("subscriptions.ip=20/60, login.ip=off")
+            .unwrap();
+        assert_eq!(
+            limits.get(LimitedRoute::Subscribe, LimitKey::Ip),
+            Some(Limit::new(20, Duration::from_secs(60)))
+        );
+        assert_eq!(limits.get(LimitedRoute::Login, LimitKey::Ip), None);
+        assert!(RateLimits::default()
+            .parse_overrides("newsletters.ip=1/60")
+            .is_err());
+        assert!(RateLimits::default()
+            .parse_overrides("login.ip=ten")
+            .is_err());
+    }
+
+    #[test]
+    fn trusted_proxies_are_a_list_of_addresses() {
+        assert_eq!(
+            parse_trusted_proxies("10.0.0.1, ::1,"),
+            Ok(vec![ip("10.0.0.1"), ip("::1")])
+        );
+        assert!(parse_trusted_proxies("10.0.0.1,proxy").is_err());
+    }
+
+    #[test]
+    fn the_email_is_read_from_the_form() {
+        let body = b"name=le%20guin&email=Ursula%40Example.com";
+        assert_eq!(form_email(body), Some("ursula@example.com".into()));
+        assert_eq!(form_email(b"name=le%20guin"), None);
+    }
+}
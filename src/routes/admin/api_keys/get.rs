@@ -0,0 +1,32 @@
+use crate::api_keys::{list_api_keys, ApiScope};
+use crate::csrf::CsrfToken;
+use crate::db::PgPool;
+use crate::db_models::ApiKey;
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+
+#[derive(Template)]
+#[template(path = "admin/api_keys/list.html")]
+struct ApiKeysPage<'a> {
+    flash_messages: &'a IncomingFlashMessages,
+    csrf_token: &'a str,
+    api_keys: Vec<ApiKey>,
+    scopes: [ApiScope; 5],
+}
+
+pub async fn api_keys_list(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = pool.get().map_err(e500)?;
+    let api_keys = list_api_keys(&mut conn).map_err(e500)?;
+    render_page(&ApiKeysPage {
+        flash_messages: &flash_messages,
+        csrf_token: csrf_token.as_str(),
+        api_keys,
+        scopes: ApiScope::ALL,
+    })
+}
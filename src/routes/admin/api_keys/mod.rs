@@ -0,0 +1,10 @@
+pub mod get;
+pub mod post;
+
+use serde::Deserialize;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct ApiKeyPath {
+    api_key_id: Uuid,
+}
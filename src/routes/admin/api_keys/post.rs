@@ -0,0 +1,88 @@
+use super::ApiKeyPath;
+use crate::api_keys::{self, ApiScope};
+use crate::clock::Clock;
+use crate::csrf::CsrfToken;
+use crate::db::PgPool;
+use crate::middleware::UserId;
+use crate::utils::{e500, render_page, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama::Template;
+use secrecy::ExposeSecret;
+
+// The form has a checkbox per scope, all named `scope`, which `web::Form` can only read as pairs.
+fn parse_form(fields: &[(String, String)]) -> Result<(String, Vec<ApiScope>), String> {
+    let name = fields
+        .iter()
+        .find(|(field, _)| field == "name")
+        .map(|(_, value)| value.trim().to_string())
+        .unwrap_or_default();
+    if name.is_empty() {
+        return Err("An API key needs a name.".to_string());
+    }
+    let scopes = fields
+        .iter()
+        .filter(|(field, _)| field == "scope")
+        .map(|(_, value)| ApiScope::parse(value))
+        .collect::<Result<Vec<_>, _>>()?;
+    if scopes.is_empty() {
+        return Err("An API key needs at least one scope.".to_string());
+    }
+    Ok((name, scopes))
+}
+
+#[derive(Template)]
+#[template(path = "admin/api_keys/created.html")]
+struct ApiKeyCreatedPage<'a> {
+    flash_messages: &'a IncomingFlashMessages,
+    csrf_token: &'a str,
+    name: &'a str,
+    key: &'a str,
+}
+
+// The new key is shown on the page answering the form rather than after a redirect, so that
+// it never ends up in a flash message cookie.
+#[tracing::instrument(
+    name = "Create an API key",
+    skip(form, pool, clock, flash_messages, csrf_token)
+)]
+pub async fn create_api_key(
+    form: web::Form<Vec<(String, String)>>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+    user_id: web::ReqData<UserId>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let (name, scopes) = match parse_form(&form) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/api_keys"));
+        }
+    };
+    let mut conn = pool.get().map_err(e500)?;
+    let key = api_keys::create_api_key(&mut conn, &name, &scopes, **user_id, clock.now())
+        .map_err(e500)?;
+    render_page(&ApiKeyCreatedPage {
+        flash_messages: &flash_messages,
+        csrf_token: csrf_token.as_str(),
+        name: &name,
+        key: key.expose_secret(),
+    })
+}
+
+#[tracing::instrument(name = "Revoke an API key", skip(path, pool, clock), fields(api_key_id = %path.api_key_id))]
+pub async fn revoke_api_key(
+    path: web::Path<ApiKeyPath>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = pool.get().map_err(e500)?;
+    if api_keys::revoke_api_key(&mut conn, path.api_key_id, clock.now()).map_err(e500)? {
+        FlashMessage::info("The API key has been revoked.").send();
+    } else {
+        FlashMessage::error("There is no such API key, or it was revoked already.").send();
+    }
+    Ok(see_other("/admin/api_keys"))
+}
@@ -1,54 +1,39 @@
+use crate::csrf::CsrfToken;
 use crate::db::PgPool;
 use crate::db_models::User;
 use crate::schema::users::dsl::*;
 use crate::session_state::TypedSession;
-use crate::utils::e500;
-use actix_web::http::header::{ContentType, LOCATION};
-use actix_web::web;
-use actix_web::{Error as ActixError, HttpResponse};
-use anyhow::Context;
+use crate::utils::{e500, render_page, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
 use diesel::prelude::*;
-use diesel::r2d2::{self, ConnectionManager};
-use diesel::PgConnection;
 use uuid::Uuid;
 
+#[derive(Template)]
+#[template(path = "admin/dashboard.html")]
+struct DashboardPage<'a> {
+    flash_messages: &'a IncomingFlashMessages,
+    csrf_token: &'a str,
+    username: &'a str,
+}
+
 pub async fn admin_dashboard(
     session: TypedSession,
     pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_name = if let Some(id_user) = session.get_user_id().map_err(e500)? {
         get_username(id_user, &pool).await.map_err(e500)?
     } else {
-        return Ok(HttpResponse::SeeOther()
-            .insert_header((LOCATION, "/login"))
-            .finish());
+        return Ok(see_other("/login"));
     };
-    Ok(HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
-            <html lang="en">
-                <head>
-                <meta http-equiv="content-type" content="text/html; charset=utf-8">
-                <title>Admin dashboard</title>
-                </head>
-            <body>
-                <p>Welcome {user_name}!</p>
-                <p>Available actions:</p>
-                <ol>
-                    <li><a href="/admin/password">Change password</a></li>
-                </ol>
-                <ol>
-                    <li><a href="/admin/password">Change password</a></li>
-                    <li>
-                    <form name="logoutForm" action="/admin/logout" method="post">
-                    <input type="submit" value="Logout">
-                    </form>
-                    </li>
-                </ol>
-            </body>
-            </html>"#
-        )))
+    render_page(&DashboardPage {
+        flash_messages: &flash_messages,
+        csrf_token: csrf_token.as_str(),
+        username: &user_name,
+    })
 }
 #[tracing::instrument(name = "Get username", skip(pool))]
 pub async fn get_username(id_user: Uuid, pool: &PgPool) -> Result<String, anyhow::Error> {
@@ -0,0 +1,102 @@
+use crate::csrf::CsrfToken;
+use crate::db::PgPool;
+use crate::db_models::EmailDelivery;
+use crate::delivery_log::{search_deliveries, DeliverySearch};
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use serde::Deserialize;
+use uuid::Uuid;
+
+const MAX_RESULTS: i64 = 100;
+
+#[derive(Deserialize)]
+pub struct SearchParameters {
+    #[serde(default)]
+    email: String,
+    #[serde(default)]
+    newsletter_issue_id: String,
+    #[serde(default)]
+    message_id: String,
+}
+
+fn non_empty(value: &str) -> Option<&str> {
+    Some(value.trim()).filter(|v| !v.is_empty())
+}
+
+struct DeliveryRow {
+    delivery: EmailDelivery,
+    title: String,
+    error: String,
+}
+
+#[derive(Template)]
+#[template(path = "admin/deliveries.html")]
+struct DeliveriesPage<'a> {
+    flash_messages: &'a IncomingFlashMessages,
+    csrf_token: &'a str,
+    parameters: &'a SearchParameters,
+    invalid_issue_id: Option<&'a str>,
+    // `None` until something is searched for.
+    deliveries: Option<Vec<DeliveryRow>>,
+}
+
+// Answers questions such as "did alice@example.com get this issue, and what happened".
+pub async fn deliveries_search(
+    parameters: web::Query<SearchParameters>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut invalid_issue_id = None;
+    let newsletter_issue_id = match non_empty(&parameters.newsletter_issue_id) {
+        None => None,
+        Some(id) => match Uuid::parse_str(id) {
+            Ok(id) => Some(id),
+            Err(_) => {
+                invalid_issue_id = Some(id);
+                None
+            }
+        },
+    };
+    let search = DeliverySearch {
+        email: non_empty(&parameters.email),
+        newsletter_issue_id,
+        provider_message_id: non_empty(&parameters.message_id),
+    };
+
+    let searched = search.email.is_some()
+        || search.newsletter_issue_id.is_some()
+        || search.provider_message_id.is_some();
+    let deliveries = if searched {
+        let mut conn = pool.get().map_err(e500)?;
+        let deliveries = search_deliveries(&mut conn, &search, MAX_RESULTS).map_err(e500)?;
+        let rows = deliveries
+            .into_iter()
+            .map(|(delivery, title)| {
+                let error = match (&delivery.error_code, &delivery.error_message) {
+                    (Some(code), Some(message)) => format!("{}: {}", code, message),
+                    (Some(code), None) => code.clone(),
+                    _ => String::new(),
+                };
+                DeliveryRow {
+                    delivery,
+                    title,
+                    error,
+                }
+            })
+            .collect();
+        Some(rows)
+    } else {
+        None
+    };
+
+    render_page(&DeliveriesPage {
+        flash_messages: &flash_messages,
+        csrf_token: csrf_token.as_str(),
+        parameters: &parameters,
+        invalid_issue_id,
+        deliveries,
+    })
+}
@@ -0,0 +1,143 @@
+use super::revisions::{get_draft, get_revision, list_drafts, list_revisions};
+use super::DraftPath;
+use crate::csrf::CsrfToken;
+use crate::db::PgPool;
+use crate::db_models::{IssueRevision, NewsletterIssue};
+use crate::middleware::UserId;
+use crate::schema::users;
+use crate::utils::{e500, render_page, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama::Template;
+use diesel::prelude::*;
+use serde::Deserialize;
+use similar::{ChangeTag, TextDiff};
+use std::fmt::Write;
+
+#[derive(Deserialize)]
+pub struct DiffParameters {
+    from: i32,
+    to: i32,
+}
+
+#[derive(Template)]
+#[template(path = "admin/drafts.html")]
+struct DraftsPage<'a> {
+    flash_messages: &'a IncomingFlashMessages,
+    csrf_token: &'a str,
+    drafts: Vec<NewsletterIssue>,
+}
+
+pub async fn drafts_list(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = pool.get().map_err(e500)?;
+    let drafts = list_drafts(&mut conn).map_err(e500)?;
+    render_page(&DraftsPage {
+        flash_messages: &flash_messages,
+        csrf_token: csrf_token.as_str(),
+        drafts,
+    })
+}
+
+#[derive(Template)]
+#[template(path = "admin/draft.html")]
+struct DraftPage<'a> {
+    flash_messages: &'a IncomingFlashMessages,
+    csrf_token: &'a str,
+    draft: NewsletterIssue,
+    revisions: Vec<IssueRevision>,
+    latest: i32,
+    // Where the admin last sent a test email.
+    test_recipient: String,
+}
+
+pub async fn draft_form(
+    path: web::Path<DraftPath>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = pool.get().map_err(e500)?;
+    let Some(draft) = get_draft(&mut conn, path.newsletter_issue_id).map_err(e500)? else {
+        FlashMessage::error("There is no such draft.").send();
+        return Ok(see_other("/admin/drafts"));
+    };
+    let revisions = list_revisions(&mut conn, draft.newsletter_issue_id).map_err(e500)?;
+    let latest = revisions.first().map(|r| r.revision_number).unwrap_or(1);
+    let test_recipient = users::table
+        .find(**user_id)
+        .select(users::email)
+        .first::<Option<String>>(&mut conn)
+        .map_err(e500)?;
+    render_page(&DraftPage {
+        flash_messages: &flash_messages,
+        csrf_token: csrf_token.as_str(),
+        draft,
+        revisions,
+        latest,
+        test_recipient: test_recipient.unwrap_or_default(),
+    })
+}
+
+// A line-based diff, prefixing added lines with `+` and removed lines with `-`.
+fn diff_lines(old: &str, new: &str) -> String {
+    let mut diff = String::new();
+    for change in TextDiff::from_lines(old, new).iter_all_changes() {
+        let sign = match change.tag() {
+            ChangeTag::Delete => "-",
+            ChangeTag::Insert => "+",
+            ChangeTag::Equal => " ",
+        };
+        let line = change.value().trim_end_matches('\n');
+        writeln!(diff, "{}{}", sign, line).unwrap();
+    }
+    diff
+}
+
+#[derive(Template)]
+#[template(path = "admin/draft_diff.html")]
+struct DraftDiffPage<'a> {
+    flash_messages: &'a IncomingFlashMessages,
+    csrf_token: &'a str,
+    from: IssueRevision,
+    to: IssueRevision,
+    title_diff: String,
+    markdown_diff: String,
+    html_diff: String,
+    text_diff: String,
+}
+
+pub async fn draft_diff(
+    path: web::Path<DraftPath>,
+    parameters: web::Query<DiffParameters>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let id = path.newsletter_issue_id;
+    let mut conn = pool.get().map_err(e500)?;
+    let from = get_revision(&mut conn, id, parameters.from).map_err(e500)?;
+    let to = get_revision(&mut conn, id, parameters.to).map_err(e500)?;
+    let (Some(from), Some(to)) = (from, to) else {
+        FlashMessage::error("There is no such revision.").send();
+        return Ok(see_other(&format!("/admin/drafts/{}", id)));
+    };
+
+    render_page(&DraftDiffPage {
+        flash_messages: &flash_messages,
+        csrf_token: csrf_token.as_str(),
+        title_diff: diff_lines(&from.title, &to.title),
+        markdown_diff: diff_lines(
+            from.markdown_content.as_deref().unwrap_or_default(),
+            to.markdown_content.as_deref().unwrap_or_default(),
+        ),
+        html_diff: diff_lines(&from.html_content, &to.html_content),
+        text_diff: diff_lines(&from.text_content, &to.text_content),
+        from,
+        to,
+    })
+}
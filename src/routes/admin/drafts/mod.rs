@@ -0,0 +1,11 @@
+pub mod get;
+pub mod post;
+mod revisions;
+
+use serde::Deserialize;
+use uuid::Uuid;
+
+#[derive(Deserialize)]
+pub struct DraftPath {
+    newsletter_issue_id: Uuid,
+}
@@ -0,0 +1,233 @@
+use super::revisions::{
+    create_draft, get_draft, get_revision, save_revision, set_issue_settings, DraftContent,
+};
+use super::DraftPath;
+use crate::clock::Clock;
+use crate::db::PgPool;
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::email_templates::load_layout;
+use crate::issue_delivery::{unsubscribe_url, IssueTemplate, MergeFields};
+use crate::markdown::{render_markdown, RenderedContent};
+use crate::middleware::UserId;
+use crate::schema::users;
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    title: String,
+    #[serde(default)]
+    html_content: String,
+    #[serde(default)]
+    text_content: String,
+    // When filled in, the HTML and plain-text content are rendered from it instead.
+    #[serde(default)]
+    markdown_content: String,
+    // Checkboxes are only submitted when checked.
+    tracking: Option<String>,
+    hide_from_archive: Option<String>,
+}
+
+#[derive(serde::Deserialize)]
+pub struct RestoreFormData {
+    revision_number: i32,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TestEmailFormData {
+    recipient: String,
+}
+
+impl FormData {
+    fn markdown(&self) -> Option<&str> {
+        Some(self.markdown_content.as_str()).filter(|m| !m.trim().is_empty())
+    }
+
+    fn render(&self) -> RenderedContent {
+        match self.markdown() {
+            Some(markdown) => render_markdown(markdown),
+            None => RenderedContent {
+                html: self.html_content.clone(),
+                text: self.text_content.clone(),
+            },
+        }
+    }
+
+    fn content<'a>(&'a self, rendered: &'a RenderedContent) -> DraftContent<'a> {
+        DraftContent {
+            title: &self.title,
+            text_content: &rendered.text,
+            html_content: &rendered.html,
+            markdown_content: self.markdown(),
+        }
+    }
+
+    fn save_settings(
+        &self,
+        conn: &mut PgConnection,
+        newsletter_issue_id: Uuid,
+    ) -> Result<usize, diesel::result::Error> {
+        set_issue_settings(
+            conn,
+            newsletter_issue_id,
+            self.tracking.is_some(),
+            self.hide_from_archive.is_none(),
+        )
+    }
+}
+
+pub async fn create_draft_issue(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if form.title.trim().is_empty() {
+        FlashMessage::error("A draft needs a title.").send();
+        return Ok(see_other("/admin/drafts"));
+    }
+    let rendered = form.render();
+    let mut conn = pool.get().map_err(e500)?;
+    let newsletter_issue_id =
+        create_draft(&mut conn, **user_id, clock.now(), &form.content(&rendered)).map_err(e500)?;
+    form.save_settings(&mut conn, newsletter_issue_id)
+        .map_err(e500)?;
+    FlashMessage::info("The draft has been created.").send();
+    Ok(see_other(&format!("/admin/drafts/{}", newsletter_issue_id)))
+}
+
+pub async fn save_draft(
+    path: web::Path<DraftPath>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let id = path.newsletter_issue_id;
+    if form.title.trim().is_empty() {
+        FlashMessage::error("A draft needs a title.").send();
+        return Ok(see_other(&format!("/admin/drafts/{}", id)));
+    }
+    let rendered = form.render();
+    let mut conn = pool.get().map_err(e500)?;
+    let content = form.content(&rendered);
+    match save_revision(&mut conn, id, **user_id, clock.now(), &content).map_err(e500)? {
+        Some(revision_number) => {
+            form.save_settings(&mut conn, id).map_err(e500)?;
+            FlashMessage::info(format!(
+                "The draft has been saved as revision {}.",
+                revision_number
+            ))
+            .send();
+            Ok(see_other(&format!("/admin/drafts/{}", id)))
+        }
+        None => {
+            FlashMessage::error("There is no such draft.").send();
+            Ok(see_other("/admin/drafts"))
+        }
+    }
+}
+
+// Restoring never rewrites history: the old content is saved again as a new revision.
+pub async fn restore_draft_revision(
+    path: web::Path<DraftPath>,
+    form: web::Form<RestoreFormData>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let id = path.newsletter_issue_id;
+    let mut conn = pool.get().map_err(e500)?;
+    let Some(revision) = get_revision(&mut conn, id, form.revision_number).map_err(e500)? else {
+        FlashMessage::error("There is no such revision.").send();
+        return Ok(see_other(&format!("/admin/drafts/{}", id)));
+    };
+    let content = DraftContent {
+        title: &revision.title,
+        text_content: &revision.text_content,
+        html_content: &revision.html_content,
+        markdown_content: revision.markdown_content.as_deref(),
+    };
+    match save_revision(&mut conn, id, **user_id, clock.now(), &content).map_err(e500)? {
+        Some(revision_number) => {
+            FlashMessage::info(format!(
+                "Revision {} has been restored as revision {}.",
+                revision.revision_number, revision_number
+            ))
+            .send();
+            Ok(see_other(&format!("/admin/drafts/{}", id)))
+        }
+        None => {
+            FlashMessage::error("There is no such draft.").send();
+            Ok(see_other("/admin/drafts"))
+        }
+    }
+}
+
+// Merge fields are filled in with the admin's username and the recipient, and the unsubscribe
+// link points to a token that does not exist. The recipient is remembered as the admin's email,
+// to fill in the form next time.
+#[tracing::instrument(
+    name = "Send a test email of a draft",
+    skip(path, form, pool, email_client, application_base_url, user_id)
+)]
+pub async fn send_draft_test_email(
+    path: web::Path<DraftPath>,
+    form: web::Form<TestEmailFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    application_base_url: web::Data<ApplicationBaseUrl>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let id = path.newsletter_issue_id;
+    let mut conn = pool.get().map_err(e500)?;
+    let Some(draft) = get_draft(&mut conn, id).map_err(e500)? else {
+        FlashMessage::error("There is no such draft.").send();
+        return Ok(see_other("/admin/drafts"));
+    };
+    let admin_email = match SubscriberEmail::parse(form.0.recipient) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&format!("/admin/drafts/{}", id)));
+        }
+    };
+    let username = diesel::update(users::table.find(**user_id))
+        .set(users::email.eq(Some(admin_email.as_ref())))
+        .returning(users::username)
+        .get_result::<String>(&mut conn)
+        .map_err(e500)?;
+    let template = match IssueTemplate::parse(&draft.html_content, &draft.text_content) {
+        Ok(template) => template,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&format!("/admin/drafts/{}", id)));
+        }
+    };
+    let unsubscribe_url = unsubscribe_url(&application_base_url.0, "test", None);
+    let content = template.render(&MergeFields {
+        subscriber_name: &username,
+        subscriber_email: admin_email.as_ref(),
+        unsubscribe_url: &unsubscribe_url,
+        issue_title: &draft.title,
+    });
+    let layout = load_layout(&mut conn, &draft.layout).map_err(e500)?;
+    let content = layout.render(&draft.title, &content, Some(&unsubscribe_url));
+
+    email_client
+        .send_email(
+            &admin_email,
+            &format!("[Test] {}", draft.title),
+            &content.html,
+            &content.text,
+        )
+        .await
+        .map_err(e500)?;
+    FlashMessage::info(format!("A test email has been sent to {}.", admin_email)).send();
+    Ok(see_other(&format!("/admin/drafts/{}", id)))
+}
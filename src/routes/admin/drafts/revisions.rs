@@ -0,0 +1,164 @@
+use crate::db_models::{IssueRevision, NewsletterIssue};
+use crate::domain::IssueStatus;
+use crate::schema::{issue_revisions, newsletter_issues};
+use chrono::{DateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+pub struct DraftContent<'a> {
+    pub title: &'a str,
+    pub text_content: &'a str,
+    pub html_content: &'a str,
+    pub markdown_content: Option<&'a str>,
+}
+
+#[tracing::instrument(name = "Create a draft issue", skip(conn, content))]
+pub fn create_draft(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    now: DateTime<Utc>,
+    content: &DraftContent,
+) -> Result<Uuid, diesel::result::Error> {
+    let newsletter_issue_id = Uuid::new_v4();
+    conn.transaction(|conn| {
+        diesel::insert_into(newsletter_issues::table)
+            .values((
+                newsletter_issues::newsletter_issue_id.eq(newsletter_issue_id),
+                newsletter_issues::title.eq(content.title),
+                newsletter_issues::text_content.eq(content.text_content),
+                newsletter_issues::html_content.eq(content.html_content),
+                newsletter_issues::markdown_content.eq(content.markdown_content),
+                newsletter_issues::status.eq(IssueStatus::Draft.as_str()),
+                newsletter_issues::created_at.eq(now),
+            ))
+            .execute(conn)?;
+        insert_revision(conn, newsletter_issue_id, 1, user_id, now, content)?;
+        Ok(newsletter_issue_id)
+    })
+}
+
+// Stores `content` as the next revision of the draft and makes it the current content.
+// Returns `None` if there is no draft with this id (e.g. it has been scheduled already).
+#[tracing::instrument(name = "Save a draft revision", skip(conn, content))]
+pub fn save_revision(
+    conn: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+    user_id: Uuid,
+    now: DateTime<Utc>,
+    content: &DraftContent,
+) -> Result<Option<i32>, diesel::result::Error> {
+    conn.transaction(|conn| {
+        // Locking the issue serialises concurrent saves, so revision numbers can't clash.
+        let draft = newsletter_issues::table
+            .find(newsletter_issue_id)
+            .filter(newsletter_issues::status.eq(IssueStatus::Draft.as_str()))
+            .select(newsletter_issues::newsletter_issue_id)
+            .for_update()
+            .first::<Uuid>(conn)
+            .optional()?;
+        if draft.is_none() {
+            return Ok(None);
+        }
+        let last_revision = issue_revisions::table
+            .filter(issue_revisions::newsletter_issue_id.eq(newsletter_issue_id))
+            .select(diesel::dsl::max(issue_revisions::revision_number))
+            .first::<Option<i32>>(conn)?
+            .unwrap_or(0);
+        let revision_number = last_revision + 1;
+        insert_revision(
+            conn,
+            newsletter_issue_id,
+            revision_number,
+            user_id,
+            now,
+            content,
+        )?;
+        diesel::update(newsletter_issues::table.find(newsletter_issue_id))
+            .set((
+                newsletter_issues::title.eq(content.title),
+                newsletter_issues::text_content.eq(content.text_content),
+                newsletter_issues::html_content.eq(content.html_content),
+                newsletter_issues::markdown_content.eq(content.markdown_content),
+            ))
+            .execute(conn)?;
+        Ok(Some(revision_number))
+    })
+}
+
+// Tracking and archiving are settings of the issue rather than part of its content, so they have
+// no revisions.
+pub fn set_issue_settings(
+    conn: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+    tracking_enabled: bool,
+    in_archive: bool,
+) -> Result<usize, diesel::result::Error> {
+    diesel::update(newsletter_issues::table.find(newsletter_issue_id))
+        .set((
+            newsletter_issues::tracking_enabled.eq(tracking_enabled),
+            newsletter_issues::in_archive.eq(in_archive),
+        ))
+        .execute(conn)
+}
+
+fn insert_revision(
+    conn: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+    revision_number: i32,
+    user_id: Uuid,
+    now: DateTime<Utc>,
+    content: &DraftContent,
+) -> Result<usize, diesel::result::Error> {
+    diesel::insert_into(issue_revisions::table)
+        .values((
+            issue_revisions::newsletter_issue_id.eq(newsletter_issue_id),
+            issue_revisions::revision_number.eq(revision_number),
+            issue_revisions::title.eq(content.title),
+            issue_revisions::text_content.eq(content.text_content),
+            issue_revisions::html_content.eq(content.html_content),
+            issue_revisions::markdown_content.eq(content.markdown_content),
+            issue_revisions::created_by.eq(user_id),
+            issue_revisions::created_at.eq(now),
+        ))
+        .execute(conn)
+}
+
+pub fn get_draft(
+    conn: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<NewsletterIssue>, diesel::result::Error> {
+    newsletter_issues::table
+        .find(newsletter_issue_id)
+        .filter(newsletter_issues::status.eq(IssueStatus::Draft.as_str()))
+        .first::<NewsletterIssue>(conn)
+        .optional()
+}
+
+pub fn list_drafts(conn: &mut PgConnection) -> Result<Vec<NewsletterIssue>, diesel::result::Error> {
+    newsletter_issues::table
+        .filter(newsletter_issues::status.eq(IssueStatus::Draft.as_str()))
+        .order(newsletter_issues::created_at.desc())
+        .load::<NewsletterIssue>(conn)
+}
+
+pub fn list_revisions(
+    conn: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+) -> Result<Vec<IssueRevision>, diesel::result::Error> {
+    issue_revisions::table
+        .filter(issue_revisions::newsletter_issue_id.eq(newsletter_issue_id))
+        .order(issue_revisions::revision_number.desc())
+        .load::<IssueRevision>(conn)
+}
+
+pub fn get_revision(
+    conn: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+    revision_number: i32,
+) -> Result<Option<IssueRevision>, diesel::result::Error> {
+    issue_revisions::table
+        .find((newsletter_issue_id, revision_number))
+        .first::<IssueRevision>(conn)
+        .optional()
+}
@@ -0,0 +1,72 @@
+use super::IssuePath;
+use crate::csrf::CsrfToken;
+use crate::db::PgPool;
+use crate::db_models::NewsletterIssue;
+use crate::domain::IssueStatus;
+use crate::schema::newsletter_issues;
+use crate::tracking::{self, IssueStats};
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use diesel::prelude::*;
+
+#[derive(Template)]
+#[template(path = "admin/issues/list.html")]
+struct IssuesPage<'a> {
+    flash_messages: &'a IncomingFlashMessages,
+    csrf_token: &'a str,
+    issues: Vec<NewsletterIssue>,
+}
+
+pub async fn issues_list(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = pool.get().map_err(e500)?;
+    let issues = newsletter_issues::table
+        .filter(newsletter_issues::status.ne(IssueStatus::Draft.as_str()))
+        .order(newsletter_issues::created_at.desc())
+        .load::<NewsletterIssue>(&mut conn)
+        .map_err(e500)?;
+    render_page(&IssuesPage {
+        flash_messages: &flash_messages,
+        csrf_token: csrf_token.as_str(),
+        issues,
+    })
+}
+
+#[derive(Template)]
+#[template(path = "admin/issues/stats.html")]
+struct IssueStatsPage<'a> {
+    flash_messages: &'a IncomingFlashMessages,
+    csrf_token: &'a str,
+    issue: NewsletterIssue,
+    stats: IssueStats,
+}
+
+pub async fn issue_stats(
+    path: web::Path<IssuePath>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = pool.get().map_err(e500)?;
+    let Some(issue) = newsletter_issues::table
+        .find(path.newsletter_issue_id)
+        .first::<NewsletterIssue>(&mut conn)
+        .optional()
+        .map_err(e500)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let stats = tracking::issue_stats(&mut conn, issue.newsletter_issue_id).map_err(e500)?;
+
+    render_page(&IssueStatsPage {
+        flash_messages: &flash_messages,
+        csrf_token: csrf_token.as_str(),
+        issue,
+        stats,
+    })
+}
@@ -0,0 +1,39 @@
+use super::IssuePath;
+use crate::db::PgPool;
+use crate::schema::newsletter_issues;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use diesel::prelude::*;
+
+#[derive(serde::Deserialize)]
+pub struct ArchiveFormData {
+    in_archive: bool,
+}
+
+#[tracing::instrument(name = "Set whether an issue is archived", skip(path, form, pool))]
+pub async fn set_issue_in_archive(
+    path: web::Path<IssuePath>,
+    form: web::Form<ArchiveFormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = pool.get().map_err(e500)?;
+    let updated = diesel::update(newsletter_issues::table.find(path.newsletter_issue_id))
+        .set(newsletter_issues::in_archive.eq(form.in_archive))
+        .execute(&mut conn)
+        .map_err(e500)?;
+
+    if updated == 0 {
+        FlashMessage::error("There is no such newsletter issue.").send();
+        return Ok(see_other("/admin/issues"));
+    }
+    if form.in_archive {
+        FlashMessage::info("The issue is now in the public archive.").send();
+    } else {
+        FlashMessage::info("The issue has been removed from the public archive.").send();
+    }
+    Ok(see_other(&format!(
+        "/admin/issues/{}/stats",
+        path.newsletter_issue_id
+    )))
+}
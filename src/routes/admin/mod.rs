@@ -1,3 +1,9 @@
+pub mod api_keys;
 pub mod dashboard;
+pub mod deliveries;
+pub mod drafts;
+pub mod issues;
 pub mod logout;
 pub mod password;
+pub mod subscribers;
+pub mod templates;
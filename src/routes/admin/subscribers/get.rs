@@ -0,0 +1,48 @@
+use crate::csrf::CsrfToken;
+use crate::db::PgPool;
+use crate::schema::subscriptions;
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use diesel::prelude::*;
+
+#[derive(Queryable)]
+struct SubscriberRow {
+    email: String,
+    name: String,
+    status: Option<String>,
+    tags: Vec<String>,
+}
+
+#[derive(Template)]
+#[template(path = "admin/subscribers.html")]
+struct SubscribersPage<'a> {
+    flash_messages: &'a IncomingFlashMessages,
+    csrf_token: &'a str,
+    subscribers: Vec<SubscriberRow>,
+}
+
+pub async fn subscribers_list(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = pool.get().map_err(e500)?;
+    let subscribers = subscriptions::table
+        .order(subscriptions::subscribed_at.desc())
+        .select((
+            subscriptions::email,
+            subscriptions::name,
+            subscriptions::status,
+            subscriptions::tags,
+        ))
+        .load::<SubscriberRow>(&mut conn)
+        .map_err(e500)?;
+
+    render_page(&SubscribersPage {
+        flash_messages: &flash_messages,
+        csrf_token: csrf_token.as_str(),
+        subscribers,
+    })
+}
@@ -0,0 +1,41 @@
+use crate::db::PgPool;
+use crate::domain::SubscriberTag;
+use crate::schema::subscriptions;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use diesel::prelude::*;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+    tags: String,
+}
+
+#[tracing::instrument(name = "Set subscriber tags", skip(form, pool), fields(subscriber_email = %form.email))]
+pub async fn set_subscriber_tags(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let new_tags = match SubscriberTag::parse_list(&form.tags) {
+        Ok(new_tags) => new_tags,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/subscribers"));
+        }
+    };
+    let new_tags: Vec<String> = new_tags.iter().map(|t| t.as_ref().to_string()).collect();
+
+    let mut conn = pool.get().map_err(e500)?;
+    let updated = diesel::update(subscriptions::table.filter(subscriptions::email.eq(&form.email)))
+        .set(subscriptions::tags.eq(new_tags))
+        .execute(&mut conn)
+        .map_err(e500)?;
+
+    if updated == 0 {
+        FlashMessage::error("There is no subscriber with this email address.").send();
+    } else {
+        FlashMessage::info("The subscriber tags have been updated.").send();
+    }
+    Ok(see_other("/admin/subscribers"))
+}
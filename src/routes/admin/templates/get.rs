@@ -0,0 +1,191 @@
+use super::TemplatePath;
+use crate::csrf::CsrfToken;
+use crate::db::PgPool;
+use crate::db_models::{EmailLayout, TransactionalTemplate};
+use crate::email_templates::{
+    get_layout, get_transactional_template, render_transactional_email, Layout, TransactionalEmail,
+};
+use crate::markdown::{render_markdown, RenderedContent};
+use crate::schema::email_layouts;
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e500, render_page, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use askama::Template;
+use diesel::prelude::*;
+
+const SAMPLE_ISSUE_TITLE: &str = "Our latest news";
+const SAMPLE_ISSUE_CONTENT: &str = "## Our latest news\n\n\
+    This is a sample issue, showing how newsletters look in this layout.\n\n\
+    - A first story\n- A second story\n\n\
+    [Read more on our website](https://example.com)";
+
+#[derive(Template)]
+#[template(path = "admin/templates/list.html")]
+struct TemplatesPage<'a> {
+    flash_messages: &'a IncomingFlashMessages,
+    csrf_token: &'a str,
+    layouts: Vec<EmailLayout>,
+    emails: [TransactionalEmail; 1],
+}
+
+pub async fn templates_list(
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = pool.get().map_err(e500)?;
+    let layouts = email_layouts::table
+        .order(email_layouts::name.asc())
+        .load::<EmailLayout>(&mut conn)
+        .map_err(e500)?;
+    render_page(&TemplatesPage {
+        flash_messages: &flash_messages,
+        csrf_token: csrf_token.as_str(),
+        layouts,
+        emails: TransactionalEmail::ALL,
+    })
+}
+
+#[derive(Template)]
+#[template(path = "admin/templates/layout.html")]
+struct LayoutPage<'a> {
+    flash_messages: &'a IncomingFlashMessages,
+    csrf_token: &'a str,
+    layout: EmailLayout,
+}
+
+pub async fn layout_form(
+    path: web::Path<TemplatePath>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = pool.get().map_err(e500)?;
+    let Some(layout) = get_layout(&mut conn, &path.name).map_err(e500)? else {
+        FlashMessage::error("There is no such layout.").send();
+        return Ok(see_other("/admin/templates"));
+    };
+    render_page(&LayoutPage {
+        flash_messages: &flash_messages,
+        csrf_token: csrf_token.as_str(),
+        layout,
+    })
+}
+
+#[derive(Template)]
+#[template(path = "admin/templates/preview.html")]
+struct PreviewPage<'a> {
+    flash_messages: &'a IncomingFlashMessages,
+    csrf_token: &'a str,
+    subject: &'a str,
+    content: &'a RenderedContent,
+    back: &'a str,
+}
+
+pub async fn layout_preview(
+    path: web::Path<TemplatePath>,
+    pool: web::Data<PgPool>,
+    application_base_url: web::Data<ApplicationBaseUrl>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = pool.get().map_err(e500)?;
+    let Some(layout) = get_layout(&mut conn, &path.name).map_err(e500)? else {
+        FlashMessage::error("There is no such layout.").send();
+        return Ok(see_other("/admin/templates"));
+    };
+    let back = format!("/admin/templates/layouts/{}", layout.name);
+    let layout = match Layout::parse(&layout) {
+        Ok(layout) => layout,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&back));
+        }
+    };
+    let unsubscribe_url = format!(
+        "{}/subscriptions/unsubscribe?subscription_token=sample",
+        application_base_url.0
+    );
+    let content = layout.render(
+        SAMPLE_ISSUE_TITLE,
+        &render_markdown(SAMPLE_ISSUE_CONTENT),
+        Some(&unsubscribe_url),
+    );
+    render_page(&PreviewPage {
+        flash_messages: &flash_messages,
+        csrf_token: csrf_token.as_str(),
+        subject: SAMPLE_ISSUE_TITLE,
+        content: &content,
+        back: &back,
+    })
+}
+
+#[derive(Template)]
+#[template(path = "admin/templates/email.html")]
+struct TransactionalTemplatePage<'a> {
+    flash_messages: &'a IncomingFlashMessages,
+    csrf_token: &'a str,
+    email: TransactionalEmail,
+    template: TransactionalTemplate,
+    layouts: Vec<String>,
+}
+
+pub async fn transactional_template_form(
+    path: web::Path<TemplatePath>,
+    pool: web::Data<PgPool>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(email) = TransactionalEmail::parse(&path.name) else {
+        FlashMessage::error("There is no such email template.").send();
+        return Ok(see_other("/admin/templates"));
+    };
+    let mut conn = pool.get().map_err(e500)?;
+    let template = get_transactional_template(&mut conn, email).map_err(e500)?;
+    let layouts = email_layouts::table
+        .order(email_layouts::name.asc())
+        .select(email_layouts::name)
+        .load::<String>(&mut conn)
+        .map_err(e500)?;
+    render_page(&TransactionalTemplatePage {
+        flash_messages: &flash_messages,
+        csrf_token: csrf_token.as_str(),
+        email,
+        template,
+        layouts,
+    })
+}
+
+pub async fn transactional_template_preview(
+    path: web::Path<TemplatePath>,
+    pool: web::Data<PgPool>,
+    application_base_url: web::Data<ApplicationBaseUrl>,
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(email) = TransactionalEmail::parse(&path.name) else {
+        FlashMessage::error("There is no such email template.").send();
+        return Ok(see_other("/admin/templates"));
+    };
+    let back = format!("/admin/templates/emails/{}", email.name());
+    let sample_values = email.sample_values(&application_base_url.0);
+    let values: Vec<(&str, &str)> = sample_values
+        .iter()
+        .map(|(name, value)| (*name, value.as_str()))
+        .collect();
+    let mut conn = pool.get().map_err(e500)?;
+    match render_transactional_email(&mut conn, email, &values) {
+        Ok((subject, content)) => render_page(&PreviewPage {
+            flash_messages: &flash_messages,
+            csrf_token: csrf_token.as_str(),
+            subject: &subject,
+            content: &content,
+            back: &back,
+        }),
+        Err(e) => {
+            FlashMessage::error(format!("{:#}", e)).send();
+            Ok(see_other(&back))
+        }
+    }
+}
@@ -0,0 +1,9 @@
+pub mod get;
+pub mod post;
+
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+pub struct TemplatePath {
+    name: String,
+}
@@ -0,0 +1,166 @@
+use super::TemplatePath;
+use crate::clock::Clock;
+use crate::db::PgPool;
+use crate::db_models::EmailLayout;
+use crate::email_templates::{get_layout, Layout, MarkdownTemplate, TransactionalEmail};
+use crate::schema::{email_layouts, transactional_templates};
+use crate::templating::Template;
+use crate::utils::{e500, see_other};
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use diesel::prelude::*;
+
+#[derive(serde::Deserialize)]
+pub struct NewLayoutFormData {
+    name: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct LayoutFormData {
+    header: String,
+    footer: String,
+    physical_address: String,
+    unsubscribe_block: String,
+}
+
+#[derive(serde::Deserialize)]
+pub struct TransactionalTemplateFormData {
+    subject: String,
+    layout: String,
+    body: String,
+}
+
+// Layout names end up in URLs, so they are kept to lowercase letters, digits, `-` and `_`.
+fn validate_layout_name(name: &str) -> Result<&str, String> {
+    let name = name.trim();
+    let is_valid = !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if !is_valid {
+        return Err(format!(
+            "{} is not a valid layout name. Use up to 64 lowercase letters, digits, `-` or `_`.",
+            name
+        ));
+    }
+    Ok(name)
+}
+
+#[tracing::instrument(name = "Create an email layout", skip(form, pool, clock))]
+pub async fn create_layout(
+    form: web::Form<NewLayoutFormData>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = match validate_layout_name(&form.name) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/templates"));
+        }
+    };
+    let mut conn = pool.get().map_err(e500)?;
+    let inserted = diesel::insert_into(email_layouts::table)
+        .values((
+            email_layouts::name.eq(name),
+            email_layouts::header.eq(""),
+            email_layouts::footer.eq(""),
+            email_layouts::physical_address.eq(""),
+            email_layouts::unsubscribe_block.eq("[Unsubscribe]({{ unsubscribe_url }})"),
+            email_layouts::updated_at.eq(clock.now()),
+        ))
+        .on_conflict_do_nothing()
+        .execute(&mut conn)
+        .map_err(e500)?;
+    if inserted == 0 {
+        FlashMessage::error("A layout with this name already exists.").send();
+        return Ok(see_other("/admin/templates"));
+    }
+    FlashMessage::info("The layout has been created.").send();
+    Ok(see_other(&format!("/admin/templates/layouts/{}", name)))
+}
+
+#[tracing::instrument(name = "Save an email layout", skip(path, form, pool, clock), fields(layout = %path.name))]
+pub async fn save_layout(
+    path: web::Path<TemplatePath>,
+    form: web::Form<LayoutFormData>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = pool.get().map_err(e500)?;
+    let Some(layout) = get_layout(&mut conn, &path.name).map_err(e500)? else {
+        FlashMessage::error("There is no such layout.").send();
+        return Ok(see_other("/admin/templates"));
+    };
+    let form_page = format!("/admin/templates/layouts/{}", layout.name);
+    let updated_layout = EmailLayout {
+        header: form.0.header,
+        footer: form.0.footer,
+        physical_address: form.0.physical_address,
+        unsubscribe_block: form.0.unsubscribe_block,
+        ..layout
+    };
+    if let Err(e) = Layout::parse(&updated_layout) {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&form_page));
+    }
+
+    diesel::update(email_layouts::table.find(&updated_layout.name))
+        .set((
+            email_layouts::header.eq(&updated_layout.header),
+            email_layouts::footer.eq(&updated_layout.footer),
+            email_layouts::physical_address.eq(&updated_layout.physical_address),
+            email_layouts::unsubscribe_block.eq(&updated_layout.unsubscribe_block),
+            email_layouts::updated_at.eq(clock.now()),
+        ))
+        .execute(&mut conn)
+        .map_err(e500)?;
+    FlashMessage::info("The layout has been saved.").send();
+    Ok(see_other(&form_page))
+}
+
+#[tracing::instrument(
+    name = "Save a transactional email template",
+    skip(path, form, pool, clock),
+    fields(template = %path.name)
+)]
+pub async fn save_transactional_template(
+    path: web::Path<TemplatePath>,
+    form: web::Form<TransactionalTemplateFormData>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(email) = TransactionalEmail::parse(&path.name) else {
+        FlashMessage::error("There is no such email template.").send();
+        return Ok(see_other("/admin/templates"));
+    };
+    let form_page = format!("/admin/templates/emails/{}", email.name());
+    let validation = Template::parse(&form.subject, email.variables())
+        .map_err(|e| format!("The subject is not a valid template. {}", e))
+        .and_then(|_| {
+            MarkdownTemplate::parse(&form.body, email.variables())
+                .map_err(|e| format!("The body is not a valid template. {}", e))
+        });
+    if let Err(e) = validation {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&form_page));
+    }
+
+    let mut conn = pool.get().map_err(e500)?;
+    if get_layout(&mut conn, &form.layout).map_err(e500)?.is_none() {
+        FlashMessage::error("There is no such layout.").send();
+        return Ok(see_other(&form_page));
+    }
+    diesel::update(transactional_templates::table.find(email.name()))
+        .set((
+            transactional_templates::subject.eq(&form.subject),
+            transactional_templates::layout.eq(&form.layout),
+            transactional_templates::body.eq(&form.body),
+            transactional_templates::updated_at.eq(clock.now()),
+        ))
+        .execute(&mut conn)
+        .map_err(e500)?;
+    FlashMessage::info("The email template has been saved.").send();
+    Ok(see_other(&form_page))
+}
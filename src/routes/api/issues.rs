@@ -0,0 +1,279 @@
+use super::{authorize, page_offset, ApiError, PAGE_SIZE};
+use crate::{
+    api_keys::ApiScope,
+    clock::Clock,
+    db::PgPool,
+    db_models::NewsletterIssue,
+    domain::IssueStatus,
+    email_client::EmailClient,
+    issue_delivery::{deliver_issue, DeliveryOutcome},
+    routes::newsletter::{store_issue, validate_scheduled_at, BodyData},
+    routes::newsletter_issues::{get_issue, update_editable_issue, IssueSummary, ScheduleData},
+    schema::newsletter_issues,
+    shutdown::Shutdown,
+    signing::SigningKeys,
+    startup::ApplicationBaseUrl,
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct IssuePath {
+    pub newsletter_issue_id: Uuid,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListParameters {
+    /// Only the issues with this status, e.g. `draft` or `sent`.
+    status: Option<String>,
+    /// Pages are numbered from 1 and hold 50 issues.
+    page: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct IssueList {
+    issues: Vec<IssueSummary>,
+    page: i64,
+    total: i64,
+}
+
+fn filtered_issues(status: Option<IssueStatus>) -> newsletter_issues::BoxedQuery<'static, Pg> {
+    let mut query = newsletter_issues::table.into_boxed();
+    if let Some(status) = status {
+        query = query.filter(newsletter_issues::status.eq(status.as_str()));
+    }
+    query
+}
+
+/// List newsletter issues
+///
+/// The newest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/issues",
+    tag = "issues",
+    params(ListParameters),
+    responses((status = 200, body = IssueList), ApiError),
+    security(("api_key" = ["issues:read"]))
+)]
+#[tracing::instrument(
+    name = "API: list newsletter issues",
+    skip(parameters, pool, clock, request),
+    fields(api_key_id=tracing::field::Empty)
+)]
+pub async fn list_issues(
+    parameters: web::Query<ListParameters>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    authorize(&request, &pool, clock.as_ref(), ApiScope::IssuesRead)?;
+    let status = parameters
+        .status
+        .as_deref()
+        .map(IssueStatus::parse)
+        .transpose()
+        .map_err(ApiError::ValidationError)?;
+    let offset = page_offset(parameters.page)?;
+
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let total = filtered_issues(status)
+        .count()
+        .get_result::<i64>(&mut conn)
+        .context("Failed to count the newsletter issues.")?;
+    let issues: Vec<IssueSummary> = filtered_issues(status)
+        .order((
+            newsletter_issues::created_at.desc(),
+            newsletter_issues::newsletter_issue_id,
+        ))
+        .limit(PAGE_SIZE)
+        .offset(offset)
+        .load::<NewsletterIssue>(&mut conn)
+        .context("Failed to fetch the newsletter issues.")?
+        .into_iter()
+        .map(IssueSummary::from)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(IssueList {
+        issues,
+        page: parameters.page.unwrap_or(1),
+        total,
+    }))
+}
+
+/// Create a newsletter issue
+///
+/// Stores a draft, or a scheduled issue if it has a `scheduled_at`. Drafts are sent with
+/// `POST /api/v1/issues/{newsletter_issue_id}/send`.
+#[utoipa::path(
+    post,
+    path = "/api/v1/issues",
+    tag = "issues",
+    request_body = BodyData,
+    responses((status = 201, body = IssueSummary), ApiError),
+    security(("api_key" = ["issues:write"]))
+)]
+#[tracing::instrument(
+    name = "API: create a newsletter issue",
+    skip(body, pool, clock, request),
+    fields(api_key_id=tracing::field::Empty)
+)]
+pub async fn create_issue(
+    body: web::Json<BodyData>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    authorize(&request, &pool, clock.as_ref(), ApiScope::IssuesWrite)?;
+    let issue = store_issue(&pool, &body, clock.now(), IssueStatus::Draft)?;
+    Ok(HttpResponse::Created().json(IssueSummary::from(issue)))
+}
+
+/// Get a newsletter issue
+#[utoipa::path(
+    get,
+    path = "/api/v1/issues/{newsletter_issue_id}",
+    tag = "issues",
+    params(IssuePath),
+    responses((status = 200, body = IssueSummary), ApiError),
+    security(("api_key" = ["issues:read"]))
+)]
+#[tracing::instrument(
+    name = "API: get a newsletter issue",
+    skip(path, pool, clock, request),
+    fields(api_key_id=tracing::field::Empty)
+)]
+pub async fn get_issue_status(
+    path: web::Path<IssuePath>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    authorize(&request, &pool, clock.as_ref(), ApiScope::IssuesRead)?;
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issue = get_issue(&mut conn, path.newsletter_issue_id)?;
+    Ok(HttpResponse::Ok().json(IssueSummary::from(issue)))
+}
+
+/// Schedule a newsletter issue
+///
+/// Drafts, scheduled and failed issues can be (re)scheduled.
+#[utoipa::path(
+    post,
+    path = "/api/v1/issues/{newsletter_issue_id}/schedule",
+    tag = "issues",
+    params(IssuePath),
+    request_body = ScheduleData,
+    responses((status = 200, body = IssueSummary), ApiError),
+    security(("api_key" = ["issues:write"]))
+)]
+#[tracing::instrument(
+    name = "API: schedule a newsletter issue",
+    skip(path, body, pool, clock, request),
+    fields(api_key_id=tracing::field::Empty)
+)]
+pub async fn schedule_issue(
+    path: web::Path<IssuePath>,
+    body: web::Json<ScheduleData>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    authorize(&request, &pool, clock.as_ref(), ApiScope::IssuesWrite)?;
+    let scheduled_at = validate_scheduled_at(body.scheduled_at, clock.now())?;
+
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issue = update_editable_issue(
+        &mut conn,
+        path.newsletter_issue_id,
+        (
+            newsletter_issues::status.eq(IssueStatus::Scheduled.as_str()),
+            newsletter_issues::scheduled_at.eq(Some(scheduled_at)),
+            newsletter_issues::delivery_failures.eq(0),
+        ),
+    )?;
+    Ok(HttpResponse::Ok().json(IssueSummary::from(issue)))
+}
+
+/// Send a newsletter issue
+///
+/// Sends a draft, scheduled or failed issue right away. Answers 202 Accepted if the application is shut
+/// down before every subscriber got it; the rest get it once it is back up.
+#[utoipa::path(
+    post,
+    path = "/api/v1/issues/{newsletter_issue_id}/send",
+    tag = "issues",
+    params(IssuePath),
+    responses(
+        (status = 200, description = "Every subscriber got the issue.", body = IssueSummary),
+        (status = 202, description = "Some subscribers are yet to get the issue.", body = IssueSummary),
+        ApiError,
+    ),
+    security(("api_key" = ["issues:write"]))
+)]
+#[tracing::instrument(
+    name = "API: send a newsletter issue",
+    skip(path, pool, email_client, clock, application_base_url, signing_keys, shutdown, request),
+    fields(api_key_id=tracing::field::Empty)
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn send_issue(
+    path: web::Path<IssuePath>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    clock: web::Data<dyn Clock>,
+    application_base_url: web::Data<ApplicationBaseUrl>,
+    signing_keys: web::Data<SigningKeys>,
+    shutdown: web::Data<Shutdown>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    authorize(&request, &pool, clock.as_ref(), ApiScope::IssuesWrite)?;
+    let issue = {
+        let mut conn = pool
+            .get()
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        // Claiming the issue first means the scheduler can't send it as well.
+        update_editable_issue(
+            &mut conn,
+            path.newsletter_issue_id,
+            (
+                newsletter_issues::status.eq(IssueStatus::Sending.as_str()),
+                newsletter_issues::claimed_at.eq(Some(clock.now())),
+                newsletter_issues::delivery_failures.eq(0),
+            ),
+        )?
+    };
+
+    let outcome = deliver_issue(
+        &pool,
+        &email_client,
+        &issue,
+        clock.as_ref(),
+        &application_base_url.0,
+        &signing_keys.tracking,
+        &shutdown,
+    )
+    .await?;
+
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issue = IssueSummary::from(get_issue(&mut conn, issue.newsletter_issue_id)?);
+    match outcome {
+        DeliveryOutcome::Interrupted => Ok(HttpResponse::Accepted().json(issue)),
+        _ => Ok(HttpResponse::Ok().json(issue)),
+    }
+}
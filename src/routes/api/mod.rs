@@ -0,0 +1,236 @@
+pub mod issues;
+pub mod stats;
+pub mod subscribers;
+
+use crate::{
+    api_keys::{find_api_key, ApiScope},
+    clock::Clock,
+    db::PgPool,
+    db_models::ApiKey,
+    routes::metrics::bearer_token,
+    routes::newsletter::PublishError,
+    routes::subscriptions::error_chain_fmt,
+};
+use actix_web::http::header::WWW_AUTHENTICATE;
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use utoipa::openapi::{ContentBuilder, Ref, RefOr, Response, ResponseBuilder};
+use utoipa::{IntoResponses, ToSchema};
+
+// How many subscribers or issues a page of a list holds.
+pub const PAGE_SIZE: i64 = 50;
+
+/// The body of every error of the API, e.g. `{"error": {"code": "not_found", "message": "..."}}`.
+#[derive(Serialize, ToSchema)]
+pub struct ErrorBody {
+    pub error: ErrorDetails,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ErrorDetails {
+    /// What went wrong, for programs: `invalid_request`, `unauthorized`, `forbidden`,
+    /// `not_found`, `conflict` or `internal_error`.
+    pub code: String,
+    /// What went wrong, for people.
+    pub message: String,
+}
+
+pub fn error_body(code: &str, message: &str) -> ErrorBody {
+    ErrorBody {
+        error: ErrorDetails {
+            code: code.to_string(),
+            message: message.to_string(),
+        },
+    }
+}
+
+// The OpenAPI responses of errors answered with an `ErrorBody`, by status code.
+pub fn error_responses<'a>(
+    errors: impl IntoIterator<Item = (StatusCode, &'a str)>,
+) -> BTreeMap<String, RefOr<Response>> {
+    errors
+        .into_iter()
+        .map(|(status, code)| {
+            let response = ResponseBuilder::new()
+                .description(format!("Fails with the `{}` error code.", code))
+                .content(
+                    "application/json",
+                    ContentBuilder::new()
+                        .schema(Some(Ref::from_schema_name("ErrorBody")))
+                        .build(),
+                )
+                .build();
+            (status.as_u16().to_string(), response.into())
+        })
+        .collect()
+}
+
+#[derive(thiserror::Error)]
+#[allow(clippy::enum_variant_names)]
+pub enum ApiError {
+    #[error("{0}")]
+    ValidationError(String),
+    #[error("A valid API key is required.")]
+    AuthError(#[source] anyhow::Error),
+    #[error("The API key does not have the {0} scope.")]
+    ScopeError(ApiScope),
+    #[error("{0}")]
+    NotFoundError(String),
+    #[error("{0}")]
+    ConflictError(String),
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl From<diesel::result::Error> for ApiError {
+    fn from(error: diesel::result::Error) -> Self {
+        ApiError::UnexpectedError(anyhow::anyhow!("Database error: {:?}", error))
+    }
+}
+
+impl From<PublishError> for ApiError {
+    fn from(error: PublishError) -> Self {
+        match error {
+            PublishError::ValidationError(message) => ApiError::ValidationError(message),
+            PublishError::AuthError(e) => ApiError::AuthError(e),
+            PublishError::NotFoundError(message) => ApiError::NotFoundError(message),
+            PublishError::ConflictError(message) => ApiError::ConflictError(message),
+            PublishError::UnexpectedError(e) => ApiError::UnexpectedError(e),
+        }
+    }
+}
+
+impl ApiError {
+    fn code(&self) -> &'static str {
+        match self {
+            ApiError::ValidationError(_) => "invalid_request",
+            ApiError::AuthError(_) => "unauthorized",
+            ApiError::ScopeError(_) => "forbidden",
+            ApiError::NotFoundError(_) => "not_found",
+            ApiError::ConflictError(_) => "conflict",
+            ApiError::UnexpectedError(_) => "internal_error",
+        }
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            ApiError::AuthError(_) => StatusCode::UNAUTHORIZED,
+            ApiError::ScopeError(_) => StatusCode::FORBIDDEN,
+            ApiError::NotFoundError(_) => StatusCode::NOT_FOUND,
+            ApiError::ConflictError(_) => StatusCode::CONFLICT,
+            ApiError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let ApiError::AuthError(_) = self {
+            response.insert_header((WWW_AUTHENTICATE, r#"Bearer realm="api""#));
+        }
+        // What went wrong on our side is logged, not shown.
+        let message = match self {
+            ApiError::UnexpectedError(_) => "Something went wrong on our side.".to_string(),
+            e => e.to_string(),
+        };
+        response.json(error_body(self.code(), &message))
+    }
+}
+
+// Every variant is listed, so that the documented errors are the ones `error_response` gives.
+impl IntoResponses for ApiError {
+    fn responses() -> BTreeMap<String, RefOr<Response>> {
+        let errors = [
+            ApiError::ValidationError(String::new()),
+            ApiError::AuthError(anyhow::anyhow!("")),
+            ApiError::ScopeError(ApiScope::SubscribersRead),
+            ApiError::NotFoundError(String::new()),
+            ApiError::ConflictError(String::new()),
+            ApiError::UnexpectedError(anyhow::anyhow!("")),
+        ];
+        error_responses(errors.iter().map(|e| (e.status_code(), e.code())))
+    }
+}
+
+fn invalid_request(e: impl std::fmt::Display) -> actix_web::Error {
+    ApiError::ValidationError(e.to_string()).into()
+}
+
+// Malformed bodies, paths and query strings get the same JSON errors as everything else.
+pub fn extractor_errors(cfg: &mut web::ServiceConfig) {
+    cfg.app_data(web::JsonConfig::default().error_handler(|e, _| invalid_request(e)))
+        .app_data(web::PathConfig::default().error_handler(|e, _| invalid_request(e)))
+        .app_data(web::QueryConfig::default().error_handler(|e, _| invalid_request(e)));
+}
+
+pub async fn not_found() -> Result<HttpResponse, ApiError> {
+    Err(ApiError::NotFoundError(
+        "There is no such API endpoint.".to_string(),
+    ))
+}
+
+// Checks the `Bearer` API key of the request and that it may be used for `scope`, and records
+// which key it is on the current span.
+pub fn authorize(
+    request: &HttpRequest,
+    pool: &PgPool,
+    clock: &dyn Clock,
+    scope: ApiScope,
+) -> Result<ApiKey, ApiError> {
+    let key = bearer_token(request).ok_or_else(|| {
+        ApiError::AuthError(anyhow::anyhow!("The request has no 'Bearer' API key."))
+    })?;
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let api_key = find_api_key(&mut conn, &key, clock.now())
+        .context("Failed to look up the API key.")?
+        .ok_or_else(|| ApiError::AuthError(anyhow::anyhow!("Unknown or revoked API key.")))?;
+    tracing::Span::current().record("api_key_id", tracing::field::display(&api_key.api_key_id));
+    if !api_key.has_scope(scope) {
+        return Err(ApiError::ScopeError(scope));
+    }
+    Ok(api_key)
+}
+
+// Pages are numbered from 1. Returns the offset of the first row of the page.
+pub fn page_offset(page: Option<i64>) -> Result<i64, ApiError> {
+    match page.unwrap_or(1) {
+        page if page >= 1 => (page - 1)
+            .checked_mul(PAGE_SIZE)
+            .ok_or_else(|| ApiError::ValidationError("There is no such page.".to_string())),
+        _ => Err(ApiError::ValidationError(
+            "Pages are numbered from 1.".to_string(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use claim::{assert_err, assert_ok_eq};
+
+    #[test]
+    fn pages_are_numbered_from_one() {
+        assert_ok_eq!(page_offset(None), 0);
+        assert_ok_eq!(page_offset(Some(3)), 2 * PAGE_SIZE);
+        assert_err!(page_offset(Some(0)));
+        assert_err!(page_offset(Some(-1)));
+    }
+
+    #[test]
+    fn pages_too_far_to_have_an_offset_are_rejected() {
+        assert_err!(page_offset(Some(i64::MAX)));
+        assert_err!(page_offset(Some(i64::MAX / PAGE_SIZE + 2)));
+    }
+}
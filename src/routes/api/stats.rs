@@ -0,0 +1,99 @@
+use super::{authorize, issues::IssuePath, ApiError};
+use crate::{
+    api_keys::ApiScope,
+    clock::Clock,
+    db::PgPool,
+    routes::newsletter_issues::get_issue,
+    schema::{newsletter_issues, subscriptions},
+    tracking::{self, IssueStats},
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use utoipa::ToSchema;
+
+#[derive(Serialize, ToSchema)]
+pub struct OverallStats {
+    /// How many subscribers have each status.
+    subscribers: BTreeMap<String, i64>,
+    /// How many newsletter issues have each status.
+    issues: BTreeMap<String, i64>,
+}
+
+/// Get the overall stats
+#[utoipa::path(
+    get,
+    path = "/api/v1/stats",
+    tag = "stats",
+    responses((status = 200, body = OverallStats), ApiError),
+    security(("api_key" = ["stats:read"]))
+)]
+#[tracing::instrument(
+    name = "API: get the overall stats",
+    skip(pool, clock, request),
+    fields(api_key_id=tracing::field::Empty)
+)]
+pub async fn overall_stats(
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    authorize(&request, &pool, clock.as_ref(), ApiScope::StatsRead)?;
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    // Subscribers from before statuses existed have none.
+    let subscribers: BTreeMap<String, i64> = subscriptions::table
+        .group_by(subscriptions::status)
+        .select((subscriptions::status, count_star()))
+        .load::<(Option<String>, i64)>(&mut conn)
+        .context("Failed to count the subscribers.")?
+        .into_iter()
+        .map(|(status, count)| (status.unwrap_or_else(|| "unknown".to_string()), count))
+        .collect();
+    let issues: BTreeMap<String, i64> = newsletter_issues::table
+        .group_by(newsletter_issues::status)
+        .select((newsletter_issues::status, count_star()))
+        .load::<(String, i64)>(&mut conn)
+        .context("Failed to count the newsletter issues.")?
+        .into_iter()
+        .collect();
+
+    Ok(HttpResponse::Ok().json(OverallStats {
+        subscribers,
+        issues,
+    }))
+}
+
+/// Get the stats of a newsletter issue
+#[utoipa::path(
+    get,
+    path = "/api/v1/issues/{newsletter_issue_id}/stats",
+    tag = "stats",
+    params(IssuePath),
+    responses((status = 200, body = IssueStats), ApiError),
+    security(("api_key" = ["stats:read"]))
+)]
+#[tracing::instrument(
+    name = "API: get the stats of a newsletter issue",
+    skip(path, pool, clock, request),
+    fields(api_key_id=tracing::field::Empty)
+)]
+pub async fn issue_stats(
+    path: web::Path<IssuePath>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    authorize(&request, &pool, clock.as_ref(), ApiScope::StatsRead)?;
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issue = get_issue(&mut conn, path.newsletter_issue_id)?;
+    let stats = tracking::issue_stats(&mut conn, issue.newsletter_issue_id)
+        .context("Failed to compute the stats of the newsletter issue.")?;
+    Ok(HttpResponse::Ok().json(stats))
+}
@@ -0,0 +1,363 @@
+use super::{authorize, page_offset, ApiError, PAGE_SIZE};
+use crate::{
+    api_keys::ApiScope,
+    clock::Clock,
+    db::PgPool,
+    db_models::Subscription,
+    domain::{NewSubscriber, SubscriberEmail, SubscriberName, SubscriberTag},
+    email_client::EmailClient,
+    routes::subscriptions::{
+        generate_subscription_token, insert_subscriber, send_confirmation_email, store_token,
+    },
+    schema::{subscription_tokens, subscriptions},
+    startup::ApplicationBaseUrl,
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use diesel::pg::Pg;
+use diesel::prelude::*;
+use diesel::result::{DatabaseErrorKind, Error as DieselError};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct SubscriberPath {
+    subscriber_id: Uuid,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListParameters {
+    /// e.g. `confirmed` or `pending_confirmation`.
+    status: Option<String>,
+    /// Only the subscribers with this tag.
+    tag: Option<String>,
+    /// Pages are numbered from 1 and hold 50 subscribers.
+    page: Option<i64>,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct NewSubscriberData {
+    email: String,
+    name: String,
+    /// Lowercased, e.g. `beta` or `early-adopter`.
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+/// Fields left out are not changed.
+#[derive(Deserialize, ToSchema)]
+pub struct SubscriberChanges {
+    name: Option<String>,
+    tags: Option<Vec<String>>,
+}
+
+#[derive(AsChangeset)]
+#[diesel(table_name = subscriptions)]
+struct SubscriberChangeset {
+    name: Option<String>,
+    tags: Option<Vec<String>>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SubscriberSummary {
+    pub id: Uuid,
+    pub email: String,
+    pub name: String,
+    /// `pending_confirmation`, `confirmed` or `unsubscribed`.
+    pub status: Option<String>,
+    pub tags: Vec<String>,
+    pub subscribed_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SubscriberList {
+    subscribers: Vec<SubscriberSummary>,
+    page: i64,
+    total: i64,
+}
+
+impl From<Subscription> for SubscriberSummary {
+    fn from(subscription: Subscription) -> Self {
+        Self {
+            id: subscription.id,
+            email: subscription.email,
+            name: subscription.name,
+            status: subscription.status,
+            tags: subscription.tags,
+            subscribed_at: subscription.subscribed_at,
+        }
+    }
+}
+
+fn parse_tags(tags: &[String]) -> Result<Vec<String>, ApiError> {
+    let mut parsed: Vec<String> = Vec::new();
+    for tag in tags {
+        let tag = SubscriberTag::parse(tag.clone()).map_err(ApiError::ValidationError)?;
+        if !parsed.iter().any(|t| t == tag.as_ref()) {
+            parsed.push(tag.as_ref().to_string());
+        }
+    }
+    Ok(parsed)
+}
+
+fn filtered_subscribers(parameters: &ListParameters) -> subscriptions::BoxedQuery<'_, Pg> {
+    let mut query = subscriptions::table.into_boxed();
+    if let Some(status) = &parameters.status {
+        query = query.filter(subscriptions::status.eq(status));
+    }
+    if let Some(tag) = &parameters.tag {
+        query = query.filter(subscriptions::tags.contains(vec![tag.to_lowercase()]));
+    }
+    query
+}
+
+fn find_subscriber(conn: &mut PgConnection, subscriber_id: Uuid) -> Result<Subscription, ApiError> {
+    subscriptions::table
+        .find(subscriber_id)
+        .first::<Subscription>(conn)
+        .optional()
+        .context("Failed to fetch the subscriber.")?
+        .ok_or_else(|| ApiError::NotFoundError("There is no such subscriber.".to_string()))
+}
+
+/// List subscribers
+///
+/// The newest first.
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers",
+    tag = "subscribers",
+    params(ListParameters),
+    responses((status = 200, body = SubscriberList), ApiError),
+    security(("api_key" = ["subscribers:read"]))
+)]
+#[tracing::instrument(
+    name = "API: list subscribers",
+    skip(parameters, pool, clock, request),
+    fields(api_key_id=tracing::field::Empty)
+)]
+pub async fn list_subscribers(
+    parameters: web::Query<ListParameters>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    authorize(&request, &pool, clock.as_ref(), ApiScope::SubscribersRead)?;
+    let offset = page_offset(parameters.page)?;
+
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let total = filtered_subscribers(&parameters)
+        .count()
+        .get_result::<i64>(&mut conn)
+        .context("Failed to count the subscribers.")?;
+    let subscribers: Vec<SubscriberSummary> = filtered_subscribers(&parameters)
+        .order((subscriptions::subscribed_at.desc(), subscriptions::id))
+        .limit(PAGE_SIZE)
+        .offset(offset)
+        .load::<Subscription>(&mut conn)
+        .context("Failed to fetch the subscribers.")?
+        .into_iter()
+        .map(SubscriberSummary::from)
+        .collect();
+
+    Ok(HttpResponse::Ok().json(SubscriberList {
+        subscribers,
+        page: parameters.page.unwrap_or(1),
+        total,
+    }))
+}
+
+/// Add a subscriber
+///
+/// Subscribers added through the API still confirm their address, like the ones who sign up.
+#[utoipa::path(
+    post,
+    path = "/api/v1/subscribers",
+    tag = "subscribers",
+    request_body = NewSubscriberData,
+    responses((status = 201, body = SubscriberSummary), ApiError),
+    security(("api_key" = ["subscribers:write"]))
+)]
+#[tracing::instrument(
+    name = "API: add a subscriber",
+    skip(body, pool, email_client, application_base_url, clock, request),
+    fields(subscriber_email = %body.email, api_key_id=tracing::field::Empty)
+)]
+pub async fn create_subscriber(
+    body: web::Json<NewSubscriberData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    application_base_url: web::Data<ApplicationBaseUrl>,
+    clock: web::Data<dyn Clock>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    authorize(&request, &pool, clock.as_ref(), ApiScope::SubscribersWrite)?;
+    let new_subscriber = NewSubscriber {
+        email: SubscriberEmail::parse(body.email.clone()).map_err(ApiError::ValidationError)?,
+        name: SubscriberName::parse(body.name.clone()).map_err(ApiError::ValidationError)?,
+    };
+    let tags = parse_tags(&body.tags)?;
+
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscription_token = generate_subscription_token();
+    let subscriber_id = conn.transaction::<_, ApiError, _>(|conn| {
+        let subscriber_id = insert_subscriber(conn, &new_subscriber).map_err(|e| match e {
+            DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _) => {
+                ApiError::ConflictError(
+                    "There is already a subscriber with this email address.".to_string(),
+                )
+            }
+            e => ApiError::UnexpectedError(
+                anyhow::Error::new(e).context("Failed to insert new subscriber in the database."),
+            ),
+        })?;
+        store_token(conn, &subscriber_id, &subscription_token)
+            .context("Failed to store the confirmation token for a new subscriber.")?;
+        diesel::update(subscriptions::table.find(subscriber_id))
+            .set(subscriptions::tags.eq(tags))
+            .execute(conn)
+            .context("Failed to tag the new subscriber.")?;
+        Ok(subscriber_id)
+    })?;
+
+    send_confirmation_email(
+        &mut conn,
+        &email_client,
+        new_subscriber,
+        &application_base_url.0,
+        &subscription_token,
+    )
+    .await
+    .context("Failed to send a confirmation email.")?;
+
+    let subscriber = find_subscriber(&mut conn, subscriber_id)?;
+    Ok(HttpResponse::Created().json(SubscriberSummary::from(subscriber)))
+}
+
+/// Get a subscriber
+#[utoipa::path(
+    get,
+    path = "/api/v1/subscribers/{subscriber_id}",
+    tag = "subscribers",
+    params(SubscriberPath),
+    responses((status = 200, body = SubscriberSummary), ApiError),
+    security(("api_key" = ["subscribers:read"]))
+)]
+#[tracing::instrument(
+    name = "API: get a subscriber",
+    skip(path, pool, clock, request),
+    fields(api_key_id=tracing::field::Empty)
+)]
+pub async fn get_subscriber(
+    path: web::Path<SubscriberPath>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    authorize(&request, &pool, clock.as_ref(), ApiScope::SubscribersRead)?;
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscriber = find_subscriber(&mut conn, path.subscriber_id)?;
+    Ok(HttpResponse::Ok().json(SubscriberSummary::from(subscriber)))
+}
+
+/// Update a subscriber
+#[utoipa::path(
+    patch,
+    path = "/api/v1/subscribers/{subscriber_id}",
+    tag = "subscribers",
+    params(SubscriberPath),
+    request_body = SubscriberChanges,
+    responses((status = 200, body = SubscriberSummary), ApiError),
+    security(("api_key" = ["subscribers:write"]))
+)]
+#[tracing::instrument(
+    name = "API: update a subscriber",
+    skip(path, body, pool, clock, request),
+    fields(api_key_id=tracing::field::Empty)
+)]
+pub async fn update_subscriber(
+    path: web::Path<SubscriberPath>,
+    body: web::Json<SubscriberChanges>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    authorize(&request, &pool, clock.as_ref(), ApiScope::SubscribersWrite)?;
+    let changes = SubscriberChangeset {
+        name: body
+            .name
+            .clone()
+            .map(|name| SubscriberName::parse(name).map(|name| name.as_ref().to_string()))
+            .transpose()
+            .map_err(ApiError::ValidationError)?,
+        tags: body.tags.as_deref().map(parse_tags).transpose()?,
+    };
+    if changes.name.is_none() && changes.tags.is_none() {
+        return Err(ApiError::ValidationError(
+            "Give a new `name` or `tags` for the subscriber.".to_string(),
+        ));
+    }
+
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let subscriber = diesel::update(subscriptions::table.find(path.subscriber_id))
+        .set(&changes)
+        .get_result::<Subscription>(&mut conn)
+        .optional()
+        .context("Failed to update the subscriber.")?
+        .ok_or_else(|| ApiError::NotFoundError("There is no such subscriber.".to_string()))?;
+    Ok(HttpResponse::Ok().json(SubscriberSummary::from(subscriber)))
+}
+
+/// Delete a subscriber
+///
+/// Deletes the subscriber for good. Their deliveries and email events are kept, without them.
+#[utoipa::path(
+    delete,
+    path = "/api/v1/subscribers/{subscriber_id}",
+    tag = "subscribers",
+    params(SubscriberPath),
+    responses((status = 204, description = "The subscriber was deleted."), ApiError),
+    security(("api_key" = ["subscribers:write"]))
+)]
+#[tracing::instrument(
+    name = "API: delete a subscriber",
+    skip(path, pool, clock, request),
+    fields(api_key_id=tracing::field::Empty)
+)]
+pub async fn delete_subscriber(
+    path: web::Path<SubscriberPath>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+    request: HttpRequest,
+) -> Result<HttpResponse, ApiError> {
+    authorize(&request, &pool, clock.as_ref(), ApiScope::SubscribersWrite)?;
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let deleted = conn.transaction::<_, ApiError, _>(|conn| {
+        diesel::delete(
+            subscription_tokens::table
+                .filter(subscription_tokens::subscriber_id.eq(path.subscriber_id)),
+        )
+        .execute(conn)?;
+        Ok(diesel::delete(subscriptions::table.find(path.subscriber_id)).execute(conn)?)
+    })?;
+    if deleted == 0 {
+        return Err(ApiError::NotFoundError(
+            "There is no such subscriber.".to_string(),
+        ));
+    }
+    Ok(HttpResponse::NoContent().finish())
+}
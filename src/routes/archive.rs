@@ -0,0 +1,258 @@
+use crate::clock::Clock;
+use crate::db::PgPool;
+use crate::db_models::NewsletterIssue;
+use crate::domain::IssueStatus;
+use crate::email_templates::load_layout;
+use crate::issue_delivery::{IssueTemplate, MergeFields};
+use crate::markdown::RenderedContent;
+use crate::schema::newsletter_issues;
+use crate::startup::ApplicationBaseUrl;
+use crate::utils::{e500, render_page};
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use diesel::pg::{Pg, PgConnection};
+use diesel::prelude::*;
+use serde::Deserialize;
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+const PAGE_SIZE: i64 = 10;
+const FEED_LENGTH: i64 = 20;
+const FEED_TITLE: &str = "Newsletter";
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ArchiveParameters {
+    /// Pages are numbered from 1.
+    page: Option<i64>,
+}
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct IssuePath {
+    newsletter_issue_id: Uuid,
+}
+
+// Sent issues that were not kept out of the archive.
+fn archived_issues() -> newsletter_issues::BoxedQuery<'static, Pg> {
+    newsletter_issues::table
+        .filter(newsletter_issues::status.eq(IssueStatus::Sent.as_str()))
+        .filter(newsletter_issues::in_archive.eq(true))
+        .into_boxed()
+}
+
+fn latest_archived_issues(
+    conn: &mut PgConnection,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<NewsletterIssue>, diesel::result::Error> {
+    archived_issues()
+        .order(newsletter_issues::sent_at.desc())
+        .limit(limit)
+        .offset(offset)
+        .load::<NewsletterIssue>(conn)
+}
+
+// The issue as anyone may read it: merge fields get neutral values, links are not tracked and the
+// layout leaves out the unsubscribe block.
+fn public_content(
+    conn: &mut PgConnection,
+    issue: &NewsletterIssue,
+    application_base_url: &str,
+) -> Result<RenderedContent, anyhow::Error> {
+    let template = IssueTemplate::parse(&issue.html_content, &issue.text_content)
+        .map_err(anyhow::Error::msg)
+        .context("The stored content of the newsletter issue is not a valid template.")?;
+    let layout = load_layout(conn, &issue.layout)?;
+    // Readers of the archive are not subscribed, so unsubscribe links lead them to the signup page.
+    let signup_url = format!("{}/subscriptions", application_base_url);
+    let content = template.render(&MergeFields {
+        subscriber_name: "reader",
+        subscriber_email: "",
+        unsubscribe_url: &signup_url,
+        issue_title: &issue.title,
+    });
+    Ok(layout.render(&issue.title, &content, None))
+}
+
+#[derive(Template)]
+#[template(path = "archive/list.html")]
+struct ArchivePage {
+    issues: Vec<NewsletterIssue>,
+    page: i64,
+    last_page: i64,
+}
+
+/// The archive
+///
+/// The sent issues, newest first.
+#[utoipa::path(
+    get,
+    path = "/archive",
+    tag = "archive",
+    params(ArchiveParameters),
+    responses((status = 200, description = "A page of the archive.", content_type = "text/html", body = String))
+)]
+pub async fn archive(
+    parameters: web::Query<ArchiveParameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = pool.get().map_err(e500)?;
+    let total = archived_issues()
+        .count()
+        .get_result::<i64>(&mut conn)
+        .map_err(e500)?;
+    let last_page = ((total + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    let page = parameters.page.unwrap_or(1);
+    if !(1..=last_page).contains(&page) {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    let issues =
+        latest_archived_issues(&mut conn, PAGE_SIZE, (page - 1) * PAGE_SIZE).map_err(e500)?;
+    render_page(&ArchivePage {
+        issues,
+        page,
+        last_page,
+    })
+}
+
+#[derive(Template)]
+#[template(path = "archive/issue.html")]
+struct ArchivedIssuePage {
+    issue: NewsletterIssue,
+    content: RenderedContent,
+}
+
+/// An archived issue
+#[utoipa::path(
+    get,
+    path = "/archive/{newsletter_issue_id}",
+    tag = "archive",
+    params(IssuePath),
+    responses(
+        (status = 200, description = "The issue.", content_type = "text/html", body = String),
+        (status = 404, description = "The issue is not in the archive."),
+    )
+)]
+pub async fn archived_issue(
+    path: web::Path<IssuePath>,
+    pool: web::Data<PgPool>,
+    application_base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = pool.get().map_err(e500)?;
+    let Some(issue) = archived_issues()
+        .filter(newsletter_issues::newsletter_issue_id.eq(path.newsletter_issue_id))
+        .first::<NewsletterIssue>(&mut conn)
+        .optional()
+        .map_err(e500)?
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let content = public_content(&mut conn, &issue, &application_base_url.0).map_err(e500)?;
+    render_page(&ArchivedIssuePage { issue, content })
+}
+
+struct FeedEntry {
+    issue: NewsletterIssue,
+    sent_at: DateTime<Utc>,
+    content: RenderedContent,
+}
+
+#[derive(Template)]
+#[template(path = "feeds/atom.xml")]
+struct AtomFeed<'a> {
+    title: &'a str,
+    base_url: &'a str,
+    updated: DateTime<Utc>,
+    entries: Vec<FeedEntry>,
+}
+
+#[derive(Template)]
+#[template(path = "feeds/rss.xml")]
+struct RssFeed<'a> {
+    title: &'a str,
+    base_url: &'a str,
+    entries: Vec<FeedEntry>,
+}
+
+fn feed_entries(
+    pool: &PgPool,
+    application_base_url: &str,
+) -> Result<Vec<FeedEntry>, anyhow::Error> {
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issues = latest_archived_issues(&mut conn, FEED_LENGTH, 0)
+        .context("Failed to load the archived newsletter issues.")?;
+    issues
+        .into_iter()
+        .map(|issue| {
+            let content = public_content(&mut conn, &issue, application_base_url)?;
+            Ok(FeedEntry {
+                sent_at: issue.sent_at.unwrap_or(issue.created_at),
+                issue,
+                content,
+            })
+        })
+        .collect()
+}
+
+fn feed_response(
+    content_type: &str,
+    feed: &impl Template,
+) -> Result<HttpResponse, actix_web::Error> {
+    let xml = feed.render().map_err(e500)?;
+    Ok(HttpResponse::Ok().content_type(content_type).body(xml))
+}
+
+/// Atom feed
+#[utoipa::path(
+    get,
+    path = "/feed.xml",
+    tag = "archive",
+    responses((status = 200, description = "The latest archived issues.", content_type = "application/atom+xml", body = String))
+)]
+pub async fn atom_feed(
+    pool: web::Data<PgPool>,
+    application_base_url: web::Data<ApplicationBaseUrl>,
+    clock: web::Data<dyn Clock>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let entries = feed_entries(&pool, &application_base_url.0).map_err(e500)?;
+    let updated = entries
+        .first()
+        .map(|entry| entry.sent_at)
+        .unwrap_or_else(|| clock.now());
+    feed_response(
+        "application/atom+xml; charset=utf-8",
+        &AtomFeed {
+            title: FEED_TITLE,
+            base_url: &application_base_url.0,
+            updated,
+            entries,
+        },
+    )
+}
+
+/// RSS feed
+#[utoipa::path(
+    get,
+    path = "/rss.xml",
+    tag = "archive",
+    responses((status = 200, description = "The latest archived issues.", content_type = "application/rss+xml", body = String))
+)]
+pub async fn rss_feed(
+    pool: web::Data<PgPool>,
+    application_base_url: web::Data<ApplicationBaseUrl>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let entries = feed_entries(&pool, &application_base_url.0).map_err(e500)?;
+    feed_response(
+        "application/rss+xml; charset=utf-8",
+        &RssFeed {
+            title: FEED_TITLE,
+            base_url: &application_base_url.0,
+            entries,
+        },
+    )
+}
@@ -1,6 +1,198 @@
-use actix_web::HttpResponse;
+use crate::db::PgPool;
+use crate::email_client::EmailClient;
+use crate::settings::Settings;
+use actix_web::{web, HttpResponse};
+use anyhow::Context;
+use diesel::RunQueryDsl;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::time::{Duration, Instant};
+use utoipa::ToSchema;
 
+/// Health check
+#[utoipa::path(
+    get,
+    path = "/health_check",
+    tag = "health",
+    responses((status = 200, description = "The application is up."))
+)]
 pub async fn health_check() -> HttpResponse {
     HttpResponse::Ok().finish()
 }
 
+// A check that takes longer than this counts as failed.
+const CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum CheckStatus {
+    Up,
+    Down,
+}
+
+#[derive(Serialize, ToSchema)]
+struct Check {
+    status: CheckStatus,
+    latency_ms: u128,
+    /// Why the check failed: `timeout` or `unavailable`. The details are only logged, since they
+    /// can name hosts and credentials.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<&'static str>,
+}
+
+#[derive(Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+enum ReadinessStatus {
+    Ok,
+    Unavailable,
+}
+
+#[derive(Serialize, ToSchema)]
+struct Readiness {
+    status: ReadinessStatus,
+    /// By dependency: `postgres`, `redis` and, when it is checked, `email_provider`.
+    checks: BTreeMap<&'static str, Check>,
+    /// The dependencies that are down.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    failing: Vec<&'static str>,
+}
+
+async fn timed(
+    dependency: &'static str,
+    check: impl Future<Output = Result<(), anyhow::Error>>,
+) -> Check {
+    let started = Instant::now();
+    let error = match tokio::time::timeout(CHECK_TIMEOUT, check).await {
+        Ok(Ok(())) => None,
+        Ok(Err(e)) => {
+            tracing::warn!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                dependency,
+                "A readiness check failed"
+            );
+            Some("unavailable")
+        }
+        Err(_) => {
+            tracing::warn!(
+                dependency,
+                "A readiness check timed out after {:?}",
+                CHECK_TIMEOUT
+            );
+            Some("timeout")
+        }
+    };
+    Check {
+        status: if error.is_none() {
+            CheckStatus::Up
+        } else {
+            CheckStatus::Down
+        },
+        latency_ms: started.elapsed().as_millis(),
+        error,
+    }
+}
+
+async fn check_postgres(pool: web::Data<PgPool>) -> Result<(), anyhow::Error> {
+    web::block(move || -> Result<(), anyhow::Error> {
+        let mut conn = pool
+            .get()
+            .context("Failed to acquire a Postgres connection from the pool")?;
+        diesel::sql_query("SELECT 1")
+            .execute(&mut conn)
+            .context("Failed to query Postgres.")?;
+        Ok(())
+    })
+    .await
+    .context("The Postgres check was cancelled.")?
+}
+
+async fn check_redis(redis: &redis::Client) -> Result<(), anyhow::Error> {
+    let mut conn = redis
+        .get_multiplexed_async_connection()
+        .await
+        .context("Failed to connect to Redis.")?;
+    redis::cmd("PING")
+        .query_async::<String>(&mut conn)
+        .await
+        .context("Failed to ping Redis.")?;
+    Ok(())
+}
+
+/// Liveness
+///
+/// The process is up and serving requests.
+#[utoipa::path(
+    get,
+    path = "/health/live",
+    tag = "health",
+    responses((status = 200, description = "`{\"status\": \"ok\"}`"))
+)]
+pub async fn health_live() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({ "status": "ok" }))
+}
+
+/// Readiness
+///
+/// Whether the dependencies needed to serve traffic can be reached.
+#[utoipa::path(
+    get,
+    path = "/health/ready",
+    tag = "health",
+    responses(
+        (status = 200, description = "Every dependency can be reached.", body = Readiness),
+        (status = 503, description = "Some dependencies are down.", body = Readiness),
+    )
+)]
+pub async fn health_ready(
+    pool: web::Data<PgPool>,
+    redis: web::Data<redis::Client>,
+    email_client: web::Data<EmailClient>,
+    settings: web::Data<Settings>,
+) -> HttpResponse {
+    let email_check = async {
+        if settings.health_check_email_provider {
+            Some(
+                timed("email_provider", async {
+                    email_client
+                        .check_reachable()
+                        .await
+                        .context("The email provider cannot be reached.")
+                })
+                .await,
+            )
+        } else {
+            None
+        }
+    };
+    let (postgres, redis, email_provider) = tokio::join!(
+        timed("postgres", check_postgres(pool)),
+        timed("redis", check_redis(&redis)),
+        email_check
+    );
+
+    let mut checks = BTreeMap::from([("postgres", postgres), ("redis", redis)]);
+    if let Some(email_provider) = email_provider {
+        checks.insert("email_provider", email_provider);
+    }
+    let failing: Vec<&'static str> = checks
+        .iter()
+        .filter(|(_, check)| check.error.is_some())
+        .map(|(name, _)| *name)
+        .collect();
+    if failing.is_empty() {
+        HttpResponse::Ok().json(Readiness {
+            status: ReadinessStatus::Ok,
+            checks,
+            failing,
+        })
+    } else {
+        tracing::warn!(failing = ?failing, "The readiness check failed");
+        HttpResponse::ServiceUnavailable().json(Readiness {
+            status: ReadinessStatus::Unavailable,
+            checks,
+            failing,
+        })
+    }
+}
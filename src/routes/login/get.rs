@@ -1,42 +1,34 @@
+use crate::csrf::CsrfToken;
+use crate::utils::render_page;
 use actix_web::cookie::Cookie;
-use actix_web::{http::header::ContentType, HttpResponse};
-use actix_web_flash_messages::{IncomingFlashMessages, Level};
-use std::fmt::Write;
+use actix_web::{web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
 
-pub async fn login_form(flash_messages: IncomingFlashMessages) -> HttpResponse {
-    let mut error_html = String::new();
-    // for m in flash_messages.iter().filter(|m| m.level() == Level::Error) {
-    //     writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
-    // }
-    for m in flash_messages.iter() {
-        writeln!(error_html, "<p><i>{}</i></p>", m.content()).unwrap();
-    }
+#[derive(Template)]
+#[template(path = "login.html")]
+struct LoginPage<'a> {
+    flash_messages: &'a IncomingFlashMessages,
+    csrf_token: &'a str,
+}
 
-    let mut response = HttpResponse::Ok()
-        .content_type(ContentType::html())
-        .body(format!(
-            r#"<!DOCTYPE html>
-            <html lang="en">
-                <head>
-                    <meta http-equiv="content-type" content="text/html; charset=utf-8">
-                    <title>Login</title>
-                </head>
-            <body>
-            {error_html}
-                <form action="/login" method="post">
-                    <label>Username
-                        <input type="text" placeholder="Enter Username" name="username">
-                    </label>
-                    <label>Password
-                        <input type="password" placeholder="Enter Password" name="password">
-                    </label>
-                    <button type="submit">Login</button>
-                </form>
-            </body>
-            </html>"#,
-        ));
+/// The login page
+#[utoipa::path(
+    get,
+    path = "/login",
+    tag = "admin",
+    responses((status = 200, description = "The login page.", content_type = "text/html", body = String))
+)]
+pub async fn login_form(
+    flash_messages: IncomingFlashMessages,
+    csrf_token: web::ReqData<CsrfToken>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut response = render_page(&LoginPage {
+        flash_messages: &flash_messages,
+        csrf_token: csrf_token.as_str(),
+    })?;
     response
         .add_removal_cookie(&Cookie::new("_flash", ""))
         .unwrap();
-    response
+    Ok(response)
 }
@@ -1,6 +1,7 @@
 use crate::{
     authentication::{validate_credentials, AuthError, Credentials},
     db::PgPool,
+    metrics::METRICS,
     routes::subscriptions::error_chain_fmt,
     session_state::TypedSession,
 };
@@ -13,10 +14,15 @@ use actix_web::{
 };
 
 use secrecy::Secret;
+use std::collections::BTreeMap;
+use utoipa::openapi::{RefOr, Response, ResponseBuilder};
+use utoipa::IntoResponses;
 
-#[derive(serde::Deserialize)]
+/// Also needs the `csrf_token` of the login page.
+#[derive(serde::Deserialize, utoipa::ToSchema)]
 pub struct FormData {
     username: String,
+    #[schema(value_type = String, format = Password)]
     password: Secret<String>,
 }
 
@@ -29,6 +35,20 @@ fn login_redirect(e: LoginError) -> InternalError<LoginError> {
     InternalError::from_response(e, response)
 }
 
+/// Log in
+///
+/// For the admin pages, which use the session cookie it sets.
+#[utoipa::path(
+    post,
+    path = "/login",
+    tag = "admin",
+    request_body(content = FormData, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 403, description = "The CSRF token is missing or wrong."),
+        (status = 429, description = "Too many login attempts from the same address."),
+        LoginError,
+    )
+)]
 #[tracing::instrument(
 skip(form, pool, session),
 fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
@@ -57,6 +77,7 @@ pub async fn login(
                 .finish())
         }
         Err(e) => {
+            METRICS.record_login_failure();
             let e = match e {
                 AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
@@ -82,6 +103,19 @@ impl std::fmt::Debug for LoginError {
         error_chain_fmt(self, f)
     }
 }
+// Logging in answers with a redirect either way, so the errors share the 303 of a success.
+impl IntoResponses for LoginError {
+    fn responses() -> BTreeMap<String, RefOr<Response>> {
+        let response = ResponseBuilder::new()
+            .description(
+                "Redirects to `/admin/dashboard` once logged in, or back to `/login`, which shows \
+                 why logging in failed.",
+            )
+            .build();
+        BTreeMap::from([(StatusCode::SEE_OTHER.as_u16().to_string(), response.into())])
+    }
+}
+
 impl ResponseError for LoginError {
     fn status_code(&self) -> StatusCode {
         StatusCode::SEE_OTHER
@@ -0,0 +1,50 @@
+use crate::db::PgPool;
+use crate::metrics::METRICS;
+use crate::routes::email_webhooks::same_secret;
+use crate::settings::Settings;
+use crate::utils::e500;
+use actix_web::http::header::{AUTHORIZATION, WWW_AUTHENTICATE};
+use actix_web::{web, HttpRequest, HttpResponse};
+use secrecy::Secret;
+
+pub fn bearer_token(request: &HttpRequest) -> Option<Secret<String>> {
+    let header = request.headers().get(AUTHORIZATION)?.to_str().ok()?;
+    header
+        .strip_prefix("Bearer ")
+        .map(|token| Secret::new(token.trim().to_string()))
+}
+
+/// Prometheus metrics
+///
+/// Served on `METRICS_PORT` instead when it is set.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "health",
+    responses(
+        (status = 200, description = "The metrics in the Prometheus text format.", content_type = "text/plain", body = String),
+        (status = 401, description = "`METRICS_BEARER_TOKEN` is set and the request does not have it."),
+    ),
+    security((), ("metrics_token" = []))
+)]
+pub async fn metrics(
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+    settings: web::Data<Settings>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(expected) = &settings.metrics_bearer_token {
+        let authorized = bearer_token(&request).is_some_and(|token| same_secret(&token, expected));
+        if !authorized {
+            return Ok(HttpResponse::Unauthorized()
+                .insert_header((WWW_AUTHENTICATE, r#"Bearer realm="metrics""#))
+                .finish());
+        }
+    }
+    let body = web::block(move || METRICS.render(&pool))
+        .await
+        .map_err(e500)?
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4; charset=utf-8")
+        .body(body))
+}
@@ -1,7 +1,14 @@
 pub mod admin;
+pub mod api;
+pub mod archive;
+pub mod email_webhooks;
 pub mod health_check;
-pub mod home;
 pub mod login;
+pub mod metrics;
 pub mod newsletter;
+pub mod newsletter_issues;
+pub mod openapi;
 pub mod subscriptions;
 pub mod subscriptions_confirm;
+pub mod subscriptions_unsubscribe;
+pub mod tracking;
@@ -1,44 +1,104 @@
 use crate::{
     authentication::{validate_credentials, AuthError, Credentials},
+    clock::Clock,
     db::PgPool,
-    domain::SubscriberEmail,
+    db_models::NewsletterIssue,
+    domain::{IssueStatus, Segment},
     email_client::EmailClient,
-    routes::subscriptions::error_chain_fmt,
-    schema::{
-        subscriptions::{self, dsl::*},
-        users,
+    email_templates::{get_layout, DEFAULT_LAYOUT},
+    issue_delivery::{
+        confirmed_subscribers_query, deliver_issue, insert_newsletter_issue, DeliveryOutcome,
+        IssueTemplate, NewNewsletterIssue,
     },
+    markdown::{render_markdown, RenderedContent},
+    routes::api::{error_body, error_responses},
+    routes::newsletter_issues::IssueSummary,
+    routes::subscriptions::error_chain_fmt,
+    shutdown::Shutdown,
+    signing::SigningKeys,
+    startup::ApplicationBaseUrl,
 };
-use actix_web::http::header::{self, HeaderMap, HeaderValue};
+use actix_web::http::header::{self, HeaderMap};
 use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
-use argon2::{Algorithm, Argon2, Params, Version};
-use argon2::{PasswordHash, PasswordVerifier}; // PasswordHash for hash_password fn; PasswordVerifier for verify_password
 use base64;
+use chrono::{DateTime, Utc};
 use diesel::prelude::*;
-use diesel::Queryable;
-use secrecy::{ExposeSecret, Secret};
-use serde::Deserialize;
+use secrecy::Secret;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use utoipa::openapi::{RefOr, Response};
+use utoipa::{IntoParams, IntoResponses, ToSchema};
 use uuid::Uuid;
-#[derive(Deserialize)]
+/// A newsletter issue to send or schedule.
+#[derive(Deserialize, ToSchema)]
 pub struct BodyData {
     title: String,
     content: Content,
+    /// Optional segment query restricting the recipients, e.g. `tag:beta AND NOT tag:churned`.
+    segment: Option<String>,
+    /// When set, the issue is stored and sent by the scheduler at that time instead of right away.
+    scheduled_at: Option<DateTime<Utc>>,
+    /// The name of the email layout to wrap the issue in, `default` if omitted.
+    layout: Option<String>,
+    /// Whether opens and clicks of the issue are tracked.
+    #[serde(default)]
+    tracking: bool,
+    /// Keeps the issue out of the public archive and feeds once it is sent.
+    #[serde(default)]
+    hide_from_archive: bool,
+}
+/// Either Markdown, from which both bodies are rendered, or hand-written HTML and plain text.
+#[derive(Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum Content {
+    Markdown { markdown: String },
+    Html { html: String, text: String },
+}
+
+impl Content {
+    pub fn markdown(&self) -> Option<&str> {
+        match self {
+            Content::Markdown { markdown } => Some(markdown),
+            Content::Html { .. } => None,
+        }
+    }
+
+    pub fn render(&self) -> RenderedContent {
+        match self {
+            Content::Markdown { markdown } => render_markdown(markdown),
+            Content::Html { html, text } => RenderedContent {
+                html: html.clone(),
+                text: text.clone(),
+            },
+        }
+    }
 }
-#[derive(Deserialize)]
-pub struct Content {
-    html: String,
-    text: String,
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct SegmentParameters {
+    /// The segment query, e.g. `tag:beta`. Every confirmed subscriber if omitted.
+    segment: Option<String>,
 }
-#[derive(Debug, Deserialize, Queryable)]
-pub struct ConfirmedSubscriber {
-    email: SubscriberEmail,
+
+#[derive(Serialize, ToSchema)]
+pub struct SegmentCount {
+    /// How many confirmed subscribers the segment matches.
+    count: i64,
 }
 
 #[derive(thiserror::Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum PublishError {
+    #[error("{0}")]
+    ValidationError(String),
     #[error("Authentication failed.")]
     AuthError(#[source] anyhow::Error),
+    #[error("{0}")]
+    NotFoundError(String),
+    #[error("{0}")]
+    ConflictError(String),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -48,27 +108,59 @@ impl std::fmt::Debug for PublishError {
         error_chain_fmt(self, f)
     }
 }
+impl PublishError {
+    fn code(&self) -> &'static str {
+        match self {
+            PublishError::ValidationError(_) => "invalid_request",
+            PublishError::AuthError(_) => "unauthorized",
+            PublishError::NotFoundError(_) => "not_found",
+            PublishError::ConflictError(_) => "conflict",
+            PublishError::UnexpectedError(_) => "internal_error",
+        }
+    }
+}
+
+impl IntoResponses for PublishError {
+    fn responses() -> BTreeMap<String, RefOr<Response>> {
+        let errors = [
+            PublishError::ValidationError(String::new()),
+            PublishError::AuthError(anyhow::anyhow!("")),
+            PublishError::NotFoundError(String::new()),
+            PublishError::ConflictError(String::new()),
+            PublishError::UnexpectedError(anyhow::anyhow!("")),
+        ];
+        error_responses(errors.iter().map(|e| (e.status_code(), e.code())))
+    }
+}
+
 impl ResponseError for PublishError {
-    fn error_response(&self) -> HttpResponse {
+    fn status_code(&self) -> StatusCode {
         match self {
-            PublishError::UnexpectedError(_) => {
-                HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
-            }
-            PublishError::AuthError(_) => {
-                let mut response = HttpResponse::new(StatusCode::UNAUTHORIZED);
-                let header_value = HeaderValue::from_str(r#"Basic realm="publish""#).unwrap();
-                response
-                    .headers_mut()
-                    // actix_web::http::header provides a collection of constants
-                    // for the names of several well-known/standard HTTP headers
-                    .insert(header::WWW_AUTHENTICATE, header_value);
-                response
-            }
+            PublishError::ValidationError(_) => StatusCode::BAD_REQUEST,
+            PublishError::AuthError(_) => StatusCode::UNAUTHORIZED,
+            PublishError::NotFoundError(_) => StatusCode::NOT_FOUND,
+            PublishError::ConflictError(_) => StatusCode::CONFLICT,
+            PublishError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    // The same JSON errors as the `/api/v1` endpoints.
+    fn error_response(&self) -> HttpResponse {
+        let mut response = HttpResponse::build(self.status_code());
+        if let PublishError::AuthError(_) = self {
+            // actix_web::http::header provides a collection of constants
+            // for the names of several well-known/standard HTTP headers
+            response.insert_header((header::WWW_AUTHENTICATE, r#"Basic realm="publish""#));
         }
+        let message = match self {
+            PublishError::UnexpectedError(_) => "Something went wrong on our side.".to_string(),
+            e => e.to_string(),
+        };
+        response.json(error_body(self.code(), &message))
     }
 }
 
-fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
+pub fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Error> {
     // The header value, if present, must be a valid UTF8 string
     let header_value = headers
         .get("Authorization")
@@ -102,79 +194,190 @@ fn basic_authentication(headers: &HeaderMap) -> Result<Credentials, anyhow::Erro
     })
 }
 
-#[tracing::instrument(name = "Get confirmed subscribers", skip(pool))]
-async fn get_confirmed_subscribers(
-    pool: &web::Data<PgPool>,
-) -> Result<Vec<Result<ConfirmedSubscriber, anyhow::Error>>, anyhow::Error> {
-    let mut conn = pool.get().expect("Failed to get db connection from pool");
+// Authenticates the caller through 'Basic' auth and records who they are on the current span.
+pub async fn authenticate(request: &HttpRequest, pool: &PgPool) -> Result<Uuid, PublishError> {
+    let credentials = basic_authentication(request.headers()).map_err(PublishError::AuthError)?;
+    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
 
-    let rows = subscriptions::table
-        .filter(subscriptions::status.eq("confirmed"))
-        .select(subscriptions::email)
-        .load::<String>(&mut conn)?;
-    let confirmed_subscribers = rows
-        .into_iter()
-        .map(|r| match SubscriberEmail::parse(r) {
-            Ok(user_email) => Ok(ConfirmedSubscriber { email: user_email }),
-            Err(error) => Err(anyhow::anyhow!(error)),
-        })
-        .collect();
+    let user_id = validate_credentials(credentials, pool)
+        .await
+        .map_err(|e| match e {
+            AuthError::InvalidCredentials(_) => PublishError::AuthError(e.into()),
+            AuthError::UnexpectedError(_) => PublishError::UnexpectedError(e.into()),
+        })?;
+    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+    Ok(user_id)
+}
 
-    Ok(confirmed_subscribers)
+pub fn parse_segment(segment: Option<&str>) -> Result<Option<Segment>, PublishError> {
+    segment
+        .filter(|s| !s.trim().is_empty())
+        .map(Segment::parse)
+        .transpose()
+        .map_err(PublishError::ValidationError)
 }
+
+// Scheduled issues must be due strictly after `now`, otherwise they would go out right away.
+pub fn validate_scheduled_at(
+    scheduled_at: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Result<DateTime<Utc>, PublishError> {
+    if scheduled_at <= now {
+        return Err(PublishError::ValidationError(
+            "The scheduled time must be in the future.".to_string(),
+        ));
+    }
+    Ok(scheduled_at)
+}
+
+fn validate_layout(pool: &PgPool, layout: &str) -> Result<(), PublishError> {
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    match get_layout(&mut conn, layout).context("Failed to fetch the email layout.")? {
+        Some(_) => Ok(()),
+        None => Err(PublishError::ValidationError(format!(
+            "There is no email layout named {}.",
+            layout
+        ))),
+    }
+}
+
+// Checks a new issue and stores it: scheduled if it has a `scheduled_at`, with
+// `unscheduled_status` otherwise.
+pub fn store_issue(
+    pool: &PgPool,
+    body: &BodyData,
+    now: DateTime<Utc>,
+    unscheduled_status: IssueStatus,
+) -> Result<NewsletterIssue, PublishError> {
+    parse_segment(body.segment.as_deref())?;
+    let scheduled_at = body
+        .scheduled_at
+        .map(|scheduled_at| validate_scheduled_at(scheduled_at, now))
+        .transpose()?;
+    let status = match scheduled_at {
+        Some(_) => IssueStatus::Scheduled,
+        None => unscheduled_status,
+    };
+
+    let content = body.content.render();
+    // Catches unknown merge fields and syntax errors before anything is stored or sent.
+    IssueTemplate::parse(&content.html, &content.text).map_err(PublishError::ValidationError)?;
+    let layout = body.layout.as_deref().unwrap_or(DEFAULT_LAYOUT);
+    validate_layout(pool, layout)?;
+
+    let issue = insert_newsletter_issue(
+        pool,
+        &NewNewsletterIssue {
+            newsletter_issue_id: Uuid::new_v4(),
+            title: &body.title,
+            text_content: &content.text,
+            html_content: &content.html,
+            markdown_content: body.content.markdown(),
+            segment: body.segment.as_deref().filter(|s| !s.trim().is_empty()),
+            layout,
+            tracking_enabled: body.tracking,
+            in_archive: !body.hide_from_archive,
+            status: status.as_str(),
+            scheduled_at,
+            // Issues sent right away are claimed by whoever stores them.
+            claimed_at: (status == IssueStatus::Sending).then_some(now),
+            created_at: now,
+        },
+    )?;
+    Ok(issue)
+}
+
+/// Publish a newsletter issue
+///
+/// Sends the issue to the confirmed subscribers right away, or stores it for the scheduler if it
+/// has a `scheduled_at`.
+#[utoipa::path(
+    post,
+    path = "/newsletters",
+    tag = "newsletters",
+    request_body = BodyData,
+    responses(
+        (status = 200, description = "The issue was scheduled and the body is its `IssueSummary`, or it was sent and the body is the id of the user who sent it."),
+        (status = 202, description = "The application was shut down before every subscriber got the issue; the rest get it once it is back up."),
+        PublishError,
+    ),
+    security(("basic" = []))
+)]
 #[tracing::instrument(
 name = "Publish a newsletter issue",
-skip(body, pool, email_client, request),
+skip(body, pool, email_client, clock, application_base_url, signing_keys, shutdown, request),
 fields(username=tracing::field::Empty, user_id=tracing::field::Empty) // Defines fields to be included in the span. Here, username and user_id are included but are initially empty. These fields will be populated later in the function.
 )]
 
+#[allow(clippy::too_many_arguments)]
 pub async fn publish_newsletter(
     body: web::Json<BodyData>,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
+    clock: web::Data<dyn Clock>,
+    application_base_url: web::Data<ApplicationBaseUrl>,
+    signing_keys: web::Data<SigningKeys>,
+    shutdown: web::Data<Shutdown>,
     request: HttpRequest,
 ) -> Result<HttpResponse, PublishError> {
-    let credentials = basic_authentication(request.headers()).map_err(PublishError::AuthError)?;
-    tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
-
-    let user_id = validate_credentials(credentials, &pool)
-        .await
-        .map_err(|e| match e {
-            AuthError::InvalidCredentials(_) => PublishError::AuthError(e.into()),
-            AuthError::UnexpectedError(_) => PublishError::UnexpectedError(e.into()),
-        })?;
-    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+    let user_id = authenticate(&request, &pool).await?;
+    let issue = store_issue(&pool, &body, clock.now(), IssueStatus::Sending)?;
+    if issue.scheduled_at.is_some() {
+        return Ok(HttpResponse::Ok().json(IssueSummary::from(issue)));
+    }
 
-    let subscribers = get_confirmed_subscribers(&pool).await?;
-
-    for subscriber in subscribers {
-        match subscriber {
-            Ok(subscriber) => {
-                email_client
-                    .send_email(
-                        &subscriber.email,
-                        &body.title,
-                        &body.content.html,
-                        &body.content.text,
-                    )
-                    .await
-                    // with_context is used to convert the error variant of Result into anyhow::Error while enriching it with contextual information.
-                    // with_context is lazy i.e., only called in case of an error
-                    .with_context(|| {
-                        format!("Failed to send newsletter issue to {}", subscriber.email)
-                    })?;
-            }
-            Err(error) => {
-                tracing::warn!(
-                // We record the error chain as a structured fieldon the log record.
-                error.cause_chain = ?error,
-                // Using `\` to split a long string literal overtwo lines, without creating a `\n` character.
-                "Skipping a confirmed subscriber. \
-                Their stored contact details are invalid",
-                );
-            }
-        }
+    let outcome = deliver_issue(
+        &pool,
+        &email_client,
+        &issue,
+        clock.as_ref(),
+        &application_base_url.0,
+        &signing_keys.tracking,
+        &shutdown,
+    )
+    .await?;
+    if outcome == DeliveryOutcome::Interrupted {
+        // The rest of the subscribers are sent the issue once the application is back up.
+        return Ok(HttpResponse::Accepted().finish());
     }
 
     Ok(HttpResponse::Ok().json(user_id))
 }
+
+/// Count the subscribers of a segment
+#[utoipa::path(
+    get,
+    path = "/newsletters/preview",
+    tag = "newsletters",
+    params(SegmentParameters),
+    responses(
+        (status = 200, description = "How many confirmed subscribers would get an issue sent to the segment.", body = SegmentCount),
+        PublishError,
+    ),
+    security(("basic" = []))
+)]
+#[tracing::instrument(
+    name = "Preview a newsletter segment",
+    skip(parameters, pool, request),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn preview_segment(
+    parameters: web::Query<SegmentParameters>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    authenticate(&request, &pool).await?;
+    let segment = parse_segment(parameters.segment.as_deref())?;
+
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let count = confirmed_subscribers_query(segment.as_ref())
+        .count()
+        .get_result::<i64>(&mut conn)
+        .context("Failed to count the subscribers matching the segment.")?;
+
+    Ok(HttpResponse::Ok().json(SegmentCount { count }))
+}
@@ -0,0 +1,207 @@
+use crate::{
+    clock::Clock,
+    db::PgPool,
+    db_models::NewsletterIssue,
+    domain::IssueStatus,
+    routes::newsletter::{authenticate, validate_scheduled_at, PublishError},
+    schema::newsletter_issues,
+};
+use actix_web::{web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct IssuePath {
+    newsletter_issue_id: Uuid,
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct ScheduleData {
+    /// When the scheduler sends the issue. Must be in the future.
+    pub scheduled_at: DateTime<Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct IssueSummary {
+    pub newsletter_issue_id: Uuid,
+    pub title: String,
+    /// `draft`, `scheduled`, `sending`, `sent`, `cancelled` or `failed`.
+    pub status: String,
+    pub scheduled_at: Option<DateTime<Utc>>,
+    pub sent_at: Option<DateTime<Utc>>,
+    /// How many subscribers the email provider refused to send the issue to.
+    pub failed_deliveries: i32,
+}
+
+impl From<NewsletterIssue> for IssueSummary {
+    fn from(issue: NewsletterIssue) -> Self {
+        Self {
+            newsletter_issue_id: issue.newsletter_issue_id,
+            title: issue.title,
+            status: issue.status,
+            scheduled_at: issue.scheduled_at,
+            sent_at: issue.sent_at,
+            failed_deliveries: issue.failed_deliveries,
+        }
+    }
+}
+
+pub fn get_issue(
+    conn: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+) -> Result<NewsletterIssue, PublishError> {
+    newsletter_issues::table
+        .find(newsletter_issue_id)
+        .first::<NewsletterIssue>(conn)
+        .optional()
+        .context("Failed to fetch the newsletter issue.")?
+        .ok_or_else(|| PublishError::NotFoundError("There is no such newsletter issue.".into()))
+}
+
+// Applies `changes` only if the issue has not started sending yet. The status check is part
+// of the UPDATE so that it cannot race with the scheduler claiming the issue.
+pub fn update_editable_issue<V>(
+    conn: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+    changes: V,
+) -> Result<NewsletterIssue, PublishError>
+where
+    V: diesel::AsChangeset<Target = newsletter_issues::table>,
+    <V as diesel::AsChangeset>::Changeset: diesel::query_builder::QueryFragment<diesel::pg::Pg>,
+{
+    let editable_statuses = [
+        IssueStatus::Draft.as_str(),
+        IssueStatus::Scheduled.as_str(),
+        IssueStatus::Failed.as_str(),
+    ];
+    let updated = diesel::update(
+        newsletter_issues::table
+            .find(newsletter_issue_id)
+            .filter(newsletter_issues::status.eq_any(editable_statuses)),
+    )
+    .set(changes)
+    .get_result::<NewsletterIssue>(conn)
+    .optional()
+    .context("Failed to update the newsletter issue.")?;
+
+    match updated {
+        Some(issue) => Ok(issue),
+        None => {
+            let issue = get_issue(conn, newsletter_issue_id)?;
+            Err(PublishError::ConflictError(format!(
+                "The newsletter issue is {} and can no longer be changed.",
+                issue.status
+            )))
+        }
+    }
+}
+
+/// Get a newsletter issue
+#[utoipa::path(
+    get,
+    path = "/newsletters/{newsletter_issue_id}",
+    tag = "newsletters",
+    params(IssuePath),
+    responses((status = 200, body = IssueSummary), PublishError),
+    security(("basic" = []))
+)]
+#[tracing::instrument(
+    name = "Get a newsletter issue",
+    skip(path, pool, request),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn get_newsletter_issue(
+    path: web::Path<IssuePath>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    authenticate(&request, &pool).await?;
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issue = get_issue(&mut conn, path.newsletter_issue_id)?;
+    Ok(HttpResponse::Ok().json(IssueSummary::from(issue)))
+}
+
+/// Reschedule a newsletter issue
+///
+/// Drafts and failed issues are scheduled too. Issues that started sending can no longer be
+/// rescheduled.
+#[utoipa::path(
+    put,
+    path = "/newsletters/{newsletter_issue_id}/schedule",
+    tag = "newsletters",
+    params(IssuePath),
+    request_body = ScheduleData,
+    responses((status = 200, body = IssueSummary), PublishError),
+    security(("basic" = []))
+)]
+#[tracing::instrument(
+    name = "Reschedule a newsletter issue",
+    skip(path, body, pool, clock, request),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn schedule_newsletter_issue(
+    path: web::Path<IssuePath>,
+    body: web::Json<ScheduleData>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    authenticate(&request, &pool).await?;
+    let scheduled_at = validate_scheduled_at(body.scheduled_at, clock.now())?;
+
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issue = update_editable_issue(
+        &mut conn,
+        path.newsletter_issue_id,
+        (
+            newsletter_issues::status.eq(IssueStatus::Scheduled.as_str()),
+            newsletter_issues::scheduled_at.eq(Some(scheduled_at)),
+            newsletter_issues::delivery_failures.eq(0),
+        ),
+    )?;
+    Ok(HttpResponse::Ok().json(IssueSummary::from(issue)))
+}
+
+/// Cancel a newsletter issue
+///
+/// Only drafts, scheduled and failed issues can be cancelled.
+#[utoipa::path(
+    post,
+    path = "/newsletters/{newsletter_issue_id}/cancel",
+    tag = "newsletters",
+    params(IssuePath),
+    responses((status = 200, body = IssueSummary), PublishError),
+    security(("basic" = []))
+)]
+#[tracing::instrument(
+    name = "Cancel a newsletter issue",
+    skip(path, pool, request),
+    fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
+)]
+pub async fn cancel_newsletter_issue(
+    path: web::Path<IssuePath>,
+    pool: web::Data<PgPool>,
+    request: HttpRequest,
+) -> Result<HttpResponse, PublishError> {
+    authenticate(&request, &pool).await?;
+
+    let mut conn = pool
+        .get()
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let issue = update_editable_issue(
+        &mut conn,
+        path.newsletter_issue_id,
+        newsletter_issues::status.eq(IssueStatus::Cancelled.as_str()),
+    )?;
+    Ok(HttpResponse::Ok().json(IssueSummary::from(issue)))
+}
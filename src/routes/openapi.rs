@@ -0,0 +1,135 @@
+use crate::routes::{
+    api::{self, ErrorBody},
+    archive, email_webhooks, health_check, login, metrics, newsletter, newsletter_issues,
+    subscriptions::{self, FieldErrorsBody},
+    subscriptions_confirm, subscriptions_unsubscribe, tracking,
+};
+use crate::utils::render_page;
+use actix_web::HttpResponse;
+use askama::Template;
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+// Every route but the admin pages, which are only for people. `tests/api/openapi.rs` fails when
+// a route is missing from `paths`.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Newsletter",
+        description = "Sign people up, publish newsletter issues and follow how they do. \
+            Errors of `/newsletters` and `/api/v1` have an `ErrorBody`."
+    ),
+    paths(
+        health_check::health_check,
+        health_check::health_live,
+        health_check::health_ready,
+        metrics::metrics,
+        subscriptions::signup_form,
+        subscriptions::subscribe,
+        subscriptions_confirm::confirm,
+        subscriptions_unsubscribe::unsubscribe_form,
+        subscriptions_unsubscribe::unsubscribe,
+        email_webhooks::receive_email_webhook,
+        tracking::track_open,
+        tracking::track_click,
+        newsletter::publish_newsletter,
+        newsletter::preview_segment,
+        newsletter_issues::get_newsletter_issue,
+        newsletter_issues::schedule_newsletter_issue,
+        newsletter_issues::cancel_newsletter_issue,
+        api::subscribers::list_subscribers,
+        api::subscribers::create_subscriber,
+        api::subscribers::get_subscriber,
+        api::subscribers::update_subscriber,
+        api::subscribers::delete_subscriber,
+        api::issues::list_issues,
+        api::issues::create_issue,
+        api::issues::get_issue_status,
+        api::issues::schedule_issue,
+        api::issues::send_issue,
+        api::stats::issue_stats,
+        api::stats::overall_stats,
+        openapi_json,
+        api_docs,
+        archive::archive,
+        archive::archived_issue,
+        archive::atom_feed,
+        archive::rss_feed,
+        login::get::login_form,
+        login::post::login,
+    ),
+    components(schemas(ErrorBody, FieldErrorsBody)),
+    modifiers(&SecuritySchemes, &HomePage),
+    tags(
+        (name = "subscriptions", description = "Signing up and unsubscribing."),
+        (name = "newsletters", description = "Publishing issues, with the credentials of an admin."),
+        (name = "subscribers", description = "`/api/v1`, with an API key."),
+        (name = "issues", description = "`/api/v1`, with an API key."),
+        (name = "stats", description = "`/api/v1`, with an API key."),
+        (name = "archive", description = "The sent issues, for anyone to read."),
+        (name = "tracking", description = "The links of tracked issues."),
+        (name = "webhooks", description = "Events from the email provider."),
+        (name = "health"),
+        (name = "admin"),
+        (name = "docs"),
+    )
+)]
+pub struct ApiDoc;
+
+struct SecuritySchemes;
+
+impl Modify for SecuritySchemes {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        let schemes = [
+            ("api_key", HttpAuthScheme::Bearer),
+            ("basic", HttpAuthScheme::Basic),
+            ("webhook", HttpAuthScheme::Basic),
+            ("metrics_token", HttpAuthScheme::Bearer),
+        ];
+        for (name, scheme) in schemes {
+            components.add_security_scheme(name, SecurityScheme::Http(Http::new(scheme)));
+        }
+    }
+}
+
+// `/` is the signup form too, and a handler can only be documented under one path.
+struct HomePage;
+
+impl Modify for HomePage {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(signup_form) = openapi.paths.paths.get("/subscriptions").cloned() {
+            let mut home = signup_form;
+            home.post = None;
+            openapi.paths.paths.insert("/".to_string(), home);
+        }
+    }
+}
+
+/// This document
+#[utoipa::path(
+    get,
+    path = "/api/openapi.json",
+    tag = "docs",
+    responses((status = 200, description = "The OpenAPI document of the application."))
+)]
+pub async fn openapi_json() -> HttpResponse {
+    HttpResponse::Ok().json(ApiDoc::openapi())
+}
+
+#[derive(Template)]
+#[template(path = "api_docs.html")]
+struct ApiDocsPage;
+
+/// The API reference
+///
+/// This document rendered by Redoc. Only served with `API_DOCS_UI=true`.
+#[utoipa::path(
+    get,
+    path = "/api/docs",
+    tag = "docs",
+    responses((status = 200, description = "The API reference.", content_type = "text/html", body = String))
+)]
+pub async fn api_docs() -> Result<HttpResponse, actix_web::Error> {
+    render_page(&ApiDocsPage)
+}
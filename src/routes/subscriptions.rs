@@ -1,12 +1,18 @@
+use crate::bot_protection::{BotProtection, Submission, Verdict};
+use crate::clock::Clock;
 use crate::db::PgPool;
 use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
 use crate::email_client::EmailClient;
+use crate::email_templates::{render_transactional_email, TransactionalEmail};
 use crate::schema::subscription_tokens;
 use crate::schema::subscription_tokens::dsl as subs_token_dsl;
-use crate::schema::subscriptions::{self, dsl::*};
+use crate::schema::subscriptions;
 use crate::startup::ApplicationBaseUrl;
-use actix_web::{http::StatusCode, web, HttpResponse, ResponseError};
+use crate::utils::{prefers_html, render_page};
+use actix_web::http::header::ContentType;
+use actix_web::{http::StatusCode, web, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
+use askama::Template;
 use chrono::Utc;
 use diesel::pg::PgConnection;
 use diesel::prelude::Insertable;
@@ -14,16 +20,37 @@ use diesel::prelude::Insertable;
 use diesel::prelude::*;
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
-use reqwest;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use thiserror::Error;
 use tracing;
+use utoipa::openapi::{ContentBuilder, Ref, RefOr, Response, ResponseBuilder};
+use utoipa::{IntoResponses, ToSchema};
 use uuid::Uuid;
 
-#[derive(Deserialize)]
+/// A signup, sent with the signup form. Every submission is checked for bots, so it needs the
+/// `form_token` of the form; programmatic signups go through `POST /api/v1/subscribers`.
+#[derive(Deserialize, ToSchema)]
 pub struct FormData {
     email: String,
     name: String,
+    /// The token the signup form was rendered with.
+    form_token: Option<String>,
+    /// The honeypot, hidden from people by the signup form.
+    website: Option<String>,
+    /// Needed when a CAPTCHA is configured.
+    #[serde(alias = "h-captcha-response", alias = "cf-turnstile-response")]
+    captcha_response: Option<String>,
+}
+
+impl FormData {
+    fn submission(&self) -> Submission<'_> {
+        Submission {
+            form_token: self.form_token.as_deref(),
+            honeypot: self.website.as_deref(),
+            captcha_response: self.captcha_response.as_deref(),
+        }
+    }
 }
 
 #[derive(Insertable, Deserialize, Queryable)]
@@ -71,10 +98,33 @@ pub fn error_chain_fmt(
     Ok(())
 }
 
+/// What is wrong with each field of a signup, shown next to the field on the signup page.
+#[derive(Debug, Default, Serialize, ToSchema)]
+pub struct FieldErrors {
+    /// Set when the signup form itself is wrong, rather than one of its fields.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub form: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub email: Option<String>,
+}
+
+impl std::fmt::Display for FieldErrors {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let errors: Vec<&str> = [&self.form, &self.name, &self.email]
+            .into_iter()
+            .flatten()
+            .map(String::as_str)
+            .collect();
+        write!(f, "{}", errors.join(" "))
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum SubscribeError {
     #[error("{0}")]
-    ValidationError(String),
+    ValidationError(FieldErrors),
 
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
@@ -92,9 +142,116 @@ impl ResponseError for SubscribeError {
             SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SubscribeError::ValidationError(errors) => {
+                HttpResponse::BadRequest().json(FieldErrorsBody { errors })
+            }
+            SubscribeError::UnexpectedError(_) => HttpResponse::new(self.status_code()),
+        }
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct FieldErrorsBody<'a> {
+    errors: &'a FieldErrors,
+}
+
+impl IntoResponses for SubscribeError {
+    fn responses() -> BTreeMap<String, RefOr<Response>> {
+        let invalid = ResponseBuilder::new()
+            .description("The signup is invalid. People get the signup form back instead.")
+            .content(
+                "application/json",
+                ContentBuilder::new()
+                    .schema(Some(Ref::from_schema_name("FieldErrorsBody")))
+                    .build(),
+            )
+            .build();
+        let unexpected = ResponseBuilder::new()
+            .description("Something went wrong on our side.")
+            .build();
+        BTreeMap::from([
+            ("400".to_string(), invalid.into()),
+            ("500".to_string(), unexpected.into()),
+        ])
+    }
+}
+
+// Where a subscriber lands while signing up. API clients get the same outcome as JSON.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SubscriptionOutcome {
+    CheckInbox,
+    Confirmed,
+    AlreadyConfirmed,
+    InvalidLink,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct OutcomeBody {
+    /// `pending_confirmation`, `confirmed`, `already_confirmed` or `invalid_token`.
+    status: &'static str,
+}
+
+#[derive(Template)]
+#[template(path = "subscriptions/check_inbox.html")]
+struct CheckInboxPage;
+
+#[derive(Template)]
+#[template(path = "subscriptions/confirmed.html")]
+struct ConfirmedPage;
+
+#[derive(Template)]
+#[template(path = "subscriptions/already_confirmed.html")]
+struct AlreadyConfirmedPage;
+
+#[derive(Template)]
+#[template(path = "subscriptions/invalid_link.html")]
+struct InvalidLinkPage;
+
+impl SubscriptionOutcome {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            SubscriptionOutcome::InvalidLink => StatusCode::UNAUTHORIZED,
+            _ => StatusCode::OK,
+        }
+    }
+
+    // The `status` of the JSON response.
+    fn as_str(&self) -> &'static str {
+        match self {
+            SubscriptionOutcome::CheckInbox => "pending_confirmation",
+            SubscriptionOutcome::Confirmed => "confirmed",
+            SubscriptionOutcome::AlreadyConfirmed => "already_confirmed",
+            SubscriptionOutcome::InvalidLink => "invalid_token",
+        }
+    }
+
+    fn render(&self) -> askama::Result<String> {
+        match self {
+            SubscriptionOutcome::CheckInbox => CheckInboxPage.render(),
+            SubscriptionOutcome::Confirmed => ConfirmedPage.render(),
+            SubscriptionOutcome::AlreadyConfirmed => AlreadyConfirmedPage.render(),
+            SubscriptionOutcome::InvalidLink => InvalidLinkPage.render(),
+        }
+    }
+
+    pub fn respond(&self, req: &HttpRequest) -> askama::Result<HttpResponse> {
+        let mut response = HttpResponse::build(self.status_code());
+        if prefers_html(req) {
+            Ok(response
+                .content_type(ContentType::html())
+                .body(self.render()?))
+        } else {
+            Ok(response.json(OutcomeBody {
+                status: self.as_str(),
+            }))
+        }
+    }
 }
 
-fn generate_subscription_token() -> String {
+pub fn generate_subscription_token() -> String {
     let mut rng = thread_rng();
     std::iter::repeat_with(|| rng.sample(Alphanumeric))
         .map(char::from)
@@ -107,7 +264,7 @@ fn generate_subscription_token() -> String {
     skip(conn, new_subscriber)
 )]
 
-fn insert_subscriber(
+pub fn insert_subscriber(
     conn: &mut PgConnection,
     new_subscriber: &NewSubscriber,
 ) -> Result<Uuid, diesel::result::Error> {
@@ -128,49 +285,141 @@ fn insert_subscriber(
 }
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber)
+    skip(conn, email_client, new_subscriber)
 )]
-async fn send_confirmation_email(
+pub async fn send_confirmation_email(
+    conn: &mut PgConnection,
     email_client: &EmailClient,
     new_subscriber: NewSubscriber,
     application_base_url: &str,
     subscription_token: &str,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), anyhow::Error> {
     let confirmation_link = format!(
         "{}/subscriptions/confirm?subscription_token={}",
         application_base_url, subscription_token
     );
-
-    let plain_body = format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
-    );
-    let html_body = format!(
-        "Welcome to our newsletter!<br />\
-        Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
-    );
+    let (subject, content) = render_transactional_email(
+        conn,
+        TransactionalEmail::Confirmation,
+        &[
+            ("subscriber.name", new_subscriber.name.as_ref()),
+            ("confirmation_link", &confirmation_link),
+        ],
+    )?;
 
     email_client
-        .send_email(&new_subscriber.email, "Welcome!", &html_body, &plain_body)
-        .await
+        .send_email(&new_subscriber.email, &subject, &content.html, &content.text)
+        .await?;
+    Ok(())
+}
+
+#[derive(Template)]
+#[template(path = "subscriptions/signup.html")]
+struct SignupPage<'a> {
+    name: &'a str,
+    email: &'a str,
+    errors: &'a FieldErrors,
+    form_token: String,
+    captcha_html: String,
+}
+
+impl<'a> SignupPage<'a> {
+    fn new(
+        bot_protection: &BotProtection,
+        now: chrono::DateTime<Utc>,
+        name: &'a str,
+        email: &'a str,
+        errors: &'a FieldErrors,
+    ) -> Self {
+        Self {
+            name,
+            email,
+            errors,
+            form_token: bot_protection.issue_token(now),
+            captcha_html: bot_protection
+                .captcha()
+                .map(|captcha| captcha.widget_html())
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// The signup form
+///
+/// Also served at `/`.
+#[utoipa::path(
+    get,
+    path = "/subscriptions",
+    tag = "subscriptions",
+    responses((status = 200, description = "The signup page.", content_type = "text/html", body = String))
+)]
+pub async fn signup_form(
+    bot_protection: web::Data<BotProtection>,
+    clock: web::Data<dyn Clock>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let errors = FieldErrors::default();
+    render_page(&SignupPage::new(
+        &bot_protection,
+        clock.now(),
+        "",
+        "",
+        &errors,
+    ))
 }
 
+/// Sign up
+///
+/// Sends the new subscriber an email to confirm their address. Browsers get HTML pages instead
+/// of JSON. Only the signup form is accepted here: a submission without its form token is
+/// rejected, and API clients sign people up with `POST /api/v1/subscribers` instead.
+#[utoipa::path(
+    post,
+    path = "/subscriptions",
+    tag = "subscriptions",
+    request_body(content = FormData, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "The subscriber was asked to confirm their address.", body = OutcomeBody),
+        (status = 429, description = "Too many signups from the same address."),
+        SubscribeError,
+    )
+)]
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, application_base_url),
+    skip(req, form, pool, email_client, application_base_url, bot_protection, clock),
     fields(
         subscriber_email = %form.email,
         subscriber_name = %form.name,
     )
 )]
 pub async fn subscribe(
+    req: HttpRequest,
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     application_base_url: web::Data<ApplicationBaseUrl>,
+    bot_protection: web::Data<BotProtection>,
+    clock: web::Data<dyn Clock>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
+    if form.form_token.is_none() {
+        let errors = FieldErrors {
+            form: Some("Please reload the page and send the form again.".to_string()),
+            ..Default::default()
+        };
+        return invalid_signup(&req, &form, &bot_protection, clock.now(), errors);
+    }
+    // Bots are told they succeeded, so they have no reason to try again differently.
+    if let Verdict::Suspicious(reason) = bot_protection
+        .check(&form.submission(), clock.now())
+        .await
+        .context("Failed to verify the CAPTCHA.")?
+    {
+        tracing::warn!(reason, "Dropped a signup that looks automated");
+        return check_your_inbox(&req);
+    }
+    let new_subscriber = match NewSubscriber::try_from(&form.0) {
+        Ok(new_subscriber) => new_subscriber,
+        Err(errors) => return invalid_signup(&req, &form, &bot_protection, clock.now(), errors),
+    };
 
     let mut conn = pool
         .get()
@@ -192,6 +441,7 @@ pub async fn subscribe(
     };
 
     send_confirmation_email(
+        &mut conn,
         &email_client,
         new_subscriber,
         &application_base_url.0,
@@ -200,7 +450,32 @@ pub async fn subscribe(
     .await
     .context("Failed to send a confirmation email.")?;
 
-    Ok(HttpResponse::Ok().finish())
+    check_your_inbox(&req)
+}
+
+// People get the form back, with what they typed and what is wrong with it.
+fn invalid_signup(
+    req: &HttpRequest,
+    form: &FormData,
+    bot_protection: &BotProtection,
+    now: chrono::DateTime<Utc>,
+    errors: FieldErrors,
+) -> Result<HttpResponse, SubscribeError> {
+    if !prefers_html(req) {
+        return Err(SubscribeError::ValidationError(errors));
+    }
+    let page = SignupPage::new(bot_protection, now, &form.name, &form.email, &errors);
+    let html = page.render().context("Failed to render the signup page.")?;
+    Ok(HttpResponse::BadRequest()
+        .content_type(ContentType::html())
+        .body(html))
+}
+
+fn check_your_inbox(req: &HttpRequest) -> Result<HttpResponse, SubscribeError> {
+    let response = SubscriptionOutcome::CheckInbox
+        .respond(req)
+        .context("Failed to render the check your inbox page.")?;
+    Ok(response)
 }
 
 #[tracing::instrument(
@@ -222,14 +497,19 @@ pub fn store_token(
     Ok(())
 }
 
-impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
-    fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let valid_name = SubscriberName::parse(value.name)?;
-        let valid_email = SubscriberEmail::parse(value.email)?;
-        Ok(Self {
-            email: valid_email,
-            name: valid_name,
-        })
+impl TryFrom<&FormData> for NewSubscriber {
+    type Error = FieldErrors;
+    fn try_from(value: &FormData) -> Result<Self, Self::Error> {
+        match (
+            SubscriberName::parse(value.name.clone()),
+            SubscriberEmail::parse(value.email.clone()),
+        ) {
+            (Ok(name), Ok(email)) => Ok(Self { email, name }),
+            (name, email) => Err(FieldErrors {
+                name: name.err(),
+                email: email.err(),
+                ..Default::default()
+            }),
+        }
     }
 }
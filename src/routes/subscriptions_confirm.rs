@@ -1,57 +1,85 @@
 use crate::{
     db::PgPool,
     db_models::SubscriptionToken,
+    routes::subscriptions::{OutcomeBody, SubscriptionOutcome},
     schema::{
         subscription_tokens::{self, dsl as subs_token_dsl},
         subscriptions::{self, dsl as subs_dsl},
     },
+    utils::e500,
 };
-use actix_web::{web, HttpResponse};
+use actix_web::{web, HttpRequest, HttpResponse};
 use diesel::prelude::*;
 use serde::Deserialize;
+use utoipa::IntoParams;
 use uuid::Uuid;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
 pub struct Parameters {
+    /// The token of the link in the confirmation email.
     subscription_token: String,
 }
 
-#[tracing::instrument(name = "Confirm a pending subscriber", skip(parameters, pool))]
-pub async fn confirm(parameters: web::Query<Parameters>, pool: web::Data<PgPool>) -> HttpResponse {
-    let id: Option<Uuid> =
-        match get_subscriber_id_from_token(&pool, parameters.subscription_token.clone()).await {
-            Ok(id) => id,
-            Err(_) => return HttpResponse::InternalServerError().finish(),
-        };
+/// Confirm a subscription
+///
+/// The link of the confirmation email. Browsers get HTML pages instead of JSON.
+#[utoipa::path(
+    get,
+    path = "/subscriptions/confirm",
+    tag = "subscriptions",
+    params(Parameters),
+    responses(
+        (status = 200, description = "The subscriber is confirmed.", body = OutcomeBody),
+        (status = 401, description = "The link is invalid.", body = OutcomeBody),
+        (status = 429, description = "Too many confirmations from the same address."),
+    )
+)]
+#[tracing::instrument(name = "Confirm a pending subscriber", skip(req, parameters, pool))]
+pub async fn confirm(
+    req: HttpRequest,
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let id = get_subscriber_id_from_token(&pool, parameters.subscription_token.clone())
+        .await
+        .map_err(e500)?;
 
-    match id {
-        None => HttpResponse::Unauthorized().finish(),
+    let outcome = match id {
+        None => SubscriptionOutcome::InvalidLink,
         Some(subscriber_id) => {
-            if confirm_subscriber(&pool, subscriber_id).await.is_err() {
-                return HttpResponse::InternalServerError().finish();
+            if confirm_subscriber(&pool, subscriber_id)
+                .await
+                .map_err(e500)?
+            {
+                SubscriptionOutcome::Confirmed
+            } else {
+                SubscriptionOutcome::AlreadyConfirmed
             }
-            HttpResponse::Ok().finish()
         }
-    }
+    };
+    outcome.respond(&req).map_err(e500)
 }
 
+// Returns `false` if the subscriber was confirmed already.
 #[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
 pub async fn confirm_subscriber(
     pool: &PgPool,
     subscriber_id: Uuid,
-) -> Result<(), diesel::result::Error> {
+) -> Result<bool, diesel::result::Error> {
     let pool = pool.clone();
 
     let result = web::block(move || {
         let mut conn = pool.get().expect("Couldn't get db connection from Pool");
         let result = diesel::update(subs_dsl::subscriptions.find(subscriber_id))
+            .filter(subs_dsl::status.is_distinct_from("confirmed"))
             .set(subs_dsl::status.eq(Some("confirmed".to_string())))
             .execute(&mut conn)?;
         Ok::<_, diesel::result::Error>(result)
     })
     .await;
     match result {
-        Ok(Ok(_message)) => Ok(()),
+        Ok(Ok(updated)) => Ok(updated > 0),
         Ok(Err(err)) => return Err(err),
         Err(_err) => {
             return Err(diesel::result::Error::DatabaseError(
@@ -89,6 +117,6 @@ pub async fn get_subscriber_id_from_token(
         }
     };
 
-    println!("user id is {:?}", subscriber_id);
+    tracing::debug!(subscriber_id = ?subscriber_id, "Looked up the subscriber of the token");
     Ok(subscriber_id)
 }
@@ -0,0 +1,143 @@
+use crate::{
+    clock::Clock,
+    db::PgPool,
+    schema::{newsletter_issues, subscription_tokens, subscriptions},
+    tracking::{record_event, TrackingEvent},
+    utils::{e500, render_page},
+};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpRequest, HttpResponse};
+use askama::Template;
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use serde::Deserialize;
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct Parameters {
+    subscription_token: String,
+    /// Set on the links sent in newsletter issues, so the unsubscribe counts towards its stats.
+    newsletter_issue_id: Option<Uuid>,
+}
+
+#[derive(Template)]
+#[template(path = "subscriptions/unsubscribe.html")]
+struct UnsubscribePage {
+    // The link itself, with its query string.
+    action: String,
+}
+
+#[derive(Template)]
+#[template(path = "subscriptions/unsubscribed.html")]
+struct UnsubscribedPage;
+
+#[derive(Template)]
+#[template(path = "subscriptions/invalid_unsubscribe_link.html")]
+struct InvalidLinkPage;
+
+fn find_subscriber(
+    conn: &mut PgConnection,
+    subscription_token: &str,
+) -> Result<Option<Uuid>, diesel::result::Error> {
+    subscription_tokens::table
+        .filter(subscription_tokens::subscription_token.eq(subscription_token))
+        .select(subscription_tokens::subscriber_id)
+        .first::<Uuid>(conn)
+        .optional()
+}
+
+fn invalid_link() -> Result<HttpResponse, actix_web::Error> {
+    let html = InvalidLinkPage.render().map_err(e500)?;
+    Ok(HttpResponse::Unauthorized()
+        .content_type(ContentType::html())
+        .body(html))
+}
+
+/// The unsubscribe page
+///
+/// The unsubscribe link of every email. It only asks to confirm, so that link scanners and
+/// prefetching don't unsubscribe anyone.
+#[utoipa::path(
+    get,
+    path = "/subscriptions/unsubscribe",
+    tag = "subscriptions",
+    params(Parameters),
+    responses(
+        (status = 200, description = "The page to confirm with.", content_type = "text/html", body = String),
+        (status = 401, description = "The link is invalid.", content_type = "text/html", body = String),
+    )
+)]
+#[tracing::instrument(name = "Show the unsubscribe page", skip(parameters, request, pool))]
+pub async fn unsubscribe_form(
+    parameters: web::Query<Parameters>,
+    request: HttpRequest,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = pool.get().map_err(e500)?;
+    if find_subscriber(&mut conn, &parameters.subscription_token)
+        .map_err(e500)?
+        .is_none()
+    {
+        return invalid_link();
+    }
+    render_page(&UnsubscribePage {
+        action: format!("/subscriptions/unsubscribe?{}", request.query_string()),
+    })
+}
+
+/// Unsubscribe
+///
+/// Sent by the unsubscribe page, and by the unsubscribe button of mail clients, which post to
+/// the `List-Unsubscribe` link of newsletter issues (RFC 8058).
+#[utoipa::path(
+    post,
+    path = "/subscriptions/unsubscribe",
+    tag = "subscriptions",
+    params(Parameters),
+    responses(
+        (status = 200, description = "The subscriber is unsubscribed.", content_type = "text/html", body = String),
+        (status = 401, description = "The link is invalid.", content_type = "text/html", body = String),
+    )
+)]
+#[tracing::instrument(name = "Unsubscribe a subscriber", skip(parameters, pool, clock))]
+pub async fn unsubscribe(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut conn = pool.get().map_err(e500)?;
+    let subscriber_id = find_subscriber(&mut conn, &parameters.subscription_token).map_err(e500)?;
+
+    match subscriber_id {
+        None => invalid_link(),
+        Some(subscriber_id) => {
+            diesel::update(subscriptions::table.find(subscriber_id))
+                .set(subscriptions::status.eq("unsubscribed"))
+                .execute(&mut conn)
+                .map_err(e500)?;
+            if let Some(newsletter_issue_id) = parameters.newsletter_issue_id {
+                let issue_exists = newsletter_issues::table
+                    .find(newsletter_issue_id)
+                    .select(newsletter_issues::newsletter_issue_id)
+                    .first::<Uuid>(&mut conn)
+                    .optional()
+                    .map_err(e500)?
+                    .is_some();
+                if issue_exists {
+                    record_event(
+                        &mut conn,
+                        newsletter_issue_id,
+                        subscriber_id,
+                        TrackingEvent::Unsubscribe,
+                        None,
+                        clock.now(),
+                    )
+                    .map_err(e500)?;
+                }
+            }
+            render_page(&UnsubscribedPage)
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use crate::{
+    clock::Clock,
+    db::PgPool,
+    schema::issue_links,
+    signing::SigningKeys,
+    tracking::{record_event, TrackingEvent, TrackingToken, PIXEL},
+    utils::e500,
+};
+use actix_web::http::header::{CacheControl, CacheDirective, LOCATION};
+use actix_web::{web, HttpResponse};
+use diesel::prelude::*;
+use serde::Deserialize;
+use utoipa::IntoParams;
+
+#[derive(Deserialize, IntoParams)]
+#[into_params(parameter_in = Path)]
+pub struct TokenPath {
+    /// The signed token of the subscriber and issue, and of the link for clicks.
+    token: String,
+}
+
+/// Track an email open
+#[utoipa::path(
+    get,
+    path = "/t/o/{token}",
+    tag = "tracking",
+    params(TokenPath),
+    responses(
+        (status = 200, description = "A transparent pixel.", content_type = "image/gif", body = Vec<u8>),
+        (status = 404, description = "The token is invalid."),
+    )
+)]
+#[tracing::instrument(name = "Track an email open", skip(path, pool, clock, signing_keys))]
+pub async fn track_open(
+    path: web::Path<TokenPath>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+    signing_keys: web::Data<SigningKeys>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(TrackingToken::Open {
+        newsletter_issue_id,
+        subscriber_id,
+    }) = TrackingToken::verify(&path.token, &signing_keys.tracking)
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    // The pixel is served even if the open could not be recorded.
+    let recorded = pool
+        .get()
+        .map_err(anyhow::Error::from)
+        .and_then(|mut conn| {
+            record_event(
+                &mut conn,
+                newsletter_issue_id,
+                subscriber_id,
+                TrackingEvent::Open,
+                None,
+                clock.now(),
+            )
+            .map_err(anyhow::Error::from)
+        });
+    if let Err(e) = recorded {
+        tracing::error!(error.cause_chain = ?e, error.message = %e, "Failed to record an email open");
+    }
+    Ok(HttpResponse::Ok()
+        .content_type("image/gif")
+        .insert_header(CacheControl(vec![
+            CacheDirective::NoStore,
+            CacheDirective::Private,
+        ]))
+        .body(PIXEL))
+}
+
+/// Track a link click
+///
+/// Only links stored for the issue can be redirected to, so the endpoint can't be abused as
+/// an open redirect.
+#[utoipa::path(
+    get,
+    path = "/t/c/{token}",
+    tag = "tracking",
+    params(TokenPath),
+    responses(
+        (status = 302, description = "Redirects to the link."),
+        (status = 404, description = "The token is invalid."),
+    )
+)]
+#[tracing::instrument(name = "Track a link click", skip(path, pool, clock, signing_keys))]
+pub async fn track_click(
+    path: web::Path<TokenPath>,
+    pool: web::Data<PgPool>,
+    clock: web::Data<dyn Clock>,
+    signing_keys: web::Data<SigningKeys>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let Some(TrackingToken::Click {
+        newsletter_issue_id,
+        subscriber_id,
+        link_number,
+    }) = TrackingToken::verify(&path.token, &signing_keys.tracking)
+    else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    let mut conn = pool.get().map_err(e500)?;
+    let url = issue_links::table
+        .find((newsletter_issue_id, link_number))
+        .select(issue_links::url)
+        .first::<String>(&mut conn)
+        .optional()
+        .map_err(e500)?;
+    let Some(url) = url else {
+        return Ok(HttpResponse::NotFound().finish());
+    };
+    if let Err(e) = record_event(
+        &mut conn,
+        newsletter_issue_id,
+        subscriber_id,
+        TrackingEvent::Click,
+        Some(&url),
+        clock.now(),
+    ) {
+        tracing::error!(error.cause_chain = ?e, error.message = %e, "Failed to record a link click");
+    }
+    Ok(HttpResponse::Found()
+        .insert_header((LOCATION, url))
+        .finish())
+}
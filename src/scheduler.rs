@@ -0,0 +1,137 @@
+use crate::{
+    clock::Clock,
+    db::PgPool,
+    db_models::NewsletterIssue,
+    domain::IssueStatus,
+    email_client::EmailClient,
+    issue_delivery::{deliver_issue, DeliveryOutcome, DELIVERY_LEASE},
+    schema::newsletter_issues,
+    shutdown::Shutdown,
+    signing::SigningKey,
+};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub async fn run_scheduler_until_stopped(
+    pool: PgPool,
+    email_client: Arc<EmailClient>,
+    clock: Arc<dyn Clock>,
+    application_base_url: String,
+    tracking_key: SigningKey,
+    poll_interval: Duration,
+    shutdown: Shutdown,
+) -> Result<(), anyhow::Error> {
+    while !shutdown.is_requested() {
+        if let Err(e) = send_due_issues(
+            &pool,
+            &email_client,
+            clock.as_ref(),
+            &application_base_url,
+            &tracking_key,
+            &shutdown,
+        )
+        .await
+        {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send due newsletter issues",
+            );
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(poll_interval) => {}
+            _ = shutdown.requested() => {}
+        }
+    }
+    Ok(())
+}
+
+// Delivers every scheduled issue whose `scheduled_at` is not later than `clock.now()`, and every
+// issue left in `sending` by a crash, returning how many issues were sent. Issues that fail are
+// scheduled again by `deliver_issue` and the others are still sent. No further issue is claimed
+// once a shutdown was requested.
+#[tracing::instrument(name = "Send due newsletter issues", skip_all)]
+pub async fn send_due_issues(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    clock: &dyn Clock,
+    application_base_url: &str,
+    tracking_key: &SigningKey,
+    shutdown: &Shutdown,
+) -> Result<usize, anyhow::Error> {
+    let mut sent = 0;
+    while !shutdown.is_requested() {
+        let issue = {
+            let mut conn = pool
+                .get()
+                .context("Failed to acquire a Postgres connection from the pool")?;
+            claim_next_due_issue(&mut conn, clock.now())
+                .context("Failed to claim a due newsletter issue.")?
+        };
+        let Some(issue) = issue else {
+            return Ok(sent);
+        };
+        let outcome = deliver_issue(
+            pool,
+            email_client,
+            &issue,
+            clock,
+            application_base_url,
+            tracking_key,
+            shutdown,
+        )
+        .await;
+        match outcome {
+            Ok(DeliveryOutcome::Sent) => sent += 1,
+            Ok(DeliveryOutcome::Interrupted) => {}
+            Err(e) => tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                newsletter_issue_id = %issue.newsletter_issue_id,
+                "Failed to send a due newsletter issue",
+            ),
+        }
+    }
+    Ok(sent)
+}
+
+// Moves the oldest due issue from `scheduled` to `sending`, or claims an issue whose sender has
+// not renewed its claim within `DELIVERY_LEASE`. Rows locked by another scheduler are skipped, so
+// an issue is never picked up twice.
+fn claim_next_due_issue(
+    conn: &mut PgConnection,
+    now: DateTime<Utc>,
+) -> Result<Option<NewsletterIssue>, diesel::result::Error> {
+    conn.transaction(|conn| {
+        let issue_id = newsletter_issues::table
+            .filter(
+                newsletter_issues::status
+                    .eq(IssueStatus::Scheduled.as_str())
+                    .and(newsletter_issues::scheduled_at.le(now))
+                    .or(newsletter_issues::status
+                        .eq(IssueStatus::Sending.as_str())
+                        .and(newsletter_issues::claimed_at.lt(now - DELIVERY_LEASE))),
+            )
+            .order(newsletter_issues::scheduled_at.asc())
+            .select(newsletter_issues::newsletter_issue_id)
+            .limit(1)
+            .for_update()
+            .skip_locked()
+            .get_result::<uuid::Uuid>(conn)
+            .optional()?;
+        match issue_id {
+            None => Ok(None),
+            Some(issue_id) => diesel::update(newsletter_issues::table.find(issue_id))
+                .set((
+                    newsletter_issues::status.eq(IssueStatus::Sending.as_str()),
+                    newsletter_issues::claimed_at.eq(Some(now)),
+                ))
+                .get_result::<NewsletterIssue>(conn)
+                .map(Some),
+        }
+    })
+}
@@ -1,5 +1,104 @@
 // @generated automatically by Diesel CLI.
 
+diesel::table! {
+    api_keys (api_key_id) {
+        api_key_id -> Uuid,
+        name -> Text,
+        prefix -> Text,
+        key_hash -> Text,
+        scopes -> Array<Text>,
+        created_by -> Uuid,
+        created_at -> Timestamptz,
+        last_used_at -> Nullable<Timestamptz>,
+        revoked_at -> Nullable<Timestamptz>,
+    }
+}
+
+diesel::table! {
+    email_deliveries (email_delivery_id) {
+        email_delivery_id -> Int8,
+        newsletter_issue_id -> Uuid,
+        subscriber_id -> Nullable<Uuid>,
+        email -> Text,
+        status -> Text,
+        provider_message_id -> Nullable<Text>,
+        error_code -> Nullable<Text>,
+        error_message -> Nullable<Text>,
+        attempted_at -> Timestamptz,
+        completed_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    email_events (email_event_id) {
+        email_event_id -> Int8,
+        provider -> Text,
+        provider_event_id -> Text,
+        kind -> Text,
+        event_type -> Text,
+        permanent -> Bool,
+        email -> Text,
+        subscriber_id -> Nullable<Uuid>,
+        message_id -> Nullable<Text>,
+        description -> Text,
+        payload -> Text,
+        received_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    email_layouts (name) {
+        name -> Text,
+        header -> Text,
+        footer -> Text,
+        physical_address -> Text,
+        unsubscribe_block -> Text,
+        updated_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    issue_links (newsletter_issue_id, link_number) {
+        newsletter_issue_id -> Uuid,
+        link_number -> Int4,
+        url -> Text,
+    }
+}
+
+diesel::table! {
+    issue_revisions (newsletter_issue_id, revision_number) {
+        newsletter_issue_id -> Uuid,
+        revision_number -> Int4,
+        title -> Text,
+        text_content -> Text,
+        html_content -> Text,
+        created_by -> Uuid,
+        created_at -> Timestamptz,
+        markdown_content -> Nullable<Text>,
+    }
+}
+
+diesel::table! {
+    newsletter_issues (newsletter_issue_id) {
+        newsletter_issue_id -> Uuid,
+        title -> Text,
+        text_content -> Text,
+        html_content -> Text,
+        segment -> Nullable<Text>,
+        status -> Text,
+        scheduled_at -> Nullable<Timestamptz>,
+        created_at -> Timestamptz,
+        sent_at -> Nullable<Timestamptz>,
+        markdown_content -> Nullable<Text>,
+        layout -> Text,
+        tracking_enabled -> Bool,
+        in_archive -> Bool,
+        failed_deliveries -> Int4,
+        claimed_at -> Nullable<Timestamptz>,
+        delivery_failures -> Int4,
+    }
+}
+
 diesel::table! {
     subscription_tokens (subscription_token) {
         subscription_token -> Text,
@@ -14,6 +113,28 @@ diesel::table! {
         name -> Text,
         subscribed_at -> Timestamptz,
         status -> Nullable<Text>,
+        tags -> Array<Text>,
+    }
+}
+
+diesel::table! {
+    tracking_events (tracking_event_id) {
+        tracking_event_id -> Int8,
+        newsletter_issue_id -> Uuid,
+        subscriber_id -> Uuid,
+        kind -> Text,
+        url -> Nullable<Text>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    transactional_templates (name) {
+        name -> Text,
+        subject -> Text,
+        layout -> Text,
+        body -> Text,
+        updated_at -> Timestamptz,
     }
 }
 
@@ -22,13 +143,34 @@ diesel::table! {
         user_id -> Uuid,
         username -> Text,
         password_hash -> Text,
+        email -> Nullable<Text>,
     }
 }
 
+diesel::joinable!(api_keys -> users (created_by));
+diesel::joinable!(email_deliveries -> newsletter_issues (newsletter_issue_id));
+diesel::joinable!(email_deliveries -> subscriptions (subscriber_id));
+diesel::joinable!(email_events -> subscriptions (subscriber_id));
+diesel::joinable!(issue_links -> newsletter_issues (newsletter_issue_id));
+diesel::joinable!(issue_revisions -> newsletter_issues (newsletter_issue_id));
+diesel::joinable!(newsletter_issues -> email_layouts (layout));
+diesel::joinable!(issue_revisions -> users (created_by));
 diesel::joinable!(subscription_tokens -> subscriptions (subscriber_id));
+diesel::joinable!(tracking_events -> newsletter_issues (newsletter_issue_id));
+diesel::joinable!(tracking_events -> subscriptions (subscriber_id));
+diesel::joinable!(transactional_templates -> email_layouts (layout));
 
 diesel::allow_tables_to_appear_in_same_query!(
+    api_keys,
+    email_deliveries,
+    email_events,
+    email_layouts,
+    issue_links,
+    issue_revisions,
+    newsletter_issues,
     subscription_tokens,
     subscriptions,
+    tracking_events,
+    transactional_templates,
     users,
 );
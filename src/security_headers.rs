@@ -0,0 +1,175 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{
+    HeaderMap, HeaderName, HeaderValue, CONTENT_SECURITY_POLICY, REFERRER_POLICY,
+    STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+};
+use actix_web::middleware::Next;
+use actix_web::web;
+use std::collections::HashMap;
+
+// Nothing but our own stylesheets and images, and forms posting back to us. No page needs a
+// script, and none may be framed.
+pub const DEFAULT_CONTENT_SECURITY_POLICY: &str = "default-src 'none'; style-src 'self'; \
+    img-src 'self'; form-action 'self'; frame-ancestors 'none'; base-uri 'none'";
+
+// Email previews show what subscribers will see: inline styles and images from anywhere.
+pub const EMAIL_PREVIEW_CONTENT_SECURITY_POLICY: &str = "default-src 'none'; \
+    style-src 'self' 'unsafe-inline'; img-src 'self' https: data:; form-action 'self'; \
+    frame-ancestors 'none'; base-uri 'none'";
+
+// The API reference is rendered by Redoc, from its CDN, with inline styles and a search worker.
+pub const API_DOCS_CONTENT_SECURITY_POLICY: &str = "default-src 'none'; \
+    script-src https://cdn.jsdelivr.net; style-src 'unsafe-inline' https://fonts.googleapis.com; \
+    font-src https://fonts.gstatic.com; img-src 'self' https: data:; connect-src 'self'; \
+    worker-src blob:; form-action 'none'; frame-ancestors 'none'; base-uri 'none'";
+
+// The headers added to every response. Routes that need a looser policy, e.g. to show an email
+// with its inline styles and remote images, get one of their own.
+#[derive(Debug, Clone)]
+pub struct SecurityHeaders {
+    content_security_policy: String,
+    // By route pattern, e.g. `/admin/templates/layouts/{name}/preview`.
+    route_policies: HashMap<String, String>,
+    frame_options: String,
+    referrer_policy: String,
+    strict_transport_security: Option<String>,
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self {
+            content_security_policy: DEFAULT_CONTENT_SECURITY_POLICY.into(),
+            route_policies: HashMap::new(),
+            frame_options: "DENY".into(),
+            referrer_policy: "same-origin".into(),
+            strict_transport_security: Some("max-age=31536000; includeSubDomains".into()),
+        }
+    }
+}
+
+impl SecurityHeaders {
+    // The defaults, with the `CONTENT_SECURITY_POLICY`, `X_FRAME_OPTIONS`, `REFERRER_POLICY` and
+    // `STRICT_TRANSPORT_SECURITY` that `variable` returns replacing them. HSTS is left out when
+    // the latter is `off`, e.g. for deployments only reachable over plain HTTP.
+    pub fn configured(variable: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = Self::default();
+        Self {
+            content_security_policy: variable("CONTENT_SECURITY_POLICY")
+                .unwrap_or(defaults.content_security_policy),
+            route_policies: defaults.route_policies,
+            frame_options: variable("X_FRAME_OPTIONS").unwrap_or(defaults.frame_options),
+            referrer_policy: variable("REFERRER_POLICY").unwrap_or(defaults.referrer_policy),
+            strict_transport_security: match variable("STRICT_TRANSPORT_SECURITY") {
+                Some(value) if value == "off" => None,
+                Some(value) => Some(value),
+                None => defaults.strict_transport_security,
+            },
+        }
+    }
+
+    pub fn with_route_policy(mut self, pattern: &str, policy: impl Into<String>) -> Self {
+        self.route_policies.insert(pattern.into(), policy.into());
+        self
+    }
+
+    pub fn content_security_policy(&self, pattern: Option<&str>) -> &str {
+        pattern
+            .and_then(|pattern| self.route_policies.get(pattern))
+            .unwrap_or(&self.content_security_policy)
+    }
+
+    fn headers(&self, pattern: Option<&str>) -> Vec<(HeaderName, &str)> {
+        let mut headers = vec![
+            (
+                CONTENT_SECURITY_POLICY,
+                self.content_security_policy(pattern),
+            ),
+            (X_FRAME_OPTIONS, self.frame_options.as_str()),
+            (REFERRER_POLICY, self.referrer_policy.as_str()),
+            (X_CONTENT_TYPE_OPTIONS, "nosniff"),
+        ];
+        if let Some(hsts) = &self.strict_transport_security {
+            headers.push((STRICT_TRANSPORT_SECURITY, hsts.as_str()));
+        }
+        headers
+    }
+
+    fn add_to(&self, headers: &mut HeaderMap, pattern: Option<&str>) {
+        for (name, value) in self.headers(pattern) {
+            if headers.contains_key(&name) {
+                continue;
+            }
+            match HeaderValue::from_str(value) {
+                Ok(value) => {
+                    headers.insert(name, value);
+                }
+                Err(e) => tracing::error!(error = %e, header = %name, "Invalid security header"),
+            }
+        }
+    }
+}
+
+// Adds the configured security headers to every response, errors included. Headers a handler set
+// itself are left alone.
+pub async fn add_security_headers(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let Some(config) = req.app_data::<web::Data<SecurityHeaders>>().cloned() else {
+        return next.call(req).await;
+    };
+    match next.call(req).await {
+        Ok(mut response) => {
+            let pattern = response.request().match_pattern();
+            config.add_to(response.headers_mut(), pattern.as_deref());
+            Ok(response)
+        }
+        // Errors are only turned into responses further out, so the headers go on that response.
+        Err(e) => {
+            let mut response = e.error_response();
+            config.add_to(response.headers_mut(), None);
+            Err(InternalError::from_response(e, response).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn routes_without_a_policy_of_their_own_get_the_default_one() {
+        let headers =
+            SecurityHeaders::default().with_route_policy("/preview/{name}", "img-src https:");
+        assert_eq!(
+            headers.content_security_policy(Some("/preview/{name}")),
+            "img-src https:"
+        );
+        assert_eq!(
+            headers.content_security_policy(Some("/login")),
+            DEFAULT_CONTENT_SECURITY_POLICY
+        );
+        assert_eq!(
+            headers.content_security_policy(None),
+            DEFAULT_CONTENT_SECURITY_POLICY
+        );
+    }
+
+    #[test]
+    fn configured_headers_replace_the_defaults() {
+        let headers = SecurityHeaders::configured(|name| match name {
+            "X_FRAME_OPTIONS" => Some("SAMEORIGIN".into()),
+            "STRICT_TRANSPORT_SECURITY" => Some("off".into()),
+            _ => None,
+        });
+        assert_eq!(headers.frame_options, "SAMEORIGIN");
+        assert_eq!(headers.strict_transport_security, None);
+        assert_eq!(headers.referrer_policy, "same-origin");
+        assert_eq!(
+            headers.content_security_policy(None),
+            DEFAULT_CONTENT_SECURITY_POLICY
+        );
+    }
+}
@@ -0,0 +1,200 @@
+use crate::authentication::Credentials;
+use crate::bot_protection::{FORM_MAX_AGE, MIN_FILL_TIME};
+use crate::captcha::{CaptchaProvider, SiteVerifyCaptcha};
+use crate::domain::SubscriberEmail;
+use crate::email_client::SendLimits;
+use crate::log_redaction::RedactionRules;
+use crate::rate_limit::{parse_trusted_proxies, RateLimits};
+use crate::security_headers::SecurityHeaders;
+use crate::signing::SigningKeys;
+use anyhow::Context;
+use secrecy::Secret;
+use std::fmt::Display;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::time::Duration;
+
+// What the application is configured with. It is read from the environment once, when the
+// application is built, and handlers get it as app data.
+pub struct Settings {
+    pub application_base_url: String,
+    pub redis_uri: Secret<String>,
+    // Derived from `HMAC_SECRET`.
+    pub signing_keys: SigningKeys,
+    // From `RATE_LIMITS`, `TRUSTED_PROXIES` and `RATE_LIMIT_KEY_PREFIX`.
+    pub rate_limits: RateLimits,
+    pub trusted_proxies: Vec<IpAddr>,
+    pub rate_limit_key_prefix: Option<String>,
+    // The credentials providers put in the webhook URL, from `EMAIL_WEBHOOK_USERNAME` and
+    // `EMAIL_WEBHOOK_PASSWORD`. Without them every webhook is rejected.
+    pub email_webhook_credentials: Option<Credentials>,
+    // When `METRICS_BEARER_TOKEN` is set, scrapers must send it as a bearer token.
+    pub metrics_bearer_token: Option<Secret<String>>,
+    // With `METRICS_PORT` set, `/metrics` is served on that port only, e.g. one that is not
+    // exposed to the internet, instead of next to the application.
+    pub metrics_port: Option<u16>,
+    // The email provider is only checked with `HEALTH_CHECK_EMAIL_PROVIDER=true`, so that an
+    // outage on their side does not take us out of rotation by default.
+    pub health_check_email_provider: bool,
+    // The API reference loads Redoc from a CDN, so it is left out unless `API_DOCS_UI=true`.
+    pub api_docs_ui: bool,
+    // How often the scheduler looks for due issues, from `SCHEDULER_POLL_INTERVAL_SECONDS`.
+    pub scheduler_poll_interval: Duration,
+    // How long in-flight deliveries may keep sending after a shutdown was requested, from
+    // `SHUTDOWN_DEADLINE_SECONDS`.
+    pub shutdown_deadline: Duration,
+    // The email provider, from `BASE_URL`, `SENDER_EMAIL`, `AUTHORIZATION_TOKEN` and
+    // `TIMEOUT_MILLISECOND`.
+    pub email_base_url: String,
+    pub sender_email: SubscriberEmail,
+    pub email_authorization_token: Secret<String>,
+    pub email_timeout: Duration,
+    // From `EMAIL_MESSAGES_PER_SECOND` and `EMAIL_MAX_IN_FLIGHT`.
+    pub send_limits: SendLimits,
+    // The timing of the signup form, from `SIGNUP_MIN_FILL_SECONDS` and
+    // `SIGNUP_FORM_MAX_AGE_SECONDS`.
+    pub signup_min_fill_time: Duration,
+    pub signup_form_max_age: Duration,
+    // Configured by `CAPTCHA_PROVIDER` (`hcaptcha` or `turnstile`), `CAPTCHA_SITE_KEY` and
+    // `CAPTCHA_SECRET`; `CAPTCHA_VERIFY_URL` overrides the provider's endpoint. `None` when no
+    // provider is set.
+    pub captcha: Option<SiteVerifyCaptcha>,
+    // See `SecurityHeaders::configured`.
+    pub security_headers: SecurityHeaders,
+    // The defaults of `RedactionRules`, adjusted by `LOG_REDACTED_FIELDS`.
+    pub log_redaction: RedactionRules,
+    // Spans are exported to the OTLP collector at `OTEL_EXPORTER_OTLP_ENDPOINT` when it is set.
+    pub otlp_endpoint: Option<String>,
+}
+
+fn required(name: &str) -> Result<String, anyhow::Error> {
+    std::env::var(name).with_context(|| format!("{} must be set", name))
+}
+
+// The value of an optional variable, or `default` when it is not set.
+fn parsed<T>(name: &str, default: T) -> Result<T, anyhow::Error>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map_err(|e| anyhow::anyhow!("{}", e))
+            .with_context(|| format!("Can't parse {}", name)),
+        Err(_) => Ok(default),
+    }
+}
+
+// `default`, adjusted by the value of the variable when it is set.
+fn adjusted<T>(
+    name: &str,
+    default: T,
+    adjust: impl FnOnce(T, &str) -> Result<T, String>,
+) -> Result<T, anyhow::Error> {
+    match std::env::var(name) {
+        Ok(value) => adjust(default, &value)
+            .map_err(anyhow::Error::msg)
+            .with_context(|| format!("Can't parse {}", name)),
+        Err(_) => Ok(default),
+    }
+}
+
+impl Settings {
+    pub fn from_env() -> Result<Self, anyhow::Error> {
+        use dotenv::dotenv;
+        use std::env;
+        dotenv().ok();
+        let flag = |name: &str| env::var(name).is_ok_and(|v| v == "true");
+        let hmac_secret = Secret::new(required("HMAC_SECRET")?);
+        let email_webhook_credentials = match (
+            env::var("EMAIL_WEBHOOK_USERNAME"),
+            env::var("EMAIL_WEBHOOK_PASSWORD"),
+        ) {
+            (Ok(username), Ok(password)) => Some(Credentials {
+                username,
+                password: Secret::new(password),
+            }),
+            _ => None,
+        };
+        let defaults = SendLimits::default();
+        let send_limits = SendLimits {
+            messages_per_second: parsed("EMAIL_MESSAGES_PER_SECOND", defaults.messages_per_second)?,
+            max_in_flight: parsed("EMAIL_MAX_IN_FLIGHT", defaults.max_in_flight)?,
+        };
+        anyhow::ensure!(
+            send_limits.messages_per_second > 0.0,
+            "EMAIL_MESSAGES_PER_SECOND must be positive"
+        );
+        anyhow::ensure!(
+            send_limits.max_in_flight > 0,
+            "EMAIL_MAX_IN_FLIGHT must be positive"
+        );
+        let captcha = match env::var("CAPTCHA_PROVIDER") {
+            Ok(provider) => {
+                let provider = CaptchaProvider::parse(&provider)
+                    .map_err(anyhow::Error::msg)
+                    .context("Can't parse CAPTCHA_PROVIDER")?;
+                Some(SiteVerifyCaptcha::new(
+                    provider,
+                    required("CAPTCHA_SITE_KEY")?,
+                    Secret::new(required("CAPTCHA_SECRET")?),
+                    env::var("CAPTCHA_VERIFY_URL")
+                        .unwrap_or_else(|_| provider.verify_url().to_string()),
+                ))
+            }
+            Err(_) => None,
+        };
+        Ok(Self {
+            application_base_url: required("APPLICATION_BASE_URL")?,
+            redis_uri: Secret::new(required("REDIS_URI")?),
+            signing_keys: SigningKeys::derive(&hmac_secret),
+            rate_limits: adjusted("RATE_LIMITS", RateLimits::default(), |limits, overrides| {
+                limits.parse_overrides(overrides)
+            })?,
+            trusted_proxies: adjusted("TRUSTED_PROXIES", Vec::new(), |_, proxies| {
+                parse_trusted_proxies(proxies)
+            })?,
+            rate_limit_key_prefix: env::var("RATE_LIMIT_KEY_PREFIX").ok(),
+            email_webhook_credentials,
+            metrics_bearer_token: env::var("METRICS_BEARER_TOKEN").ok().map(Secret::new),
+            metrics_port: env::var("METRICS_PORT")
+                .ok()
+                .map(|port| port.parse().expect("Can't parse METRICS_PORT")),
+            health_check_email_provider: flag("HEALTH_CHECK_EMAIL_PROVIDER"),
+            api_docs_ui: flag("API_DOCS_UI"),
+            scheduler_poll_interval: Duration::from_secs(parsed(
+                "SCHEDULER_POLL_INTERVAL_SECONDS",
+                10,
+            )?),
+            shutdown_deadline: Duration::from_secs(parsed("SHUTDOWN_DEADLINE_SECONDS", 30)?),
+            email_base_url: required("BASE_URL")?,
+            sender_email: SubscriberEmail::parse(required("SENDER_EMAIL")?)
+                .map_err(anyhow::Error::msg)
+                .context("Invalid SENDER_EMAIL")?,
+            email_authorization_token: Secret::new(required("AUTHORIZATION_TOKEN")?),
+            email_timeout: Duration::from_millis(
+                required("TIMEOUT_MILLISECOND")?
+                    .parse()
+                    .context("Can't parse TIMEOUT_MILLISECOND")?,
+            ),
+            send_limits,
+            signup_min_fill_time: Duration::from_secs(parsed(
+                "SIGNUP_MIN_FILL_SECONDS",
+                MIN_FILL_TIME.as_secs(),
+            )?),
+            signup_form_max_age: Duration::from_secs(parsed(
+                "SIGNUP_FORM_MAX_AGE_SECONDS",
+                FORM_MAX_AGE.as_secs(),
+            )?),
+            captcha,
+            security_headers: SecurityHeaders::configured(|name| env::var(name).ok()),
+            log_redaction: adjusted(
+                "LOG_REDACTED_FIELDS",
+                RedactionRules::default(),
+                |rules, overrides| rules.parse_overrides(overrides),
+            )?,
+            otlp_endpoint: env::var("OTEL_EXPORTER_OTLP_ENDPOINT").ok(),
+        })
+    }
+}
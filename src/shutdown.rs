@@ -0,0 +1,95 @@
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::web;
+use std::future::Future;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+// Shared by the HTTP server, the scheduler and every delivery. Once requested, no new work is
+// started; once the deadline has passed as well, deliveries stop between batches and save where
+// they got to.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    requested: CancellationToken,
+    deadline_passed: CancellationToken,
+    in_flight: TaskTracker,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn trigger(&self, deadline: Duration) {
+        if self.requested.is_cancelled() {
+            return;
+        }
+        tracing::info!(
+            deadline_seconds = deadline.as_secs_f64(),
+            "Shutting down, waiting for in-flight deliveries"
+        );
+        self.requested.cancel();
+        let deadline_passed = self.deadline_passed.clone();
+        if deadline.is_zero() {
+            deadline_passed.cancel();
+            return;
+        }
+        tokio::spawn(async move {
+            tokio::time::sleep(deadline).await;
+            deadline_passed.cancel();
+        });
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.is_cancelled()
+    }
+
+    pub async fn requested(&self) {
+        self.requested.cancelled().await
+    }
+
+    pub fn deadline_passed(&self) -> bool {
+        self.deadline_passed.is_cancelled()
+    }
+
+    pub async fn track<F: Future>(&self, work: F) -> F::Output {
+        self.in_flight.track_future(work).await
+    }
+
+    // Resolves once no tracked work is left.
+    pub async fn drained(&self) {
+        self.in_flight.close();
+        self.in_flight.wait().await
+    }
+}
+
+// Tracks every request, so that the server is only stopped once they have all been answered.
+// Stopping actix-web with requests in flight can drop their connections before they finish.
+pub async fn track_in_flight_requests(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    match req.app_data::<web::Data<Shutdown>>().cloned() {
+        Some(shutdown) => shutdown.track(next.call(req)).await,
+        None => next.call(req).await,
+    }
+}
+
+// Resolves on SIGTERM or Ctrl-C.
+pub async fn wait_for_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+        tokio::select! {
+            _ = terminate.recv() => {}
+            _ = tokio::signal::ctrl_c() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
@@ -0,0 +1,107 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Sha256, Sha512};
+use std::sync::Arc;
+
+// Signatures are truncated to 128 bits to keep the tokens, and the URLs holding them, short.
+const SIGNATURE_LENGTH: usize = 16;
+
+// A key for a single purpose. It is as long as the cookie key has to be.
+#[derive(Clone)]
+pub struct SigningKey(Arc<Secret<Vec<u8>>>);
+
+impl SigningKey {
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(self.0.expose_secret())
+            .expect("HMAC can take a key of any size")
+    }
+
+    // A URL-safe token holding `payload` and its signature.
+    pub fn sign(&self, payload: &[u8]) -> String {
+        let mut mac = self.mac();
+        mac.update(payload);
+        let signature = mac.finalize().into_bytes();
+        let token = [payload, &signature[..SIGNATURE_LENGTH]].concat();
+        base64::encode_config(token, base64::URL_SAFE_NO_PAD)
+    }
+
+    // The payload of `token`, or `None` for anything that was not signed with this key.
+    pub fn verify(&self, token: &str) -> Option<Vec<u8>> {
+        let mut bytes = base64::decode_config(token, base64::URL_SAFE_NO_PAD).ok()?;
+        let signature = bytes.split_off(bytes.len().checked_sub(SIGNATURE_LENGTH)?);
+        let mut mac = self.mac();
+        mac.update(&bytes);
+        mac.verify_truncated_left(&signature).ok()?;
+        Some(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        self.0.expose_secret()
+    }
+}
+
+// Everything the application signs, each with a key of its own, so that what was signed for one
+// purpose is never accepted for another. The keys are derived from `HMAC_SECRET`.
+#[derive(Clone)]
+pub struct SigningKeys {
+    // Sessions and flash messages.
+    pub cookies: SigningKey,
+    // The open and click links of tracked issues.
+    pub tracking: SigningKey,
+    // The tokens of the signup form.
+    pub form_tokens: SigningKey,
+}
+
+impl SigningKeys {
+    pub fn derive(secret: &Secret<String>) -> Self {
+        // An HMAC of the purpose, keyed with the secret.
+        let derive = |purpose: &str| {
+            let mut mac = Hmac::<Sha512>::new_from_slice(secret.expose_secret().as_bytes())
+                .expect("HMAC can take a key of any size");
+            mac.update(b"newsletter:");
+            mac.update(purpose.as_bytes());
+            SigningKey(Arc::new(Secret::new(mac.finalize().into_bytes().to_vec())))
+        };
+        Self {
+            cookies: derive("cookies"),
+            tracking: derive("tracking"),
+            form_tokens: derive("form_tokens"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SigningKeys;
+    use secrecy::Secret;
+
+    #[test]
+    fn a_token_verifies_to_its_payload_with_the_key_it_was_signed_with_only() {
+        let keys = SigningKeys::derive(&Secret::new("secret".to_string()));
+        let token = keys.tracking.sign(b"payload");
+
+        assert_eq!(
+            keys.tracking.verify(&token).as_deref(),
+            Some(&b"payload"[..])
+        );
+        assert_eq!(keys.form_tokens.verify(&token), None);
+        let mut bytes = base64::decode_config(&token, base64::URL_SAFE_NO_PAD).unwrap();
+        bytes[0] ^= 1;
+        let altered = base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+        assert_eq!(keys.tracking.verify(&altered), None);
+        assert_eq!(keys.tracking.verify("short"), None);
+        assert_eq!(keys.tracking.verify("not base64!"), None);
+    }
+
+    #[test]
+    fn every_purpose_gets_a_different_key() {
+        let keys = SigningKeys::derive(&Secret::new("secret".to_string()));
+        let other_keys = SigningKeys::derive(&Secret::new("another secret".to_string()));
+
+        assert_ne!(keys.cookies.as_bytes(), keys.tracking.as_bytes());
+        assert_ne!(keys.tracking.as_bytes(), keys.form_tokens.as_bytes());
+        assert_ne!(keys.cookies.as_bytes(), keys.form_tokens.as_bytes());
+        assert_ne!(keys.tracking.as_bytes(), other_keys.tracking.as_bytes());
+        assert_eq!(keys.cookies.as_bytes().len(), 64);
+    }
+}
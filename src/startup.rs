@@ -1,17 +1,30 @@
-use crate::domain::SubscriberEmail;
+use crate::bot_protection::BotProtection;
+use crate::clock::Clock;
+use crate::csrf::reject_forged_requests;
 use crate::email_client::EmailClient;
+use crate::metrics::record_http_metrics;
 use crate::middleware::reject_anonymous_users;
+use crate::rate_limit::{enforce_rate_limit, LimitedRoute, RateLimiter};
+use crate::scheduler::run_scheduler_until_stopped;
+use crate::security_headers::{
+    add_security_headers, API_DOCS_CONTENT_SECURITY_POLICY, EMAIL_PREVIEW_CONTENT_SECURITY_POLICY,
+};
+use crate::settings::Settings;
+use crate::shutdown::{track_in_flight_requests, wait_for_signal, Shutdown};
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
 use actix_web::cookie::Key;
 use actix_web::dev::Server;
-use actix_web::{web, App, HttpServer};
+use actix_web::http::Method;
+use actix_web::{web, App, HttpServer, Route, Scope};
 use actix_web_flash_messages::storage::CookieMessageStore;
 use actix_web_flash_messages::FlashMessagesFramework;
 use actix_web_lab::middleware::from_fn;
 use secrecy::ExposeSecret;
-use secrecy::Secret;
 use std::net::TcpListener;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::task::JoinHandle;
 
 // use actix_web::middleware::Logger;
 use tracing_actix_web::TracingLogger;
@@ -20,52 +33,114 @@ use crate::db::PgPool;
 
 use crate::routes::{
     admin::{
+        api_keys::{
+            get::api_keys_list,
+            post::{create_api_key, revoke_api_key},
+        },
         dashboard::admin_dashboard,
+        deliveries::deliveries_search,
+        drafts::{
+            get::{draft_diff, draft_form, drafts_list},
+            post::{create_draft_issue, restore_draft_revision, save_draft, send_draft_test_email},
+        },
+        issues::{
+            get::{issue_stats, issues_list},
+            post::set_issue_in_archive,
+        },
         logout::log_out,
         password::{get::change_password_form, post::change_password},
+        subscribers::{get::subscribers_list, post::set_subscriber_tags},
+        templates::{
+            get::{
+                layout_form, layout_preview, templates_list, transactional_template_form,
+                transactional_template_preview,
+            },
+            post::{create_layout, save_layout, save_transactional_template},
+        },
+    },
+    api::{
+        extractor_errors,
+        issues::{create_issue, get_issue_status, list_issues, schedule_issue, send_issue},
+        not_found,
+        stats::{issue_stats as api_issue_stats, overall_stats},
+        subscribers::{
+            create_subscriber, delete_subscriber, get_subscriber, list_subscribers,
+            update_subscriber,
+        },
     },
-    health_check::health_check,
-    home::home::home,
+    archive::{archive, archived_issue, atom_feed, rss_feed},
+    email_webhooks::receive_email_webhook,
+    health_check::{health_check, health_live, health_ready},
     login::{get::login_form, post::login},
-    newsletter::publish_newsletter,
-    subscriptions::subscribe,
+    metrics::metrics,
+    newsletter::{preview_segment, publish_newsletter},
+    newsletter_issues::{cancel_newsletter_issue, get_newsletter_issue, schedule_newsletter_issue},
+    openapi::{api_docs, openapi_json},
+    subscriptions::{signup_form, subscribe},
     subscriptions_confirm::confirm,
+    subscriptions_unsubscribe::{unsubscribe, unsubscribe_form},
+    tracking::{track_click, track_open},
 };
 
 pub struct Application {
     port: u16,
     server: Server,
+    metrics_server: Option<Server>,
+    scheduler: JoinHandle<Result<(), anyhow::Error>>,
+    shutdown: Shutdown,
+    shutdown_deadline: Duration,
+}
+
+fn run_metrics_server(
+    port: u16,
+    db_pool: PgPool,
+    settings: web::Data<Settings>,
+) -> Result<Server, anyhow::Error> {
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", port))?;
+    let db_pool = web::Data::new(db_pool);
+    let server = HttpServer::new(move || {
+        App::new()
+            .wrap(TracingLogger::default())
+            .app_data(db_pool.clone())
+            .app_data(settings.clone())
+            .route("/metrics", web::get().to(metrics))
+    })
+    .listen(listener)?
+    .disable_signals()
+    .run();
+    Ok(server)
 }
 impl Application {
     pub async fn build(
+        settings: Settings,
         port: u16,
         pool: PgPool,
         mock_server_uri: Option<String>,
+        clock: Arc<dyn Clock>,
     ) -> Result<Self, anyhow::Error> {
-        use dotenv::dotenv;
-        use std::env;
-        dotenv().ok();
-        let application_base_url =
-            env::var("APPLICATION_BASE_URL").expect("Failed to get application base url");
-        let timeout = EmailClient::timeout();
-        let sender =
-            SubscriberEmail::parse(env::var("SENDER_EMAIL").expect("Failed to get sender email "))
-                .expect("Invalid sender email address");
-        let email_client = if let Some(uri) = mock_server_uri {
-            EmailClient::new(
-                uri,
-                sender,
-                Secret::new(env::var("AUTHORIZATION_TOKEN").expect("Failed to get auth token")),
-                timeout,
-            )
-        } else {
-            EmailClient::new(
-                env::var("BASE_URL").expect("Failed to get base URL"),
-                sender,
-                Secret::new(env::var("AUTHORIZATION_TOKEN").expect("Failed to get auth token")),
-                timeout,
-            )
-        };
+        // Tests send emails to a mock server instead of the provider.
+        let email_client = EmailClient::new(
+            mock_server_uri.unwrap_or_else(|| settings.email_base_url.clone()),
+            settings.sender_email.clone(),
+            settings.email_authorization_token.clone(),
+            settings.email_timeout,
+        );
+        let email_client = Arc::new(email_client.with_limits(settings.send_limits));
+        if settings.scheduler_poll_interval.is_zero() {
+            anyhow::bail!("SCHEDULER_POLL_INTERVAL_SECONDS must be positive");
+        }
+
+        let shutdown = Shutdown::new();
+        let shutdown_deadline = settings.shutdown_deadline;
+        let scheduler = tokio::spawn(run_scheduler_until_stopped(
+            pool.clone(),
+            email_client.clone(),
+            clock.clone(),
+            settings.application_base_url.clone(),
+            settings.signing_keys.tracking.clone(),
+            settings.scheduler_poll_interval,
+            shutdown.clone(),
+        ));
 
         let (listener, actual_port) = if port == 0 {
             let listener = TcpListener::bind("127.0.0.1:0")?;
@@ -77,20 +152,29 @@ impl Application {
             (listener, port)
         };
 
-        let redis_uri =
-            Secret::new(env::var("REDIS_URI").expect("Failed to get redis configurations"));
+        let settings = web::Data::new(settings);
+        let metrics_server = match settings.metrics_port {
+            Some(port) => Some(run_metrics_server(port, pool.clone(), settings.clone())?),
+            None => None,
+        };
         let server = run(
             listener,
             pool,
             email_client,
-            application_base_url,
-            redis_uri,
+            settings,
+            clock,
+            metrics_server.is_none(),
+            shutdown.clone(),
         )
         .await?;
 
         Ok(Self {
             port: actual_port,
             server,
+            metrics_server,
+            scheduler,
+            shutdown,
+            shutdown_deadline,
         })
     }
 
@@ -98,36 +182,539 @@ impl Application {
         self.port
     }
 
+    // Triggering it has the same effect as SIGTERM.
+    pub fn shutdown_handle(&self) -> Shutdown {
+        self.shutdown.clone()
+    }
+
+    // On SIGTERM or Ctrl-C, stops accepting connections and waits for in-flight requests and
+    // deliveries. Deliveries save their progress once `SHUTDOWN_DEADLINE_SECONDS` have passed;
+    // requests still running a few seconds after that are dropped.
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        let server_handle = self.server.handle();
+        let metrics_handle = self.metrics_server.map(|metrics_server| {
+            let handle = metrics_server.handle();
+            tokio::spawn(metrics_server);
+            handle
+        });
+        let shutdown = self.shutdown.clone();
+        let deadline = self.shutdown_deadline;
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = wait_for_signal() => shutdown.trigger(deadline),
+                _ = shutdown.requested() => {}
+            }
+            if let Some(metrics_handle) = metrics_handle {
+                metrics_handle.stop(true).await;
+            }
+            server_handle.pause().await;
+            let grace = deadline + Duration::from_secs(5);
+            if tokio::time::timeout(grace, shutdown.drained())
+                .await
+                .is_err()
+            {
+                tracing::warn!("Requests still in flight after the shutdown deadline are dropped");
+            }
+            server_handle.stop(true).await;
+        });
+
+        self.server.await?;
+        match self.scheduler.await {
+            Ok(Err(e)) => tracing::error!(error.cause_chain = ?e, "The scheduler failed"),
+            Err(e) => tracing::error!(error.cause_chain = ?e, "The scheduler panicked"),
+            Ok(Ok(())) => {}
+        }
+        tracing::info!("Shut down");
+        Ok(())
     }
 }
 
 pub struct ApplicationBaseUrl(pub String);
 
+// The routes of the application by method and path, recorded as they are registered so that
+// tests can check each of them against the OpenAPI document.
+#[derive(Default)]
+pub struct RouteTable {
+    prefix: String,
+    routes: Vec<(Method, String)>,
+}
+
+impl RouteTable {
+    fn route(&mut self, cfg: &mut web::ServiceConfig, method: Method, path: &str, route: Route) {
+        self.routes
+            .push((method.clone(), format!("{}{}", self.prefix, path)));
+        cfg.route(path, route.method(method));
+    }
+
+    // The routes `register` adds are under `prefix`.
+    fn scope(
+        &mut self,
+        prefix: &str,
+        register: impl FnOnce(&mut web::ServiceConfig, &mut Self),
+    ) -> Scope {
+        let outer = self.prefix.clone();
+        self.prefix.push_str(prefix);
+        let scope = web::scope(prefix).configure(|cfg| register(cfg, self));
+        self.prefix = outer;
+        scope
+    }
+}
+
+// Every route of the application, including the optional ones.
+pub fn route_table() -> Vec<(Method, String)> {
+    let mut routes = RouteTable::default();
+    let _ = App::new().configure(|cfg| register_routes(cfg, &mut routes, true, true));
+    routes.routes
+}
+
+// Routes are only added through `routes`, so that `route_table` lists all of them.
+fn register_routes(
+    cfg: &mut web::ServiceConfig,
+    routes: &mut RouteTable,
+    serve_metrics: bool,
+    serve_api_docs: bool,
+) {
+    routes.route(
+        cfg,
+        Method::GET,
+        "/health_check",
+        web::route().to(health_check),
+    );
+    routes.route(
+        cfg,
+        Method::GET,
+        "/health/live",
+        web::route().to(health_live),
+    );
+    routes.route(
+        cfg,
+        Method::GET,
+        "/health/ready",
+        web::route().to(health_ready),
+    );
+    if serve_metrics {
+        routes.route(cfg, Method::GET, "/metrics", web::route().to(metrics));
+    }
+    routes.route(
+        cfg,
+        Method::GET,
+        "/subscriptions",
+        web::route().to(signup_form),
+    );
+    routes.route(
+        cfg,
+        Method::POST,
+        "/subscriptions",
+        web::route()
+            .to(subscribe)
+            .wrap(actix_web::middleware::from_fn(|req, next| {
+                enforce_rate_limit(LimitedRoute::Subscribe, req, next)
+            })),
+    );
+    routes.route(
+        cfg,
+        Method::GET,
+        "/subscriptions/confirm",
+        web::route()
+            .to(confirm)
+            .wrap(actix_web::middleware::from_fn(|req, next| {
+                enforce_rate_limit(LimitedRoute::ConfirmSubscription, req, next)
+            })),
+    );
+    routes.route(
+        cfg,
+        Method::GET,
+        "/subscriptions/unsubscribe",
+        web::route().to(unsubscribe_form),
+    );
+    routes.route(
+        cfg,
+        Method::POST,
+        "/subscriptions/unsubscribe",
+        web::route().to(unsubscribe),
+    );
+    routes.route(
+        cfg,
+        Method::POST,
+        "/webhooks/email/{provider}",
+        web::route().to(receive_email_webhook),
+    );
+    routes.route(
+        cfg,
+        Method::GET,
+        "/t/o/{token}",
+        web::route().to(track_open),
+    );
+    routes.route(
+        cfg,
+        Method::GET,
+        "/t/c/{token}",
+        web::route().to(track_click),
+    );
+    routes.route(
+        cfg,
+        Method::POST,
+        "/newsletters",
+        web::route().to(publish_newsletter),
+    );
+    routes.route(
+        cfg,
+        Method::GET,
+        "/newsletters/preview",
+        web::route().to(preview_segment),
+    );
+    routes.route(
+        cfg,
+        Method::GET,
+        "/newsletters/{newsletter_issue_id}",
+        web::route().to(get_newsletter_issue),
+    );
+    routes.route(
+        cfg,
+        Method::PUT,
+        "/newsletters/{newsletter_issue_id}/schedule",
+        web::route().to(schedule_newsletter_issue),
+    );
+    routes.route(
+        cfg,
+        Method::POST,
+        "/newsletters/{newsletter_issue_id}/cancel",
+        web::route().to(cancel_newsletter_issue),
+    );
+    routes.route(
+        cfg,
+        Method::GET,
+        "/api/openapi.json",
+        web::route().to(openapi_json),
+    );
+    if serve_api_docs {
+        routes.route(cfg, Method::GET, "/api/docs", web::route().to(api_docs));
+    }
+    let api = routes.scope("/api/v1", |cfg, routes| {
+        routes.route(
+            cfg,
+            Method::GET,
+            "/subscribers",
+            web::route().to(list_subscribers),
+        );
+        routes.route(
+            cfg,
+            Method::POST,
+            "/subscribers",
+            web::route().to(create_subscriber),
+        );
+        routes.route(
+            cfg,
+            Method::GET,
+            "/subscribers/{subscriber_id}",
+            web::route().to(get_subscriber),
+        );
+        routes.route(
+            cfg,
+            Method::PATCH,
+            "/subscribers/{subscriber_id}",
+            web::route().to(update_subscriber),
+        );
+        routes.route(
+            cfg,
+            Method::DELETE,
+            "/subscribers/{subscriber_id}",
+            web::route().to(delete_subscriber),
+        );
+        routes.route(cfg, Method::GET, "/issues", web::route().to(list_issues));
+        routes.route(cfg, Method::POST, "/issues", web::route().to(create_issue));
+        routes.route(
+            cfg,
+            Method::GET,
+            "/issues/{newsletter_issue_id}",
+            web::route().to(get_issue_status),
+        );
+        routes.route(
+            cfg,
+            Method::POST,
+            "/issues/{newsletter_issue_id}/schedule",
+            web::route().to(schedule_issue),
+        );
+        routes.route(
+            cfg,
+            Method::POST,
+            "/issues/{newsletter_issue_id}/send",
+            web::route().to(send_issue),
+        );
+        routes.route(
+            cfg,
+            Method::GET,
+            "/issues/{newsletter_issue_id}/stats",
+            web::route().to(api_issue_stats),
+        );
+        routes.route(cfg, Method::GET, "/stats", web::route().to(overall_stats));
+    });
+    cfg.service(
+        api.configure(extractor_errors)
+            .default_service(web::to(not_found)),
+    );
+    routes.route(cfg, Method::GET, "/", web::route().to(signup_form));
+    routes.route(cfg, Method::GET, "/archive", web::route().to(archive));
+    routes.route(
+        cfg,
+        Method::GET,
+        "/archive/{newsletter_issue_id}",
+        web::route().to(archived_issue),
+    );
+    routes.route(cfg, Method::GET, "/feed.xml", web::route().to(atom_feed));
+    routes.route(cfg, Method::GET, "/rss.xml", web::route().to(rss_feed));
+    routes.route(
+        cfg,
+        Method::GET,
+        "/login",
+        web::route()
+            .to(login_form)
+            .wrap(actix_web::middleware::from_fn(reject_forged_requests)),
+    );
+    routes.route(
+        cfg,
+        Method::POST,
+        "/login",
+        web::route()
+            .to(login)
+            .wrap(actix_web::middleware::from_fn(reject_forged_requests))
+            .wrap(actix_web::middleware::from_fn(|req, next| {
+                enforce_rate_limit(LimitedRoute::Login, req, next)
+            })),
+    );
+    let admin = routes.scope("/admin", |cfg, routes| {
+        routes.route(
+            cfg,
+            Method::GET,
+            "/dashboard",
+            web::route().to(admin_dashboard),
+        );
+        routes.route(
+            cfg,
+            Method::GET,
+            "/password",
+            web::route().to(change_password_form),
+        );
+        routes.route(
+            cfg,
+            Method::POST,
+            "/password",
+            web::route().to(change_password),
+        );
+        routes.route(cfg, Method::POST, "/logout", web::route().to(log_out));
+        routes.route(
+            cfg,
+            Method::GET,
+            "/subscribers",
+            web::route().to(subscribers_list),
+        );
+        routes.route(
+            cfg,
+            Method::POST,
+            "/subscribers/tags",
+            web::route().to(set_subscriber_tags),
+        );
+        routes.route(cfg, Method::GET, "/drafts", web::route().to(drafts_list));
+        routes.route(
+            cfg,
+            Method::POST,
+            "/drafts",
+            web::route().to(create_draft_issue),
+        );
+        routes.route(
+            cfg,
+            Method::GET,
+            "/drafts/{newsletter_issue_id}",
+            web::route().to(draft_form),
+        );
+        routes.route(
+            cfg,
+            Method::POST,
+            "/drafts/{newsletter_issue_id}",
+            web::route().to(save_draft),
+        );
+        routes.route(
+            cfg,
+            Method::GET,
+            "/drafts/{newsletter_issue_id}/diff",
+            web::route().to(draft_diff),
+        );
+        routes.route(
+            cfg,
+            Method::POST,
+            "/drafts/{newsletter_issue_id}/restore",
+            web::route().to(restore_draft_revision),
+        );
+        routes.route(
+            cfg,
+            Method::POST,
+            "/drafts/{newsletter_issue_id}/test",
+            web::route().to(send_draft_test_email),
+        );
+        routes.route(
+            cfg,
+            Method::GET,
+            "/deliveries",
+            web::route().to(deliveries_search),
+        );
+        routes.route(cfg, Method::GET, "/issues", web::route().to(issues_list));
+        routes.route(
+            cfg,
+            Method::GET,
+            "/issues/{newsletter_issue_id}/stats",
+            web::route().to(issue_stats),
+        );
+        routes.route(
+            cfg,
+            Method::POST,
+            "/issues/{newsletter_issue_id}/archive",
+            web::route().to(set_issue_in_archive),
+        );
+        routes.route(
+            cfg,
+            Method::GET,
+            "/api_keys",
+            web::route().to(api_keys_list),
+        );
+        routes.route(
+            cfg,
+            Method::POST,
+            "/api_keys",
+            web::route().to(create_api_key),
+        );
+        routes.route(
+            cfg,
+            Method::POST,
+            "/api_keys/{api_key_id}/revoke",
+            web::route().to(revoke_api_key),
+        );
+        routes.route(
+            cfg,
+            Method::GET,
+            "/templates",
+            web::route().to(templates_list),
+        );
+        routes.route(
+            cfg,
+            Method::POST,
+            "/templates/layouts",
+            web::route().to(create_layout),
+        );
+        routes.route(
+            cfg,
+            Method::GET,
+            "/templates/layouts/{name}",
+            web::route().to(layout_form),
+        );
+        routes.route(
+            cfg,
+            Method::POST,
+            "/templates/layouts/{name}",
+            web::route().to(save_layout),
+        );
+        routes.route(
+            cfg,
+            Method::GET,
+            "/templates/layouts/{name}/preview",
+            web::route().to(layout_preview),
+        );
+        routes.route(
+            cfg,
+            Method::GET,
+            "/templates/emails/{name}",
+            web::route().to(transactional_template_form),
+        );
+        routes.route(
+            cfg,
+            Method::POST,
+            "/templates/emails/{name}",
+            web::route().to(save_transactional_template),
+        );
+        routes.route(
+            cfg,
+            Method::GET,
+            "/templates/emails/{name}/preview",
+            web::route().to(transactional_template_preview),
+        );
+    });
+    cfg.service(
+        admin
+            // Only logged in users get as far as the CSRF check.
+            .wrap(actix_web::middleware::from_fn(reject_forged_requests))
+            .wrap(from_fn(reject_anonymous_users)),
+    );
+}
+
 // We need to mark `run` as public,  It is no longer a binary entrypoint, therefore we can mark it as async, without having to use any proc-macro incantation.
+#[allow(clippy::too_many_arguments)]
 async fn run(
     listener: TcpListener,
     db_pool: PgPool,
-    email_client: EmailClient,
-    application_base_url: String,
-    redis_uri: Secret<String>,
+    email_client: Arc<EmailClient>,
+    settings: web::Data<Settings>,
+    clock: Arc<dyn Clock>,
+    serve_metrics: bool,
+    shutdown: Shutdown,
 ) -> Result<Server, anyhow::Error> {
-    use dotenv::dotenv;
-    dotenv().ok();
-    let hmac_secret = Secret::new(std::env::var("HMAC_SECRET").expect("HMAC_SECRET must be set"));
-    let secret_key = Key::from(hmac_secret.expose_secret().as_bytes());
-    let application_base_url = web::Data::new(ApplicationBaseUrl(application_base_url));
+    let secret_key = Key::from(settings.signing_keys.cookies.as_bytes());
+    let application_base_url =
+        web::Data::new(ApplicationBaseUrl(settings.application_base_url.clone()));
     let db_pool = web::Data::new(db_pool);
-    let email_client = web::Data::new(email_client);
-    let message_store =
-        CookieMessageStore::builder(Key::from(hmac_secret.expose_secret().as_bytes())).build();
+    let email_client = web::Data::from(email_client);
+    let clock: web::Data<dyn Clock> = web::Data::from(clock);
+    let shutdown = web::Data::new(shutdown);
+    let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
     // let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    let redis_uri = &settings.redis_uri;
+    let redis_client = web::Data::new(redis::Client::open(redis_uri.expose_secret().as_str())?);
+    let rate_limiter = RateLimiter::new(
+        redis_client.as_ref().clone(),
+        settings.rate_limits.clone(),
+        settings.trusted_proxies.clone(),
+    );
+    let rate_limiter = web::Data::new(match &settings.rate_limit_key_prefix {
+        Some(prefix) => rate_limiter.with_key_prefix(prefix),
+        None => rate_limiter,
+    });
+    let mut bot_protection = BotProtection::new(
+        settings.signing_keys.form_tokens.clone(),
+        redis_client.as_ref().clone(),
+    )
+    .with_timing(settings.signup_min_fill_time, settings.signup_form_max_age);
+    if let Some(captcha) = &settings.captcha {
+        bot_protection = bot_protection.with_captcha(Box::new(captcha.clone()));
+    }
+    let bot_protection = web::Data::new(bot_protection);
+    let signing_keys = web::Data::new(settings.signing_keys.clone());
+    let mut security_headers = settings
+        .security_headers
+        .clone()
+        .with_route_policy(
+            "/admin/templates/layouts/{name}/preview",
+            EMAIL_PREVIEW_CONTENT_SECURITY_POLICY,
+        )
+        .with_route_policy(
+            "/admin/templates/emails/{name}/preview",
+            EMAIL_PREVIEW_CONTENT_SECURITY_POLICY,
+        )
+        .with_route_policy(
+            "/archive/{newsletter_issue_id}",
+            EMAIL_PREVIEW_CONTENT_SECURITY_POLICY,
+        )
+        .with_route_policy("/api/docs", API_DOCS_CONTENT_SECURITY_POLICY);
+    if let Some(captcha) = bot_protection.captcha() {
+        for signup_page in ["/", "/subscriptions"] {
+            security_headers =
+                security_headers.with_route_policy(signup_page, captcha.content_security_policy());
+        }
+    }
+    let security_headers = web::Data::new(security_headers);
+    let serve_api_docs = settings.api_docs_ui;
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret())
         .await
         .map_err(|e| {
-            eprintln!("Failed to create Redis session store: {:?}", e);
+            tracing::error!(error.cause_chain = ?e, "Failed to create the Redis session store");
             std::io::Error::new(std::io::ErrorKind::Other, "Redis connection failed")
         })?;
     let server = HttpServer::new(move || {
@@ -138,26 +725,32 @@ async fn run(
                 secret_key.clone(),
             ))
             .wrap(TracingLogger::default())
+            .wrap(actix_web::middleware::from_fn(record_http_metrics))
+            .wrap(actix_web::middleware::from_fn(track_in_flight_requests))
+            .wrap(actix_web::middleware::from_fn(add_security_headers))
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(application_base_url.clone())
-            .route("/health_check", web::get().to(health_check))
-            .route("/subscriptions", web::post().to(subscribe))
-            .route("/subscriptions/confirm", web::get().to(confirm))
-            .route("/newsletters", web::post().to(publish_newsletter))
-            .route("/", web::get().to(home))
-            .route("/login", web::get().to(login_form))
-            .route("/login", web::post().to(login))
-            .service(
-                web::scope("/admin")
-                    .wrap(from_fn(reject_anonymous_users))
-                    .route("/dashboard", web::get().to(admin_dashboard))
-                    .route("/password", web::get().to(change_password_form))
-                    .route("/password", web::post().to(change_password))
-                    .route("/logout", web::post().to(log_out)),
-            )
+            .app_data(clock.clone())
+            .app_data(redis_client.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(bot_protection.clone())
+            .app_data(signing_keys.clone())
+            .app_data(settings.clone())
+            .app_data(security_headers.clone())
+            .app_data(shutdown.clone())
+            .configure(|cfg| {
+                register_routes(
+                    cfg,
+                    &mut RouteTable::default(),
+                    serve_metrics,
+                    serve_api_docs,
+                )
+            })
     })
     .listen(listener)?
+    // Signals are handled by `Application::run_until_stopped`, so the scheduler stops too.
+    .disable_signals()
     .run();
     // No .await here
     Ok(server)
@@ -1,3 +1,15 @@
+use crate::log_redaction::{RedactingMakeWriter, RedactionRules};
+use opentelemetry::propagation::Injector;
+use opentelemetry::trace::{Status, TraceError, TracerProvider as _};
+use opentelemetry::{global, KeyValue, Value};
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::export::trace::{ExportResult, SpanData, SpanExporter};
+use opentelemetry_sdk::propagation::TraceContextPropagator;
+use opentelemetry_sdk::trace::{Config, Tracer, TracerProvider};
+use opentelemetry_sdk::{runtime, Resource};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::future::Future;
+use std::pin::Pin;
 use tracing::subscriber::set_global_default;
 use tracing::Subscriber;
 use tracing_bunyan_formatter::{BunyanFormattingLayer, JsonStorageLayer};
@@ -6,23 +18,167 @@ use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Registry};
 use tracing_log::LogTracer;
 //for tests
 use tokio::task::JoinHandle;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::fmt::MakeWriter;
 
+// Email addresses, tokens and credentials are masked in every record, see `RedactionRules`.
+// Spans are also exported to `otlp_endpoint`, when there is one, masked with the same rules;
+// this has to be called from within a Tokio runtime, which runs the exporter.
 pub fn get_subscriber<Sink>(
     name: String,
     env_filter: String,
     sink: Sink,
+    rules: RedactionRules,
+    otlp_endpoint: Option<&str>,
+) -> Result<impl Subscriber + Sync + Send, TraceError>
+where
+    Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
+{
+    let tracer = otlp_endpoint
+        .map(|endpoint| otlp_tracer(&name, endpoint, rules.clone()))
+        .transpose()?;
+    Ok(build_subscriber(name, env_filter, sink, rules, tracer))
+}
+
+pub fn build_subscriber<Sink>(
+    name: String,
+    env_filter: String,
+    sink: Sink,
+    rules: RedactionRules,
+    tracer: Option<Tracer>,
 ) -> impl Subscriber + Sync + Send
 where
     Sink: for<'a> MakeWriter<'a> + Send + Sync + 'static,
 {
     let env_filter =
         EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(env_filter));
-    let formatting_layer = BunyanFormattingLayer::new(name, sink);
+    let formatting_layer = BunyanFormattingLayer::new(name, RedactingMakeWriter::new(sink, rules));
+    if tracer.is_some() {
+        // Used by `TracingLogger` to pick up the `traceparent` of incoming requests.
+        global::set_text_map_propagator(TraceContextPropagator::new());
+    }
+    let otel_layer = tracer.map(|tracer| tracing_opentelemetry::layer().with_tracer(tracer));
     Registry::default()
         .with(env_filter)
         .with(JsonStorageLayer)
         .with(formatting_layer)
+        .with(otel_layer)
+}
+
+// Exports spans in batches to an OTLP collector over gRPC, e.g. `http://localhost:4317`.
+pub fn otlp_tracer(
+    service_name: &str,
+    endpoint: &str,
+    rules: RedactionRules,
+) -> Result<Tracer, TraceError> {
+    let exporter = opentelemetry_otlp::new_exporter()
+        .tonic()
+        .with_endpoint(endpoint)
+        .build_span_exporter()?;
+    let provider = TracerProvider::builder()
+        .with_batch_exporter(RedactingExporter::new(exporter, rules), runtime::Tokio)
+        .with_config(
+            Config::default().with_resource(Resource::new(vec![KeyValue::new(
+                "service.name",
+                service_name.to_string(),
+            )])),
+        )
+        .build();
+    let tracer = provider.tracer(service_name.to_string());
+    global::set_tracer_provider(provider);
+    Ok(tracer)
+}
+
+// Spans carry the fields of `tracing` spans and events as attributes, so they are masked like
+// log records before they leave the process.
+#[derive(Debug)]
+pub struct RedactingExporter<E> {
+    inner: E,
+    rules: RedactionRules,
+}
+
+impl<E> RedactingExporter<E> {
+    pub fn new(inner: E, rules: RedactionRules) -> Self {
+        Self { inner, rules }
+    }
+
+    fn redact_span(&self, span: &mut SpanData) {
+        self.redact_attributes(&mut span.attributes);
+        for event in span.events.events.iter_mut() {
+            event.name = self.rules.redact_text(&event.name).into();
+            self.redact_attributes(&mut event.attributes);
+        }
+        if let Status::Error { description } = &mut span.status {
+            *description = self.rules.redact_text(description).into();
+        }
+    }
+
+    // Only strings can hold personal data; `tracing` records `Debug` and `Display` fields as such.
+    fn redact_attributes(&self, attributes: &mut [KeyValue]) {
+        for attribute in attributes {
+            let Value::String(text) = &attribute.value else {
+                continue;
+            };
+            let mut value = serde_json::Value::String(text.as_str().to_string());
+            self.rules.redact_field(attribute.key.as_str(), &mut value);
+            if let serde_json::Value::String(text) = value {
+                attribute.value = text.into();
+            }
+        }
+    }
+}
+
+impl<E: SpanExporter> SpanExporter for RedactingExporter<E> {
+    fn export(
+        &mut self,
+        mut batch: Vec<SpanData>,
+    ) -> Pin<Box<dyn Future<Output = ExportResult> + Send + 'static>> {
+        for span in batch.iter_mut() {
+            self.redact_span(span);
+        }
+        self.inner.export(batch)
+    }
+
+    fn shutdown(&mut self) {
+        self.inner.shutdown()
+    }
+
+    fn force_flush(&mut self) -> Pin<Box<dyn Future<Output = ExportResult> + Send + 'static>> {
+        self.inner.force_flush()
+    }
+
+    fn set_resource(&mut self, resource: &Resource) {
+        self.inner.set_resource(resource)
+    }
+}
+
+// Exports the spans that are still buffered; call before the process exits.
+pub fn shutdown_tracing() {
+    global::shutdown_tracer_provider();
+}
+
+struct HeaderInjector<'a>(&'a mut HeaderMap);
+
+impl Injector for HeaderInjector<'_> {
+    fn set(&mut self, key: &str, value: String) {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(key.as_bytes()),
+            HeaderValue::from_str(&value),
+        ) {
+            self.0.insert(name, value);
+        }
+    }
+}
+
+// The W3C `traceparent` of the current span, to send along with outgoing requests.
+// Empty unless spans are exported.
+pub fn trace_context_headers() -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    let context = tracing::Span::current().context();
+    global::get_text_map_propagator(|propagator| {
+        propagator.inject_context(&context, &mut HeaderInjector(&mut headers))
+    });
+    headers
 }
 
 pub fn init_subscriber(subscriber: impl Subscriber + Sync + Send) {
@@ -40,3 +196,131 @@ where
     tokio::task::spawn_blocking(move || current_span.in_scope(f))
 }
 
+#[cfg(test)]
+mod tests {
+    use super::{build_subscriber, trace_context_headers, RedactingExporter};
+    use crate::log_redaction::RedactionRules;
+    use actix_web::test::{call_service, init_service, TestRequest};
+    use actix_web::{web, App, HttpResponse};
+    use opentelemetry::global;
+    use opentelemetry::trace::{TraceContextExt, TracerProvider as _};
+    use opentelemetry_sdk::propagation::TraceContextPropagator;
+    use opentelemetry_sdk::testing::trace::InMemorySpanExporter;
+    use opentelemetry_sdk::trace::TracerProvider;
+    use tracing_actix_web::TracingLogger;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+
+    const TRACEPARENT: &str = "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01";
+
+    fn in_memory_tracing() -> (
+        InMemorySpanExporter,
+        TracerProvider,
+        impl tracing::Subscriber + Send + Sync,
+    ) {
+        let exporter = InMemorySpanExporter::default();
+        let provider = TracerProvider::builder()
+            .with_simple_exporter(RedactingExporter::new(
+                exporter.clone(),
+                RedactionRules::default(),
+            ))
+            .build();
+        let subscriber = build_subscriber(
+            "test".into(),
+            "info".into(),
+            std::io::sink,
+            RedactionRules::default(),
+            Some(provider.tracer("test")),
+        );
+        global::set_text_map_propagator(TraceContextPropagator::new());
+        (exporter, provider, subscriber)
+    }
+
+    #[tracing::instrument(name = "Adding a new subscriber")]
+    fn subscribe() {}
+
+    #[test]
+    fn instrumented_spans_are_exported() {
+        let (exporter, provider, subscriber) = in_memory_tracing();
+        tracing::subscriber::with_default(subscriber, subscribe);
+        provider.force_flush();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        assert!(spans.iter().any(|s| s.name == "Adding a new subscriber"));
+    }
+
+    #[tracing::instrument(
+        name = "Adding a new subscriber",
+        fields(subscriber_email = %email, subscriber_name = %name)
+    )]
+    fn subscribe_with(email: &str, name: &str) {
+        tracing::warn!("Failed to send a confirmation email to {}", email);
+    }
+
+    #[test]
+    fn exported_spans_carry_no_email_addresses() {
+        let (exporter, provider, subscriber) = in_memory_tracing();
+        tracing::subscriber::with_default(subscriber, || {
+            subscribe_with("ursula_le_guin@gmail.com", "le guin")
+        });
+        provider.force_flush();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let span = spans
+            .iter()
+            .find(|s| s.name == "Adding a new subscriber")
+            .unwrap();
+        let attribute = |key: &str| {
+            span.attributes
+                .iter()
+                .find(|a| a.key.as_str() == key)
+                .map(|a| a.value.to_string())
+        };
+        assert_eq!(attribute("subscriber_email").unwrap(), "u***@gmail.com");
+        assert_eq!(attribute("subscriber_name").unwrap(), "le guin");
+        let exported = format!("{:?}", spans);
+        assert!(!exported.contains("ursula_le_guin@gmail.com"));
+        assert!(exported.contains("u***@gmail.com"));
+    }
+
+    #[test]
+    fn outgoing_requests_carry_the_current_trace() {
+        let (_exporter, _provider, subscriber) = in_memory_tracing();
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("Sending an email request");
+            let _guard = span.enter();
+            let trace_id = span.context().span().span_context().trace_id();
+
+            let headers = trace_context_headers();
+            let traceparent = headers["traceparent"].to_str().unwrap();
+            assert!(traceparent.starts_with(&format!("00-{}-", trace_id)));
+        });
+    }
+
+    #[actix_web::test]
+    async fn incoming_requests_continue_the_callers_trace() {
+        let (exporter, provider, subscriber) = in_memory_tracing();
+        let _default = tracing::subscriber::set_default(subscriber);
+        let app = init_service(
+            App::new()
+                .wrap(TracingLogger::default())
+                .route("/", web::get().to(HttpResponse::Ok)),
+        )
+        .await;
+
+        let request = TestRequest::get()
+            .uri("/")
+            .insert_header(("traceparent", TRACEPARENT))
+            .to_request();
+        call_service(&app, request).await;
+        provider.force_flush();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        assert!(!spans.is_empty());
+        for span in spans {
+            assert_eq!(
+                span.span_context.trace_id().to_string(),
+                "4bf92f3577b34da6a3ce929d0e0e4736"
+            );
+        }
+    }
+}
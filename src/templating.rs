@@ -0,0 +1,165 @@
+// A minimal template language: `{{ name }}` is replaced by the value of the variable `name`.
+// Templates are parsed against the list of variables they may use, so mistakes are caught
+// before anything is rendered.
+
+#[derive(Debug, PartialEq)]
+enum Part {
+    Literal(String),
+    Variable(String),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    pub fn parse(source: &str, variables: &[&str]) -> Result<Self, String> {
+        let mut parts = vec![];
+        let mut rest = source;
+        while let Some(start) = rest.find("{{") {
+            if start > 0 {
+                parts.push(Part::Literal(rest[..start].to_string()));
+            }
+            let line = line_number(source, source.len() - rest.len() + start);
+            let Some(end) = rest[start..].find("}}") else {
+                return Err(format!("Unclosed `{{{{` on line {}.", line));
+            };
+            let name = rest[start + 2..start + end].trim();
+            if name.is_empty() {
+                return Err(format!(
+                    "Missing variable name in `{{{{ }}}}` on line {}.",
+                    line
+                ));
+            }
+            if !variables.contains(&name) {
+                return Err(format!(
+                    "Unknown variable `{}` on line {}. Available variables are: {}.",
+                    name,
+                    line,
+                    variables.join(", ")
+                ));
+            }
+            parts.push(Part::Variable(name.to_string()));
+            rest = &rest[start + end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Literal(rest.to_string()));
+        }
+        Ok(Self { parts })
+    }
+
+    // Values are HTML-escaped, so that subscriber data can never inject markup.
+    pub fn render_html(&self, values: &[(&str, &str)]) -> String {
+        self.render(values, escape_html)
+    }
+
+    pub fn render_text(&self, values: &[(&str, &str)]) -> String {
+        self.render(values, str::to_string)
+    }
+
+    fn render(&self, values: &[(&str, &str)], escape: fn(&str) -> String) -> String {
+        let mut output = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(literal) => output.push_str(literal),
+                Part::Variable(name) => {
+                    let value = values
+                        .iter()
+                        .find(|(n, _)| n == name)
+                        .map(|(_, v)| *v)
+                        .unwrap_or_default();
+                    output.push_str(&escape(value));
+                }
+            }
+        }
+        output
+    }
+}
+
+fn line_number(source: &str, offset: usize) -> usize {
+    source[..offset].matches('\n').count() + 1
+}
+
+// Unlike `htmlescape::encode_minimal`, quotes are escaped too, so values are safe inside
+// attributes such as `href="{{ unsubscribe_url }}"`.
+pub fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Template;
+
+    const VARIABLES: &[&str] = &["subscriber.name", "unsubscribe_url"];
+
+    #[test]
+    fn variables_are_replaced_by_their_value() {
+        let template = Template::parse(
+            "Hi {{ subscriber.name }}, bye {{unsubscribe_url}}",
+            VARIABLES,
+        )
+        .unwrap();
+        let values = [
+            ("subscriber.name", "Ursula"),
+            ("unsubscribe_url", "https://x.com/u"),
+        ];
+        assert_eq!(
+            template.render_text(&values),
+            "Hi Ursula, bye https://x.com/u"
+        );
+    }
+
+    #[test]
+    fn values_are_escaped_in_html() {
+        let template =
+            Template::parse(r#"<a title="{{ subscriber.name }}">hi</a>"#, VARIABLES).unwrap();
+        let values = [("subscriber.name", r#""><script>alert('x')</script>"#)];
+        assert_eq!(
+            template.render_html(&values),
+            r#"<a title="&quot;&gt;&lt;script&gt;alert(&#x27;x&#x27;)&lt;/script&gt;">hi</a>"#
+        );
+        assert_eq!(
+            template.render_text(&values),
+            r#"<a title=""><script>alert('x')</script>">hi</a>"#
+        );
+    }
+
+    #[test]
+    fn text_without_variables_is_left_alone() {
+        let source = "No variables here, just } and { and }}.";
+        let template = Template::parse(source, VARIABLES).unwrap();
+        assert_eq!(template.render_text(&[]), source);
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        let error = Template::parse("Hi\n{{ subscriber.age }}", VARIABLES).unwrap_err();
+        assert_eq!(
+            error,
+            "Unknown variable `subscriber.age` on line 2. Available variables are: subscriber.name, unsubscribe_url."
+        );
+    }
+
+    #[test]
+    fn unclosed_tags_are_rejected() {
+        let error = Template::parse("Hi {{ subscriber.name", VARIABLES).unwrap_err();
+        assert_eq!(error, "Unclosed `{{` on line 1.");
+    }
+
+    #[test]
+    fn empty_tags_are_rejected() {
+        assert!(Template::parse("Hi {{ }}", VARIABLES).is_err());
+    }
+}
@@ -0,0 +1,369 @@
+use crate::db_models::IssueLink;
+use crate::schema::{issue_links, tracking_events};
+use crate::signing::SigningKey;
+use crate::templating::escape_html;
+use chrono::{DateTime, Utc};
+use diesel::dsl::{count_distinct, count_star};
+use diesel::pg::PgConnection;
+use diesel::prelude::*;
+use uuid::Uuid;
+
+const OPEN: u8 = b'o';
+const CLICK: u8 = b'c';
+
+// A transparent 1x1 GIF.
+pub const PIXEL: &[u8] = b"GIF89a\x01\x00\x01\x00\x80\x00\x00\x00\x00\x00\xff\xff\xff!\xf9\x04\x01\x00\x00\x00\x00,\x00\x00\x00\x00\x01\x00\x01\x00\x00\x02\x02D\x01\x00;";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrackingEvent {
+    Delivered,
+    Open,
+    Click,
+    Unsubscribe,
+}
+
+impl TrackingEvent {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TrackingEvent::Delivered => "delivered",
+            TrackingEvent::Open => "open",
+            TrackingEvent::Click => "click",
+            TrackingEvent::Unsubscribe => "unsubscribe",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum TrackingToken {
+    Open {
+        newsletter_issue_id: Uuid,
+        subscriber_id: Uuid,
+    },
+    Click {
+        newsletter_issue_id: Uuid,
+        subscriber_id: Uuid,
+        link_number: i32,
+    },
+}
+
+impl TrackingToken {
+    fn payload(&self) -> Vec<u8> {
+        match self {
+            TrackingToken::Open {
+                newsletter_issue_id,
+                subscriber_id,
+            } => [
+                &[OPEN][..],
+                newsletter_issue_id.as_bytes(),
+                subscriber_id.as_bytes(),
+            ]
+            .concat(),
+            TrackingToken::Click {
+                newsletter_issue_id,
+                subscriber_id,
+                link_number,
+            } => [
+                &[CLICK][..],
+                newsletter_issue_id.as_bytes(),
+                subscriber_id.as_bytes(),
+                &link_number.to_be_bytes(),
+            ]
+            .concat(),
+        }
+    }
+
+    pub fn sign(&self, key: &SigningKey) -> String {
+        key.sign(&self.payload())
+    }
+
+    // Returns `None` for anything that was not signed with `key`.
+    pub fn verify(token: &str, key: &SigningKey) -> Option<Self> {
+        let payload = key.verify(token)?;
+        let uuid_at = |start: usize| Uuid::from_slice(payload.get(start..start + 16)?).ok();
+        match (payload.first()?, payload.len()) {
+            (&OPEN, 33) => Some(TrackingToken::Open {
+                newsletter_issue_id: uuid_at(1)?,
+                subscriber_id: uuid_at(17)?,
+            }),
+            (&CLICK, 37) => Some(TrackingToken::Click {
+                newsletter_issue_id: uuid_at(1)?,
+                subscriber_id: uuid_at(17)?,
+                link_number: i32::from_be_bytes(payload[33..37].try_into().ok()?),
+            }),
+            _ => None,
+        }
+    }
+}
+
+// Finds the quoted `href` attributes of `html`, as (start, end) byte offsets of their values.
+fn href_values(html: &str) -> Vec<(usize, usize)> {
+    let lowercase = html.to_ascii_lowercase();
+    let mut values = vec![];
+    let mut offset = 0;
+    while let Some(position) = lowercase[offset..].find("href=") {
+        let start = offset + position + "href=".len();
+        offset = start;
+        let Some(quote) = html[start..]
+            .chars()
+            .next()
+            .filter(|c| *c == '"' || *c == '\'')
+        else {
+            continue;
+        };
+        let Some(length) = html[start + 1..].find(quote) else {
+            break;
+        };
+        values.push((start + 1, start + 1 + length));
+        offset = start + 1 + length;
+    }
+    values
+}
+
+fn is_trackable(url: &str) -> bool {
+    (url.starts_with("http://") || url.starts_with("https://")) && !url.contains("{{")
+}
+
+// The distinct web links of an issue, in order of appearance. Links built from merge fields,
+// such as the unsubscribe link, differ for every subscriber and are left alone.
+pub fn extract_links(html: &str) -> Vec<String> {
+    let mut links: Vec<String> = vec![];
+    for (start, end) in href_values(html) {
+        let url = htmlescape::decode_html(&html[start..end])
+            .unwrap_or_else(|_| html[start..end].to_string());
+        if is_trackable(&url) && !links.contains(&url) {
+            links.push(url);
+        }
+    }
+    links
+}
+
+// Replaces every link for which `tracking_url` returns a URL.
+pub fn rewrite_links(html: &str, tracking_url: impl Fn(&str) -> Option<String>) -> String {
+    let mut rewritten = String::with_capacity(html.len());
+    let mut copied = 0;
+    for (start, end) in href_values(html) {
+        let url = htmlescape::decode_html(&html[start..end])
+            .unwrap_or_else(|_| html[start..end].to_string());
+        if let Some(tracking_url) = tracking_url(&url) {
+            rewritten.push_str(&html[copied..start]);
+            rewritten.push_str(&escape_html(&tracking_url));
+            copied = end;
+        }
+    }
+    rewritten.push_str(&html[copied..]);
+    rewritten
+}
+
+// Points the links of the issue at the click tracking endpoint and adds the open pixel.
+pub fn track_html(
+    html: &str,
+    links: &[IssueLink],
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    application_base_url: &str,
+    key: &SigningKey,
+) -> String {
+    let mut tracked = rewrite_links(html, |url| {
+        let link = links.iter().find(|link| link.url == url)?;
+        let token = TrackingToken::Click {
+            newsletter_issue_id,
+            subscriber_id,
+            link_number: link.link_number,
+        };
+        Some(format!("{}/t/c/{}", application_base_url, token.sign(key)))
+    });
+    let token = TrackingToken::Open {
+        newsletter_issue_id,
+        subscriber_id,
+    };
+    let open_url = format!("{}/t/o/{}", application_base_url, token.sign(key));
+    tracked.push_str(&format!(
+        r#"<img src="{}" width="1" height="1" alt="" style="border: 0;">"#,
+        escape_html(&open_url)
+    ));
+    tracked
+}
+
+// Stores the links of an issue, numbered in order of appearance, and returns all of them.
+// Storing them again, e.g. when a delivery is retried, is a no-op.
+pub fn store_issue_links(
+    conn: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+    links: &[String],
+) -> Result<Vec<IssueLink>, diesel::result::Error> {
+    let rows: Vec<_> = links
+        .iter()
+        .enumerate()
+        .map(|(index, url)| {
+            (
+                issue_links::newsletter_issue_id.eq(newsletter_issue_id),
+                issue_links::link_number.eq(index as i32 + 1),
+                issue_links::url.eq(url),
+            )
+        })
+        .collect();
+    diesel::insert_into(issue_links::table)
+        .values(&rows)
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+    issue_links::table
+        .filter(issue_links::newsletter_issue_id.eq(newsletter_issue_id))
+        .order(issue_links::link_number.asc())
+        .load::<IssueLink>(conn)
+}
+
+pub fn record_event(
+    conn: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+    subscriber_id: Uuid,
+    event: TrackingEvent,
+    url: Option<&str>,
+    now: DateTime<Utc>,
+) -> Result<usize, diesel::result::Error> {
+    diesel::insert_into(tracking_events::table)
+        .values((
+            tracking_events::newsletter_issue_id.eq(newsletter_issue_id),
+            tracking_events::subscriber_id.eq(subscriber_id),
+            tracking_events::kind.eq(event.as_str()),
+            tracking_events::url.eq(url),
+            tracking_events::created_at.eq(now),
+        ))
+        .execute(conn)
+}
+
+const TOP_LINKS: i64 = 10;
+
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct LinkClicks {
+    pub url: Option<String>,
+    pub clicks: i64,
+}
+
+// What happened to an issue after it was sent. Opens and clicks are counted once per
+// subscriber, however often they happen.
+#[derive(Debug, serde::Serialize, utoipa::ToSchema)]
+pub struct IssueStats {
+    pub delivered: i64,
+    pub opens: i64,
+    pub clicks: i64,
+    pub unsubscribes: i64,
+    /// The most clicked links, with every click counted.
+    pub top_links: Vec<LinkClicks>,
+}
+
+pub fn issue_stats(
+    conn: &mut PgConnection,
+    newsletter_issue_id: Uuid,
+) -> Result<IssueStats, diesel::result::Error> {
+    let counts = tracking_events::table
+        .filter(tracking_events::newsletter_issue_id.eq(newsletter_issue_id))
+        .group_by(tracking_events::kind)
+        .select((
+            tracking_events::kind,
+            count_distinct(tracking_events::subscriber_id),
+        ))
+        .load::<(String, i64)>(conn)?;
+    let count = |event: TrackingEvent| {
+        counts
+            .iter()
+            .find(|(kind, _)| kind == event.as_str())
+            .map(|(_, count)| *count)
+            .unwrap_or(0)
+    };
+    let top_links = tracking_events::table
+        .filter(tracking_events::newsletter_issue_id.eq(newsletter_issue_id))
+        .filter(tracking_events::kind.eq(TrackingEvent::Click.as_str()))
+        .group_by(tracking_events::url)
+        .select((tracking_events::url, count_star()))
+        .order((count_star().desc(), tracking_events::url.asc()))
+        .limit(TOP_LINKS)
+        .load::<(Option<String>, i64)>(conn)?
+        .into_iter()
+        .map(|(url, clicks)| LinkClicks { url, clicks })
+        .collect();
+    Ok(IssueStats {
+        delivered: count(TrackingEvent::Delivered),
+        opens: count(TrackingEvent::Open),
+        clicks: count(TrackingEvent::Click),
+        unsubscribes: count(TrackingEvent::Unsubscribe),
+        top_links,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{extract_links, rewrite_links, TrackingToken};
+    use crate::signing::{SigningKey, SigningKeys};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn key() -> SigningKey {
+        SigningKeys::derive(&Secret::new("a-secret-key".to_string())).tracking
+    }
+
+    #[test]
+    fn signed_tokens_can_be_verified() {
+        let tokens = [
+            TrackingToken::Open {
+                newsletter_issue_id: Uuid::new_v4(),
+                subscriber_id: Uuid::new_v4(),
+            },
+            TrackingToken::Click {
+                newsletter_issue_id: Uuid::new_v4(),
+                subscriber_id: Uuid::new_v4(),
+                link_number: 3,
+            },
+        ];
+        for token in tokens {
+            let signed = token.sign(&key());
+            assert_eq!(TrackingToken::verify(&signed, &key()), Some(token));
+        }
+    }
+
+    #[test]
+    fn tampered_tokens_are_rejected() {
+        let token = TrackingToken::Click {
+            newsletter_issue_id: Uuid::new_v4(),
+            subscriber_id: Uuid::new_v4(),
+            link_number: 1,
+        };
+        let signed = token.sign(&key());
+        let other_key = SigningKeys::derive(&Secret::new("another-key".to_string())).tracking;
+        assert_eq!(TrackingToken::verify(&signed, &other_key), None);
+        let form_token_key =
+            SigningKeys::derive(&Secret::new("a-secret-key".to_string())).form_tokens;
+        assert_eq!(TrackingToken::verify(&signed, &form_token_key), None);
+
+        let mut bytes = base64::decode_config(&signed, base64::URL_SAFE_NO_PAD).unwrap();
+        bytes[36] = 2;
+        let forged = base64::encode_config(bytes, base64::URL_SAFE_NO_PAD);
+        assert_eq!(TrackingToken::verify(&forged, &key()), None);
+        assert_eq!(TrackingToken::verify("not-a-token", &key()), None);
+        assert_eq!(TrackingToken::verify("", &key()), None);
+    }
+
+    #[test]
+    fn links_are_extracted_once_in_order() {
+        let html = r#"<a href="https://b.com/?x=1&amp;y=2">b</a> <A HREF='https://a.com'>a</A>
+            <a href="https://b.com/?x=1&amp;y=2">b again</a> <a href="mailto:me@x.com">mail</a>
+            <a href="{{ unsubscribe_url }}">unsubscribe</a> <a href=https://unquoted.com>u</a>"#;
+        assert_eq!(
+            extract_links(html),
+            vec![
+                "https://b.com/?x=1&y=2".to_string(),
+                "https://a.com".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn only_selected_links_are_rewritten() {
+        let html =
+            r#"<p><a href="https://a.com/?x=1&amp;y=2">a</a> <a href="https://keep.com">k</a></p>"#;
+        let rewritten = rewrite_links(html, |url| {
+            (url == "https://a.com/?x=1&y=2").then(|| "https://t.com/t/c/abc".to_string())
+        });
+        assert!(rewritten.contains(r#"<a href="https://t.com/t/c/abc">a</a>"#));
+        assert!(rewritten.contains(r#"<a href="https://keep.com">k</a></p>"#));
+    }
+}
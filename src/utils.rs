@@ -1,4 +1,7 @@
-use actix_web::{http::header::LOCATION, HttpResponse};
+use actix_web::dev::ServiceRequest;
+use actix_web::http::header::{Accept, ContentType, Header};
+use actix_web::{http::header::LOCATION, web, HttpRequest, HttpResponse};
+use askama::Template;
 
 pub fn e500<T>(e: T) -> actix_web::Error
 where
@@ -11,3 +14,25 @@ pub fn see_other(location: &str) -> HttpResponse {
         .insert_header((LOCATION, location))
         .finish()
 }
+
+// Serves a page rendered from one of the templates in `templates/`.
+pub fn render_page(page: &impl Template) -> Result<HttpResponse, actix_web::Error> {
+    let html = page.render().map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(html))
+}
+
+// Whether the client would rather get a web page, as browsers do, than the JSON API clients get.
+pub fn prefers_html(req: &HttpRequest) -> bool {
+    Accept::parse(req).is_ok_and(|accept| accept.preference().essence_str() == "text/html")
+}
+
+// Reads the body of a request in a middleware, and puts it back for the handler.
+pub async fn peek_body(req: &mut ServiceRequest) -> Result<web::Bytes, actix_web::Error> {
+    let body = req.extract::<web::Bytes>().await?;
+    let (_, mut payload) = actix_http::h1::Payload::create(true);
+    payload.unread_data(body.clone());
+    req.set_payload(payload.into());
+    Ok(body)
+}
@@ -0,0 +1,102 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use diesel::prelude::*;
+use newsletter::db::drop_database;
+use newsletter::schema::api_keys;
+use reqwest::Method;
+use uuid::Uuid;
+
+#[tokio::test]
+async fn created_keys_are_shown_once_and_only_their_hash_is_stored() {
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+
+    let key = app
+        .create_api_key(&["subscribers:read", "stats:read"])
+        .await;
+    assert!(key.starts_with("nl_"));
+
+    let mut conn = app.db_pool.get().unwrap();
+    let (prefix, key_hash, scopes) = api_keys::table
+        .select((api_keys::prefix, api_keys::key_hash, api_keys::scopes))
+        .first::<(String, String, Vec<String>)>(&mut conn)
+        .unwrap();
+    assert!(key.starts_with(&prefix));
+    assert!(!key_hash.contains(&key[prefix.len()..]));
+    assert_eq!(scopes, vec!["subscribers:read", "stats:read"]);
+
+    let html_page = app.get_admin_page_html("/admin/api_keys").await;
+    assert!(html_page.contains(&prefix));
+    assert!(html_page.contains("subscribers:read, stats:read"));
+    assert!(!html_page.contains(&key));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn revoked_keys_are_rejected() {
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+    let key = app.create_api_key(&["subscribers:read"]).await;
+    let response = app
+        .api_request(Method::GET, "/subscribers", &key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let api_key_id = {
+        let mut conn = app.db_pool.get().unwrap();
+        api_keys::table
+            .select(api_keys::api_key_id)
+            .first::<Uuid>(&mut conn)
+            .unwrap()
+    };
+    let response = app
+        .post_admin_form(&format!("/admin/api_keys/{}/revoke", api_key_id), &())
+        .await;
+    assert_is_redirect_to(&response, "/admin/api_keys");
+    let html_page = app.get_admin_page_html("/admin/api_keys").await;
+    assert!(html_page.contains("<p><i>The API key has been revoked.</i></p>"));
+
+    let response = app
+        .api_request(Method::GET, "/subscribers", &key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 401);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn keys_need_a_name_and_a_scope() {
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+
+    let test_cases = [
+        (
+            vec![("name", ""), ("scope", "stats:read")],
+            "An API key needs a name.",
+        ),
+        (
+            vec![("name", "CRM")],
+            "An API key needs at least one scope.",
+        ),
+        (
+            vec![("name", "CRM"), ("scope", "admin")],
+            "admin is not a valid API key scope.",
+        ),
+    ];
+    for (form, error) in test_cases {
+        let response = app.post_admin_form("/admin/api_keys", &form).await;
+        assert_is_redirect_to(&response, "/admin/api_keys");
+        let html_page = app.get_admin_page_html("/admin/api_keys").await;
+        assert!(html_page.contains(&format!("<p><i>{}</i></p>", error)));
+    }
+
+    let mut conn = app.db_pool.get().unwrap();
+    let count = api_keys::table
+        .count()
+        .get_result::<i64>(&mut conn)
+        .unwrap();
+    assert_eq!(count, 0);
+    drop_database(&app.database_name);
+}
@@ -0,0 +1,118 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter_tests::create_confirmed_subscriber;
+use diesel::prelude::*;
+use newsletter::db::drop_database;
+use newsletter::schema::newsletter_issues;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_newsletter(app: &TestApp) -> reqwest::Response {
+    app.post_newsletters(serde_json::json!({
+        "title": "Issue 42",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        }
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_search_the_delivery_log() {
+    let app = spawn_app().await;
+    let response = app
+        .api_client
+        .get(format!("{}/admin/deliveries", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/login");
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn deliveries_can_be_found_by_email_and_message_id() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!([{
+                "To": "ursula_le_guin@gmail.com",
+                "SubmittedAt": "2026-10-18T07:25:01.4178645-05:00",
+                "MessageID": "b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+                "ErrorCode": 0,
+                "Message": "OK"
+            }])),
+        )
+        .mount(&app.email_server)
+        .await;
+    let response = publish_newsletter(&app).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.login_as_test_user().await;
+    for query in [
+        "email=Ursula_Le_Guin%40gmail.com",
+        "message_id=b7bc2f4a-e38e-4336-af7d-e6c392c2f817",
+    ] {
+        let html_page = app
+            .get_admin_page_html(&format!("/admin/deliveries?{}", query))
+            .await;
+        assert!(html_page.contains(">Issue 42</a></td><td>ursula_le_guin@gmail.com</td><td>sent</td><td>b7bc2f4a-e38e-4336-af7d-e6c392c2f817</td><td></td>"));
+    }
+    let html_page = app
+        .get_admin_page_html("/admin/deliveries?email=someone_else%40gmail.com")
+        .await;
+    assert!(html_page.contains("<p>No deliveries found.</p>"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_logged_with_the_provider_error() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(
+            ResponseTemplate::new(200).set_body_json(serde_json::json!([{
+                "ErrorCode": 406,
+                "Message": "You tried to send to a recipient that has been marked as inactive."
+            }])),
+        )
+        // The recipient is rejected for good, so it is not tried again.
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = publish_newsletter(&app).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let mut conn = app.db_pool.get().unwrap();
+    let (status, failed_deliveries) = newsletter_issues::table
+        .select((
+            newsletter_issues::status,
+            newsletter_issues::failed_deliveries,
+        ))
+        .get_result::<(String, i32)>(&mut conn)
+        .unwrap();
+    assert_eq!(status, "sent");
+    assert_eq!(failed_deliveries, 1);
+    drop(conn);
+
+    app.login_as_test_user().await;
+    let html_page = app
+        .get_admin_page_html("/admin/deliveries?email=ursula_le_guin%40gmail.com")
+        .await;
+    assert!(html_page.contains("<td>rejected</td><td></td><td>406: The email provider rejected"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn invalid_issue_ids_are_reported() {
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+
+    let html_page = app
+        .get_admin_page_html("/admin/deliveries?newsletter_issue_id=42")
+        .await;
+    assert!(html_page.contains("<p><i>42 is not a valid issue id.</i></p>"));
+    drop_database(&app.database_name);
+}
@@ -0,0 +1,221 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter_tests::create_confirmed_subscriber;
+use newsletter::db::drop_database;
+use wiremock::matchers::{any, body_partial_json, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+// Creates a draft through the admin form and returns the path of its edit page.
+async fn create_draft(app: &TestApp, title: &str, text_content: &str) -> String {
+    let response = app
+        .post_admin_form(
+            "/admin/drafts",
+            &serde_json::json!({
+                "title": title,
+                "html_content": format!("<p>{}</p>", text_content),
+                "text_content": text_content,
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap()
+        .to_string()
+}
+
+async fn save_draft(app: &TestApp, draft_page: &str, title: &str, text_content: &str) {
+    let response = app
+        .post_admin_form(
+            draft_page,
+            &serde_json::json!({
+                "title": title,
+                "html_content": format!("<p>{}</p>", text_content),
+                "text_content": text_content,
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, draft_page);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_drafts() {
+    let app = spawn_app().await;
+    let response = app
+        .api_client
+        .get(format!("{}/admin/drafts", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/login");
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn saving_a_draft_records_a_new_revision() {
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+
+    let draft_page = create_draft(&app, "First title", "first line\nsecond line").await;
+    save_draft(&app, &draft_page, "Second title", "first line\nchanged line").await;
+
+    let html_page = app.get_admin_page_html(&draft_page).await;
+    assert!(html_page.contains("<p><i>The draft has been saved as revision 2.</i></p>"));
+    assert!(html_page.contains(r#"value="Second title""#));
+    assert!(html_page.contains("Revision 1 - First title"));
+    assert!(html_page.contains("Revision 2 - Second title"));
+
+    let html_page = app
+        .get_admin_page_html(&format!("{}/diff?from=1&to=2", draft_page))
+        .await;
+    assert!(html_page.contains("-First title"));
+    assert!(html_page.contains("+Second title"));
+    assert!(html_page.contains(" first line"));
+    assert!(html_page.contains("-second line"));
+    assert!(html_page.contains("+changed line"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn restoring_a_revision_saves_its_content_as_the_latest_revision() {
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+    let draft_page = create_draft(&app, "First title", "original").await;
+    save_draft(&app, &draft_page, "Second title", "rewritten").await;
+
+    let response = app
+        .post_admin_form(
+            &format!("{}/restore", draft_page),
+            &serde_json::json!({ "revision_number": 1 }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &draft_page);
+
+    let html_page = app.get_admin_page_html(&draft_page).await;
+    assert!(html_page.contains("<p><i>Revision 1 has been restored as revision 3.</i></p>"));
+    assert!(html_page.contains(r#"value="First title""#));
+    assert!(html_page.contains(">original</textarea>"));
+    assert!(html_page.contains("Revision 2 - Second title"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn draft_content_is_escaped_in_the_edit_form() {
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+    let draft_page = create_draft(&app, r#""><script>alert(1)</script>"#, "</textarea>").await;
+
+    let html_page = app.get_admin_page_html(&draft_page).await;
+    assert!(!html_page.contains("<script>alert(1)</script>"));
+    assert!(html_page.contains("&lt;/textarea&gt;</textarea>"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn a_test_email_is_sent_only_to_the_given_recipient() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_as_test_user().await;
+    let draft_page = create_draft(&app, "Draft title", "Draft body").await;
+    let html_page = app.get_admin_page_html(&draft_page).await;
+    assert!(html_page.contains(r#"name="recipient" value="""#));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .and(body_partial_json(serde_json::json!({
+            "To": "editor@example.com",
+            "Subject": "[Test] Draft title",
+        })))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_admin_form(
+            &format!("{}/test", draft_page),
+            &serde_json::json!({ "recipient": "editor@example.com" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &draft_page);
+
+    let html_page = app.get_admin_page_html(&draft_page).await;
+    assert!(html_page.contains("<p><i>A test email has been sent to editor@example.com.</i></p>"));
+    // The recipient is filled in for the next test.
+    assert!(html_page.contains(r#"name="recipient" value="editor@example.com""#));
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    let body: serde_json::Value =
+        serde_json::from_slice(&email_requests.last().unwrap().body).unwrap();
+    assert!(body["TextBody"].as_str().unwrap().starts_with("Draft body\n\n----------"));
+
+    let response = app
+        .post_admin_form(
+            &format!("{}/test", draft_page),
+            &serde_json::json!({ "recipient": "not an email" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &draft_page);
+    let html_page = app.get_admin_page_html(&draft_page).await;
+    assert!(html_page.contains("not an email is not a valid subscriber email."));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn drafts_written_in_markdown_are_rendered_on_save() {
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+
+    let response = app
+        .post_admin_form(
+            "/admin/drafts",
+            &serde_json::json!({
+                "title": "Markdown draft",
+                "markdown_content": "Some **bold** news, see [here](https://example.com).",
+                "html_content": "",
+                "text_content": "",
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    let draft_page = response.headers().get("Location").unwrap().to_str().unwrap();
+
+    let html_page = app.get_admin_page_html(draft_page).await;
+    assert!(html_page.contains("Some **bold** news, see [here](https://example.com).</textarea>"));
+    assert!(html_page.contains("&lt;strong&gt;bold&lt;/strong&gt;"));
+    assert!(html_page.contains("Some bold news, see here [1].\n\n[1] https://example.com</textarea>"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn tracking_can_be_turned_on_and_off_for_a_draft() {
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+
+    let response = app
+        .post_admin_form(
+            "/admin/drafts",
+            &serde_json::json!({
+                "title": "Tracked draft",
+                "text_content": "Draft body",
+                "tracking": "on",
+            }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    let draft_page = response.headers().get("Location").unwrap().to_str().unwrap();
+    let html_page = app.get_admin_page_html(draft_page).await;
+    assert!(html_page.contains(r#"name="tracking" value="on" checked>"#));
+
+    // An unchecked checkbox is not submitted at all.
+    save_draft(&app, draft_page, "Tracked draft", "Draft body").await;
+    let html_page = app.get_admin_page_html(draft_page).await;
+    assert!(html_page.contains(r#"name="tracking" value="on">"#));
+    drop_database(&app.database_name);
+}
@@ -0,0 +1,63 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use newsletter::db::drop_database;
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_subscribers() {
+    let app = spawn_app().await;
+    let response = app
+        .api_client
+        .get(format!("{}/admin/subscribers", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/login");
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_tag_subscribers() {
+    let app = spawn_app().await;
+    let response = app
+        .post_subscriber_tags(&serde_json::json!({
+            "email": "ursula_le_guin@gmail.com",
+            "tags": "beta",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn invalid_tags_are_rejected_with_a_flash_message() {
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+
+    let response = app
+        .post_subscriber_tags(&serde_json::json!({
+            "email": "ursula_le_guin@gmail.com",
+            "tags": "beta, not:valid",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/subscribers");
+
+    let html_page = app.get_admin_subscribers_html().await;
+    assert!(html_page.contains("<p><i>not:valid is not a valid subscriber tag.</i></p>"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn submitted_values_are_escaped_on_the_page() {
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+
+    app.post_subscriber_tags(&serde_json::json!({
+        "email": "ursula_le_guin@gmail.com",
+        "tags": "<b>bold</b>",
+    }))
+    .await;
+
+    let html_page = app.get_admin_subscribers_html().await;
+    assert!(html_page.contains("<p><i>&lt;b&gt;bold&lt;/b&gt; is not a valid subscriber tag."));
+    assert!(html_page.contains(r#"<a href="/admin/drafts">Drafts</a>"#));
+    drop_database(&app.database_name);
+}
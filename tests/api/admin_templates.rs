@@ -0,0 +1,228 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use crate::newsletter_tests::create_confirmed_subscriber;
+use newsletter::db::drop_database;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_the_email_templates() {
+    let app = spawn_app().await;
+    let response = app
+        .api_client
+        .get(format!("{}/admin/templates", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/login");
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn confirmation_emails_use_the_edited_template() {
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+
+    let response = app
+        .post_admin_form(
+            "/admin/templates/emails/confirmation",
+            &serde_json::json!({
+                "subject": "Confirm your subscription, {{ subscriber.name }}",
+                "layout": "default",
+                "body": "Hey {{ subscriber.name }}, [click here]({{ confirmation_link }}).",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/templates/emails/confirmation");
+    let html_page = app
+        .get_admin_page_html("/admin/templates/emails/confirmation")
+        .await;
+    assert!(html_page.contains("<p><i>The email template has been saved.</i></p>"));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await
+        .error_for_status()
+        .unwrap();
+
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(
+        body["Subject"].as_str().unwrap(),
+        "Confirm your subscription, le guin"
+    );
+    assert!(body["HtmlBody"].as_str().unwrap().contains("Hey le guin, "));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Hey le guin, click here [1]."));
+    // The layout's unsubscribe block is only meant for newsletters.
+    assert!(!body["TextBody"].as_str().unwrap().contains("Unsubscribe"));
+
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn templates_with_unknown_variables_are_not_saved() {
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+
+    let response = app
+        .post_admin_form(
+            "/admin/templates/emails/confirmation",
+            &serde_json::json!({
+                "subject": "Welcome!",
+                "layout": "default",
+                "body": "Hey {{ subscriber.email }}",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/templates/emails/confirmation");
+
+    let html_page = app
+        .get_admin_page_html("/admin/templates/emails/confirmation")
+        .await;
+    assert!(html_page.contains(
+        "<p><i>The body is not a valid template. Unknown variable `subscriber.email` on line 1."
+    ));
+    assert!(!html_page.contains("Hey {{ subscriber.email }}"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn newsletters_are_wrapped_in_the_edited_layout() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    app.login_as_test_user().await;
+
+    let response = app
+        .post_admin_form(
+            "/admin/templates/layouts/default",
+            &serde_json::json!({
+                "header": "**The Weekly** - {{ subject }}",
+                "footer": "Thanks for reading!",
+                "physical_address": "1 Main Street\nSpringfield",
+                "unsubscribe_block": "Had enough? [Unsubscribe]({{ unsubscribe_url }})",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/templates/layouts/default");
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Issue 1",
+            "content": { "markdown": "The news." },
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = app.batch_emails().await.pop().unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains("<strong>The Weekly</strong> - Issue 1"));
+    assert!(html_body.contains("1 Main Street<br>Springfield"));
+    let text_body = body["TextBody"].as_str().unwrap();
+    assert!(text_body
+        .starts_with("The Weekly - Issue 1\n\nThe news.\n\n----------\n\nThanks for reading!"));
+    assert!(text_body.contains("Had enough? Unsubscribe [1]\n\n[1] http://127.0.0.1/subscriptions/unsubscribe?subscription_token="));
+    assert!(text_body.ends_with("\n\n1 Main Street\nSpringfield"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn invalid_layouts_are_not_saved() {
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+
+    let response = app
+        .post_admin_form(
+            "/admin/templates/layouts/default",
+            &serde_json::json!({
+                "header": "",
+                "footer": "Bye {{ subscriber.name }}",
+                "physical_address": "",
+                "unsubscribe_block": "",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/templates/layouts/default");
+
+    let html_page = app
+        .get_admin_page_html("/admin/templates/layouts/default")
+        .await;
+    assert!(html_page.contains("<p><i>The footer is not a valid template."));
+    assert!(html_page.contains("You are receiving this email because you signed up"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn new_layouts_can_be_created_and_previewed() {
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+
+    let response = app
+        .post_admin_form(
+            "/admin/templates/layouts",
+            &serde_json::json!({ "name": "announcements" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/templates/layouts/announcements");
+    let html_page = app.get_admin_page_html("/admin/templates").await;
+    assert!(html_page.contains(r#"<a href="/admin/templates/layouts/announcements">"#));
+
+    let html_page = app
+        .get_admin_page_html("/admin/templates/layouts/announcements/preview")
+        .await;
+    assert!(html_page.contains("Subject: Our latest news"));
+    assert!(html_page.contains("This is a sample issue"));
+    assert!(html_page.contains("/subscriptions/unsubscribe?subscription_token=sample"));
+
+    let response = app
+        .post_admin_form(
+            "/admin/templates/layouts",
+            &serde_json::json!({ "name": "Not Valid!" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/templates");
+    let html_page = app.get_admin_page_html("/admin/templates").await;
+    assert!(html_page.contains("Not Valid! is not a valid layout name."));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn transactional_templates_can_be_previewed_with_sample_data() {
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+
+    let html_page = app
+        .get_admin_page_html("/admin/templates/emails/confirmation/preview")
+        .await;
+    assert!(html_page.contains("Subject: Welcome!"));
+    assert!(html_page.contains("Welcome to our newsletter, Ursula Le Guin!"));
+    assert!(html_page.contains("/subscriptions/confirm?subscription_token=sample"));
+
+    // Only emails the application sends have a template.
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/admin/templates/emails/password_reset/preview",
+            &app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/admin/templates");
+    drop_database(&app.database_name);
+}
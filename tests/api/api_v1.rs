@@ -0,0 +1,331 @@
+use crate::helpers::{spawn_app, TestApp};
+use crate::newsletter_tests::create_confirmed_subscriber;
+use newsletter::clock::Clock;
+use newsletter::db::drop_database;
+use reqwest::Method;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const ALL_SCOPES: [&str; 5] = [
+    "subscribers:read",
+    "subscribers:write",
+    "issues:read",
+    "issues:write",
+    "stats:read",
+];
+
+async fn api_key(app: &TestApp, scopes: &[&str]) -> String {
+    app.login_as_test_user().await;
+    app.create_api_key(scopes).await
+}
+
+async fn assert_json_error(response: reqwest::Response, status: u16, code: &str) {
+    assert_eq!(response.status().as_u16(), status);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], code);
+    assert!(body["error"]["message"].is_string());
+}
+
+#[tokio::test]
+async fn requests_without_a_valid_api_key_are_rejected() {
+    let app = spawn_app().await;
+    let url = format!("{}/api/v1/subscribers", &app.address);
+
+    let requests = [
+        app.api_client.get(&url),
+        app.api_client.get(&url).bearer_auth("nl_not-a-key"),
+        app.api_client
+            .get(&url)
+            .basic_auth(&app.test_user.username, Some(&app.test_user.password)),
+    ];
+    for request in requests {
+        let response = request.send().await.unwrap();
+        assert_eq!(
+            response.headers().get("WWW-Authenticate").unwrap(),
+            r#"Bearer realm="api""#
+        );
+        assert_json_error(response, 401, "unauthorized").await;
+    }
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn api_keys_only_grant_their_scopes() {
+    let app = spawn_app().await;
+    let key = api_key(&app, &["subscribers:read"]).await;
+
+    let response = app
+        .api_request(Method::GET, "/subscribers", &key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    let response = app
+        .api_request(Method::POST, "/subscribers", &key)
+        .json(&serde_json::json!({ "email": "ursula@example.com", "name": "Ursula" }))
+        .send()
+        .await
+        .unwrap();
+    assert_json_error(response, 403, "forbidden").await;
+    let response = app
+        .api_request(Method::GET, "/stats", &key)
+        .send()
+        .await
+        .unwrap();
+    assert_json_error(response, 403, "forbidden").await;
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn subscribers_can_be_added_read_updated_and_deleted() {
+    let app = spawn_app().await;
+    let key = api_key(&app, &ALL_SCOPES).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .api_request(Method::POST, "/subscribers", &key)
+        .json(&serde_json::json!({
+            "email": "ursula@example.com",
+            "name": "Ursula",
+            "tags": ["Beta", "beta", "early-adopter"],
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 201);
+    let subscriber: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(subscriber["status"], "pending_confirmation");
+    assert_eq!(
+        subscriber["tags"],
+        serde_json::json!(["beta", "early-adopter"])
+    );
+    let subscriber_path = format!("/subscribers/{}", subscriber["id"].as_str().unwrap());
+
+    let response = app
+        .api_request(Method::PATCH, &subscriber_path, &key)
+        .json(&serde_json::json!({ "tags": ["churned"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app
+        .api_request(Method::GET, &subscriber_path, &key)
+        .send()
+        .await
+        .unwrap();
+    let subscriber: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(subscriber["name"], "Ursula");
+    assert_eq!(subscriber["tags"], serde_json::json!(["churned"]));
+
+    let response = app
+        .api_request(Method::GET, "/subscribers?tag=churned", &key)
+        .send()
+        .await
+        .unwrap();
+    let list: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(list["total"], 1);
+    assert_eq!(list["subscribers"][0]["email"], "ursula@example.com");
+    let response = app
+        .api_request(Method::GET, "/subscribers?tag=beta", &key)
+        .send()
+        .await
+        .unwrap();
+    let list: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(list["total"], 0);
+
+    let response = app
+        .api_request(Method::DELETE, &subscriber_path, &key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 204);
+    let response = app
+        .api_request(Method::GET, &subscriber_path, &key)
+        .send()
+        .await
+        .unwrap();
+    assert_json_error(response, 404, "not_found").await;
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn invalid_and_duplicate_subscribers_are_rejected_with_json_errors() {
+    let app = spawn_app().await;
+    let key = api_key(&app, &ALL_SCOPES).await;
+    create_confirmed_subscriber(&app).await;
+
+    let test_cases = [
+        (
+            serde_json::json!({ "email": "not-an-email", "name": "Ursula" }),
+            400,
+            "invalid_request",
+        ),
+        (
+            serde_json::json!({ "email": "ursula@example.com", "name": "Ursula", "tags": ["a b"] }),
+            400,
+            "invalid_request",
+        ),
+        (
+            serde_json::json!({ "name": "Ursula" }),
+            400,
+            "invalid_request",
+        ),
+        (
+            serde_json::json!({ "email": "ursula_le_guin@gmail.com", "name": "Ursula" }),
+            409,
+            "conflict",
+        ),
+    ];
+    for (body, status, code) in test_cases {
+        let response = app
+            .api_request(Method::POST, "/subscribers", &key)
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_json_error(response, status, code).await;
+    }
+
+    let response = app
+        .api_request(Method::GET, "/subscribers/not-a-uuid", &key)
+        .send()
+        .await
+        .unwrap();
+    assert_json_error(response, 400, "invalid_request").await;
+    let response = app
+        .api_request(Method::GET, "/subscribers?page=9223372036854775807", &key)
+        .send()
+        .await
+        .unwrap();
+    assert_json_error(response, 400, "invalid_request").await;
+    let response = app
+        .api_request(Method::GET, "/does-not-exist", &key)
+        .send()
+        .await
+        .unwrap();
+    assert_json_error(response, 404, "not_found").await;
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn issues_can_be_created_scheduled_and_sent() {
+    let app = spawn_app().await;
+    let key = api_key(&app, &ALL_SCOPES).await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .api_request(Method::POST, "/issues", &key)
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "markdown": "Hi {{ subscriber.name }}" },
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 201);
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["status"], "draft");
+    let issue_path = format!("/issues/{}", issue["newsletter_issue_id"].as_str().unwrap());
+
+    let scheduled_at = app.clock.now() + chrono::Duration::days(1);
+    let response = app
+        .api_request(Method::POST, &format!("{}/schedule", issue_path), &key)
+        .json(&serde_json::json!({ "scheduled_at": scheduled_at }))
+        .send()
+        .await
+        .unwrap();
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["status"], "scheduled");
+
+    let response = app
+        .api_request(Method::POST, &format!("{}/send", issue_path), &key)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["status"], "sent");
+    let email = app.batch_emails().await.pop().unwrap();
+    assert!(email["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Hi le guin"));
+
+    let response = app
+        .api_request(Method::GET, &issue_path, &key)
+        .send()
+        .await
+        .unwrap();
+    let issue: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(issue["status"], "sent");
+    let response = app
+        .api_request(Method::POST, &format!("{}/send", issue_path), &key)
+        .send()
+        .await
+        .unwrap();
+    assert_json_error(response, 409, "conflict").await;
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn stats_are_reported_for_subscribers_and_issues() {
+    let app = spawn_app().await;
+    let key = api_key(&app, &ALL_SCOPES).await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .api_request(Method::POST, "/issues", &key)
+        .json(&serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "markdown": "Read [the post](https://example.com/post)." },
+            "tracking": true,
+        }))
+        .send()
+        .await
+        .unwrap();
+    let issue: serde_json::Value = response.json().await.unwrap();
+    let issue_path = format!("/issues/{}", issue["newsletter_issue_id"].as_str().unwrap());
+    app.api_request(Method::POST, &format!("{}/send", issue_path), &key)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    let response = app
+        .api_request(Method::GET, "/stats", &key)
+        .send()
+        .await
+        .unwrap();
+    let stats: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(stats["subscribers"]["confirmed"], 1);
+    assert_eq!(stats["issues"]["sent"], 1);
+
+    let response = app
+        .api_request(Method::GET, &format!("{}/stats", issue_path), &key)
+        .send()
+        .await
+        .unwrap();
+    let stats: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(stats["delivered"], 1);
+    assert_eq!(stats["opens"], 0);
+    assert_eq!(stats["clicks"], 0);
+    assert_eq!(stats["top_links"], serde_json::json!([]));
+    drop_database(&app.database_name);
+}
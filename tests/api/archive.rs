@@ -0,0 +1,163 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app, TestApp};
+use crate::newsletter_tests::create_confirmed_subscriber;
+use diesel::prelude::*;
+use newsletter::db::drop_database;
+use newsletter::schema::newsletter_issues;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_issue(app: &TestApp, title: &str, hide_from_archive: bool) -> Uuid {
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": title,
+            "content": {
+                "html": r#"<p>Hi {{ subscriber.name }}, read <a href="https://example.com/post">the post</a>.</p>"#,
+                "text": "Hi {{ subscriber.name }}, read the post: https://example.com/post",
+            },
+            "tracking": true,
+            "hide_from_archive": hide_from_archive,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let mut conn = app.db_pool.get().unwrap();
+    newsletter_issues::table
+        .filter(newsletter_issues::title.eq(title))
+        .select(newsletter_issues::newsletter_issue_id)
+        .first::<Uuid>(&mut conn)
+        .unwrap()
+}
+
+async fn spawn_app_with_a_subscriber() -> TestApp {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app
+}
+
+async fn get(app: &TestApp, page: &str) -> reqwest::Response {
+    app.api_client
+        .get(format!("{}{}", &app.address, page))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+#[tokio::test]
+async fn sent_issues_are_published_without_subscriber_data() {
+    let app = spawn_app_with_a_subscriber().await;
+    let issue_id = publish_issue(&app, "Issue <1>", false).await;
+
+    let html = get(&app, "/archive").await.text().await.unwrap();
+    assert!(html.contains(&format!(
+        r#"<a href="/archive/{}">Issue &lt;1&gt;</a>"#,
+        issue_id
+    )));
+
+    let response = get(&app, &format!("/archive/{}", issue_id)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains("Hi reader, read"));
+    assert!(html.contains(r#"href="https://example.com/post""#));
+    assert!(!html.contains("le guin"));
+    assert!(!html.contains("ursula_le_guin@gmail.com"));
+    assert!(!html.contains("/t/"));
+    assert!(!html.contains("/subscriptions/unsubscribe"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn issues_kept_out_of_the_archive_are_not_published() {
+    let app = spawn_app_with_a_subscriber().await;
+    let issue_id = publish_issue(&app, "Members only", true).await;
+
+    let html = get(&app, "/archive").await.text().await.unwrap();
+    assert!(!html.contains("Members only"));
+    let response = get(&app, &format!("/archive/{}", issue_id)).await;
+    assert_eq!(response.status().as_u16(), 404);
+    let feed = get(&app, "/feed.xml").await.text().await.unwrap();
+    assert!(!feed.contains("Members only"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn admins_can_take_an_issue_out_of_the_archive_and_back() {
+    let app = spawn_app_with_a_subscriber().await;
+    let issue_id = publish_issue(&app, "Newsletter title", false).await;
+    app.login_as_test_user().await;
+    let archive_path = format!("/admin/issues/{}/archive", issue_id);
+    let stats_path = format!("/admin/issues/{}/stats", issue_id);
+
+    let response = app
+        .post_admin_form(&archive_path, &serde_json::json!({ "in_archive": false }))
+        .await;
+    assert_is_redirect_to(&response, &stats_path);
+    let response = get(&app, &format!("/archive/{}", issue_id)).await;
+    assert_eq!(response.status().as_u16(), 404);
+
+    let response = app
+        .post_admin_form(&archive_path, &serde_json::json!({ "in_archive": true }))
+        .await;
+    assert_is_redirect_to(&response, &stats_path);
+    let response = get(&app, &format!("/archive/{}", issue_id)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn the_archive_is_paginated() {
+    let app = spawn_app_with_a_subscriber().await;
+    for n in 1..=11 {
+        publish_issue(&app, &format!("Issue {}", n), false).await;
+    }
+
+    let first_page = get(&app, "/archive").await.text().await.unwrap();
+    assert_eq!(first_page.matches(r#"<a href="/archive/"#).count(), 10);
+    assert!(first_page.contains(r#"href="/archive?page=2""#));
+    let second_page = get(&app, "/archive?page=2").await.text().await.unwrap();
+    assert_eq!(second_page.matches(r#"<a href="/archive/"#).count(), 1);
+    assert!(second_page.contains(r#"href="/archive?page=1""#));
+    assert!(!second_page.contains(r#"href="/archive?page=3""#));
+
+    let response = get(&app, "/archive?page=3").await;
+    assert_eq!(response.status().as_u16(), 404);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn archived_issues_are_listed_in_the_atom_and_rss_feeds() {
+    let app = spawn_app_with_a_subscriber().await;
+    let issue_id = publish_issue(&app, "Issue <1>", false).await;
+    let permalink = format!("/archive/{}", issue_id);
+
+    let response = get(&app, "/feed.xml").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "application/atom+xml; charset=utf-8"
+    );
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains(r#"<feed xmlns="http://www.w3.org/2005/Atom">"#));
+    assert!(feed.contains("<title>Issue &lt;1&gt;</title>"));
+    assert!(feed.contains(&format!("<id>urn:uuid:{}</id>", issue_id)));
+    assert!(feed.contains(&permalink));
+    // The content is HTML, escaped once more to fit in the XML document.
+    assert!(feed.contains("&lt;p&gt;Hi reader, read"));
+
+    let response = get(&app, "/rss.xml").await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers().get("Content-Type").unwrap(),
+        "application/rss+xml; charset=utf-8"
+    );
+    let feed = response.text().await.unwrap();
+    assert!(feed.contains(r#"<rss version="2.0""#));
+    assert!(feed.contains("<title>Issue &lt;1&gt;</title>"));
+    assert!(feed.contains(&permalink));
+    drop_database(&app.database_name);
+}
@@ -0,0 +1,304 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp, BROWSER_ACCEPT};
+use chrono::Duration;
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use newsletter::bot_protection::{BotProtection, FormToken, Submission, Verdict};
+use newsletter::captcha::{CaptchaProvider, CaptchaVerifier, SiteVerifyCaptcha};
+use newsletter::clock::Clock;
+use newsletter::db::drop_database;
+use newsletter::schema::subscriptions;
+use newsletter::signing::SigningKeys;
+use secrecy::Secret;
+use serde_json::json;
+use wiremock::matchers::{body_string_contains, method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const BODY: &str = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+
+async fn mount_no_emails(app: &TestApp) {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+}
+
+fn stored_subscribers(app: &TestApp) -> i64 {
+    let mut conn = app.db_pool.get().unwrap();
+    subscriptions::table
+        .select(count_star())
+        .get_result(&mut conn)
+        .unwrap()
+}
+
+fn token_issued(app: &TestApp, ago: Duration) -> String {
+    FormToken::new(app.clock.now() - ago).sign(&app.signing_keys.form_tokens)
+}
+
+#[tokio::test]
+async fn suspicious_signups_are_accepted_but_neither_stored_nor_mailed() {
+    // Arrange
+    // The same address is submitted more often than its rate limit allows.
+    let app = spawn_app_with(|settings| {
+        settings.rate_limits = settings
+            .rate_limits
+            .clone()
+            .parse_overrides("subscriptions.email=off")
+            .unwrap()
+    })
+    .await;
+    mount_no_emails(&app).await;
+    let forged = FormToken::new(app.clock.now() - Duration::seconds(10))
+        .sign(&SigningKeys::derive(&Secret::new("not the secret".to_string())).form_tokens);
+    let signed_for_tracking =
+        FormToken::new(app.clock.now() - Duration::seconds(10)).sign(&app.signing_keys.tracking);
+    let test_cases = vec![
+        (format!("{}&form_token=garbage", BODY), "a malformed token"),
+        (format!("{}&form_token={}", BODY, forged), "a forged token"),
+        (
+            format!("{}&form_token={}", BODY, signed_for_tracking),
+            "a token signed with another key",
+        ),
+        (
+            format!(
+                "{}&form_token={}",
+                BODY,
+                token_issued(&app, Duration::seconds(1))
+            ),
+            "a form filled in too fast",
+        ),
+        (
+            format!(
+                "{}&form_token={}",
+                BODY,
+                token_issued(&app, Duration::days(2))
+            ),
+            "an expired token",
+        ),
+        (
+            format!(
+                "{}&form_token={}&website=spam.example.com",
+                BODY,
+                token_issued(&app, Duration::seconds(10))
+            ),
+            "the honeypot filled in",
+        ),
+    ];
+
+    for (body, description) in test_cases {
+        // Act
+        let response = app.post_subscriptions_as_is(body, app.client_ip).await;
+
+        // Assert
+        assert_eq!(
+            response.status().as_u16(),
+            200,
+            "The API did not pretend to accept a signup with {}.",
+            description
+        );
+        assert_eq!(
+            stored_subscribers(&app),
+            0,
+            "A signup with {} was stored.",
+            description
+        );
+    }
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn a_signup_sent_without_a_form_token_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    mount_no_emails(&app).await;
+
+    // Act
+    let from_browser = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .header("Accept", BROWSER_ACCEPT)
+        .body(BODY)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    let from_a_script = app
+        .post_subscriptions_as_is(BODY.to_string(), app.client_ip)
+        .await;
+
+    // Assert
+    assert_eq!(from_browser.status().as_u16(), 400);
+    let html = from_browser.text().await.unwrap();
+    assert!(html.contains("Please reload the page and send the form again."));
+    assert!(html.contains(r#"name="form_token""#));
+    assert_eq!(from_a_script.status().as_u16(), 400);
+    let body: serde_json::Value = from_a_script.json().await.unwrap();
+    assert_eq!(
+        body["errors"]["form"],
+        "Please reload the page and send the form again."
+    );
+    assert_eq!(stored_subscribers(&app), 0);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn a_form_token_can_only_be_used_once() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let form_token = token_issued(&app, Duration::seconds(10));
+
+    // Act
+    let first = app
+        .post_subscriptions_as_is(format!("{}&form_token={}", BODY, form_token), app.client_ip)
+        .await;
+    let replayed = app
+        .post_subscriptions_as_is(
+            format!(
+                "name=le%20guin&email=another%40example.com&form_token={}",
+                form_token
+            ),
+            app.client_ip,
+        )
+        .await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(replayed.status().as_u16(), 200);
+    assert_eq!(stored_subscribers(&app), 1);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn the_signup_form_carries_a_fresh_form_token_and_a_honeypot() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/subscriptions", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"name="website""#));
+    let form_token = html
+        .split(r#"name="form_token" value=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .expect("The form has no form token.");
+    let form_token = FormToken::verify(form_token, &app.signing_keys.form_tokens).unwrap();
+    assert_eq!(
+        form_token.issued_at.timestamp(),
+        app.clock.now().timestamp()
+    );
+    drop_database(&app.database_name);
+}
+
+fn captcha(server: &MockServer) -> SiteVerifyCaptcha {
+    SiteVerifyCaptcha::new(
+        CaptchaProvider::Turnstile,
+        "site-key".into(),
+        Secret::new("captcha-secret".to_string()),
+        format!("{}/siteverify", server.uri()),
+    )
+}
+
+#[tokio::test]
+async fn the_captcha_response_is_checked_with_the_provider() {
+    // Arrange
+    let server = MockServer::start().await;
+    Mock::given(path("/siteverify"))
+        .and(method("POST"))
+        .and(body_string_contains("secret=captcha-secret"))
+        .and(body_string_contains("response=genuine"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "success": true })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    Mock::given(path("/siteverify"))
+        .and(method("POST"))
+        .and(body_string_contains("response=forged"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "success": false,
+            "error-codes": ["invalid-input-response"]
+        })))
+        .expect(1)
+        .mount(&server)
+        .await;
+    let captcha = captcha(&server);
+
+    // Act & Assert
+    assert!(captcha.verify("genuine").await.unwrap());
+    assert!(!captcha.verify("forged").await.unwrap());
+    assert!(captcha.widget_html().contains(r#"data-sitekey="site-key""#));
+}
+
+#[tokio::test]
+async fn signups_failing_the_captcha_are_suspicious() {
+    // Arrange
+    let server = MockServer::start().await;
+    Mock::given(path("/siteverify"))
+        .and(body_string_contains("response=genuine"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "success": true })))
+        .mount(&server)
+        .await;
+    Mock::given(path("/siteverify"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({ "success": false })))
+        .mount(&server)
+        .await;
+    let redis = redis::Client::open(std::env::var("REDIS_URI").unwrap()).unwrap();
+    let key = SigningKeys::derive(&Secret::new("secret".to_string())).form_tokens;
+    let protection =
+        BotProtection::new(key.clone(), redis).with_captcha(Box::new(captcha(&server)));
+    let protection = &protection;
+    let now = chrono::Utc::now();
+    let submission = |captcha_response: Option<&'static str>| {
+        let form_token = FormToken::new(now - Duration::seconds(10)).sign(&key);
+        async move {
+            let submission = Submission {
+                form_token: Some(&form_token),
+                captcha_response,
+                ..Default::default()
+            };
+            protection.check(&submission, now).await.unwrap()
+        }
+    };
+
+    // Act & Assert
+    assert_eq!(
+        submission(None).await,
+        Verdict::Suspicious("CAPTCHA missing")
+    );
+    assert_eq!(
+        submission(Some("forged")).await,
+        Verdict::Suspicious("CAPTCHA failed")
+    );
+    assert_eq!(submission(Some("genuine")).await, Verdict::Human);
+}
+
+#[tokio::test]
+async fn the_captcha_provider_being_unreachable_is_an_error() {
+    // Arrange
+    let server = MockServer::start().await;
+    Mock::given(path("/siteverify"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&server)
+        .await;
+
+    // Act
+    let outcome = captcha(&server).verify("genuine").await;
+
+    // Assert
+    assert!(outcome.is_err());
+}
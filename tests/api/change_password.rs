@@ -1,4 +1,4 @@
-use crate::helpers::{assert_is_redirect_to, spawn_app};
+use crate::helpers::{assert_is_redirect_to, csrf_token_in, spawn_app};
 use newsletter::db::drop_database;
 use uuid::Uuid;
 
@@ -54,7 +54,7 @@ async fn new_password_fields_must_match() {
     // Act - Part 3 - Follow the redirect
     let html_page = app.get_change_password_html().await;
     dbg!(&html_page);
-    assert!(html_page.contains("<p><i>New Password and Check new password doesn't match</i></p>"));
+    assert!(html_page.contains("<p><i>New Password and Check new password doesn&#x27;t match</i></p>"));
     drop_database(&app.database_name);
 }
 
@@ -152,3 +152,93 @@ async fn changing_password_works() {
     assert_is_redirect_to(&response, "/admin/dashboard");
     drop_database(&app.database_name);
 }
+
+#[tokio::test]
+async fn changing_password_without_a_csrf_token_is_forbidden() {
+    // Arrange
+    let app = spawn_app().await;
+    let new_password = Uuid::new_v4().to_string();
+    app.login_as_test_user().await;
+    // Act - Part 1 - Submit the form without its token, as another site would
+    let response = app
+        .api_client
+        .post(format!("{}/admin/password", &app.address))
+        .form(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    // Act - Part 2 - Follow up with the form
+    let html_page = app.get_change_password_html().await;
+    assert!(html_page
+        .contains("<p><i>The form has expired, please reload the page and try again.</i></p>"));
+    // Act - Part 3 - The old password still works
+    app.post_logout().await;
+    app.login_as_test_user().await;
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn changing_password_with_the_csrf_token_of_another_session_is_forbidden() {
+    // Arrange
+    let app = spawn_app().await;
+    let new_password = Uuid::new_v4().to_string();
+    app.login_as_test_user().await;
+    // A token the attacker got from their own session.
+    let attacker = reqwest::Client::builder()
+        .cookie_store(true)
+        .build()
+        .unwrap();
+    let login_html = attacker
+        .get(format!("{}/login", &app.address))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let attacker_token = csrf_token_in(&login_html);
+    assert_ne!(attacker_token, app.csrf_token().await);
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/password", &app.address))
+        .form(&serde_json::json!({
+        "current_password": &app.test_user.password,
+        "new_password": &new_password,
+        "new_password_check": &new_password,
+        "csrf_token": &attacker_token,
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    app.post_logout().await;
+    app.login_as_test_user().await;
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn logging_out_without_a_csrf_token_is_forbidden() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/logout", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+    drop_database(&app.database_name);
+}
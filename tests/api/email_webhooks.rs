@@ -0,0 +1,174 @@
+use crate::helpers::{spawn_app, TestApp, WEBHOOK_USERNAME};
+use crate::newsletter_tests::create_confirmed_subscriber;
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use newsletter::db::drop_database;
+use newsletter::schema::{email_events, subscriptions};
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+const HARD_BOUNCE: &str = include_str!("fixtures/postmark/hard_bounce.json");
+const SOFT_BOUNCE: &str = include_str!("fixtures/postmark/soft_bounce.json");
+const SPAM_COMPLAINT: &str = include_str!("fixtures/postmark/spam_complaint.json");
+const DELIVERY: &str = include_str!("fixtures/postmark/delivery.json");
+
+fn subscriber_status(app: &TestApp) -> Option<String> {
+    let mut conn = app.db_pool.get().unwrap();
+    subscriptions::table
+        .select(subscriptions::status)
+        .first::<Option<String>>(&mut conn)
+        .unwrap()
+}
+
+fn stored_events(app: &TestApp) -> i64 {
+    let mut conn = app.db_pool.get().unwrap();
+    email_events::table
+        .select(count_star())
+        .first::<i64>(&mut conn)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn hard_bounced_subscribers_are_skipped_by_later_newsletters() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app.post_email_webhook("postmark", HARD_BOUNCE).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).as_deref(), Some("bounced"));
+    assert_eq!(stored_events(&app), 1);
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn spam_complaints_are_not_downgraded_by_later_bounces() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app.post_email_webhook("postmark", SPAM_COMPLAINT).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).as_deref(), Some("complained"));
+
+    app.post_email_webhook("postmark", HARD_BOUNCE).await;
+    assert_eq!(subscriber_status(&app).as_deref(), Some("complained"));
+    assert_eq!(stored_events(&app), 2);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn soft_bounces_are_recorded_without_changing_the_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app.post_email_webhook("postmark", SOFT_BOUNCE).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).as_deref(), Some("confirmed"));
+    assert_eq!(stored_events(&app), 1);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn redelivered_webhooks_are_only_stored_once() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    for _ in 0..2 {
+        let response = app.post_email_webhook("postmark", HARD_BOUNCE).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+    assert_eq!(stored_events(&app), 1);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn other_record_types_are_acknowledged_and_ignored() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let response = app.post_email_webhook("postmark", DELIVERY).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscriber_status(&app).as_deref(), Some("confirmed"));
+    assert_eq!(stored_events(&app), 0);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn invalid_payloads_are_rejected_with_a_400() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        ("not json", "invalid JSON"),
+        (r#"{"RecordType": "Bounce"}"#, "a bounce without an email"),
+    ];
+    for (payload, description) in test_cases {
+        let response = app.post_email_webhook("postmark", payload).await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The webhook did not fail with 400 for {}.",
+            description
+        );
+    }
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn webhooks_for_unknown_providers_are_rejected_with_a_404() {
+    let app = spawn_app().await;
+    let response = app.post_email_webhook("mailchimp", HARD_BOUNCE).await;
+    assert_eq!(response.status().as_u16(), 404);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn webhooks_without_valid_credentials_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let url = format!("{}/webhooks/email/postmark", &app.address);
+
+    let requests = vec![
+        (app.api_client.post(&url), "no credentials"),
+        (
+            app.api_client
+                .post(&url)
+                .basic_auth(WEBHOOK_USERNAME, Some("wrong-password")),
+            "a wrong password",
+        ),
+        (
+            app.api_client
+                .post(&url)
+                .basic_auth("someone", Some("webhook-password")),
+            "a wrong username",
+        ),
+    ];
+    for (request, description) in requests {
+        let response = request.body(HARD_BOUNCE).send().await.unwrap();
+        assert_eq!(
+            response.status().as_u16(),
+            401,
+            "The webhook was not rejected with {}.",
+            description
+        );
+        assert_eq!(
+            r#"Basic realm="webhooks""#,
+            response.headers()["WWW-Authenticate"]
+        );
+    }
+    assert_eq!(subscriber_status(&app).as_deref(), Some("confirmed"));
+    drop_database(&app.database_name);
+}
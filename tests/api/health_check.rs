@@ -1,6 +1,8 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with};
 use newsletter::db::drop_database;
 use reqwest::Client;
+use wiremock::matchers::path;
+use wiremock::{Mock, ResponseTemplate};
 
 #[tokio::test]
 async fn health_check_works() {
@@ -16,3 +18,71 @@ async fn health_check_works() {
     assert_eq!(Some(0), response.content_length());
     drop_database(&app.database_name);
 }
+
+async fn get_health(address: &str, probe: &str) -> (u16, serde_json::Value) {
+    let response = Client::new()
+        .get(format!("{}/health/{}", address, probe))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    (
+        response.status().as_u16(),
+        response.json().await.expect("The response is not JSON."),
+    )
+}
+
+#[tokio::test]
+async fn liveness_does_not_depend_on_anything() {
+    let app = spawn_app().await;
+    drop_database(&app.database_name);
+
+    let (status, body) = get_health(&app.address, "live").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "ok");
+}
+
+#[tokio::test]
+async fn readiness_reports_every_dependency() {
+    let app = spawn_app_with(|settings| settings.health_check_email_provider = true).await;
+
+    let (status, body) = get_health(&app.address, "ready").await;
+    assert_eq!(status, 200);
+    assert_eq!(body["status"], "ok");
+    for dependency in ["postgres", "redis", "email_provider"] {
+        assert_eq!(body["checks"][dependency]["status"], "up", "{}", dependency);
+        assert!(body["checks"][dependency]["latency_ms"].is_u64());
+    }
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn readiness_fails_when_postgres_is_gone() {
+    let app = spawn_app().await;
+    drop_database(&app.database_name);
+
+    let (status, body) = get_health(&app.address, "ready").await;
+    assert_eq!(status, 503);
+    assert_eq!(body["status"], "unavailable");
+    assert_eq!(body["failing"], serde_json::json!(["postgres"]));
+    assert_eq!(body["checks"]["postgres"]["status"], "down");
+    // The cause is logged rather than shown, since it names the database.
+    let error = body["checks"]["postgres"]["error"].as_str().unwrap();
+    assert!(["timeout", "unavailable"].contains(&error), "{}", error);
+    assert_eq!(body["checks"]["redis"]["status"], "up");
+    // The email provider is only checked when asked to.
+    assert!(body["checks"]["email_provider"].is_null());
+}
+
+#[tokio::test]
+async fn readiness_fails_when_the_email_provider_is_down() {
+    let app = spawn_app_with(|settings| settings.health_check_email_provider = true).await;
+    Mock::given(path("/server"))
+        .respond_with(ResponseTemplate::new(503))
+        .mount(&app.email_server)
+        .await;
+
+    let (status, body) = get_health(&app.address, "ready").await;
+    assert_eq!(status, 503);
+    assert_eq!(body["failing"], serde_json::json!(["email_provider"]));
+    drop_database(&app.database_name);
+}
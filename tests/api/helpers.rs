@@ -3,18 +3,31 @@ use diesel_migrations::{embed_migrations, EmbeddedMigrations};
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("migrations");
 
 use argon2::{password_hash::SaltString, Argon2, PasswordHasher};
+use chrono::Utc;
 use diesel::r2d2::{ConnectionManager, Pool};
 use diesel_migrations::MigrationHarness;
 use dotenv::dotenv;
+use newsletter::authentication::Credentials;
+use newsletter::bot_protection::FormToken;
+use newsletter::clock::{Clock, MockClock};
 use newsletter::db::create_database;
 use newsletter::db::PgPool;
 use newsletter::db_models::User;
+use newsletter::domain::SubscriberEmail;
+use newsletter::email_client::EmailClient;
+use newsletter::log_redaction::RedactionRules;
+use newsletter::scheduler::send_due_issues;
 use newsletter::schema::users::{self, dsl::*};
+use newsletter::settings::Settings;
+use newsletter::shutdown::Shutdown;
+use newsletter::signing::SigningKeys;
 use newsletter::startup::Application;
 use newsletter::telemetry::{get_subscriber, init_subscriber};
 use once_cell::sync::Lazy;
 use secrecy::{ExposeSecret, Secret};
 use std::env;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
 use tokio;
 use uuid::Uuid;
 use wiremock::MockServer;
@@ -26,15 +39,53 @@ static TRACING: Lazy<()> = Lazy::new(|| {
     // We cannot assign the output of `get_subscriber` to a variable based on the value of `TEST_LOG`
     // because the sink is part of the type returned by `get_subscriber`, therefore they are not the
     // same type. We could work around it, but this is the most straight-forward way of moving forward.
+    let rules = RedactionRules::default();
     if std::env::var("TEST_LOG").is_ok() {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::stdout);
+        let subscriber = get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::stdout,
+            rules,
+            None,
+        )
+        .unwrap();
         init_subscriber(subscriber);
     } else {
-        let subscriber = get_subscriber(subscriber_name, default_filter_level, std::io::sink);
+        let subscriber = get_subscriber(
+            subscriber_name,
+            default_filter_level,
+            std::io::sink,
+            rules,
+            None,
+        )
+        .unwrap();
         init_subscriber(subscriber);
     };
 });
 
+pub const WEBHOOK_USERNAME: &str = "postmark";
+pub const WEBHOOK_PASSWORD: &str = "webhook-password";
+
+pub const METRICS_TOKEN: &str = "metrics-token";
+
+// What browsers send, as opposed to API clients.
+pub const BROWSER_ACCEPT: &str = "text/html,application/xhtml+xml,application/xml;q=0.9,*/*;q=0.8";
+
+// What every test app is configured with, on top of the environment. Each app sends its
+// requests through the "proxy" at 127.0.0.1 from an address of its own, and keeps its rate limit
+// counters under a prefix of its own, so apps do not share limits.
+fn test_settings() -> Settings {
+    let mut settings = Settings::from_env().expect("Failed to read the settings");
+    settings.trusted_proxies = vec![IpAddr::from([127, 0, 0, 1])];
+    settings.rate_limit_key_prefix = Some(format!("test-{}", Uuid::new_v4()));
+    settings.email_webhook_credentials = Some(Credentials {
+        username: WEBHOOK_USERNAME.into(),
+        password: Secret::new(WEBHOOK_PASSWORD.into()),
+    });
+    settings.metrics_bearer_token = Some(Secret::new(METRICS_TOKEN.into()));
+    settings
+}
+
 pub struct TestUser {
     pub user_id: Uuid,
     pub username: String,
@@ -76,6 +127,16 @@ pub struct TestApp {
     pub email_server: MockServer,
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
+    pub clock: Arc<MockClock>,
+    // The keys the application signs with.
+    pub signing_keys: SigningKeys,
+    // The address `api_client` requests come from, as forwarded by the proxy.
+    pub client_ip: IpAddr,
+    // Triggering it shuts the application down, as SIGTERM would.
+    pub shutdown: Shutdown,
+    pub stopped: tokio::task::JoinHandle<Result<(), std::io::Error>>,
+    // The CSRF token of `api_client`'s session, once read from the login form.
+    csrf_token: Mutex<Option<String>>,
 }
 pub struct ConfirmationLinks {
     pub html: reqwest::Url,
@@ -84,18 +145,55 @@ pub struct ConfirmationLinks {
 
 impl TestApp {
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
+        self.post_subscriptions_from(body, self.client_ip).await
+    }
+
+    // Submits the signup form with a token it was served with a little while ago.
+    pub async fn post_subscriptions_from(&self, body: String, ip: IpAddr) -> reqwest::Response {
+        let form_token = FormToken::new(self.clock.now() - chrono::Duration::seconds(10));
+        let body = format!(
+            "{}&form_token={}",
+            body,
+            form_token.sign(&self.signing_keys.form_tokens)
+        );
+        self.post_subscriptions_as_is(body, ip).await
+    }
+
+    // Posts `body` without adding a form token.
+    pub async fn post_subscriptions_as_is(&self, body: String, ip: IpAddr) -> reqwest::Response {
         self.api_client
             .post(&format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("X-Forwarded-For", ip.to_string())
             .body(body)
             .send()
             .await
             .expect("Failed to execute request.")
     }
 
+    // Submits the signup form from a browser, which asks for a web page in return.
+    pub async fn post_signup_form(&self, body: &str) -> reqwest::Response {
+        let form_token = FormToken::new(self.clock.now() - chrono::Duration::seconds(10));
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Accept", BROWSER_ACCEPT)
+            .header("X-Forwarded-For", self.client_ip.to_string())
+            .body(format!(
+                "{}&form_token={}",
+                body,
+                form_token.sign(&self.signing_keys.form_tokens)
+            ))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
-
+        self.get_links(&body)
+    }
+    pub fn get_links(&self, body: &serde_json::Value) -> ConfirmationLinks {
         let get_link = |s: &str| {
             let links: Vec<_> = linkify::LinkFinder::new()
                 .links(s)
@@ -116,6 +214,21 @@ impl TestApp {
 
         ConfirmationLinks { html, plain_text }
     }
+    /// Every message sent through the batch API so far, in the order they were sent.
+    pub async fn batch_emails(&self) -> Vec<serde_json::Value> {
+        self.email_server
+            .received_requests()
+            .await
+            .unwrap()
+            .iter()
+            .filter(|request| request.url.path() == "/email/batch")
+            .flat_map(|request| {
+                let messages: Vec<serde_json::Value> =
+                    serde_json::from_slice(&request.body).unwrap();
+                messages
+            })
+            .collect()
+    }
     pub async fn post_newsletters(&self, body: serde_json::Value) -> reqwest::Response {
         let (user_name, pass_word) = self.test_user().await;
 
@@ -127,6 +240,69 @@ impl TestApp {
             .await
             .expect("Failed to execute request.")
     }
+    pub async fn get_segment_preview(&self, segment: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/newsletters/preview", &self.address))
+            .query(&[("segment", segment)])
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn get_newsletter_issue(&self, newsletter_issue_id: &str) -> reqwest::Response {
+        self.api_client
+            .get(format!("{}/newsletters/{}", &self.address, newsletter_issue_id))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn put_newsletter_schedule(
+        &self,
+        newsletter_issue_id: &str,
+        body: serde_json::Value,
+    ) -> reqwest::Response {
+        self.api_client
+            .put(format!(
+                "{}/newsletters/{}/schedule",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .json(&body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    pub async fn post_newsletter_cancel(&self, newsletter_issue_id: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!(
+                "{}/newsletters/{}/cancel",
+                &self.address, newsletter_issue_id
+            ))
+            .basic_auth(&self.test_user.username, Some(&self.test_user.password))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+    // Runs one scheduler pass against the test database, using the app's mock clock.
+    pub async fn dispatch_due_issues(&self) -> usize {
+        let email_client = EmailClient::new(
+            self.email_server.uri(),
+            SubscriberEmail::parse("sender@example.com".into()).unwrap(),
+            Secret::new(Uuid::new_v4().to_string()),
+            std::time::Duration::from_millis(200),
+        );
+        send_due_issues(
+            &self.db_pool,
+            &email_client,
+            self.clock.as_ref(),
+            &self.address,
+            &self.signing_keys.tracking,
+            &Shutdown::new(),
+        )
+            .await
+            .expect("Failed to send due issues")
+    }
     pub async fn test_user(&self) -> (String, String) {
         let mut conn = self
             .db_pool
@@ -146,10 +322,32 @@ impl TestApp {
     where
         Body: serde::Serialize,
     {
+        self.post_form(&format!("{}/login", &self.address), body)
+            .await
+    }
+    // The token is read once per session, since reading the login form clears its flash messages.
+    pub async fn csrf_token(&self) -> String {
+        if let Some(token) = self.csrf_token.lock().unwrap().clone() {
+            return token;
+        }
+        let token = csrf_token_in(&self.get_login_html().await);
+        *self.csrf_token.lock().unwrap() = Some(token.clone());
+        token
+    }
+    // Submits a form the way the browser would, with the session's CSRF token.
+    async fn post_form<Body>(&self, url: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        let mut form = serde_urlencoded::to_string(body).unwrap();
+        if !form.is_empty() {
+            form.push('&');
+        }
+        form.push_str(&format!("csrf_token={}", self.csrf_token().await));
         self.api_client
-            .post(&format!("{}/login", &self.address))
-            // This `reqwest` method makes sure that the body is URL-encoded and the `Content-Type` header is set accordingly.
-            .form(body)
+            .post(url)
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(form)
             .send()
             .await
             .expect("Failed to execute request.")
@@ -195,36 +393,141 @@ impl TestApp {
     where
         Body: serde::Serialize,
     {
-        self.api_client
-            .post(&format!("{}/admin/password", &self.address))
-            .form(body)
-            .send()
+        self.post_form(&format!("{}/admin/password", &self.address), body)
             .await
-            .expect("Failed to execute request.")
     }
     pub async fn get_change_password_html(&self) -> String {
         // get_change_password is an asynchronous method in Rust that fetches and returns the HTML content of a change password page
         self.get_change_password().await.text().await.unwrap()
     }
-    pub async fn post_logout(&self) -> reqwest::Response {
+    pub async fn get_admin_subscribers_html(&self) -> String {
+        self.api_client
+            .get(format!("{}/admin/subscribers", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+    pub async fn post_subscriber_tags<Body>(&self, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_form(&format!("{}/admin/subscribers/tags", &self.address), body)
+            .await
+    }
+    pub async fn get_admin_page_html(&self, page: &str) -> String {
+        self.api_client
+            .get(format!("{}{}", &self.address, page))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+            .text()
+            .await
+            .unwrap()
+    }
+    pub async fn post_admin_form<Body>(&self, page: &str, body: &Body) -> reqwest::Response
+    where
+        Body: serde::Serialize,
+    {
+        self.post_form(&format!("{}{}", &self.address, page), body)
+            .await
+    }
+    // Creates an API key with the given scopes in the admin, which needs a logged in session,
+    // and returns the key.
+    pub async fn create_api_key(&self, scopes: &[&str]) -> String {
+        let mut form = vec![("name", "Integration")];
+        form.extend(scopes.iter().map(|scope| ("scope", *scope)));
+        let html = self
+            .post_admin_form("/admin/api_keys", &form)
+            .await
+            .text()
+            .await
+            .unwrap();
+        html.split("<p><code>")
+            .nth(1)
+            .and_then(|rest| rest.split('<').next())
+            .expect("The page shows no API key.")
+            .to_string()
+    }
+    // A request to the `/api/v1` endpoints, authenticated with `api_key`.
+    pub fn api_request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+        api_key: &str,
+    ) -> reqwest::RequestBuilder {
         self.api_client
-            .post(&format!("{}/admin/logout", &self.address))
+            .request(method, format!("{}/api/v1{}", &self.address, path))
+            .bearer_auth(api_key)
+    }
+    pub async fn login_as_test_user(&self) {
+        let response = self
+            .post_login(&serde_json::json!({
+                "username": &self.test_user.username,
+                "password": &self.test_user.password
+            }))
+            .await;
+        assert_is_redirect_to(&response, "/admin/dashboard");
+    }
+    pub async fn post_email_webhook(&self, provider: &str, payload: &str) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/webhooks/email/{}", &self.address, provider))
+            .basic_auth(WEBHOOK_USERNAME, Some(WEBHOOK_PASSWORD))
+            .header("Content-Type", "application/json")
+            .body(payload.to_string())
             .send()
             .await
             .expect("Failed to execute request.")
     }
+    pub async fn get_metrics(&self, token: Option<&str>) -> reqwest::Response {
+        let mut request = self.api_client.get(format!("{}/metrics", &self.address));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+    pub async fn post_logout(&self) -> reqwest::Response {
+        let response = self
+            .post_form(&format!("{}/admin/logout", &self.address), &())
+            .await;
+        // Logging out ends the session, and its token with it.
+        *self.csrf_token.lock().unwrap() = None;
+        response
+    }
 }
 pub fn assert_is_redirect_to(response: &reqwest::Response, location: &str) {
     assert_eq!(response.status().as_u16(), 303);
     assert_eq!(response.headers().get("Location").unwrap(), location);
 }
 
+// The CSRF token rendered into the forms of a page.
+pub fn csrf_token_in(html: &str) -> String {
+    html.split(r#"name="csrf_token" value=""#)
+        .nth(1)
+        .and_then(|rest| rest.split('"').next())
+        .expect("The page has no CSRF token.")
+        .to_string()
+}
+
 pub fn run_db_migrations(conn: &mut impl MigrationHarness<diesel::pg::Pg>) {
     conn.run_pending_migrations(MIGRATIONS)
         .expect("Could not run migrations");
 }
 
+// A random address, unique enough to keep apps apart.
+pub fn random_ip() -> IpAddr {
+    let [a, b, ..] = Uuid::new_v4().into_bytes();
+    IpAddr::from([198, 51, a, b])
+}
+
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+// Spawns an app whose settings were changed by `configure` first.
+pub async fn spawn_app_with(configure: impl FnOnce(&mut Settings)) -> TestApp {
     // To Ensure that the tracing stack is only initialized once
     Lazy::force(&TRACING);
     let email_server = MockServer::start().await;
@@ -245,16 +548,28 @@ pub async fn spawn_app() -> TestApp {
     let mut conn = pool.get().expect("Couldn't get db connection from Pool");
     run_db_migrations(&mut conn);
 
-    let application = Application::build(0, pool.clone(), Some(base_uri))
+    let clock = Arc::new(MockClock::new(Utc::now()));
+    let mut settings = test_settings();
+    configure(&mut settings);
+    let signing_keys = settings.signing_keys.clone();
+    let application = Application::build(settings, 0, pool.clone(), Some(base_uri), clock.clone())
         .await
         .expect("Failed to build application");
     let application_port = application.port();
     let address = format!("http://127.0.0.1:{}", application_port);
-    let _ = tokio::spawn(application.run_until_stopped());
+    let shutdown = application.shutdown_handle();
+    let stopped = tokio::spawn(application.run_until_stopped());
 
+    let client_ip = random_ip();
+    let mut default_headers = reqwest::header::HeaderMap::new();
+    default_headers.insert(
+        "X-Forwarded-For",
+        client_ip.to_string().parse().unwrap(),
+    );
     let client = reqwest::Client::builder()
         .redirect(reqwest::redirect::Policy::none())
         .cookie_store(true)
+        .default_headers(default_headers)
         .build()
         .unwrap();
 
@@ -266,6 +581,12 @@ pub async fn spawn_app() -> TestApp {
         email_server,
         test_user: TestUser::generate(),
         api_client: client,
+        clock,
+        signing_keys,
+        client_ip,
+        shutdown,
+        stopped,
+        csrf_token: Mutex::new(None),
     };
     testapp.test_user.store(&testapp.db_pool).await;
     testapp
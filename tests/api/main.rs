@@ -1,8 +1,25 @@
+mod admin_api_keys;
 mod admin_dashboard;
+mod admin_deliveries;
+mod admin_drafts;
+mod admin_subscribers;
+mod admin_templates;
+mod api_v1;
+mod archive;
+mod bot_protection;
 mod change_password;
+mod email_webhooks;
 mod health_check;
 mod helpers;
 mod login;
+mod metrics;
 mod newsletter_tests;
+mod openapi;
+mod rate_limit;
+mod scheduled_newsletters;
+mod security_headers;
+mod shutdown;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_unsubscribe;
+mod tracking;
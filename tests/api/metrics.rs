@@ -0,0 +1,55 @@
+use crate::helpers::{spawn_app, METRICS_TOKEN};
+use crate::newsletter_tests::{
+    create_confirmed_subscriber, create_unconfirmed_subscriber_with_email,
+};
+use newsletter::db::drop_database;
+
+#[tokio::test]
+async fn metrics_require_the_bearer_token() {
+    let app = spawn_app().await;
+
+    for token in [None, Some("wrong-token")] {
+        let response = app.get_metrics(token).await;
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(
+            response.headers()["WWW-Authenticate"],
+            r#"Bearer realm="metrics""#
+        );
+    }
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn metrics_are_exported_in_the_prometheus_format() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    create_unconfirmed_subscriber_with_email(&app, "someone_else@gmail.com").await;
+    app.post_login(&serde_json::json!({
+        "username": "random-username",
+        "password": "random-password"
+    }))
+    .await;
+
+    let response = app.get_metrics(Some(METRICS_TOKEN)).await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.headers()["Content-Type"]
+        .to_str()
+        .unwrap()
+        .starts_with("text/plain; version=0.0.4"));
+    let body = response.text().await.unwrap();
+
+    assert!(body.contains(r#"subscribers{status="confirmed"} 1"#));
+    assert!(body.contains(r#"subscribers{status="pending_confirmation"} 1"#));
+    assert!(body.contains(r#"newsletter_issues_queued{status="scheduled"} 0"#));
+    assert!(body.contains(r#"db_pool_connections{state="in_use"}"#));
+    assert!(body.contains(r#"emails_total{error_class="",outcome="sent",provider="postmark"}"#));
+    assert!(body.contains("login_failures_total "));
+    // Requests are labelled by route pattern, not by path.
+    assert!(body.contains(
+        r#"http_requests_total{method="GET",route="/subscriptions/confirm",status="200"}"#
+    ));
+    assert!(body.contains(
+        r#"http_request_duration_seconds_bucket{method="POST",route="/subscriptions",status="200""#
+    ));
+    drop_database(&app.database_name);
+}
@@ -1,14 +1,20 @@
 use crate::helpers::{spawn_app, ConfirmationLinks, TestApp};
+use fake::faker::internet::en::SafeEmail;
+use fake::Fake;
 use newsletter::db::drop_database;
 use serde_json;
 use uuid::Uuid;
-use wiremock::matchers::{any, method, path};
+use wiremock::matchers::{any, body_partial_json, method, path};
 use wiremock::{Mock, ResponseTemplate};
 
 /// Use the public API of the application under test to create
 /// an unconfirmed subscriber.
-async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
-    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com";
+pub async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
+    create_unconfirmed_subscriber_with_email(app, "ursula_le_guin@gmail.com").await
+}
+
+pub async fn create_unconfirmed_subscriber_with_email(app: &TestApp, email: &str) -> ConfirmationLinks {
+    let body = format!("name=le%20guin&email={}", urlencoding::encode(email));
     let _mock_guard = Mock::given(path("/email"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
@@ -16,7 +22,7 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
         .expect(1)
         .mount_as_scoped(&app.email_server)
         .await;
-    app.post_subscriptions(body.into())
+    app.post_subscriptions(body)
         .await
         .error_for_status()
         .unwrap();
@@ -31,8 +37,12 @@ async fn create_unconfirmed_subscriber(app: &TestApp) -> ConfirmationLinks {
     app.get_confirmation_links(&email_request)
 }
 
-async fn create_confirmed_subscriber(app: &TestApp) {
-    let confirmation_link = create_unconfirmed_subscriber(app).await;
+pub async fn create_confirmed_subscriber(app: &TestApp) {
+    create_confirmed_subscriber_with_email(app, "ursula_le_guin@gmail.com").await;
+}
+
+pub async fn create_confirmed_subscriber_with_email(app: &TestApp, email: &str) {
+    let confirmation_link = create_unconfirmed_subscriber_with_email(app, email).await;
     reqwest::get(confirmation_link.html)
         .await
         .unwrap()
@@ -40,6 +50,13 @@ async fn create_confirmed_subscriber(app: &TestApp) {
         .unwrap();
 }
 
+pub async fn tag_subscriber(app: &TestApp, email: &str, tags: &str) {
+    let response = app
+        .post_subscriber_tags(&serde_json::json!({ "email": email, "tags": tags }))
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+}
+
 #[tokio::test]
 async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
     let app = spawn_app().await;
@@ -70,7 +87,7 @@ async fn newsletters_are_not_delivered_to_unconfirmed_subscribers() {
 async fn newsletters_are_delivered_to_confirmed_subscribers() {
     let app = spawn_app().await;
     create_confirmed_subscriber(&app).await;
-    Mock::given(path("/email"))
+    Mock::given(path("/email/batch"))
         .and(method("POST"))
         .respond_with(ResponseTemplate::new(200))
         .expect(1)
@@ -91,6 +108,166 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     // Mock verifies on Drop that we have sent the newsletter email
 }
 
+#[tokio::test]
+async fn markdown_newsletters_are_delivered_as_rendered_html_and_plain_text() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "markdown": "# Hello\n\nRead [the post](https://example.com/post).<script>alert(1)</script>",
+        }
+    });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = app.batch_emails().await.pop().unwrap();
+    let html_body = body["HtmlBody"].as_str().unwrap();
+    assert!(html_body.contains("<h1 style="));
+    assert!(html_body.contains(r#"href="https://example.com/post""#));
+    assert!(!html_body.contains("<script>"));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Hello\n\nRead the post [1].\n\n[1] https://example.com/post\n\n"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn merge_fields_are_filled_in_for_each_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Issue <1>",
+        "content": {
+            "html": "<p>Hi {{ subscriber.name }}, welcome to {{ issue.title }}</p>",
+            "text": "Hi {{subscriber.name}} ({{ subscriber.email }}), welcome to {{ issue.title }}",
+        }
+    });
+    let response = app.post_newsletters(newsletter_request_body).await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let body = app.batch_emails().await.pop().unwrap();
+    assert!(body["HtmlBody"]
+        .as_str()
+        .unwrap()
+        .contains("<p>Hi le guin, welcome to Issue &lt;1&gt;</p>"));
+    assert!(body["TextBody"]
+        .as_str()
+        .unwrap()
+        .starts_with("Hi le guin (ursula_le_guin@gmail.com), welcome to Issue <1>\n\n"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn only_the_failed_emails_of_a_batch_are_sent_again() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, &SafeEmail().fake::<String>()).await;
+    create_confirmed_subscriber_with_email(&app, &SafeEmail().fake::<String>()).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!([
+            { "ErrorCode": 0, "Message": "OK", "MessageID": Uuid::new_v4() },
+            { "ErrorCode": 429, "Message": "Rate limit exceeded." },
+        ])))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "text": "Hi", "html": "<p>Hi</p>" }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let emails = app.batch_emails().await;
+    assert_eq!(emails.len(), 3);
+    assert_eq!(emails[2]["To"], emails[1]["To"]);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn newsletters_with_invalid_templates_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let test_cases = vec![
+        ("<p>Hi {{ subscriber.age }}</p>", "unknown variable"),
+        ("<p>Hi {{ subscriber.name </p>", "unclosed tag"),
+    ];
+    for (html, description) in test_cases {
+        let response = app
+            .post_newsletters(serde_json::json!({
+                "title": "Newsletter title",
+                "content": { "html": html, "text": "Hi" }
+            }))
+            .await;
+        assert_eq!(
+            response.status().as_u16(),
+            400,
+            "The API did not fail with 400 Bad Request when the template had an {}.",
+            description
+        );
+    }
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn newsletters_with_an_unknown_layout_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": { "markdown": "Hi" },
+            "layout": "does-not-exist",
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "invalid_request");
+    assert_eq!(
+        body["error"]["message"],
+        "There is no email layout named does-not-exist."
+    );
+    drop_database(&app.database_name);
+}
+
 #[tokio::test]
 async fn newsletters_returns_400_for_invalid_data() {
     let app = spawn_app().await;
@@ -206,3 +383,90 @@ async fn invalid_password_is_rejected() {
     );
     drop_database(&app.database_name);
 }
+
+#[tokio::test]
+async fn newsletters_are_delivered_only_to_subscribers_in_the_segment() {
+    let app = spawn_app().await;
+    let beta_email: String = SafeEmail().fake();
+    let other_email: String = SafeEmail().fake();
+    create_confirmed_subscriber_with_email(&app, &beta_email).await;
+    create_confirmed_subscriber_with_email(&app, &other_email).await;
+    app.login_as_test_user().await;
+    tag_subscriber(&app, &beta_email, "beta").await;
+    tag_subscriber(&app, &other_email, "beta, churned").await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .and(body_partial_json(serde_json::json!([{ "To": beta_email }])))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email/batch"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+        "segment": "tag:beta AND subscribed_after:2024-01-01 AND NOT tag:churned",
+    });
+    let response = app.post_newsletters(newsletter_request_body).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn newsletters_with_an_invalid_segment_are_rejected() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "content": {
+            "text": "Newsletter body as plain text",
+            "html": "<p>Newsletter body as HTML</p>",
+        },
+        "segment": "tag:beta AND",
+    });
+    let response = app.post_newsletters(newsletter_request_body).await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn segment_preview_returns_the_number_of_matching_subscribers() {
+    let app = spawn_app().await;
+    let beta_email: String = SafeEmail().fake();
+    create_confirmed_subscriber_with_email(&app, &beta_email).await;
+    create_confirmed_subscriber_with_email(&app, &SafeEmail().fake::<String>()).await;
+    app.login_as_test_user().await;
+    tag_subscriber(&app, &beta_email, "beta").await;
+
+    for (segment, expected_count) in [("tag:beta", 1), ("NOT tag:beta", 1), ("", 2)] {
+        let response = app.get_segment_preview(segment).await;
+        assert_eq!(response.status().as_u16(), 200);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(
+            body["count"], expected_count,
+            "Unexpected count for segment `{}`",
+            segment
+        );
+    }
+
+    let response = app.get_segment_preview("unknown:field").await;
+    assert_eq!(response.status().as_u16(), 400);
+    drop_database(&app.database_name);
+}
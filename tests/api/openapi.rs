@@ -0,0 +1,129 @@
+use crate::helpers::{spawn_app, spawn_app_with, BROWSER_ACCEPT};
+use newsletter::db::drop_database;
+use newsletter::startup::route_table;
+use std::collections::BTreeSet;
+
+// The admin pages are only for people, and are left out of the document.
+const UNDOCUMENTED_SCOPE: &str = "/admin";
+
+async fn openapi_document(address: &str) -> serde_json::Value {
+    let response = reqwest::get(format!("{}/api/openapi.json", address))
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn every_route_is_documented() {
+    let app = spawn_app().await;
+    let document = openapi_document(&app.address).await;
+    let documented: BTreeSet<(String, String)> = document["paths"]
+        .as_object()
+        .unwrap()
+        .iter()
+        .flat_map(|(path, operations)| {
+            operations
+                .as_object()
+                .unwrap()
+                .keys()
+                .map(move |method| (method.clone(), path.clone()))
+        })
+        .collect();
+
+    let registered: BTreeSet<(String, String)> = route_table()
+        .into_iter()
+        .map(|(method, path)| (method.as_str().to_lowercase(), path))
+        .collect();
+    // Scoped routes are listed with the prefix of their scope.
+    assert!(registered.contains(&("post".into(), "/admin/logout".into())));
+    let registered: BTreeSet<(String, String)> = registered
+        .into_iter()
+        .filter(|(_, path)| !path.starts_with(UNDOCUMENTED_SCOPE))
+        .collect();
+
+    let undocumented: Vec<_> = registered.difference(&documented).collect();
+    assert!(
+        undocumented.is_empty(),
+        "Undocumented routes: {:?}",
+        undocumented
+    );
+    let unknown: Vec<_> = documented.difference(&registered).collect();
+    assert!(
+        unknown.is_empty(),
+        "Documented routes that don't exist: {:?}",
+        unknown
+    );
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn request_bodies_and_errors_are_described_by_their_types() {
+    let app = spawn_app().await;
+    let document = openapi_document(&app.address).await;
+
+    let schemas = &document["components"]["schemas"];
+    for schema in [
+        "BodyData",
+        "Content",
+        "FormData",
+        "ErrorBody",
+        "FieldErrorsBody",
+    ] {
+        assert!(schemas[schema].is_object(), "{} is missing", schema);
+    }
+    assert_eq!(schemas["Content"]["oneOf"].as_array().unwrap().len(), 2);
+    assert_eq!(
+        schemas["CheckStatus"]["enum"],
+        serde_json::json!(["up", "down"])
+    );
+    assert_eq!(
+        schemas["ReadinessStatus"]["enum"],
+        serde_json::json!(["ok", "unavailable"])
+    );
+
+    let publish = &document["paths"]["/newsletters"]["post"];
+    assert_eq!(
+        publish["requestBody"]["content"]["application/json"]["schema"]["$ref"],
+        "#/components/schemas/BodyData"
+    );
+    for status in ["400", "401", "404", "409", "500"] {
+        assert_eq!(
+            publish["responses"][status]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/ErrorBody"
+        );
+    }
+    let list_subscribers = &document["paths"]["/api/v1/subscribers"]["get"];
+    assert_eq!(
+        list_subscribers["security"],
+        serde_json::json!([{ "api_key": ["subscribers:read"] }])
+    );
+    assert!(list_subscribers["responses"]["403"].is_object());
+    let subscribe = &document["paths"]["/subscriptions"]["post"];
+    assert!(subscribe["requestBody"]["content"]["application/x-www-form-urlencoded"].is_object());
+    assert!(subscribe["responses"]["400"].is_object());
+    assert!(document["paths"]["/login"]["post"]["responses"]["303"].is_object());
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn the_api_reference_renders_the_document() {
+    let app = spawn_app_with(|settings| settings.api_docs_ui = true).await;
+
+    let response = app
+        .api_client
+        .get(format!("{}/api/docs", &app.address))
+        .header("Accept", BROWSER_ACCEPT)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let policy = response.headers()["Content-Security-Policy"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    assert!(policy.contains("script-src https://cdn.jsdelivr.net"));
+    let html = response.text().await.unwrap();
+    assert!(html.contains(r#"<redoc spec-url="/api/openapi.json"></redoc>"#));
+    drop_database(&app.database_name);
+}
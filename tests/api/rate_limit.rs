@@ -0,0 +1,98 @@
+use crate::helpers::{random_ip, spawn_app};
+use newsletter::db::drop_database;
+use newsletter::rate_limit::{Decision, LimitKey, LimitedRoute, RateLimiter, RateLimits};
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn subscription_body() -> String {
+    format!("name=le%20guin&email={}%40example.com", Uuid::new_v4())
+}
+
+#[tokio::test]
+async fn subscribing_too_often_from_one_ip_is_rejected_with_429() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        // The rejected request sends no email.
+        .expect(11)
+        .mount(&app.email_server)
+        .await;
+    for _ in 0..10 {
+        let response = app.post_subscriptions(subscription_body()).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Act
+    let response = app.post_subscriptions(subscription_body()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    let retry_after: u64 = response.headers()["Retry-After"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(retry_after > 0 && retry_after <= 3600);
+    // Other clients are not affected.
+    let response = app
+        .post_subscriptions_from(subscription_body(), random_ip())
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn the_same_email_is_limited_whichever_ip_it_is_submitted_from() {
+    // Arrange
+    let redis = redis::Client::open(std::env::var("REDIS_URI").unwrap()).unwrap();
+    let limits = RateLimits::default()
+        .parse_overrides("subscriptions.email=2/3600")
+        .unwrap();
+    let limiter = RateLimiter::new(redis, limits, vec![]).with_key_prefix(Uuid::new_v4());
+    let email = format!("{}@example.com", Uuid::new_v4());
+
+    // Act
+    let mut decisions = vec![];
+    for _ in 0..3 {
+        let decision = limiter
+            .check(LimitedRoute::Subscribe, LimitKey::Email, &email)
+            .await
+            .unwrap();
+        decisions.push(decision);
+    }
+
+    // Assert
+    assert_eq!(decisions[..2], [Decision::Allowed, Decision::Allowed]);
+    match decisions[2] {
+        Decision::Limited { retry_after } => {
+            assert!(retry_after > Duration::ZERO && retry_after <= Duration::from_secs(3600))
+        }
+        Decision::Allowed => panic!("The third submission of the email was not limited."),
+    }
+}
+
+#[tokio::test]
+async fn too_many_login_attempts_are_rejected_with_429() {
+    // Arrange
+    let app = spawn_app().await;
+    let login_body = serde_json::json!({
+        "username": "random-username",
+        "password": "random-password"
+    });
+    for _ in 0..10 {
+        let response = app.post_login(&login_body).await;
+        assert_eq!(response.status().as_u16(), 303);
+    }
+
+    // Act
+    let response = app.post_login(&login_body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 429);
+    assert!(response.headers().contains_key("Retry-After"));
+    drop_database(&app.database_name);
+}
@@ -0,0 +1,301 @@
+use crate::helpers::{spawn_app, TestApp};
+use crate::newsletter_tests::{
+    create_confirmed_subscriber, create_confirmed_subscriber_with_email,
+};
+use chrono::{DateTime, Duration, Utc};
+use diesel::prelude::*;
+use newsletter::clock::Clock;
+use newsletter::db::drop_database;
+use newsletter::schema::newsletter_issues;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, Request, ResponseTemplate};
+
+async fn schedule_newsletter(app: &TestApp, scheduled_at: DateTime<Utc>) -> String {
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "scheduled_at": scheduled_at,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "scheduled");
+    body["newsletter_issue_id"].as_str().unwrap().to_string()
+}
+
+async fn issue_status(app: &TestApp, newsletter_issue_id: &str) -> String {
+    let response = app.get_newsletter_issue(newsletter_issue_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    body["status"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_not_sent_before_they_are_due() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = schedule_newsletter(&app, app.clock.now() + Duration::hours(1)).await;
+    app.clock.advance(Duration::minutes(59));
+
+    assert_eq!(app.dispatch_due_issues().await, 0);
+    assert_eq!(issue_status(&app, &issue_id).await, "scheduled");
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_sent_once_they_are_due() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = schedule_newsletter(&app, app.clock.now() + Duration::hours(1)).await;
+    app.clock.advance(Duration::hours(1));
+
+    app.dispatch_due_issues().await;
+    assert_eq!(issue_status(&app, &issue_id).await, "sent");
+    // A second pass must not send the issue again.
+    assert_eq!(app.dispatch_due_issues().await, 0);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn issues_cannot_be_scheduled_in_the_past() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "text": "Newsletter body as plain text",
+                "html": "<p>Newsletter body as HTML</p>",
+            },
+            "scheduled_at": app.clock.now() - Duration::minutes(1),
+        }))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn cancelled_issues_are_never_sent() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = schedule_newsletter(&app, app.clock.now() + Duration::hours(1)).await;
+    let response = app.post_newsletter_cancel(&issue_id).await;
+    assert_eq!(response.status().as_u16(), 200);
+    app.clock.advance(Duration::days(1));
+
+    assert_eq!(app.dispatch_due_issues().await, 0);
+    assert_eq!(issue_status(&app, &issue_id).await, "cancelled");
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn rescheduled_issues_are_sent_at_the_new_time() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = schedule_newsletter(&app, app.clock.now() + Duration::hours(1)).await;
+    let response = app
+        .put_newsletter_schedule(
+            &issue_id,
+            serde_json::json!({ "scheduled_at": app.clock.now() + Duration::hours(3) }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.clock.advance(Duration::hours(2));
+    assert_eq!(app.dispatch_due_issues().await, 0);
+    app.clock.advance(Duration::hours(1));
+    app.dispatch_due_issues().await;
+    assert_eq!(issue_status(&app, &issue_id).await, "sent");
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn sent_issues_can_no_longer_be_rescheduled_or_cancelled() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = schedule_newsletter(&app, app.clock.now() + Duration::hours(1)).await;
+    app.clock.advance(Duration::hours(1));
+    app.dispatch_due_issues().await;
+
+    let response = app.post_newsletter_cancel(&issue_id).await;
+    assert_eq!(response.status().as_u16(), 409);
+    let response = app
+        .put_newsletter_schedule(
+            &issue_id,
+            serde_json::json!({ "scheduled_at": app.clock.now() + Duration::hours(1) }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 409);
+    assert_eq!(issue_status(&app, &issue_id).await, "sent");
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn issues_that_fail_to_send_are_tried_again_later_then_marked_as_failed() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        // Five runs of three attempts each.
+        .expect(15)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = schedule_newsletter(&app, app.clock.now() + Duration::hours(1)).await;
+    app.clock.advance(Duration::hours(1));
+    assert_eq!(app.dispatch_due_issues().await, 0);
+    assert_eq!(issue_status(&app, &issue_id).await, "scheduled");
+    for delay in [1, 2, 4, 8] {
+        app.clock
+            .advance(Duration::minutes(delay) - Duration::seconds(1));
+        assert_eq!(app.dispatch_due_issues().await, 0);
+        app.clock.advance(Duration::seconds(1));
+        assert_eq!(app.dispatch_due_issues().await, 0);
+    }
+    assert_eq!(issue_status(&app, &issue_id).await, "failed");
+
+    // Failed issues can be given another go.
+    let response = app
+        .put_newsletter_schedule(
+            &issue_id,
+            serde_json::json!({ "scheduled_at": app.clock.now() + Duration::hours(1) }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(issue_status(&app, &issue_id).await, "scheduled");
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn recipients_rejected_for_good_are_not_sent_an_issue_tried_again() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber_with_email(&app, "inactive@example.com").await;
+    create_confirmed_subscriber_with_email(&app, "overloaded@example.com").await;
+    // The provider refuses the inactive recipient and keeps throttling the other one.
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(|request: &Request| {
+            let emails: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+            let results: Vec<_> = emails
+                .iter()
+                .map(|email| match email["To"].as_str() {
+                    Some("inactive@example.com") => serde_json::json!({
+                        "ErrorCode": 406,
+                        "Message": "The recipient has been marked as inactive.",
+                    }),
+                    _ => serde_json::json!({ "ErrorCode": 429, "Message": "Slow down." }),
+                })
+                .collect();
+            ResponseTemplate::new(200).set_body_json(results)
+        })
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = schedule_newsletter(&app, app.clock.now() + Duration::hours(1)).await;
+    app.clock.advance(Duration::hours(1));
+    assert_eq!(app.dispatch_due_issues().await, 0);
+    assert_eq!(issue_status(&app, &issue_id).await, "scheduled");
+    app.clock.advance(Duration::minutes(1));
+    assert_eq!(app.dispatch_due_issues().await, 0);
+
+    let requests = app.email_server.received_requests().await.unwrap();
+    let sent_to = |address: &str| {
+        requests
+            .iter()
+            .filter(|request| request.url.path() == "/email/batch")
+            .filter(|request| String::from_utf8_lossy(&request.body).contains(address))
+            .count()
+    };
+    assert_eq!(sent_to("inactive@example.com"), 1);
+    // Two runs of three attempts each.
+    assert_eq!(sent_to("overloaded@example.com"), 6);
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn issues_left_sending_by_a_crash_are_resumed_once_their_claim_runs_out() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let issue_id = schedule_newsletter(&app, app.clock.now() + Duration::hours(1)).await;
+    app.clock.advance(Duration::hours(1));
+    // The application crashes right after claiming the issue.
+    let mut conn = app.db_pool.get().unwrap();
+    diesel::update(newsletter_issues::table)
+        .set((
+            newsletter_issues::status.eq("sending"),
+            newsletter_issues::claimed_at.eq(Some(app.clock.now())),
+        ))
+        .execute(&mut conn)
+        .unwrap();
+    drop(conn);
+
+    app.clock.advance(Duration::minutes(5));
+    assert_eq!(app.dispatch_due_issues().await, 0);
+    assert_eq!(issue_status(&app, &issue_id).await, "sending");
+    app.clock.advance(Duration::minutes(6));
+    assert_eq!(app.dispatch_due_issues().await, 1);
+    assert_eq!(issue_status(&app, &issue_id).await, "sent");
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn unknown_issues_return_404() {
+    let app = spawn_app().await;
+    let issue_id = uuid::Uuid::new_v4().to_string();
+
+    let response = app.get_newsletter_issue(&issue_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+    let response = app.post_newsletter_cancel(&issue_id).await;
+    assert_eq!(response.status().as_u16(), 404);
+    drop_database(&app.database_name);
+}
@@ -0,0 +1,85 @@
+use crate::helpers::{assert_is_redirect_to, spawn_app};
+use newsletter::db::drop_database;
+use newsletter::security_headers::{
+    DEFAULT_CONTENT_SECURITY_POLICY, EMAIL_PREVIEW_CONTENT_SECURITY_POLICY,
+};
+
+fn header<'a>(response: &'a reqwest::Response, name: &str) -> &'a str {
+    response
+        .headers()
+        .get(name)
+        .unwrap_or_else(|| panic!("The response has no {} header.", name))
+        .to_str()
+        .unwrap()
+}
+
+#[tokio::test]
+async fn html_pages_are_served_with_the_security_headers() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!("{}/login", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        header(&response, "Content-Security-Policy"),
+        DEFAULT_CONTENT_SECURITY_POLICY
+    );
+    assert_eq!(header(&response, "X-Frame-Options"), "DENY");
+    assert_eq!(header(&response, "X-Content-Type-Options"), "nosniff");
+    assert_eq!(header(&response, "Referrer-Policy"), "same-origin");
+    assert!(header(&response, "Strict-Transport-Security").starts_with("max-age="));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn responses_rejected_by_a_middleware_get_the_security_headers_too() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app.get_admin_dashboard().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert_eq!(
+        header(&response, "Content-Security-Policy"),
+        DEFAULT_CONTENT_SECURITY_POLICY
+    );
+    assert_eq!(header(&response, "X-Frame-Options"), "DENY");
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn email_previews_get_a_policy_allowing_inline_styles_and_remote_images() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login_as_test_user().await;
+
+    // Act
+    let response = app
+        .api_client
+        .get(format!(
+            "{}/admin/templates/layouts/default/preview",
+            &app.address
+        ))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        header(&response, "Content-Security-Policy"),
+        EMAIL_PREVIEW_CONTENT_SECURITY_POLICY
+    );
+    assert_eq!(header(&response, "X-Frame-Options"), "DENY");
+    drop_database(&app.database_name);
+}
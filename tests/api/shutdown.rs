@@ -0,0 +1,100 @@
+use crate::helpers::spawn_app;
+use chrono::Utc;
+use diesel::dsl::count_star;
+use diesel::prelude::*;
+use newsletter::db::drop_database;
+use newsletter::email_client::MAX_BATCH_SIZE;
+use newsletter::schema::{email_deliveries, newsletter_issues, subscriptions};
+use serde_json::json;
+use std::time::Duration;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+#[tokio::test]
+async fn shutting_down_during_a_delivery_saves_its_progress_and_it_resumes_later() {
+    // Arrange
+    let mut app = spawn_app().await;
+    // One more subscriber than fits in a batch, so the delivery needs a second one.
+    let subscribers: Vec<_> = (0..=MAX_BATCH_SIZE)
+        .map(|i| {
+            (
+                subscriptions::id.eq(Uuid::new_v4()),
+                subscriptions::email.eq(format!("subscriber{}@example.com", i)),
+                subscriptions::name.eq("Subscriber"),
+                subscriptions::subscribed_at.eq(Utc::now()),
+                subscriptions::status.eq("confirmed"),
+            )
+        })
+        .collect();
+    let mut conn = app.db_pool.get().unwrap();
+    diesel::insert_into(subscriptions::table)
+        .values(&subscribers)
+        .execute(&mut conn)
+        .unwrap();
+    let sent = |count: usize| {
+        let receipts: Vec<_> = (0..count)
+            .map(|_| json!({ "ErrorCode": 0, "Message": "OK", "MessageID": Uuid::new_v4() }))
+            .collect();
+        ResponseTemplate::new(200).set_body_json(receipts)
+    };
+    // The first batch is still in flight when the shutdown is triggered.
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(sent(MAX_BATCH_SIZE).set_delay(Duration::from_millis(500)))
+        .up_to_n_times(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(sent(1))
+        .mount(&app.email_server)
+        .await;
+
+    // Act - Part 1 - Shut down while the first batch is being sent
+    let (response, _) = tokio::join!(
+        app.post_newsletters(json!({
+            "title": "Newsletter title",
+            "content": { "text": "Hi", "html": "<p>Hi</p>" }
+        })),
+        async {
+            while app.email_server.received_requests().await.unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(20)).await;
+            }
+            app.shutdown.trigger(Duration::ZERO);
+        }
+    );
+
+    // Assert - Part 1 - The first batch was delivered and the rest was saved for later
+    assert_eq!(response.status().as_u16(), 202);
+    tokio::time::timeout(Duration::from_secs(10), &mut app.stopped)
+        .await
+        .expect("The application did not stop")
+        .unwrap()
+        .unwrap();
+    assert_eq!(app.batch_emails().await.len(), MAX_BATCH_SIZE);
+    let sent = email_deliveries::table
+        .filter(email_deliveries::status.eq("sent"))
+        .select(count_star())
+        .get_result::<i64>(&mut conn)
+        .unwrap();
+    assert_eq!(sent, MAX_BATCH_SIZE as i64);
+    let status = newsletter_issues::table
+        .select(newsletter_issues::status)
+        .get_result::<String>(&mut conn)
+        .unwrap();
+    assert_eq!(status, "scheduled");
+
+    // Act - Part 2 - The scheduler picks the issue up again
+    assert_eq!(app.dispatch_due_issues().await, 1);
+
+    // Assert - Part 2 - Only the subscriber left out was sent the issue
+    assert_eq!(app.batch_emails().await.len(), MAX_BATCH_SIZE + 1);
+    let status = newsletter_issues::table
+        .select(newsletter_issues::status)
+        .get_result::<String>(&mut conn)
+        .unwrap();
+    assert_eq!(status, "sent");
+    drop(conn);
+    drop_database(&app.database_name);
+}
@@ -154,3 +154,91 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     assert_eq!(response.status().as_u16(), 500);
     drop_database(&app.database_name);
 }
+
+#[tokio::test]
+async fn the_home_page_is_the_signup_form() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .get(&app.address)
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(r#"<form action="/subscriptions" method="post">"#));
+    assert!(html_page.contains(r#"name="form_token""#));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn invalid_fields_are_shown_next_to_the_form_in_a_browser() {
+    let app = spawn_app().await;
+
+    let response = app
+        .post_signup_form("name=ursula&email=not-an-%3Cemail%3E")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let html_page = response.text().await.unwrap();
+    assert!(
+        html_page.contains("<p><i>not-an-&lt;email&gt; is not a valid subscriber email.</i></p>")
+    );
+    assert!(html_page.contains(r#"name="name" value="ursula""#));
+    assert!(html_page.contains(r#"name="email" value="not-an-&lt;email&gt;""#));
+    assert!(!html_page.contains("is not a valid subscriber name"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn a_browser_is_told_to_check_its_inbox() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_signup_form("name=le%20guin&email=ursula_le_guin%40gmail.com")
+        .await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("<h1>Check your inbox</h1>"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn api_clients_get_json() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let accepted = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let rejected = app
+        .post_subscriptions("name=&email=not-an-email".into())
+        .await;
+
+    assert_eq!(accepted.status().as_u16(), 200);
+    let body: serde_json::Value = accepted.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+    assert_eq!(rejected.status().as_u16(), 400);
+    let body: serde_json::Value = rejected.json().await.unwrap();
+    assert_eq!(
+        body["errors"]["email"],
+        "not-an-email is not a valid subscriber email."
+    );
+    assert_eq!(body["errors"]["name"], " is not a valid subscriber name.");
+    drop_database(&app.database_name);
+}
@@ -1,4 +1,4 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, BROWSER_ACCEPT};
 use diesel::prelude::*;
 use newsletter::db::drop_database;
 use newsletter::db_models::Subscription;
@@ -80,3 +80,80 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
 
     drop_database(&app.database_name);
 }
+
+#[tokio::test]
+async fn browsers_get_a_page_saying_how_the_confirmation_went() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+    let mut invalid_link = confirmation_link.clone();
+    invalid_link.set_query(Some("subscription_token=not-a-token"));
+    let visit = |link: reqwest::Url| {
+        app.api_client
+            .get(link)
+            .header("Accept", BROWSER_ACCEPT)
+            .send()
+    };
+
+    let first = visit(confirmation_link.clone()).await.unwrap();
+    let second = visit(confirmation_link).await.unwrap();
+    let invalid = visit(invalid_link).await.unwrap();
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert!(first
+        .text()
+        .await
+        .unwrap()
+        .contains("<h1>Subscription confirmed</h1>"));
+    assert_eq!(second.status().as_u16(), 200);
+    assert!(second
+        .text()
+        .await
+        .unwrap()
+        .contains("<h1>Already confirmed</h1>"));
+    assert_eq!(invalid.status().as_u16(), 401);
+    assert!(invalid
+        .text()
+        .await
+        .unwrap()
+        .contains("<h1>Link expired or invalid</h1>"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn api_clients_get_the_confirmation_status_as_json() {
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    app.post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_link = app.get_confirmation_links(email_request).html;
+
+    let first: serde_json::Value = reqwest::get(confirmation_link.clone())
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let second: serde_json::Value = reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(first["status"], "confirmed");
+    assert_eq!(second["status"], "already_confirmed");
+    drop_database(&app.database_name);
+}
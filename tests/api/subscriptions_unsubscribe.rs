@@ -0,0 +1,135 @@
+use crate::helpers::{spawn_app, TestApp};
+use crate::newsletter_tests::create_confirmed_subscriber;
+use diesel::prelude::*;
+use newsletter::db::drop_database;
+use newsletter::schema::{subscription_tokens, subscriptions};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn subscription_status(app: &TestApp) -> Option<String> {
+    let mut conn = app.db_pool.get().unwrap();
+    subscriptions::table
+        .select(subscriptions::status)
+        .first::<Option<String>>(&mut conn)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn unsubscribing_with_an_unknown_token_is_rejected_with_a_401() {
+    let app = spawn_app().await;
+    let link = format!(
+        "{}/subscriptions/unsubscribe?subscription_token=unknown",
+        app.address
+    );
+
+    let page = reqwest::get(&link).await.unwrap();
+    let unsubscribed = app.api_client.post(&link).send().await.unwrap();
+
+    assert_eq!(page.status().as_u16(), 401);
+    assert_eq!(unsubscribed.status().as_u16(), 401);
+    assert!(unsubscribed
+        .text()
+        .await
+        .unwrap()
+        .contains("This unsubscribe link is not valid."));
+    drop_database(&app.database_name);
+}
+
+// The default layout adds the unsubscribe link to every newsletter.
+#[tokio::test]
+async fn the_unsubscribe_link_in_a_newsletter_unsubscribes_the_subscriber() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "markdown": "Hi {{ subscriber.name }}",
+            }
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email = app.batch_emails().await.pop().unwrap();
+    let unsubscribe_links = app.get_links(&email);
+    assert_eq!(unsubscribe_links.html.path(), "/subscriptions/unsubscribe");
+    assert_eq!(
+        unsubscribe_links.plain_text.path(),
+        "/subscriptions/unsubscribe"
+    );
+    let link = format!(
+        "{}?{}",
+        unsubscribe_links.plain_text.path(),
+        unsubscribe_links.plain_text.query().unwrap()
+    );
+    assert_eq!(email["Headers"][0]["Name"], "List-Unsubscribe");
+    assert!(email["Headers"][0]["Value"]
+        .as_str()
+        .unwrap()
+        .ends_with(&format!("{}>", link)));
+    assert_eq!(
+        email["Headers"][1],
+        serde_json::json!({
+            "Name": "List-Unsubscribe-Post",
+            "Value": "List-Unsubscribe=One-Click",
+        })
+    );
+
+    // Opening the link, as link scanners do, only asks to confirm.
+    let response = reqwest::get(unsubscribe_links.plain_text.clone())
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    let action = format!(
+        r#"<form action="{}" method="post">"#,
+        link.replace('&', "&amp;")
+    );
+    assert!(html_page.contains(&action));
+    assert_eq!(subscription_status(&app).as_deref(), Some("confirmed"));
+
+    let response = app
+        .api_client
+        .post(unsubscribe_links.plain_text)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscription_status(&app).as_deref(), Some("unsubscribed"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn mail_clients_unsubscribe_in_one_click() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let subscription_token = {
+        let mut conn = app.db_pool.get().unwrap();
+        subscription_tokens::table
+            .select(subscription_tokens::subscription_token)
+            .first::<String>(&mut conn)
+            .unwrap()
+    };
+
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/subscriptions/unsubscribe?subscription_token={}",
+            app.address, subscription_token
+        ))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(subscription_status(&app).as_deref(), Some("unsubscribed"));
+    drop_database(&app.database_name);
+}
@@ -0,0 +1,156 @@
+use crate::helpers::{spawn_app, TestApp};
+use crate::newsletter_tests::create_confirmed_subscriber;
+use diesel::prelude::*;
+use newsletter::db::drop_database;
+use newsletter::schema::newsletter_issues;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn send_newsletter(app: &TestApp, tracking: bool) -> serde_json::Value {
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+    let response = app
+        .post_newsletters(serde_json::json!({
+            "title": "Newsletter title",
+            "content": {
+                "html": r#"<p>Read <a href="https://example.com/post?a=1&amp;b=2">the post</a>.</p>"#,
+                "text": "Read the post: https://example.com/post?a=1&b=2",
+            },
+            "tracking": tracking,
+        }))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.batch_emails().await.pop().unwrap()
+}
+
+// Finds the tracking URL with the given prefix, e.g. `/t/c/`, and points it at the test app.
+fn tracking_url(app: &TestApp, html: &str, prefix: &str) -> reqwest::Url {
+    let start = html
+        .find(&format!("http://127.0.0.1{}", prefix))
+        .expect("No tracking URL found.");
+    let end = start + html[start..].find('"').unwrap();
+    let mut url = reqwest::Url::parse(&html[start..end]).unwrap();
+    url.set_port(Some(app.port)).unwrap();
+    url
+}
+
+fn issue_id(app: &TestApp) -> Uuid {
+    let mut conn = app.db_pool.get().unwrap();
+    newsletter_issues::table
+        .select(newsletter_issues::newsletter_issue_id)
+        .first::<Uuid>(&mut conn)
+        .unwrap()
+}
+
+#[tokio::test]
+async fn tracked_issues_count_opens_and_clicks() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let email = send_newsletter(&app, true).await;
+    let html = email["HtmlBody"].as_str().unwrap();
+    assert!(!html.contains(r#"href="https://example.com/post"#));
+    assert!(email["TextBody"]
+        .as_str()
+        .unwrap()
+        .contains("https://example.com/post?a=1&b=2"));
+
+    let click_url = tracking_url(&app, html, "/t/c/");
+    let response = app.api_client.get(click_url.clone()).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 302);
+    assert_eq!(
+        response.headers().get("Location").unwrap(),
+        "https://example.com/post?a=1&b=2"
+    );
+    // Clicking twice still counts as one unique click.
+    app.api_client.get(click_url).send().await.unwrap();
+
+    let response = app
+        .api_client
+        .get(tracking_url(&app, html, "/t/o/"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(response.headers().get("Content-Type").unwrap(), "image/gif");
+
+    app.login_as_test_user().await;
+    let html_page = app
+        .get_admin_page_html(&format!("/admin/issues/{}/stats", issue_id(&app)))
+        .await;
+    assert!(html_page.contains("<li>Delivered: 1</li>"));
+    assert!(html_page.contains("<li>Unique opens: 1</li>"));
+    assert!(html_page.contains("<li>Unique clicks: 1</li>"));
+    assert!(html_page.contains("<tr><td>https://example.com/post?a=1&amp;b=2</td><td>2</td></tr>"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn untracked_issues_are_sent_unchanged() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+
+    let email = send_newsletter(&app, false).await;
+    let html = email["HtmlBody"].as_str().unwrap();
+    assert!(html.contains(r#"href="https://example.com/post?a=1&amp;b=2""#));
+    assert!(!html.contains("/t/o/"));
+    assert!(!html.contains("/t/c/"));
+
+    // Deliveries are recorded either way.
+    app.login_as_test_user().await;
+    let html_page = app
+        .get_admin_page_html(&format!("/admin/issues/{}/stats", issue_id(&app)))
+        .await;
+    assert!(html_page.contains("<li>Delivered: 1</li>"));
+    assert!(html_page.contains("tracking is disabled"));
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn forged_tracking_tokens_are_rejected_with_a_404() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = send_newsletter(&app, true).await;
+    let click_url = tracking_url(&app, email["HtmlBody"].as_str().unwrap(), "/t/c/");
+
+    // Changes a character of the issue id, leaving the signature as it was.
+    let mut tampered: Vec<char> = click_url.path().chars().collect();
+    tampered[10] = if tampered[10] == 'A' { 'B' } else { 'A' };
+    let tampered: String = tampered.into_iter().collect();
+    for path in [tampered.as_str(), "/t/c/not-a-token", "/t/o/not-a-token"] {
+        let response = app
+            .api_client
+            .get(format!("{}{}", app.address, path))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 404, "{}", path);
+    }
+    drop_database(&app.database_name);
+}
+
+#[tokio::test]
+async fn unsubscribes_are_attributed_to_the_issue() {
+    let app = spawn_app().await;
+    create_confirmed_subscriber(&app).await;
+    let email = send_newsletter(&app, false).await;
+
+    let html = email["HtmlBody"].as_str().unwrap();
+    let unsubscribe_url = tracking_url(&app, html, "/subscriptions/unsubscribe");
+    let unsubscribe_url =
+        reqwest::Url::parse(&unsubscribe_url.as_str().replace("&amp;", "&")).unwrap();
+    let response = app.api_client.post(unsubscribe_url).send().await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    app.login_as_test_user().await;
+    let html_page = app
+        .get_admin_page_html(&format!("/admin/issues/{}/stats", issue_id(&app)))
+        .await;
+    assert!(html_page.contains("<li>Unsubscribes: 1</li>"));
+    drop_database(&app.database_name);
+}